@@ -3,6 +3,7 @@
 import argparse
 import os
 import socket
+import sys
 import time
 from datetime import datetime, timedelta
 
@@ -19,6 +20,16 @@ from datetime import datetime, timedelta
 
 BUFFER_SIZE = 40960
 
+# the payload behind the 4-byte sequence number is filled with a pattern derived
+# from the sequence number: byte i = (packet_number + i) & 0xFF
+# nettest on D3OS checks it, a mismatch means the frame got corrupted on the way
+# (e.g. while the ne2000 recovers from a receive buffer overflow)
+PATTERN = bytes(range(256)) * 8
+
+def fill_pattern(packet, packet_number):
+    offset = packet_number & 0xFF
+    packet[4:] = PATTERN[offset:offset + len(packet) - 4]
+
 def receive_traffic(sock): 
 
     packet_count = 0
@@ -210,7 +221,7 @@ def tcp_server(conn, addr):
 
 
 
-def send_traffic(sock, addr, packet_length, duration):
+def send_traffic(sock, addr, packet_length, duration, pps=1000):
 
     bytes_sent_in_interval = 0
     interval_counter = 0
@@ -223,9 +234,8 @@ def send_traffic(sock, addr, packet_length, duration):
     # limit how many packets per second should be sent 
     # because of slirp errors in qemu the OS isn't can't process 
     # a big amount of packets coming in a short amount of time
-    pps = 1000
-
-    interval = 1.0 / pps
+    # pps = None floods the card (see --flood), this overflows the receive ring of the ne2000
+    interval = 1.0 / pps if pps else None
 
     #test_finish_time = int(time.time()) + interval 
     seconds_passed = int(time.time()) + 1 
@@ -245,6 +255,7 @@ def send_traffic(sock, addr, packet_length, duration):
         packet[1] = (packet_number >> 16) & 0xFF
         packet[2] = (packet_number >> 8) & 0xFF
         packet[3] = (packet_number ) & 0xFF
+        fill_pattern(packet, packet_number)
 
         sock.sendto(packet, addr)
 
@@ -300,7 +311,43 @@ def send_traffic(sock, addr, packet_length, duration):
         
 
 
-def client(sock, addr, packet_length, interval):
+def check_result(sock, addr, attempts=10):
+    # ask nettest on D3OS for the result of the flood test
+    # (see udp_receive_traffic() in nettest.rs), it answers the exit message
+    # with "result <received> <corrupted> <overflows>"
+    # the exit message may get lost in the flood, so it is repeated
+    # no answer at all means, that the card doesn't receive anymore (e.g. it hangs after an overflow)
+    sock.settimeout(1.0)
+    for _ in range(attempts):
+        try:
+            data, _ = sock.recvfrom(BUFFER_SIZE)
+        except socket.timeout:
+            sock.sendto(b"exit\n", addr)
+            continue
+        if not data.startswith(b"result "):
+            continue
+
+        received, corrupted, overflows = (int(value) for value in data.split()[1:4])
+        print(f"packets received           : {received}")
+        print(f"corrupted packets          : {corrupted}")
+        print(f"receive buffer overflows   : {overflows}")
+        if received == 0:
+            print("FAILED: no packets received")
+            return 1
+        if corrupted > 0:
+            print("FAILED: the payload of some packets has been corrupted")
+            return 1
+        if overflows == 0:
+            print("FAILED: the flood didn't overflow the receive ring, send longer or faster")
+            return 1
+        print("PASSED")
+        return 0
+
+    print("FAILED: no result from nettest, the card doesn't receive anymore")
+    return 1
+
+
+def client(sock, addr, packet_length, interval, pps=1000, check=False, attempts=60):
 
 
     #print(f"nettest: client listening on {local_address}! Send 'exit' to leave.")
@@ -308,18 +355,23 @@ def client(sock, addr, packet_length, interval):
     print(f"UDP: sending Init to {addr}")
 
     init_msg = b"Init\n"
-    # send init msg to server 
-    sock.sendto(init_msg, addr)
-
-
-    while True:
-        data, addr = sock.recvfrom(BUFFER_SIZE)
-        if not data:
+    # send init msg to server, until it answers
+    # (it may not be listening yet, e.g. while D3OS is booting)
+    sock.settimeout(1.0)
+    for _ in range(attempts):
+        sock.sendto(init_msg, addr)
+        try:
+            data, addr = sock.recvfrom(BUFFER_SIZE)
+        except socket.timeout:
             continue
 
-
         if data == init_msg:
-            return send_traffic(sock, addr, packet_length, interval)
+            sock.settimeout(None)
+            send_traffic(sock, addr, packet_length, interval, pps)
+            return check_result(sock, addr) if check else 0
+
+    print("no answer to Init")
+    return 1
 
 
 def tcp_client(packet_length, duration):
@@ -450,8 +502,12 @@ def main():
                        help="Seconds to send for (overrides --count)")
     group.add_argument("--packet_length", "-p", type=int, default=1024,
                        help="define the packet length (client mode)")
-    ap.add_argument("--pps", type=float,
-                    help="Packets per second (rate limit). If unset, send as fast as possible.")
+    ap.add_argument("--pps", type=float, default=1000,
+                    help="Packets per second (rate limit). Default: 1000")
+    ap.add_argument("--flood", action="store_true",
+                    help="send as fast as possible, regression test for the ne2000 overflow recovery")
+    ap.add_argument("--check", action="store_true",
+                    help="client mode: exit with 1, unless nettest on D3OS reports an overflow and no corrupted packets")
     ap.add_argument("--no-connect", action="store_true",
                     help="Use sendto() instead of connect()+send()")
     args = ap.parse_args()
@@ -482,7 +538,7 @@ def main():
 
     if args.mode == 1:
         if args.type == "udp":
-            return client(sock, address_remote, packet_length, timing_interval )
+            return client(sock, address_remote, packet_length, timing_interval, None if args.flood else args.pps, args.check)
         return tcp_client(packet_length, timing_interval)
 
     if args.type == "udp":
@@ -492,6 +548,6 @@ def main():
 
 if __name__ == "__main__":
     try:
-        main()
+        sys.exit(main())
     except KeyboardInterrupt:
         print("closing...")
@@ -1,18 +1,226 @@
-//! ip – show the current IP address
+//! ip – show the current IP address and the statistics of the network cards,
+//! add and remove addresses, routes and neighbor cache entries
 
 #![no_std]
 extern crate alloc;
 
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 #[allow(unused_imports)]
 use runtime::*;
-use network::get_ip_addresses;
+use network::{
+    add_ip_address, add_neighbor, add_route, flush_neighbors, get_interface_links, get_ip_addresses, get_neighbors, get_network_stats,
+    get_routes, remove_ip_address, remove_neighbor, remove_route, NeighborState, NetworkError,
+};
 use terminal::{print, println};
 
 #[unsafe(no_mangle)]
 fn main() {
-    // ignore all args for now
+    let mut args = env::args();
+    // the first argument is the program name, ignore it
+    args.next();
 
-    for ip in get_ip_addresses() {
-        println!("{}", ip)
+    let args: Vec<String> = args.collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        [] => {
+            for ip in get_ip_addresses() {
+                println!("{}", ip)
+            }
+            Ok(())
+        }
+        ["-s"] | ["stats"] => {
+            print_stats();
+            Ok(())
+        }
+        ["link"] | ["link", "show"] => {
+            print_links();
+            Ok(())
+        }
+        ["neigh"] | ["neigh", "show"] => {
+            print_neighbors();
+            Ok(())
+        }
+        ["neigh", "add", address, "lladdr", mac, "dev", interface] => match (address.parse(), parse_mac(mac)) {
+            (Ok(address), Some(mac)) => add_neighbor(interface, address, mac),
+            _ => Err(NetworkError::InvalidAddress),
+        },
+        ["neigh", "del", address, "dev", interface] => match address.parse() {
+            Ok(address) => remove_neighbor(interface, address),
+            Err(_) => Err(NetworkError::InvalidAddress),
+        },
+        ["neigh", "flush", rest @ ..] => match parse_device(rest) {
+            Some(interface) => flush_neighbors(interface),
+            None => Err(NetworkError::InvalidArgument),
+        },
+        ["route"] | ["route", "show"] => {
+            print_routes();
+            Ok(())
+        }
+        ["addr", "add", cidr, "dev", interface] => match parse_cidr(cidr) {
+            Some((address, prefix_len)) => add_ip_address(interface, address, prefix_len),
+            None => Err(NetworkError::InvalidAddress),
+        },
+        ["addr", "del", address, "dev", interface] => match address.split('/').next().unwrap_or_default().parse() {
+            Ok(address) => remove_ip_address(interface, address),
+            Err(_) => Err(NetworkError::InvalidAddress),
+        },
+        ["route", "add", destination, "via", gateway, rest @ ..] => {
+            match (parse_destination(destination, gateway), gateway.parse(), parse_device(rest)) {
+                (Some((destination, prefix_len)), Ok(gateway), Some(interface)) => {
+                    add_route(interface, destination, prefix_len, gateway)
+                }
+                _ => Err(NetworkError::InvalidAddress),
+            }
+        }
+        // without a gateway, the address family of the default route is ambiguous, so IPv4 is assumed
+        ["route", "del", destination, rest @ ..] => match (parse_destination(destination, "0.0.0.0"), parse_device(rest)) {
+            (Some((destination, prefix_len)), Some(interface)) => remove_route(interface, destination, prefix_len),
+            _ => Err(NetworkError::InvalidAddress),
+        },
+        _ => {
+            print_usage();
+            Ok(())
+        }
+    };
+
+    match result {
+        Ok(()) => {}
+        Err(NetworkError::NotFound) => println!("ip: no such interface, address, route or neighbor"),
+        Err(NetworkError::AlreadyExists) => println!("ip: already exists"),
+        Err(NetworkError::TableFull) => println!("ip: the interface can't hold any more addresses, routes or neighbors"),
+        Err(NetworkError::PermissionDenied) => println!("ip: not allowed, ip has to be listed with raw= in network.conf"),
+        Err(NetworkError::InvalidAddress) => println!("ip: invalid address"),
+        Err(error) => println!("ip: {:?}", error),
+    }
+}
+
+fn print_usage() {
+    println!("Usage:
+    ip
+        show the IP addresses of this host
+    ip -s | ip stats
+        show the traffic and error counters of the network cards
+    ip link
+        show the MAC address, MTU and link state of the network cards
+    ip addr add <address>/<prefix> dev <interface>
+        add an address to the network card, whose driver name contains <interface> (e.g. NE2000)
+    ip addr del <address> dev <interface>
+        remove an address from the network card
+    ip route add <network>/<prefix>|default via <gateway> [dev <interface>]
+        send the packets for a network to a gateway
+    ip route
+        show the routes of all interfaces
+    ip route del <network>/<prefix>|default [dev <interface>]
+        remove a route (from all interfaces, if none is given)
+    ip neigh
+        show the neighbor cache (ARP and IPv6 neighbor discovery) of all interfaces
+    ip neigh add <address> lladdr <mac> dev <interface>
+        add a permanent entry (the address must be in the network of the interface)
+    ip neigh del <address> dev <interface>
+        remove an entry
+    ip neigh flush [dev <interface>]
+        forget the learned entries (of all interfaces, if none is given)");
+}
+
+/// Parse "address/prefix", the prefix length is required.
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix_len) = cidr.split_once('/')?;
+    let address: IpAddr = address.parse().ok()?;
+    let prefix_len: u8 = prefix_len.parse().ok()?;
+    let max_len = if address.is_ipv4() { 32 } else { 128 };
+    (prefix_len <= max_len).then_some((address, prefix_len))
+}
+
+/// Parse the destination of a route, "default" uses the address family of `gateway`.
+fn parse_destination(destination: &str, gateway: &str) -> Option<(IpAddr, u8)> {
+    if destination == "default" {
+        match gateway.parse().ok()? {
+            IpAddr::V4(_) => Some((IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)),
+            IpAddr::V6(_) => Some((IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)),
+        }
+    } else {
+        parse_cidr(destination)
+    }
+}
+
+/// Parse an optional "dev <interface>" at the end of the arguments.
+fn parse_device<'a>(args: &[&'a str]) -> Option<Option<&'a str>> {
+    match args {
+        [] => Some(None),
+        ["dev", interface] => Some(Some(interface)),
+        _ => None,
+    }
+}
+
+/// Parse a MAC address like "52:54:00:12:34:56".
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let mut bytes = [0; 6];
+    let mut parts = mac.split(':');
+    for byte in bytes.iter_mut() {
+        *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    parts.next().is_none().then_some(bytes)
+}
+
+fn format_mac(mac: [u8; 6]) -> String {
+    let [m0, m1, m2, m3, m4, m5] = mac;
+    format!("{m0:02x}:{m1:02x}:{m2:02x}:{m3:02x}:{m4:02x}:{m5:02x}")
+}
+
+fn print_links() {
+    for link in get_interface_links() {
+        let state = if link.up { "UP" } else { "DOWN" };
+        match link.mac() {
+            Some(mac) => println!("{}: mtu {} state {}\n    link/ether {}", link.name(), link.mtu, state, format_mac(mac)),
+            None => println!("{}: mtu {} state {}\n    link/loopback", link.name(), link.mtu, state),
+        }
+    }
+}
+
+fn print_neighbors() {
+    for neighbor in get_neighbors() {
+        let state = match neighbor.state() {
+            NeighborState::Reachable => "REACHABLE",
+            NeighborState::Expired => "STALE",
+            NeighborState::Permanent => "PERMANENT",
+        };
+        print!("{} dev {} lladdr {} {}", neighbor.address(), neighbor.interface(), format_mac(neighbor.mac), state);
+        match neighbor.expires_in() {
+            Some(expires_in) if neighbor.state() == NeighborState::Reachable => println!(" (expires in {} s)", expires_in.as_secs()),
+            _ => println!(""),
+        }
+    }
+}
+
+fn print_routes() {
+    for route in get_routes() {
+        if route.prefix_len == 0 {
+            print!("default");
+        } else {
+            print!("{}/{}", route.destination(), route.prefix_len);
+        }
+        print!(" via {} dev {}", route.gateway(), route.interface());
+        match route.expires_in() {
+            Some(expires_in) => println!(" (expires in {} s)", expires_in.as_secs()),
+            None => println!(""),
+        }
+    }
+}
+
+fn print_stats() {
+    for stats in get_network_stats() {
+        println!("{}:", stats.name());
+        println!("    RX: {} packets, {} bytes, {} errors, {} dropped, {} overflows",
+            stats.rx_packets, stats.rx_bytes, stats.rx_errors, stats.rx_dropped, stats.rx_overflows);
+        println!("        crc {}, frame {}, fifo {}, missed {}",
+            stats.rx_crc_errors, stats.rx_frame_errors, stats.rx_fifo_errors, stats.rx_missed);
+        println!("    TX: {} packets, {} bytes, {} errors, {} collisions",
+            stats.tx_packets, stats.tx_bytes, stats.tx_errors, stats.tx_collisions);
+        println!("        aborted {}, fifo {}, carrier {}",
+            stats.tx_aborted, stats.tx_fifo_errors, stats.tx_carrier_errors);
     }
 }
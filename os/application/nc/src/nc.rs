@@ -1,13 +1,16 @@
 #![no_std]
 extern crate alloc;
 
-use core::net::{IpAddr, Ipv6Addr, SocketAddr};
+use core::{net::{IpAddr, Ipv6Addr, SocketAddr}, time::Duration};
 
 use alloc::string::String;
-use network::{resolve_hostname, TcpListener, TcpStream, UdpSocket};
+use network::{poll, resolve_hostname, PollFlags, TcpListener, TcpStream, UdpSocket};
 #[allow(unused_imports)]
 use runtime::*;
-use terminal::{print, println, read::read};
+use terminal::{print, println, read::read_nb};
+
+/// How long to wait for the socket, before checking the keyboard again
+const KEYBOARD_INTERVAL: Duration = Duration::from_millis(10);
 
 enum Protocol {
     Udp, Tcp,
@@ -68,7 +71,13 @@ Examples:
     // for connect, this is the remote host to connect to
     let addr = if let Some(host) = args.next() && let Some(port_str) = args.next() {
         // just take the first IP address
-        let ip = resolve_hostname(&host).into_iter().next().unwrap();
+        let ip = match resolve_hostname(&host) {
+            Ok(ips) => ips[0],
+            Err(error) => {
+                println!("failed to resolve {}: {:?}", host, error);
+                return;
+            }
+        };
         let port: u16 = port_str.parse().expect("failed to parse port");
         SocketAddr::new(ip, port)
     } else {
@@ -95,11 +104,32 @@ Examples:
         },
     };
 
+    let mut entries = [match socket {
+        Socket::Udp(ref sock) => sock.poll_entry(PollFlags::READABLE),
+        Socket::Tcp(ref sock) => sock.poll_entry(PollFlags::READABLE),
+    }];
+
     // loop: send and receive
     let mut buf = [0u8; 1024];
     loop {
-        let key = read();
-        if let Some(key) = key {
+        // the keyboard can't be polled, so only wait a short time for the socket
+        if poll(&mut entries, Some(KEYBOARD_INTERVAL)).expect("failed to poll socket") > 0 {
+            let result = match socket {
+                Socket::Udp(ref sock) => sock.recv_from(&mut buf).map(|(len, _)| len),
+                Socket::Tcp(ref sock) => sock.read(&mut buf),
+            };
+            let len = result.expect("failed to receive char");
+            // for TCP, this means that the remote host has closed the connection
+            if len == 0 && matches!(socket, Socket::Tcp(_)) {
+                println!("[connection closed]");
+                return;
+            }
+            if len > 0 {
+                let text = str::from_utf8(&buf[0..len]).expect("failed to parse received string");
+                print!("{text}");
+            }
+        }
+        if let Some(key) = read_nb() {
             let string = key.encode_utf8(&mut buf);
             match socket {
                 Socket::Udp(ref sock) => sock.send_to(string.as_bytes(), addr)
@@ -108,16 +138,6 @@ Examples:
                     .expect("failed to send char"),
             };
         }
-        let len = match socket {
-            Socket::Udp(ref sock) => sock.recv_from(&mut buf)
-                .expect("failed to receive char").0,
-            Socket::Tcp(ref sock) => sock.read(&mut buf)
-                .expect("failed to receive char"),
-        };
-        if len > 0 {
-            let text = str::from_utf8(&buf[0..len]).expect("failed to parse received string");
-            print!("{text}");
-        }
     }
 
 }
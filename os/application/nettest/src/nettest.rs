@@ -16,6 +16,7 @@
 #![no_std]
 extern crate alloc;
 
+use alloc::format;
 use alloc::string::String;
 
 use alloc::vec;
@@ -27,7 +28,7 @@ use core::{
 };
 use network::NetworkError;
 #[allow(unused_imports)]
-use network::{TcpListener, TcpStream, UdpSocket, resolve_hostname};
+use network::{TcpListener, TcpStream, UdpSocket, get_network_stats, resolve_hostname};
 use runtime::*;
 use smoltcp::wire::*;
 use syscall::return_vals::Errno;
@@ -115,7 +116,13 @@ fn main() {
         && let Some(port_str) = args.next()
     {
         // just take the first IP address
-        let ip = resolve_hostname(&host).into_iter().next().unwrap();
+        let ip = match resolve_hostname(&host) {
+            Ok(ips) => ips[0],
+            Err(error) => {
+                println!("[failed to resolve {}: {:?}]", host, error);
+                return;
+            }
+        };
         let port: u16 = port_str.parse().expect("[failed to parse port]");
         SocketAddr::new(ip, port)
     } else {
@@ -130,7 +137,13 @@ fn main() {
         && let Some(port_rem_str) = args.next()
     {
         // just take the first IP address
-        let ip_rem = resolve_hostname(&host_rem).into_iter().next().unwrap();
+        let ip_rem = match resolve_hostname(&host_rem) {
+            Ok(ips) => ips[0],
+            Err(error) => {
+                println!("[failed to resolve {}: {:?}]", host_rem, error);
+                return;
+            }
+        };
         let port_rem: u16 = port_rem_str.parse().expect("[failed to parse port.]");
         SocketAddr::new(ip_rem, port_rem)
     } else if server_mode {
@@ -237,12 +250,17 @@ pub fn run_tcp_server(socket: TcpStream) -> Result<()> {
     // =============================================================================
     let deadline = time::systime() + Duration::seconds(5); // set 5s deadline for packet to arrive
     println!("[waiting for first packet...]");
+    // read blocks, so let it give up in time for the deadline check
+    socket.set_timeout(Some(core::time::Duration::from_secs(5))).expect("[failed to set timeout!]");
     loop {
         if time::systime() > deadline {
             panic!("[timeout waiting for first packet]");
         }
 
-        let result_size = TcpStream::read(&socket, &mut buf).expect("[failed to receive datagram!]");
+        let result_size = match TcpStream::read(&socket, &mut buf) {
+            Err(NetworkError::TimedOut) => 0,
+            result => result.expect("[failed to receive datagram!]"),
+        };
         let recv_data = &buf[..result_size];
         if recv_data.len() >= 4 {
             println!("");
@@ -274,6 +292,7 @@ pub fn run_tcp_server(socket: TcpStream) -> Result<()> {
             break;
         }
     }
+    socket.set_timeout(None).expect("[failed to reset timeout!]");
 
     // =============================================================================
     // receive packets
@@ -282,10 +301,15 @@ pub fn run_tcp_server(socket: TcpStream) -> Result<()> {
     loop {
         let result_size = TcpStream::read(&socket, &mut buf).map_err(|errno| match errno {
             network::NetworkError::Unknown(Errno::ECONNRESET) => exit = true,
-            NetworkError::DeviceBusy => todo!(),
-            NetworkError::InvalidAddress => todo!(),
-            NetworkError::Unknown(_errno) => todo!(),
+            errno => {
+                println!("[failed to receive data: {:?}]", errno);
+                exit = true;
+            },
         });
+        // the client has closed the connection
+        if result_size == Ok(0) {
+            exit = true;
+        }
         if exit {
             break;
         }
@@ -510,6 +534,8 @@ pub fn run_udp_client(socket: UdpSocket, dest_addr: SocketAddr, time_interval: T
     let deadline = time::systime() + TimeDelta::seconds(5); // 5s timeout
     //let deadline = time::systime().as_seconds_f64() + 5.0; // 5s timeout
     println!("[Waiting for server reply...]");
+    // recv_from blocks, so let it give up in time for the deadline check
+    socket.set_timeout(Some(core::time::Duration::from_secs(5))).expect("[failed to set timeout!]");
     // ======================================
     // loop until a socket has been received
     // or the timeout is reached
@@ -520,7 +546,10 @@ pub fn run_udp_client(socket: UdpSocket, dest_addr: SocketAddr, time_interval: T
             return Err(smoltcp::wire::Error);
         }
         // get the length of the reply
-        let len = UdpSocket::recv_from(&socket, &mut buf).expect("[failed to receive over UDP!]").0;
+        let len = match UdpSocket::recv_from(&socket, &mut buf) {
+            Err(NetworkError::TimedOut) => 0,
+            result => result.expect("[failed to receive over UDP!]").0,
+        };
 
         // ======================================
         // if a reply has been received and is not
@@ -536,6 +565,7 @@ pub fn run_udp_client(socket: UdpSocket, dest_addr: SocketAddr, time_interval: T
             // ======================================
             if ack == str::from_utf8(init_msg).unwrap() {
                 println!("[Received expected Init response.]");
+                socket.set_timeout(None).expect("[failed to reset timeout!]");
                 return udp_send_traffic(socket, dest_addr, time_interval, packet_length);
             } else {
                 println!("[Unexpected data: {:?}.]", ack);
@@ -789,15 +819,42 @@ pub fn run_udp_server(socket: UdpSocket) -> Result<()> {
     }
 }
 
+// =============================================================================
+// function payload_intact:
+// =============================================================================
+// nettest.py fills the payload behind the 4 byte packet number with a pattern
+// derived from the packet number: byte i = (packet_number + i) & 0xFF
+// (see fill_pattern() in nettest.py)
+// returns false, if the payload has been corrupted on the way
+// =============================================================================
+fn payload_intact(payload: &[u8], packet_number: u32) -> bool {
+    payload
+        .iter()
+        .enumerate()
+        .all(|(i, byte)| *byte == (packet_number as usize + i) as u8)
+}
+
+// =============================================================================
+// function receive_overflows:
+// =============================================================================
+// sum of the receive buffer overflows of all network cards,
+// used to show that the flood test (nettest.py --flood) really overflowed the ring
+// =============================================================================
+fn receive_overflows() -> u64 {
+    get_network_stats().iter().map(|stats| stats.rx_overflows).sum()
+}
+
 // =============================================================================
 // function udp_receive_traffic:
 // =============================================================================
 // function processes in a loop incoming packets
-// and checks if a packet has been duplicated or
-// is out of order
+// and checks if a packet has been duplicated, is out of order
+// or has a corrupted payload
 // every second the current bytes received are printed to the terminal
 // the function ends if the server sends an exit request
 // and prints out statistics about the received traffic
+// the client gets them as "result <received> <corrupted> <overflows>\n",
+// so that nettest.py --check can decide, whether the flood test has passed
 // =============================================================================
 pub fn udp_receive_traffic(sock: UdpSocket) -> Result<()> {
     // =============================================================================
@@ -806,6 +863,7 @@ pub fn udp_receive_traffic(sock: UdpSocket) -> Result<()> {
     let mut packets_received: u32 = 0;
     let mut packets_out_of_order: u32 = 0;
     let mut duplicated_packets: u32 = 0;
+    let mut corrupted_packets: u32 = 0;
     let mut current_packet_number: u32 = 0;
     let mut previous_packet_number: u32 = 0;
     let mut interval_counter: usize = 0;
@@ -818,8 +876,10 @@ pub fn udp_receive_traffic(sock: UdpSocket) -> Result<()> {
     let mut packet_payload_length = 0;
     // define exit_msg
     let exit_msg = b"exit\n";
+    let init_msg = b"Init\n";
     // define a buffer in which the received packetgets saved to
     let mut buf = vec![0; 2048];
+    let overflows_at_start = receive_overflows();
 
     println!("[nettest: server listening! Send 'exit' to leave.]");
 
@@ -835,12 +895,17 @@ pub fn udp_receive_traffic(sock: UdpSocket) -> Result<()> {
     // =============================================================================
     let deadline = time::systime() + Duration::seconds(5); // set 5s deadline for packet to arrive
     println!("[waiting for first packet...]");
+    // recv_from blocks, so let it give up in time for the deadline check
+    sock.set_timeout(Some(core::time::Duration::from_secs(5))).expect("[failed to set timeout!]");
     loop {
         if time::systime() > deadline {
             panic!("[timeout waiting for first packet]");
         }
 
-        let result = UdpSocket::recv_from(&sock, &mut buf).expect("failed to receive datagram");
+        let result = match UdpSocket::recv_from(&sock, &mut buf) {
+            Err(NetworkError::TimedOut) => (0, SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)),
+            result => result.expect("failed to receive datagram"),
+        };
         let recv_data = &buf[..result.0];
         if recv_data.len() >= 4 {
             println!("[UDP: received first packet from {:?}:{:?}]", result.1.ip(), result.1.port());
@@ -870,20 +935,28 @@ pub fn udp_receive_traffic(sock: UdpSocket) -> Result<()> {
             // the number of the nth packet, which has been sent by the client
             // =============================================================================
             previous_packet_number = u32::from_be_bytes([recv_data[0], recv_data[1], recv_data[2], recv_data[3]]);
+            if !payload_intact(&recv_data[4..], previous_packet_number) {
+                corrupted_packets += 1;
+            }
             break;
         }
     }
+    sock.set_timeout(None).expect("[failed to reset timeout!]");
 
     // =============================================================================
     // receive packets
     // until an exit msg is sent
     // =============================================================================
-    loop {
+    let client = loop {
         let result = UdpSocket::recv_from(&sock, &mut buf).expect("failed to parse datagram");
         let recv_data = &buf[..result.0];
         // exit condition
         if recv_data == exit_msg {
-            break;
+            break result.1;
+        }
+        // the client repeats Init until it gets the reply, late copies are no test packets
+        if recv_data == init_msg {
+            continue;
         }
         if recv_data.len() >= 4 {
             // count number of packets received
@@ -895,6 +968,13 @@ pub fn udp_receive_traffic(sock: UdpSocket) -> Result<()> {
             // cast to u8 because of overflow error thrown by compiler
             current_packet_number = u32::from_be_bytes([recv_data[0], recv_data[1], recv_data[2], recv_data[3]]);
 
+            // =============================================================================
+            // check the payload pattern behind the packet number
+            // =============================================================================
+            if !payload_intact(&recv_data[4..], current_packet_number) {
+                corrupted_packets += 1;
+            }
+
             // =============================================================================
             // if the first 4 bytes of the previous and current packet
             // are equal then the packet had been retransmitted
@@ -929,8 +1009,9 @@ pub fn udp_receive_traffic(sock: UdpSocket) -> Result<()> {
                 seconds_passed += TimeDelta::seconds(1);
             }
         }
-    }
+    };
     bytes_received += bytes_received_in_interval;
+    let overflows = receive_overflows() - overflows_at_start;
 
     println!(
         "[{} - {}] : [{} KB/s]",
@@ -953,6 +1034,14 @@ pub fn udp_receive_traffic(sock: UdpSocket) -> Result<()> {
     );
     println!("  [packets out of order]       ==> {} / {}", packets_out_of_order, packets_received);
     println!("  [duplicated packets]         ==> {}", duplicated_packets);
+    println!("  [corrupted packets]          ==> {}", corrupted_packets);
+    println!("  [receive buffer overflows]   ==> {}", overflows);
     println!("[======================================================]");
+
+    // =============================================================================
+    // report the result to the client
+    // =============================================================================
+    let report = format!("result {} {} {}\n", packets_received, corrupted_packets, overflows);
+    UdpSocket::send_to(&sock, report.as_bytes(), client).expect("[failed to send result!]");
     return Ok(());
 }
@@ -0,0 +1,139 @@
+//! pcap – capture the frames of the network cards into a libpcap file
+//!
+//! The file can be copied out of the guest and opened with Wireshark or `tcpdump -r`.
+#![no_std]
+extern crate alloc;
+
+use alloc::{string::String, vec};
+use concurrent::thread::sleep;
+use naming::shared_types::OpenOptions;
+use network::{CAPTURE_READ_SIZE, CaptureFilter, NetworkError, pcap_file_header, read_capture, start_capture, stop_capture};
+#[allow(unused_imports)]
+use runtime::*;
+use terminal::{print, println};
+
+/// How long to wait between two reads of the capture buffer (in ms)
+const READ_INTERVAL: usize = 100;
+
+const USAGE: &str = "Usage:
+    pcap [-i interface] [-e ethertype] [-p protocol] [-P port] [-d seconds] [--promisc] file
+
+Options:
+    -i interface: only capture cards, whose driver name contains this (e.g. NE2000)
+    -e ethertype: only capture frames with this EtherType (e.g. 0x0800 for IPv4)
+    -p protocol: only capture IP packets with this protocol number (e.g. 17 for UDP)
+    -P port: only capture TCP and UDP packets from or to this port
+    -d seconds: capture for this long (default: 10)
+    --promisc: also capture frames for other hosts
+
+Examples:
+    pcap -e 0x0806 /arp.pcap
+        capture ARP for 10 seconds
+    pcap -i NE2000 -p 17 -P 1798 -d 30 /nettest.pcap
+        capture the UDP traffic of nettest on the NE2000 for 30 seconds";
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = env::args().peekable();
+    // the first argument is the program name, ignore it
+    args.next();
+
+    let mut interface = None;
+    let mut filter = CaptureFilter::default();
+    let mut duration = 10;
+
+    // check the next arguments for flags
+    loop {
+        match args.peek().map(String::as_str) {
+            Some("-h") | Some("--help") => {
+                println!("{}", USAGE);
+                return;
+            }
+            Some("-i") => {
+                args.next();
+                interface = args.next();
+            }
+            Some("-e") => {
+                args.next();
+                filter.ether_type = Some(parse_number(&args.next().unwrap_or_default()) as u16);
+            }
+            Some("-p") => {
+                args.next();
+                filter.ip_protocol = Some(parse_number(&args.next().unwrap_or_default()) as u8);
+            }
+            Some("-P") => {
+                args.next();
+                filter.port = Some(parse_number(&args.next().unwrap_or_default()) as u16);
+            }
+            Some("-d") => {
+                args.next();
+                duration = parse_number(&args.next().unwrap_or_default());
+            }
+            Some("--promisc") => {
+                args.next();
+                filter.promiscuous = true;
+            }
+            // now, we're finally past the options
+            Some(_) => break,
+            None => {
+                println!("{}", USAGE);
+                return;
+            }
+        }
+    }
+    filter.interface = interface.as_deref();
+
+    let Some(path) = args.next() else {
+        println!("{}", USAGE);
+        return;
+    };
+    match start_capture(&filter) {
+        Ok(true) => {}
+        Ok(false) => println!("warning: promiscuous mode is not supported by every card"),
+        Err(NetworkError::PermissionDenied) => {
+            println!("pcap may not capture, it has to be listed with raw= in network.conf");
+            return;
+        }
+        Err(error) => panic!("failed to start capture: {:?}", error),
+    }
+    let file = naming::open(&path, OpenOptions::READWRITE | OpenOptions::CREATE).expect("failed to open file");
+    naming::write(file, &pcap_file_header()).expect("failed to write file");
+    println!("capturing for {} seconds into {}", duration, path);
+
+    let mut buf = vec![0u8; 64 * CAPTURE_READ_SIZE];
+    let mut written = 0;
+    let deadline = time::systime().num_milliseconds() + duration as i64 * 1000;
+    let mut capturing = true;
+    loop {
+        if capturing && time::systime().num_milliseconds() > deadline {
+            let dropped = stop_capture().expect("failed to stop capture");
+            if dropped > 0 {
+                println!("{} frames have been dropped, the capture buffer was full", dropped);
+            }
+            capturing = false;
+        }
+
+        let len = read_capture(&mut buf).expect("failed to read capture");
+        if len > 0 {
+            naming::write(file, &buf[..len]).expect("failed to write file");
+            written += len;
+        } else if capturing {
+            sleep(READ_INTERVAL);
+        } else {
+            // the capture has been stopped and all frames have been read
+            break;
+        }
+    }
+
+    naming::close(file).expect("failed to close file");
+    println!("wrote {} bytes of frames to {}", written, path);
+}
+
+/// Parse a decimal or (with 0x) hexadecimal number.
+fn parse_number(text: &str) -> usize {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .unwrap_or_else(|_| panic!("invalid number: {text:?}"))
+}
@@ -1,15 +1,16 @@
-//! ping – send and receive ICMP echo requests
+//! ping – send and receive ICMP echo requests (ICMPv6 for IPv6 hosts)
 //! 
 //! This is based on the [smoltcp echo example](https://github.com/smoltcp-rs/smoltcp/blob/main/examples/ping.rs).
 #![no_std]
 extern crate alloc;
 
-use alloc::{string::String, vec};
+use alloc::{string::String, vec, vec::Vec};
+use core::net::{IpAddr, Ipv6Addr};
 use concurrent::thread::sleep;
 use network::{resolve_hostname, IcmpSocket};
 #[allow(unused_imports)]
 use runtime::*;
-use smoltcp::{phy::ChecksumCapabilities, wire::{Icmpv4Packet, Icmpv4Repr}};
+use smoltcp::{phy::ChecksumCapabilities, wire::{Icmpv4Packet, Icmpv4Repr, Icmpv6Packet, Icmpv6Repr}};
 use terminal::{print, println};
 
 #[unsafe(no_mangle)]
@@ -29,7 +30,9 @@ fn main() {
 
 Examples:
     ping -c 2 1.2.3.4
-        ping 1.2.3.4 two times");
+        ping 1.2.3.4 two times
+    ping ::1
+        ping the loopback interface via IPv6");
                 return;
             }
             Some("-c") => {
@@ -51,32 +54,32 @@ Examples:
         return;
     };
     // just take the first IP address
-    let ip = resolve_hostname(&host).into_iter().next().unwrap();
+    let ip = match resolve_hostname(&host) {
+        Ok(ips) => ips[0],
+        Err(error) => {
+            println!("failed to resolve {}: {:?}", host, error);
+            return;
+        }
+    };
 
     let ident = 0x1234;
     let socket = IcmpSocket::bind(ident).expect("failed to open socket");
     for seq_no in 0..count {
         let send_time: [u8; 8] = time::date().timestamp_millis().to_ne_bytes();
-        // TODO: IPv6
-        let request = Icmpv4Repr::EchoRequest { ident, seq_no, data: &send_time };
-        let mut packet_buffer = vec![0u8; request.buffer_len()];
-        let mut packet = Icmpv4Packet::new_checked(&mut packet_buffer).unwrap();
-        request.emit(&mut packet, &ChecksumCapabilities::ignored());
+        let packet_buffer = echo_request(ip, ident, seq_no, &send_time);
         socket.send_to(&packet_buffer, ip).expect("failed to send ping");
 
         let mut recv_buffer = [0u8; 4096];
-        let addr = loop {
+        let (len, addr) = loop {
             let (len, addr) = socket
                 .recv(&mut recv_buffer)
                 .expect("failed to receive ping reply");
             if len != 0 {
-                break addr;
+                break (len, addr);
             }
             sleep(50);
         };
-        let response_packet = Icmpv4Packet::new_checked(&recv_buffer).expect("received packet is invalid");
-        let response = Icmpv4Repr::parse(&response_packet, &ChecksumCapabilities::ignored()).expect("received packet is invalid");
-        if let Icmpv4Repr::EchoReply { seq_no, data, .. } = response {
+        if let Some((seq_no, data)) = echo_reply(addr, &recv_buffer[..len]) {
             let timestamp_ms = i64::from_ne_bytes(data[0..8].try_into().unwrap());
             let timedelta = time::date().timestamp_millis() - timestamp_ms;
             println!("{} bytes from {}: seq={}, time={}ms", data.len(), addr, seq_no, timedelta);
@@ -85,3 +88,45 @@ Examples:
         }
     }
 }
+
+/// Build an echo request for `ip` (ICMPv4 or ICMPv6).
+///
+/// The checksum is left to the kernel, ICMPv6 covers the source address, which we don't know.
+fn echo_request(ip: IpAddr, ident: u16, seq_no: u16, data: &[u8]) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(_) => {
+            let request = Icmpv4Repr::EchoRequest { ident, seq_no, data };
+            let mut packet_buffer = vec![0u8; request.buffer_len()];
+            let mut packet = Icmpv4Packet::new_checked(&mut packet_buffer).unwrap();
+            request.emit(&mut packet, &ChecksumCapabilities::ignored());
+            packet_buffer
+        }
+        IpAddr::V6(ip) => {
+            let request = Icmpv6Repr::EchoRequest { ident, seq_no, data };
+            let mut packet_buffer = vec![0u8; request.buffer_len()];
+            let mut packet = Icmpv6Packet::new_checked(&mut packet_buffer).unwrap();
+            request.emit(&Ipv6Addr::UNSPECIFIED, &ip, &mut packet, &ChecksumCapabilities::ignored());
+            packet_buffer
+        }
+    }
+}
+
+/// Get the sequence number and the data of an echo reply from `addr`.
+fn echo_reply(addr: IpAddr, packet_buffer: &[u8]) -> Option<(u16, &[u8])> {
+    match addr {
+        IpAddr::V4(_) => {
+            let packet = Icmpv4Packet::new_checked(packet_buffer).ok()?;
+            match Icmpv4Repr::parse(&packet, &ChecksumCapabilities::ignored()).ok()? {
+                Icmpv4Repr::EchoReply { seq_no, data, .. } => Some((seq_no, data)),
+                _ => None,
+            }
+        }
+        IpAddr::V6(addr) => {
+            let packet = Icmpv6Packet::new_checked(packet_buffer).ok()?;
+            match Icmpv6Repr::parse(&addr, &Ipv6Addr::UNSPECIFIED, &packet, &ChecksumCapabilities::ignored()).ok()? {
+                Icmpv6Repr::EchoReply { seq_no, data, .. } => Some((seq_no, data)),
+                _ => None,
+            }
+        }
+    }
+}
@@ -261,12 +261,17 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
     // Initialize storage devices
     storage::init();
 
+    // Load initial ramdisk (the network stack reads its configuration file from it)
+    init_initrd(initrd_tag);
+
     //=================================================================
     // Initialize network stack
     // - starts the init() function in network/mod.rs
     // - which searches on the pci bus for available devices
+    // - static addresses can be set on the kernel command line (see network/config.rs)
     //=================================================================
-    network::init();
+    let command_line = multiboot.command_line_tag().and_then(|tag| tag.cmdline().ok()).unwrap_or("");
+    network::init(command_line);
 
     // set flag for enabling/disabling network cards at boot
     /*  let enable_ne2k = true;
@@ -335,7 +340,8 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
             //loop {
             // receive = false => send
             // receive = true => receive
-            benchmark::benchmark(true);
+            // the second argument selects the nic by its driver name ("NE2000", "RTL8139", "virtio" or "e1000")
+            benchmark::benchmark(true, "NE2000");
             //}
         }
         scheduler().ready(Thread::new_kernel_thread(benchmark, "benchmark"));
@@ -372,9 +378,6 @@ pub extern "C" fn start(multiboot2_magic: u32, multiboot2_addr: *const BootInfor
         }
     }
 
-    // Load initial ramdisk
-    init_initrd(initrd_tag);
-
     // Init naming service
     naming::api::init();
 
@@ -0,0 +1,602 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::ops::BitOr;
+use core::ptr;
+use core::sync::atomic::{Ordering, fence};
+use log::info;
+use nolock::queues::mpmc;
+use pci_types::{CommandRegister, EndpointHeader};
+use smoltcp::phy::{DeviceCapabilities, Medium};
+use smoltcp::wire::EthernetAddress;
+use spin::{Mutex, RwLock};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::structures::paging::frame::PhysFrameRange;
+
+use crate::device::nic::{NetworkDevice, NetworkStats, PacketAllocator, StatsCounters};
+use crate::interrupt::interrupt_dispatcher::InterruptVector;
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::memory::vma::VmaType;
+use crate::memory::{PAGE_SIZE, vmm};
+use crate::{apic, interrupt_dispatcher, network, pci_bus, process_manager, scheduler};
+
+// Driver for Intel 8254x network cards (tested with the 82540EM emulated by QEMU),
+// based on the "PCI/PCI-X Family of Gigabit Ethernet Controllers Software Developer's Manual"
+// (https://www.intel.com/content/dam/doc/manual/pci-pci-x-family-gbe-controllers-software-dev-manual.pdf).
+// QEMU provides a compatible card with `-nic user,model=e1000`.
+
+pub const VENDOR_ID: u16 = 0x8086;
+/// 82540EM (desktop)
+pub const DEVICE_ID: u16 = 0x100e;
+
+/// Number of descriptors per ring (the length of a ring in bytes must be a multiple of 128)
+const RECEIVE_DESCRIPTORS: usize = 32;
+const TRANSMIT_DESCRIPTORS: usize = 32;
+const RECV_QUEUE_CAP: usize = 64;
+
+// Register offsets (section 13.4)
+const CTRL: usize = 0x0000;
+const STATUS: usize = 0x0008;
+const EERD: usize = 0x0014;
+const ICR: usize = 0x00c0;
+const IMS: usize = 0x00d0;
+const IMC: usize = 0x00d8;
+const RCTL: usize = 0x0100;
+const TCTL: usize = 0x0400;
+const TIPG: usize = 0x0410;
+const RDBAL: usize = 0x2800;
+const RDBAH: usize = 0x2804;
+const RDLEN: usize = 0x2808;
+const RDH: usize = 0x2810;
+const RDT: usize = 0x2818;
+const TDBAL: usize = 0x3800;
+const TDBAH: usize = 0x3804;
+const TDLEN: usize = 0x3808;
+const TDH: usize = 0x3810;
+const TDT: usize = 0x3818;
+const MTA: usize = 0x5200;
+const MTA_ENTRIES: usize = 128;
+const RAL: usize = 0x5400;
+const RAH: usize = 0x5404;
+
+/// Inter packet gap for IEEE 802.3 (IPGT = 10, IPGR1 = 8, IPGR2 = 6)
+const TIPG_DEFAULT: u32 = 10 | (8 << 10) | (6 << 20);
+
+bitflags! {
+    pub struct Control: u32 {
+        const FULL_DUPLEX = 1 << 0;
+        const LINK_RESET = 1 << 3;
+        const AUTO_SPEED_DETECTION = 1 << 5;
+        const SET_LINK_UP = 1 << 6;
+        const INVERT_LOSS_OF_SIGNAL = 1 << 7;
+        const RESET = 1 << 26;
+        const VLAN_MODE = 1 << 30;
+        const PHY_RESET = 1 << 31;
+    }
+}
+
+bitflags! {
+    pub struct Status: u32 {
+        const FULL_DUPLEX = 1 << 0;
+        const LINK_UP = 1 << 1;
+    }
+}
+
+bitflags! {
+    pub struct ReceiveControl: u32 {
+        const ENABLE = 1 << 1;
+        const STORE_BAD_PACKETS = 1 << 2;
+        const UNICAST_PROMISCUOUS = 1 << 3;
+        const MULTICAST_PROMISCUOUS = 1 << 4;
+        const LONG_PACKETS = 1 << 5;
+        const BROADCAST_ACCEPT = 1 << 15;
+        // BSIZE = 00 -> 2048 byte buffers
+        // MO = 00 -> the multicast table is indexed with bits 47:36 of the address
+        const STRIP_CRC = 1 << 26;
+    }
+}
+
+bitflags! {
+    pub struct TransmitControl: u32 {
+        const ENABLE = 1 << 1;
+        const PAD_SHORT_PACKETS = 1 << 3;
+        // collision threshold and distance, as recommended for full duplex
+        const COLLISION_THRESHOLD = 0x0f << 4;
+        const COLLISION_DISTANCE = 0x40 << 12;
+    }
+}
+
+bitflags! {
+    pub struct Interrupt: u32 {
+        const TRANSMIT_DESCRIPTOR_WRITTEN_BACK = 1 << 0;
+        const TRANSMIT_QUEUE_EMPTY = 1 << 1;
+        const LINK_STATUS_CHANGE = 1 << 2;
+        const RECEIVE_DESCRIPTOR_MINIMUM = 1 << 4;
+        const RECEIVER_OVERRUN = 1 << 6;
+        const RECEIVER_TIMER = 1 << 7;
+    }
+}
+
+bitflags! {
+    pub struct DescriptorStatus: u8 {
+        const DONE = 1 << 0;
+        const END_OF_PACKET = 1 << 1;
+    }
+}
+
+bitflags! {
+    pub struct TransmitCommand: u8 {
+        const END_OF_PACKET = 1 << 0;
+        const INSERT_FCS = 1 << 1;
+        const REPORT_STATUS = 1 << 3;
+    }
+}
+
+/// Receive address high register: address valid
+const RAH_ADDRESS_VALID: u32 = 1 << 31;
+const EERD_START: u32 = 1 << 0;
+const EERD_DONE: u32 = 1 << 4;
+
+#[repr(C)]
+struct ReceiveDescriptor {
+    address: u64,
+    length: u16,
+    checksum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+}
+
+/// Legacy transmit descriptor
+#[repr(C)]
+struct TransmitDescriptor {
+    address: u64,
+    length: u16,
+    checksum_offset: u8,
+    command: u8,
+    status: u8,
+    checksum_start: u8,
+    special: u16,
+}
+
+/// The memory mapped registers of the card (BAR0).
+struct Registers {
+    base_address: u64,
+}
+
+/// A ring of descriptors, which is shared with the card.
+/// Every descriptor owns one page frame as buffer.
+struct DescriptorRing<T> {
+    memory: PhysFrameRange,
+    descriptors: *mut T,
+    buffers: Vec<PhysFrameRange>,
+    /// Next descriptor to be checked (receive) or used (transmit) by the driver
+    next: usize,
+}
+
+// The raw pointers point to memory, which is exclusively owned by the ring
+unsafe impl<T> Send for DescriptorRing<T> {}
+
+pub struct E1000 {
+    registers: Registers,
+    interrupt: InterruptVector,
+    receive_ring: Mutex<DescriptorRing<ReceiveDescriptor>>,
+    transmit_ring: Mutex<DescriptorRing<TransmitDescriptor>>,
+    recv_buffers_empty: (
+        mpmc::bounded::scq::Receiver<Vec<u8, PacketAllocator>>,
+        mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>,
+    ),
+    receive_filter: Mutex<ReceiveFilter>,
+    stats: StatsCounters,
+}
+
+/// State of the receive filter, that is not kept in the multicast table.
+struct ReceiveFilter {
+    /// Accept all multicast frames (until the network stack sets the filter, see `set_multicast_filter()`)
+    all_multicast: bool,
+    promiscuous: bool,
+}
+
+pub struct E1000InterruptHandler {
+    device: Arc<E1000>,
+}
+
+impl Registers {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.base_address as usize + offset) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ((self.base_address as usize + offset) as *mut u32).write_volatile(value) }
+    }
+
+    /// Read a 16-bit word from the EEPROM (section 5.3.1).
+    fn read_eeprom(&self, address: u8) -> u16 {
+        self.write(EERD, EERD_START | ((address as u32) << 8));
+        loop {
+            let value = self.read(EERD);
+            if value & EERD_DONE != 0 {
+                return (value >> 16) as u16;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl ReceiveFilter {
+    fn receive_control(&self) -> ReceiveControl {
+        let mut control = ReceiveControl::ENABLE | ReceiveControl::BROADCAST_ACCEPT | ReceiveControl::STRIP_CRC;
+        if self.all_multicast || self.promiscuous {
+            control.insert(ReceiveControl::MULTICAST_PROMISCUOUS);
+        }
+        if self.promiscuous {
+            control.insert(ReceiveControl::UNICAST_PROMISCUOUS);
+        }
+
+        control
+    }
+}
+
+impl<T> DescriptorRing<T> {
+    fn new(count: usize) -> Self {
+        let bytes = count * size_of::<T>();
+        let memory = unsafe { vmm::alloc_frames(bytes.div_ceil(PAGE_SIZE)) };
+        let descriptors = memory.start.start_address().as_u64() as *mut T;
+        unsafe { ptr::write_bytes(descriptors as *mut u8, 0, bytes) };
+
+        let buffers = (0..count).map(|_| unsafe { vmm::alloc_frames(1) }).collect();
+
+        Self { memory, descriptors, buffers, next: 0 }
+    }
+
+    fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    fn physical_address(&self) -> u64 {
+        self.memory.start.start_address().as_u64()
+    }
+
+    fn buffer_address(&self, index: usize) -> u64 {
+        self.buffers[index].start.start_address().as_u64()
+    }
+
+    fn buffer(&mut self, index: usize) -> &mut [u8] {
+        let address = self.buffer_address(index);
+        unsafe { core::slice::from_raw_parts_mut(address as *mut u8, PAGE_SIZE) }
+    }
+
+    fn descriptor(&self, index: usize) -> *mut T {
+        unsafe { self.descriptors.add(index) }
+    }
+}
+
+impl NetworkDevice for E1000 {
+    fn mac_address(&self) -> EthernetAddress {
+        self.read_mac_address()
+    }
+
+    fn link_up(&self) -> bool {
+        Status::from_bits_retain(self.registers.read(STATUS)).contains(Status::LINK_UP)
+    }
+
+    fn stats(&self) -> NetworkStats {
+        self.stats.snapshot()
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1514;
+        caps.max_burst_size = None;
+        caps.medium = Medium::Ethernet;
+
+        caps
+    }
+
+    /// Fill the multicast table array with the hashes of `addresses` (section 13.5.1).
+    /// The hash is made of the upper 12 bits of the address (RCTL.MO = 00),
+    /// the upper 7 bits of it select the register and the lower 5 bits the bit in it.
+    fn set_multicast_filter(&self, addresses: &[EthernetAddress]) {
+        let mut table = [0u32; MTA_ENTRIES];
+        for address in addresses {
+            let bytes = address.as_bytes();
+            let hash = (bytes[4] >> 4) as usize | ((bytes[5] as usize) << 4);
+            table[hash >> 5] |= 1 << (hash & 0x1f);
+        }
+
+        let mut filter = self.receive_filter.lock();
+        for (i, value) in table.iter().enumerate() {
+            self.registers.write(MTA + i * 4, *value);
+        }
+        filter.all_multicast = false;
+        self.registers.write(RCTL, filter.receive_control().bits());
+    }
+
+    fn set_promiscuous(&self, enabled: bool) -> bool {
+        let mut filter = self.receive_filter.lock();
+        filter.promiscuous = enabled;
+        self.registers.write(RCTL, filter.receive_control().bits());
+        true
+    }
+
+    fn plugin(self: Arc<Self>) {
+        E1000::plugin(self);
+    }
+
+    fn receive(&self) -> Option<(Vec<u8, PacketAllocator>, usize)> {
+        let mut ring = self.receive_ring.lock();
+        loop {
+            let index = ring.next;
+            let descriptor = ring.descriptor(index);
+            let status = DescriptorStatus::from_bits_retain(unsafe { (&raw const (*descriptor).status).read_volatile() });
+            if !status.contains(DescriptorStatus::DONE) {
+                return None;
+            }
+            fence(Ordering::SeqCst);
+
+            let (length, errors) = unsafe { ((&raw const (*descriptor).length).read_volatile() as usize, (&raw const (*descriptor).errors).read_volatile()) };
+
+            // Frames larger than one buffer are not expected (long packets are disabled), so they are treated like errors
+            let target = if errors != 0 || !status.contains(DescriptorStatus::END_OF_PACKET) {
+                self.stats.receive_error();
+                None
+            } else {
+                // Copy frame to a new buffer and give the descriptor back to the card
+                match self.recv_buffers_empty.0.try_dequeue() {
+                    Ok(mut target) => {
+                        target[0..length].copy_from_slice(&ring.buffer(index)[0..length]);
+                        self.stats.received(length);
+                        Some((target, length))
+                    }
+                    Err(_) => {
+                        self.stats.dropped();
+                        None
+                    }
+                }
+            };
+
+            let address = ring.buffer_address(index);
+            unsafe {
+                descriptor.write_volatile(ReceiveDescriptor {
+                    address,
+                    length: 0,
+                    checksum: 0,
+                    status: 0,
+                    errors: 0,
+                    special: 0,
+                });
+            }
+            ring.next = (index + 1) % ring.len();
+
+            // The tail points one behind the last descriptor, that is owned by the card
+            fence(Ordering::SeqCst);
+            self.registers.write(RDT, index as u32);
+
+            if target.is_some() {
+                return target;
+            }
+        }
+    }
+
+    fn recycle(&self, buffer: Vec<u8, PacketAllocator>) {
+        self.recv_buffers_empty
+            .1
+            .try_enqueue(buffer)
+            .expect("Failed to enqueue used receive buffer!");
+    }
+
+    fn transmit(&self, len: usize, fill: &mut dyn FnMut(&mut [u8])) {
+        if len > PAGE_SIZE {
+            panic!("Packet length may not exceed page size!");
+        }
+
+        // Wait for the card to finish the previous transmission with this descriptor
+        let mut ring = loop {
+            let ring = self.transmit_ring.lock();
+            let status = unsafe { (&raw const (*ring.descriptor(ring.next)).status).read_volatile() };
+            if DescriptorStatus::from_bits_retain(status).contains(DescriptorStatus::DONE) {
+                break ring;
+            }
+            drop(ring);
+            scheduler().switch_thread_no_interrupt();
+        };
+
+        let index = ring.next;
+        fill(&mut ring.buffer(index)[0..len]);
+
+        let address = ring.buffer_address(index);
+        unsafe {
+            ring.descriptor(index).write_volatile(TransmitDescriptor {
+                address,
+                length: len as u16,
+                checksum_offset: 0,
+                command: (TransmitCommand::END_OF_PACKET | TransmitCommand::INSERT_FCS | TransmitCommand::REPORT_STATUS).bits(),
+                status: 0,
+                checksum_start: 0,
+                special: 0,
+            });
+        }
+        ring.next = (index + 1) % ring.len();
+
+        // Moving the tail hands the descriptor to the card
+        fence(Ordering::SeqCst);
+        self.registers.write(TDT, ring.next as u32);
+        self.stats.transmitted(len);
+    }
+}
+
+impl E1000InterruptHandler {
+    pub fn new(device: Arc<E1000>) -> Self {
+        Self { device }
+    }
+}
+
+impl InterruptHandler for E1000InterruptHandler {
+    fn trigger(&self) {
+        // Reading the interrupt cause register acknowledges all pending interrupts.
+        // Received frames and finished transmissions are collected by the network thread (see `receive()` and `transmit()`).
+        let cause = Interrupt::from_bits_retain(self.device.registers.read(ICR));
+        if cause.contains(Interrupt::RECEIVER_OVERRUN) {
+            self.device.stats.dropped();
+        }
+
+        network::notify_worker();
+    }
+}
+
+impl E1000 {
+    pub fn new(pci_device: &RwLock<EndpointHeader>) -> Self {
+        info!("Configuring PCI registers");
+        let pci_config_space = pci_bus().config_space();
+        let mut pci_device = pci_device.write();
+
+        // Make sure bus master and memory space are enabled
+        pci_device.update_command(pci_config_space, |command| {
+            command.bitor(CommandRegister::BUS_MASTER_ENABLE | CommandRegister::MEMORY_ENABLE)
+        });
+
+        // Read register base address from BAR0 and map the registers into the kernel address space
+        let bar0 = pci_device.bar(0, pci_config_space).expect("Failed to read base address!");
+        let (address, size) = bar0.unwrap_mem();
+        info!("e1000 register base address: [0x{address:x}], size: [0x{size:x}]");
+
+        let kernel_process = process_manager().read().kernel_process().expect("Kernel process not initialized!");
+        let page = kernel_process.virtual_address_space.kernel_map_devm_identity(
+            address as u64,
+            (address + size) as u64,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE,
+            VmaType::DeviceMemory,
+            "e1000",
+        );
+
+        let interrupt = InterruptVector::try_from(pci_device.interrupt(pci_config_space).1 + 32).unwrap();
+        let registers = Registers { base_address: page.start_address().as_u64() };
+
+        info!("Resetting device");
+        registers.write(IMC, u32::MAX);
+        registers.write(CTRL, registers.read(CTRL) | Control::RESET.bits());
+        while Control::from_bits_retain(registers.read(CTRL)).contains(Control::RESET) {
+            core::hint::spin_loop();
+        }
+        // The reset enables interrupts again
+        registers.write(IMC, u32::MAX);
+        registers.read(ICR);
+
+        // Let the card negotiate speed and duplex with the link partner
+        let mut control = Control::from_bits_retain(registers.read(CTRL));
+        control.remove(Control::LINK_RESET | Control::PHY_RESET | Control::INVERT_LOSS_OF_SIGNAL | Control::VLAN_MODE);
+        control.insert(Control::SET_LINK_UP | Control::AUTO_SPEED_DETECTION);
+        registers.write(CTRL, control.bits());
+
+        Self::setup_receive_address(&registers);
+
+        // Accept all multicast frames (with RCTL.MPE), until the network stack sets the filter
+        for i in 0..MTA_ENTRIES {
+            registers.write(MTA + i * 4, 0);
+        }
+
+        info!("Setting up descriptor rings");
+        let receive_ring = DescriptorRing::<ReceiveDescriptor>::new(RECEIVE_DESCRIPTORS);
+        for index in 0..receive_ring.len() {
+            unsafe {
+                receive_ring.descriptor(index).write_volatile(ReceiveDescriptor {
+                    address: receive_ring.buffer_address(index),
+                    length: 0,
+                    checksum: 0,
+                    status: 0,
+                    errors: 0,
+                    special: 0,
+                });
+            }
+        }
+        registers.write(RDBAL, receive_ring.physical_address() as u32);
+        registers.write(RDBAH, (receive_ring.physical_address() >> 32) as u32);
+        registers.write(RDLEN, (receive_ring.len() * size_of::<ReceiveDescriptor>()) as u32);
+        registers.write(RDH, 0);
+        // All descriptors except one belong to the card (head == tail means the ring is empty)
+        registers.write(RDT, (receive_ring.len() - 1) as u32);
+
+        let transmit_ring = DescriptorRing::<TransmitDescriptor>::new(TRANSMIT_DESCRIPTORS);
+        for index in 0..transmit_ring.len() {
+            // Mark all descriptors as done, so that `transmit()` can use them right away
+            unsafe { (&raw mut (*transmit_ring.descriptor(index)).status).write_volatile(DescriptorStatus::DONE.bits()) };
+        }
+        registers.write(TDBAL, transmit_ring.physical_address() as u32);
+        registers.write(TDBAH, (transmit_ring.physical_address() >> 32) as u32);
+        registers.write(TDLEN, (transmit_ring.len() * size_of::<TransmitDescriptor>()) as u32);
+        registers.write(TDH, 0);
+        registers.write(TDT, 0);
+
+        let recv_buffers = mpmc::bounded::scq::queue(RECV_QUEUE_CAP);
+        for _ in 0..RECV_QUEUE_CAP {
+            let phys_frame = unsafe { vmm::alloc_frames(1) };
+            let buffer = unsafe {
+                Vec::from_raw_parts_in(
+                    phys_frame.start.start_address().as_u64() as *mut u8,
+                    PAGE_SIZE,
+                    PAGE_SIZE,
+                    PacketAllocator::default(),
+                )
+            };
+            recv_buffers.1.try_enqueue(buffer).expect("Failed to enqueue receive buffer!");
+        }
+
+        info!("Enabling receiver and transmitter");
+        let receive_filter = ReceiveFilter { all_multicast: true, promiscuous: false };
+        registers.write(RCTL, receive_filter.receive_control().bits());
+        registers.write(
+            TCTL,
+            (TransmitControl::ENABLE | TransmitControl::PAD_SHORT_PACKETS | TransmitControl::COLLISION_THRESHOLD | TransmitControl::COLLISION_DISTANCE).bits(),
+        );
+        registers.write(TIPG, TIPG_DEFAULT);
+
+        Self {
+            registers,
+            interrupt,
+            receive_ring: Mutex::new(receive_ring),
+            transmit_ring: Mutex::new(transmit_ring),
+            recv_buffers_empty: recv_buffers,
+            receive_filter: Mutex::new(receive_filter),
+            stats: StatsCounters::default(),
+        }
+    }
+
+    pub fn plugin(device: Arc<E1000>) {
+        let interrupt = device.interrupt;
+        interrupt_dispatcher().assign(device.interrupt, Box::new(E1000InterruptHandler::new(Arc::clone(&device))));
+        apic().allow(interrupt);
+
+        // Only unmask the interrupts of the card after the handler has been registered
+        device.registers.read(ICR);
+        device.registers.write(
+            IMS,
+            (Interrupt::RECEIVER_TIMER | Interrupt::RECEIVE_DESCRIPTOR_MINIMUM | Interrupt::RECEIVER_OVERRUN | Interrupt::LINK_STATUS_CHANGE | Interrupt::TRANSMIT_DESCRIPTOR_WRITTEN_BACK).bits(),
+        );
+    }
+
+    pub fn read_mac_address(&self) -> EthernetAddress {
+        let mut mac = [0u8; 6];
+        mac[0..4].copy_from_slice(&self.registers.read(RAL).to_le_bytes());
+        mac[4..6].copy_from_slice(&(self.registers.read(RAH) as u16).to_le_bytes());
+
+        EthernetAddress::from_bytes(&mac)
+    }
+
+    /// Make sure, that the card accepts frames for its own address.
+    /// Usually, the card loads the address from the EEPROM after a reset, otherwise we read it ourselves (words 0 to 2).
+    fn setup_receive_address(registers: &Registers) {
+        if registers.read(RAH) & RAH_ADDRESS_VALID != 0 {
+            return;
+        }
+
+        let mut mac = [0u8; 6];
+        for word in 0..3u8 {
+            let value = registers.read_eeprom(word);
+            mac[word as usize * 2..word as usize * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        }
+
+        registers.write(RAL, u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]));
+        registers.write(RAH, u16::from_le_bytes([mac[4], mac[5]]) as u32 | RAH_ADDRESS_VALID);
+    }
+}
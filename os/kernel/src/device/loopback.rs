@@ -0,0 +1,115 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use smoltcp::phy::{self, ChecksumCapabilities, Device, DeviceCapabilities, Medium, RxToken, TxToken};
+use smoltcp::time::Instant;
+use smoltcp::wire::EthernetAddress;
+use spin::Mutex;
+
+use crate::device::nic::{NetworkDevice, NetworkStats, PacketAllocator, StatsCounters};
+use crate::memory::{PAGE_SIZE, vmm};
+use crate::network;
+
+// Loopback device for 127.0.0.1 and ::1.
+// Every packet, that is sent, is queued by smoltcp's `Loopback` device and received again by the next poll.
+// It doesn't depend on any hardware, so the network stack always has at least this interface
+// (e.g. for tests, that run without a network card).
+// There is no link layer, the packets are plain IP packets (`Medium::Ip`), so no neighbor discovery is needed.
+
+/// Name of the loopback interface (used like the driver name of a network card, e.g. by `ip -s`)
+pub const NAME: &str = "Loopback";
+
+pub struct Loopback {
+    device: Mutex<phy::Loopback>,
+    /// Page buffers, that have been handed to smoltcp and recycled, ready for the next received packet
+    free_buffers: Mutex<Vec<Vec<u8, PacketAllocator>>>,
+    stats: StatsCounters,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Self {
+            device: Mutex::new(phy::Loopback::new(Medium::Ip)),
+            free_buffers: Mutex::new(Vec::new()),
+            stats: StatsCounters::default(),
+        }
+    }
+
+    fn alloc_buffer(&self) -> Vec<u8, PacketAllocator> {
+        if let Some(buffer) = self.free_buffers.lock().pop() {
+            return buffer;
+        }
+
+        // received packets are handed to smoltcp in page frames, just like the buffers of a network card
+        let frame = unsafe { vmm::alloc_frames(1) };
+        unsafe {
+            Vec::from_raw_parts_in(
+                frame.start.start_address().as_u64() as *mut u8,
+                PAGE_SIZE,
+                PAGE_SIZE,
+                PacketAllocator::default(),
+            )
+        }
+    }
+}
+
+impl NetworkDevice for Loopback {
+    /// Unused, the interface has no hardware address (see `capabilities()`)
+    fn mac_address(&self) -> EthernetAddress {
+        EthernetAddress([0; 6])
+    }
+
+    fn stats(&self) -> NetworkStats {
+        self.stats.snapshot()
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        // every packet has to fit into a page buffer
+        caps.max_transmission_unit = PAGE_SIZE;
+        caps.max_burst_size = None;
+        caps.medium = Medium::Ip;
+        // packets never leave memory, so they can't get corrupted
+        caps.checksum = ChecksumCapabilities::ignored();
+
+        caps
+    }
+
+    /// The loopback interface receives everything, that is sent through it, anyway.
+    fn set_promiscuous(&self, _enabled: bool) -> bool {
+        true
+    }
+
+    /// There is no interrupt, `transmit()` wakes up the network thread itself.
+    fn plugin(self: Arc<Self>) {}
+
+    fn receive(&self) -> Option<(Vec<u8, PacketAllocator>, usize)> {
+        let mut device = self.device.lock();
+        let (token, _) = device.receive(Instant::ZERO)?;
+        let mut buffer = self.alloc_buffer();
+        let len = token.consume(|packet| {
+            buffer[..packet.len()].copy_from_slice(packet);
+            self.stats.received(packet.len());
+            packet.len()
+        });
+
+        Some((buffer, len))
+    }
+
+    fn recycle(&self, buffer: Vec<u8, PacketAllocator>) {
+        self.free_buffers.lock().push(buffer);
+    }
+
+    fn transmit(&self, len: usize, fill: &mut dyn FnMut(&mut [u8])) {
+        if len > PAGE_SIZE {
+            panic!("Packet length may not exceed page size!");
+        }
+
+        let mut device = self.device.lock();
+        let token = device.transmit(Instant::ZERO).expect("Loopback device can always transmit");
+        token.consume(len, |packet| fill(packet));
+        self.stats.transmitted(len);
+
+        // the packet is received by the next poll, which should happen right away
+        network::notify_worker();
+    }
+}
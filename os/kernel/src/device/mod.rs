@@ -6,11 +6,15 @@ pub mod speaker;
 #[macro_use]
 pub mod terminal;
 pub mod cpu;
+pub mod e1000;
 pub mod ide;
 pub mod lfb_terminal;
+pub mod loopback;
+pub mod nic;
 pub mod pci;
 pub mod rtl8139;
 pub mod serial;
+pub mod virtio_net;
 
 // make module public
 pub mod ne2k;
@@ -24,12 +24,20 @@ const ENABLE_POLL: bool = true;
 // function benchmark
 // =============================================================================
 // start client or server from this function
+// nic selects the card by its driver name (e.g. "NE2000", "RTL8139" or "e1000"),
+// the benchmark uses the address, which the card got via DHCP
 // =============================================================================
 
-pub fn benchmark(receive: bool) {
+pub fn benchmark(receive: bool, nic: &str) {
     let dest_ip = smoltcp::wire::IpAddress::Ipv4(Ipv4Address::new(10, 0, 2, 2));
     let dest_port: u16 = 2000;
-    let source_ip = smoltcp::wire::IpAddress::Ipv4(Ipv4Address::new(10, 0, 2, 15));
+    let source_ip = match wait_for_address(nic) {
+        Some(address) => smoltcp::wire::IpAddress::Ipv4(address),
+        None => {
+            error!("no address for network card {}, falling back to 10.0.2.15", nic);
+            smoltcp::wire::IpAddress::Ipv4(Ipv4Address::new(10, 0, 2, 15))
+        }
+    };
     let source_port = 1798;
     let timing_interval = 20;
     let packet_length: u16 = 64;
@@ -42,15 +50,40 @@ pub fn benchmark(receive: bool) {
     if receive {
         return run_udp_server(sock, dest_addr).expect("failed to start server!");
     }
-    return run_udp_client(sock, dest_addr, timing_interval, packet_length).expect("failed to start client");
+    return run_udp_client(sock, dest_addr, timing_interval, packet_length, nic).expect("failed to start client");
+}
+// =============================================================================
+// function wait_for_address
+// =============================================================================
+// wait up to 10 seconds for the DHCP lease of the selected card
+// =============================================================================
+
+fn wait_for_address(nic: &str) -> Option<Ipv4Address> {
+    let deadline = timer().systime_ms() + 10_000;
+    while timer().systime_ms() < deadline {
+        if let Some(address) = network::interface_ipv4_address(nic) {
+            info!("benchmark uses network card {} with address {}", nic, address);
+            return Some(address);
+        }
+        scheduler().sleep(100);
+    }
+
+    None
 }
+
 // =============================================================================
 // function run_udp_client
 // =============================================================================
 // Sends "Init\n" to server, waits for an "Init\n" response, then returns.
 // =============================================================================
 
-pub fn run_udp_client(sock: SocketHandle, addr: (IpAddress, u16), timing_interval: u16, packet_length: u16) -> Result<(), &'static str> {
+pub fn run_udp_client(
+    sock: SocketHandle,
+    addr: (IpAddress, u16),
+    timing_interval: u16,
+    packet_length: u16,
+    nic: &str,
+) -> Result<(), &'static str> {
     // =============================================================================
     // define variables
     // =============================================================================
@@ -91,7 +124,7 @@ pub fn run_udp_client(sock: SocketHandle, addr: (IpAddress, u16), timing_interva
             // ======================================
             if recv_data == b"Init\n" {
                 info!("Received expected Init response");
-                let _ = udp_send_traffic(sock, addr, timing_interval, packet_length).expect("failed to start send loop!");
+                let _ = udp_send_traffic(sock, addr, timing_interval, packet_length, nic).expect("failed to start send loop!");
                 return Ok(());
             } else {
                 warn!("Unexpected data: {:?}", recv_data);
@@ -111,8 +144,19 @@ pub fn run_udp_client(sock: SocketHandle, addr: (IpAddress, u16), timing_interva
 // old test worked until the TX ring filled, then it paniced the kernel because call .expect("Failed to send UDP datagram").
 // new version doesn’t crash because it handles backpressure (BufferFull) by polling/yielding and retrying instead of panicking.
 // =============================================================================
+// at the end, the frames which have actually left the card are printed
+// (taken from the traffic counters of the nic, which count a frame after its transmission has finished)
+// for measuring the gain of the ping-pong transmit of the NE2000,
+// run the benchmark with TRANSMIT_SLOTS = 1 and TRANSMIT_SLOTS = 2 (see consts.rs)
+// =============================================================================
 
-pub fn udp_send_traffic(sock: SocketHandle, addr: (IpAddress, u16), interval: u16, packet_length: u16) -> Result<(), &'static str> {
+pub fn udp_send_traffic(
+    sock: SocketHandle,
+    addr: (IpAddress, u16),
+    interval: u16,
+    packet_length: u16,
+    nic: &str,
+) -> Result<(), &'static str> {
     // =============================================================================
     // define variables
     // =============================================================================
@@ -123,8 +167,12 @@ pub fn udp_send_traffic(sock: SocketHandle, addr: (IpAddress, u16), interval: u1
     let datagram: &mut [u8] = &mut buf;
     let mut seconds_passed = 0;
 
+    // traffic counters of the nic at the start of the test
+    let test_start_time = timer().systime_ms();
+    let nic_stats_start = network::interface_stats(nic);
+
     // define the time for exit
-    let test_finish_time = timer().systime_ms() + 20_000;
+    let test_finish_time = test_start_time + 20_000;
     // define counter variable seconds_passed for each passing second
     seconds_passed = timer().systime_ms() + 1_000;
     info!("Start: {} - End: {}", timer().systime_ms(), test_finish_time);
@@ -218,6 +266,18 @@ pub fn udp_send_traffic(sock: SocketHandle, addr: (IpAddress, u16), interval: u1
     info!("Packets transmitted : {}", packet_number);
     info!("Bytes transmitted: {}", sent_bytes);
     info!("Average: {} KB/s", (sent_bytes as f64 / interval as f64) / 1000.0);
+
+    // ======================================
+    // frames, which the nic has put on the
+    // wire during the test
+    // ======================================
+    if let (Some(start), Some(end)) = (nic_stats_start, network::interface_stats(nic)) {
+        let duration = (timer().systime_ms() - test_start_time) as f64 / 1000.0;
+        let frames = end.tx_packets - start.tx_packets;
+        let frame_bytes = end.tx_bytes - start.tx_bytes;
+        info!("Frames transmitted by {}: {} ({} frames/s)", nic, frames, frames as f64 / duration);
+        info!("Wire throughput: {} KB/s", (frame_bytes as f64 / duration) / 1000.0);
+    }
     return Ok(());
 }
 
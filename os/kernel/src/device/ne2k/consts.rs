@@ -12,7 +12,6 @@
 // DEPENDENCIES:
 // =============================================================================
 pub(crate) use bitflags::bitflags;
-use core::sync::atomic::AtomicU8;
 use core::sync::atomic::AtomicUsize;
 
 // =============================================================================
@@ -93,6 +92,17 @@ pub const RECV_QUEUE_CAP: usize = 9000;
 // Buffer Start Page for the transmitted pages
 pub const TRANSMIT_START_PAGE: u8 = 0x40;
 
+// number of transmit buffers (ping-pong transmit)
+// while the frame in one slot is on the wire, the next frame
+// gets uploaded into the other slot per remote dma
+// set to 1 for comparing against a single transmit buffer (see benchmark.rs)
+pub const TRANSMIT_SLOTS: usize = 2;
+// each slot holds one frame with a MTU (= 6 pages)
+// slot 0: 0x40 - 0x45, slot 1: 0x46 - 0x4B
+pub const TRANSMIT_SLOT_PAGES: u8 = BUFFER_PAGES as u8;
+// value of the active transmit slot, while no frame is on the wire
+pub const TRANSMIT_IDLE: usize = usize::MAX;
+
 // Define the range of a size for an ethernet packet
 pub const MINIMUM_ETHERNET_PACKET_SIZE: u8 = 64;
 pub const MAXIMUM_ETHERNET_PACKET_SIZE: u32 = 1522;
@@ -100,7 +110,8 @@ pub const MAXIMUM_ETHERNET_PACKET_SIZE: u32 = 1522;
 // Reception Buffer Ring Start Page
 // http://www.osdever.net/documents/WritingDriversForTheDP8390.pdf
 // Page 4 PSTART
-pub const RECEIVE_START_PAGE: u8 = 0x46;
+// the receive ring starts behind the transmit slots
+pub const RECEIVE_START_PAGE: u8 = TRANSMIT_START_PAGE + TRANSMIT_SLOTS as u8 * TRANSMIT_SLOT_PAGES; // = 0x4C
 
 //Reception Buffer Ring End
 //P.4 PSTOP http://www.osdever.net/documents/WritingDriversForTheDP8390.pdf
@@ -108,15 +119,15 @@ pub const RECEIVE_STOP_PAGE: u8 = 0x80;
 //static RECEIVE_STOP_PAGE: u8 = 0x48;
 //static RECEIVE_STOP_PAGE: u8 = 0x50;
 
-// 0x80 - 0x46 = 0x58 = 58 pages
-// total buffer size = 58 * 256 Bytes  = 14.KiB
+// 0x80 - 0x4C = 0x34 = 52 pages
+// total buffer size = 52 * 256 Bytes  = 13 KiB
 
-// use static here, because the variable gets changed
-// during the program and a mutable value is needed
+// time the nic needs to finish a reception or transmission after a stop command
+// p.9 DP8390D datasheet, Section "Buffer Overflow": wait for at least 1.6 ms
+pub const STOP_COMPLETION_DELAY_US: usize = 1600;
 
-// this variable points to the next packet to be read
-//static mut CURRENT_NEXT_PAGE_POINTER: u8 = 0;
-pub static CURRENT_NEXT_PAGE_POINTER: AtomicU8 = AtomicU8::new(0);
+// the pointer to the next packet to be read lives in the Ne2000 struct
+// (next_page_pointer), so that every card keeps its own receive ring state
 pub static GLOBAL_THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 pub mod page_registers_offsets {
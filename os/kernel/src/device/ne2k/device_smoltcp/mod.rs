@@ -1,128 +1,172 @@
 // =============================================================================
 // FILE        : device_smoltcp/mod.rs
 // AUTHOR      : Johann Spenrath <johann.spenrath@hhu.de>
-// DESCRIPTION : file includes the trait implementations for TxToken, RxToken
-//               and phy:Device for the NE2000 driver
-//               which is provided by the smoltcp crate
-//               and implementations for Packet allocation and deallocation
+// DESCRIPTION : file includes the implementation of the NetworkDevice trait
+//               for the NE2000 driver, the network stack uses it to
+//               hand packets to smoltcp (see device/nic.rs)
 // =============================================================================
 //
-//& borrowing the Struct Ne2000
-// 'a lifetime annotation
 // implementation is orientated on the rtl8139.rs module
-// changed to mut because send packet expects mutable self reference
+// the trait only hands out shared references, the driver functions lock the
+// registers of the card themselves (see Ne2000::registers)
 //
 
 use super::ne2000::*;
 use crate::device::ne2k::consts::TOTAL_BUFFER_BYTES;
-use crate::memory::{PAGE_SIZE, vmm};
+use crate::device::nic::{NetworkDevice, NetworkStats, PacketAllocator};
+use crate::memory::vmm;
 use crate::process_manager;
-use core::{ptr, slice};
-// for allocator impl
-use core::alloc::{AllocError, Allocator, Layout};
-// for allocator impl
-use core::ptr::NonNull;
-
-// lock free algorithms and datastructes
-// queues: different queue implementations
-// mpsc : has the jiffy queue ; lock-free unbounded
+use core::sync::atomic::Ordering;
+use core::slice;
 
 // smoltcp provides a full network stack for creating packets, sending, receiving etc.
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use smoltcp::phy::{self};
 use smoltcp::phy::{DeviceCapabilities, Medium};
-use smoltcp::time::Instant;
+use smoltcp::wire::EthernetAddress;
 
 // for writing to the registers
-use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::VirtAddr;
 use x86_64::structures::paging::page::PageRange;
-use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
-use x86_64::{PhysAddr, VirtAddr};
+use x86_64::structures::paging::{Page, PageTableFlags};
 
 // =============================================================================
-// ==== STRUCTS
+// ==== IMPLEMENTATIONS
 // =============================================================================
 
 // ==========================================
-// RxToken and TxToken
-// - tokens are types that allow
-//   to receive/send a single packet,
-// ==========================================
-
-// ==========================================
-// Transmit Token for the driver
-// - device: points to the ne2000 struct,
+// NetworkDevice impl
 // ==========================================
-pub struct Ne2kTxToken<'a> {
-    device: &'a mut Ne2000,
-}
-// ==========================================
-// Receive Token for the driver
-// ==========================================
-// - device: points to the ne2000 struct,
-// - buffer: contains the payload of the
-//           received packet
+// An interface for sending and receiving raw network frames
+//
+// called, when polling for new packets in network/mod.rs
+// the adapter in device/nic.rs turns these functions into the
+// RxToken and TxToken of smoltcp
+// From https://docs.rs/smoltcp/latest/smoltcp/phy/trait.Device.html
 // ==========================================
-pub struct Ne2kRxToken<'a> {
-    buffer: Vec<u8, PacketAllocator>,
-    device: &'a Ne2000,
-}
+impl NetworkDevice for Ne2000 {
+    fn mac_address(&self) -> EthernetAddress {
+        self.get_mac()
+    }
 
-// ==========================================
-// PacketAllocator
-// ==========================================
-// allocate blocks of data
-// Ne2000 uses buffer ring,
-// packets can be overwritten by new incoming
-// packets once the buffer is full
-// driver allocates memory to copy the
-// packet there and frees the buffer on NE2000
-// ==========================================
+    // the NE2000 has no register for the link state,
+    // so the default (always up) is used
 
-#[derive(Default)]
-pub struct PacketAllocator;
+    fn stats(&self) -> NetworkStats {
+        self.stats.snapshot()
+    }
 
-// =============================================================================
-// ==== IMPLEMENTATIONS
-// =============================================================================
+    // ==========================================
+    // device capabilities function
+    // ==========================================
+    //
+    // define what the device should support
+    // ==========================================
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        // max_transmission_unit = define max. size of a packet
+        // this is the size of one ethernet frame
+        // see: https://en.wikipedia.org/wiki/Ethernet_frame
+        caps.max_transmission_unit = TOTAL_BUFFER_BYTES;
+        //max_burst_size = only send one packet at a time
+        //caps.max_burst_size = Some(100);
+        // None = no limit on the number of packets send
+        // Note: changing this value does not increase the speed of the Ne2000
+        // card (in qemu)
+        // Note by Johann Spenrath on 02.09.2025:
+        // changing this value changed nothing in transmission speed
+        // or the overall performance of the card in qemu
+        caps.max_burst_size = None;
+        // medium = send the packet over Ethernet
+        caps.medium = Medium::Ethernet;
 
-// ==========================================
-// TxToken impl
-// ==========================================
-// implementation is orientated on the
-// rtl8139.rs module, generate new transmission
-// token, a token to send a single network packet
-// see:
-// https://docs.rs/smoltcp/latest/smoltcp/phy/trait.TxToken.html
-// ==========================================
+        // return capabilities
+        caps
+    }
 
-impl<'a> Ne2kTxToken<'a> {
-    pub fn new(device: &'a mut Ne2000) -> Self {
-        Self { device }
+    // assign the interrupt handler
+    fn plugin(self: Arc<Self>) {
+        Ne2000::assign(self);
+    }
+
+    // =============================================================================
+    // function process_interrupts()
+    // check for overflow interrupt and receive packet interrupt
+    // the trigger function sets the booleans , this function calls
+    // the appropriate function to handle the interrupt
+    // =============================================================================
+    fn process_interrupts(&self) {
+        // check if interrupt occured
+        // Packet received ?
+        if self.check_interrupts.prx.load(Ordering::Relaxed) {
+            // reset the AtomicBool after handling the interrupt
+            self.check_interrupts.prx.store(false, Ordering::Relaxed);
+            self.receive_packet();
+        }
+        // Receive Buffer Overwrite?
+        if self.check_interrupts.ovw.load(Ordering::Relaxed) {
+            // reset the AtomicBool after handling the interrupt
+            self.check_interrupts.ovw.store(false, Ordering::Relaxed);
+            self.handle_overflow();
+        }
+        // receive filter changed? (see set_multicast_filter())
+        self.apply_receive_filter();
+        // Packet send?
+        // free the transmit slot and send the frame waiting in the other slot
+        if self.check_interrupts.ptx.load(Ordering::Relaxed) {
+            self.check_interrupts.ptx.store(false, Ordering::Relaxed);
+            self.finish_transmit();
+        }
+    }
+
+    // the multicast hash filter and promiscuous mode are written to
+    // the nic by the network thread (see apply_receive_filter())
+    fn set_multicast_filter(&self, addresses: &[EthernetAddress]) {
+        Ne2000::set_multicast_filter(self, addresses);
+    }
+
+    fn set_promiscuous(&self, enabled: bool) -> bool {
+        Ne2000::set_promiscuous(self, enabled);
+        true
+    }
+
+    // ==========================================
+    // receive function
+    // ==========================================
+    // dequeue a filled buffer from receive_messages,
+    // the buffer contains the payload of the received packet,
+    // which has been written to by the receive_packet function,
+    // together with the length of the frame in it
+    // 0 is the receiver of the queue
+    // if no packet has been received and is waiting in the queue,
+    // return None
+    // ==========================================
+    fn receive(&self) -> Option<(Vec<u8, PacketAllocator>, usize)> {
+        self.receive_messages.0.try_dequeue().ok()
+    }
+
+    // ==========================================
+    // recycle function
+    // ==========================================
+    // enqueue the used allocated buffer to the receive_buffers_empty
+    // queue, to use it again during packet reception
+    // ==========================================
+    fn recycle(&self, buffer: Vec<u8, PacketAllocator>) {
+        self.receive_buffers_empty
+            .1
+            .try_enqueue(buffer)
+            .expect("Failed to enqueue used receive buffer!");
     }
-}
 
-// ==========================================
-// phy::TxToken impl
-// ==========================================
-//
-// implementation is orientated on the
-// rtl8139.rs module
-// len: size of packet
-// ==========================================
-impl<'a> phy::TxToken for Ne2kTxToken<'a> {
     // ==========================================
-    // consume function()
+    // transmit function
     // ==========================================
     // call send method using the NE2000
-    // consumes the token to send a single network packet
-    // constructs buffer (size len) -> calls passed closure f
+    // constructs buffer (size len) -> calls passed closure fill
     // in the closure a valid network packet should be constructed
     // when closure returns, packet gets send out
-    fn consume<R, F>(self, len: usize, f: F) -> R
-    where
-        F: FnOnce(&mut [u8]) -> R,
-    {
+    // ==========================================
+    fn transmit(&self, len: usize, fill: &mut dyn FnMut(&mut [u8])) {
         // Allocate and fill local buffer
         // allocate one pyhsical frame
         // the phys_buffers gets a start and end PhysFrame (Range)
@@ -142,196 +186,21 @@ impl<'a> phy::TxToken for Ne2kTxToken<'a> {
             .virtual_address_space
             .set_flags(pages, PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE);
 
-        // Queue physical memory buffer for deallocation after transmission (.enqueue)
-        // .1 is the Sender here
-        // nic then sends the packet over the network
-        self.device.send_queue.1.enqueue(phys_buffer).expect("Failed to enqueue physical buffer!");
-
         // Let smoltcp write the packet data to the buffer
-        // slice : a view into a block of memory represented as a pointer and a length.
-        // example:
-        // let mut x = [1, 2, 3];
-        // let x = &mut x[..]; // Take a full slice of `x`.
-        // x[1] = 7;
-        // assert_eq!(x, &[1, 7, 3]);
         // from_raw_parts_mut : Forms a mutable slice from a pointer and a length.
-
         let buffer = unsafe { slice::from_raw_parts_mut(phys_buffer.start.start_address().as_u64() as *mut u8, len) };
         // closure builds the ethernet frame
-        // closure that, when run, writes whatever bytes are sent onto the link
-        let result = f(buffer);
+        fill(buffer);
 
         // Send packet
         // function sets the register, writes the packet per remote dma from the host to the local
         // buffer memory of the nic and triggers a send operation
-        self.device.send_packet(buffer);
-
-        // return the result from the closure, then the packet is send out
-        result
-    }
-}
-
-// ==========================================
-// PacketAllocator impl
-// ==========================================
-// allocate memory for the buffer of
-// the receive token
-// and functions for deallocation
-// ==========================================
+        self.send_packet(buffer);
 
-unsafe impl Allocator for PacketAllocator {
-    // from rtl8139.rs
-    // used for allocating memory for the RxToken Buffer
-    // allocates a block of memory
-    // returns NonNull, which meets the size and alignment of layout, remains
-    // valid as long as it is currently allocated
-    fn allocate(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        panic!("PacketAllocator does not support allocate!");
-    }
-
-    // deallocate memory referenced by the pointer
-    // return one page frame of physical memory back to allocator
-    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
-        if layout.size() != PAGE_SIZE {
-            panic!("PacketAllocator may only be used with page frames!");
-        }
-
-        // get the raw pointer, convert to u64 -> physical memory adress
-        // PhysAddr wraps address in a PhysAddr type
-        // contruct Frame from the Address, must be page-aligned, (divisible by page size )
-        let start = PhysFrame::from_start_address(PhysAddr::new(ptr.as_ptr() as u64)).expect("PacketAllocator may only be used with page frames!");
-        unsafe {
-            // create one physical page frage
-            // frames::free -> return to memory allocator
-            vmm::free_frames(PhysFrameRange { start, end: start + 1 })
-        }
-    }
-}
-
-impl<'a> Ne2kRxToken<'a> {
-    pub fn new(buffer: Vec<u8, PacketAllocator>, device: &'a Ne2000) -> Self {
-        Self { buffer, device }
-    }
-}
-
-// ==========================================
-// phy::RxToken impl
-// ==========================================
-// usage:
-// - implement the consume() function for the receive token
-// - returns the payload of the received packet, stored in buffer
-// - used buffer gets enqueued for storing new packets
-// ==========================================
-impl<'a> phy::RxToken for Ne2kRxToken<'a> {
-    fn consume<R, F>(mut self, f: F) -> R
-    where
-        F: FnOnce(&[u8]) -> R,
-    {
-        // buffer contains payload, which has been written to by the
-        // receive_packages function
-        // get the result by applying the closure to the buffer
-        let result = f(&mut self.buffer);
-        // enqueue the used allocated buffer to the receive_buffers_empty
-        // queue, to use it again during packet reception
-        self.device
-            .receive_buffers_empty
-            .1
-            .try_enqueue(self.buffer)
-            .expect("Failed to enqueue used receive buffer!");
-
-        //return the result (the received data), smoltcp then processes the packet
-        result
-    }
-}
-
-// ==========================================
-// Device impl
-// ==========================================
-// An interface for sending and receiving raw network frames
-//
-// called, when polling for new packets in network/mod.rs
-// From https://docs.rs/smoltcp/latest/smoltcp/phy/trait.Device.html
-// uses tokens, which are types that allow to receive/transmit a single packet
-// receive() and transmit() construct the tokens only, real sending, tranmitting
-// is done by the consume() functions of the Tokens
-// ==========================================
-impl phy::Device for Ne2000 {
-    type RxToken<'a>
-        = Ne2kRxToken<'a>
-    where
-        Self: 'a;
-    type TxToken<'a>
-        = Ne2kTxToken<'a>
-    where
-        Self: 'a;
-
-    // ==========================================
-    // receive function
-    // ==========================================
-    // creates a new RxToken and calls it's consume() function
-    //
-    // Info: The additional transmit token makes it possible to generate a reply packet
-    // based on the contents of the received packet. For example, this makes it
-    // possible to handle arbitrarily large ICMP echo (“ping”) requests,
-    // where the all received bytes need to be sent back, without heap allocation.
-    // =============================================================================
-    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        let device = unsafe { ptr::from_ref(self).as_ref()? };
-        // dequeue an empty buffer from receive_messages, which gets assigned
-        // to the Ne2000RxToken for getting the packet payload
-        // 0 is the receiver of the queue
-        // if a packet is enqueued, it returns Ok(rec_buf)
-        // and tries to pop it off the receive_messages queue
-        // Ne2000RxToken – gives smoltcp read-only access to the just-received Ethernet frame.
-        // new(recv_buf, device) ties the buffer together
-        // with a reference to the NIC so that, when smoltcp
-        // finishes with the frame, the token can safely return the buffer to the driver.
-        match self.receive_messages.0.try_dequeue() {
-            Ok(recv_buf) => Some((Ne2kRxToken::new(recv_buf, device), Ne2kTxToken::new(self))),
-            // no packet has been received and is waiting in the queue
-            Err(_) => None,
-        }
-    }
-
-    // ==========================================
-    // transmit function
-    // ==========================================
-    // creates a new TxToken and calls it's consume function
-    // Returns a TxToken, which accepts the packet contents
-    // Converts &mut self to &Ne2000 safely.
-    // Needed because RxToken and TxToken store a shared reference to the driver
-    // (not &mut self). See RTL8139 impl
-    // =============================================================================
-    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
-        //let device = unsafe { ptr::from_ref(self).as_ref()? };
-        Some(Ne2kTxToken::new(self))
-    }
-
-    // ==========================================
-    // device capabilities function
-    // ==========================================
-    //
-    // define what the device should support
-    // ==========================================
-    fn capabilities(&self) -> DeviceCapabilities {
-        let mut caps = DeviceCapabilities::default();
-        // max_transmission_unit = define max. size of a packet
-        // this is the size of one ethernet frame
-        // see: https://en.wikipedia.org/wiki/Ethernet_frame
-        caps.max_transmission_unit = TOTAL_BUFFER_BYTES;
-        //max_burst_size = only send one packet at a time
-        //caps.max_burst_size = Some(100);
-        // None = no limit on the number of packets send
-        // Note: changing this value does not increase the speed of the Ne2000
-        // card (in qemu)
-        // Note by Johann Spenrath on 02.09.2025:
-        // changing this value changed nothing in transmission speed
-        // or the overall performance of the card in qemu
-        caps.max_burst_size = None;
-        // medium = send the packet over Ethernet
-        caps.medium = Medium::Ethernet;
-
-        // return capabilities
-        caps
+        // Queue physical memory buffer for deallocation after transmission (.enqueue)
+        // .1 is the Sender here
+        // this is done after the upload, because with two transmit slots the PTX interrupt
+        // of the previous frame can arrive, while this buffer is still being filled
+        self.send_queue.1.enqueue(phys_buffer).expect("Failed to enqueue physical buffer!");
     }
 }
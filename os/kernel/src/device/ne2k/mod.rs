@@ -12,7 +12,9 @@
 pub mod benchmark;
 // constants for addressing the registers of the NE2000
 pub mod consts;
-// Trait Implementations for smoltcp
+// NetworkDevice implementation, used by smoltcp through device/nic.rs
 pub mod device_smoltcp;
 // main driver functionalities
 pub mod ne2000;
+// traffic and error counters
+pub mod stats;
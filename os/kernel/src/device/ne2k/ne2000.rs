@@ -7,10 +7,10 @@
 // =============================================================================
 use crate::interrupt::interrupt_dispatcher::InterruptVector;
 use crate::memory::{PAGE_SIZE, vmm};
-use crate::{apic, interrupt_dispatcher, pci_bus, process_manager, scheduler};
+use crate::{apic, interrupt_dispatcher, network, pci_bus, process_manager, scheduler, timer};
 use core::mem;
 // for calling the methods outside the interrupt handler
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU16, AtomicUsize, Ordering};
 // print to terminal
 use log::info;
 // for allocator impl
@@ -34,6 +34,7 @@ use alloc::vec::Vec;
 use smoltcp::wire::EthernetAddress;
 
 use x86_64::VirtAddr;
+use x86_64::instructions::interrupts;
 use x86_64::instructions::port::Port;
 use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::structures::paging::page::PageRange;
@@ -46,9 +47,10 @@ use x86_64::structures::paging::{Page, PageTableFlags};
 // load the bitflags for the register into the module
 use super::consts::page_registers_offsets::*;
 use super::consts::*;
-// smoltcp configuration
-use super::device_smoltcp::*;
+// receive buffers and traffic counters shared by all network drivers
+use crate::device::nic::PacketAllocator;
 use crate::device::ne2k::consts;
+use super::stats::Ne2000Stats;
 
 // =============================================================================
 // ==== STRUCTS
@@ -75,23 +77,21 @@ pub struct Page0 {
     rsar_1_port: Port<u8>,
     rbcr_0_port: Port<u8>,
     rbcr_1_port: Port<u8>,
-    // receive status register
-    rsr_port: Port<u8>,
+    // transmit status register, number of collisions register
+    tsr_port: Port<u8>,
+    ncr_port: Port<u8>,
 }
 
 // ================================
 // Registers on Page1
 // ================================
 pub struct Page1 {
-    //Physical Address Registers, for Reading the MAC Address
-    // Reference:
-    // section "10.8 PHYSICAL ADDRESS REGISTERS (PAR0-PAR5)",
-    // https://web.archive.org/web/20010612150713/http://www.national.com/ds/DP/DP8390D.pdf
-    par: [Mutex<Port<u8>>; 6],
     mar: [Port<u8>; 8],
     current_port: Port<u8>,
 }
 // define read + write ports for the registers of the ne2k
+// these are only used by the network thread (remote dma, transmit, receive ring, receive filter)
+// and are locked as a whole (see Ne2000::registers)
 pub struct Registers {
     reset_port: Port<u8>,
     command_port: Port<u8>,
@@ -99,12 +99,27 @@ pub struct Registers {
     data_port: Port<u8>,
     // data port for wordwise transfer
     data_port_u16: Port<u16>,
-    isr_port: Mutex<Port<u8>>,
-    imr_port: Mutex<Port<u8>>,
     page0: Page0,
     page1: Page1,
 }
 
+// ports, which are used by the interrupt handler as well
+// the interrupt handler must not wait for the network thread,
+// so these are not part of Registers and each of them has its own Mutex
+pub struct SharedRegisters {
+    isr_port: Mutex<Port<u8>>,
+    imr_port: Mutex<Port<u8>>,
+    // receive status register (page 0)
+    rsr_port: Mutex<Port<u8>>,
+    // tally counters CNTR0-2 (page 0)
+    cntr_ports: Mutex<[Port<u8>; 3]>,
+    //Physical Address Registers, for Reading the MAC Address (page 1)
+    // Reference:
+    // section "10.8 PHYSICAL ADDRESS REGISTERS (PAR0-PAR5)",
+    // https://web.archive.org/web/20010612150713/http://www.national.com/ds/DP/DP8390D.pdf
+    par: [Mutex<Port<u8>>; 6],
+}
+
 // The Structure of the PacketHeader is definied in the datasheet
 // Header is 4 KB
 // Reference: p.8, Section "Beginning of Reception", p.11 Section "Storage Format for Received Packets",
@@ -119,6 +134,18 @@ struct PacketHeader {
     length: u16,
 }
 
+// interrupts, which are enabled during operation
+// - PRX, PTX: packet received, packet transmitted
+// - RXE, TXE: receive / transmit error (for the statistics, see stats.rs)
+// - OVW: receive ring full
+// - CNT: one of the tally counters is half full and has to be read
+const ENABLED_INTERRUPTS: InterruptMaskRegister = InterruptMaskRegister::IMR_PRXE
+    .union(InterruptMaskRegister::IMR_PTXE)
+    .union(InterruptMaskRegister::IMR_RXEE)
+    .union(InterruptMaskRegister::IMR_TXEE)
+    .union(InterruptMaskRegister::IMR_OVWE)
+    .union(InterruptMaskRegister::IMR_CNTE);
+
 // define Atomics which are used for the trigger function
 // each time one of the interrupts occurs (PRX or OVW), set
 // AtomicBool to 'true', which triggers the check() function
@@ -137,8 +164,9 @@ pub struct Ne2000InterruptHandler {
 
 // Struct for the Ne2000 driver
 pub struct Ne2000 {
-    base_address: u16,
-    pub registers: Registers,
+    // the lock keeps the register sequences of send_packet(), receive_packet() etc. from interleaving
+    pub registers: Mutex<Registers>,
+    pub shared_registers: SharedRegisters,
     // - physical memory pages, that need transmitting
     // - in TxToken consume the outgoing packet gets loaded into the buffer
     // - multiple producers can add items to the
@@ -160,14 +188,34 @@ pub struct Ne2000 {
         // Sender send data to a set of Receivers
         mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>,
     ),
-    // - contains the actual data which is received by the card
+    // - contains the actual data which is received by the card,
+    //   together with the length of the frame in the buffer
     // - scq: Scalable-Circular-Queue implementation
     pub receive_messages: (
-        mpmc::bounded::scq::Receiver<Vec<u8, PacketAllocator>>,
-        mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>,
+        mpmc::bounded::scq::Receiver<(Vec<u8, PacketAllocator>, usize)>,
+        mpmc::bounded::scq::Sender<(Vec<u8, PacketAllocator>, usize)>,
     ),
     interrupt: InterruptVector,
     pub(crate) check_interrupts: CheckInterrupts,
+    // points to the next packet to be read from the receive ring
+    // (kept per device, so that several cards don't share one ring pointer)
+    next_page_pointer: AtomicU8,
+    // ping-pong transmit (see send_packet)
+    // - length of the frame, which has been uploaded into each transmit slot (0 = slot is free)
+    // - slot, into which the next frame gets uploaded
+    // - slot, whose frame is currently on the wire (TRANSMIT_IDLE, if the transmitter is idle)
+    tx_slot_length: [AtomicU16; TRANSMIT_SLOTS],
+    tx_next_slot: AtomicUsize,
+    tx_active_slot: AtomicUsize,
+    // receive filter (see set_multicast_filter() and set_promiscuous())
+    // - multicast hash filter for MAR0-MAR7
+    // - promiscuous mode (RCR_PRO)
+    // - set, if the filter has changed and has to be written to the nic by the network thread
+    multicast_filter: [AtomicU8; 8],
+    promiscuous: AtomicBool,
+    receive_filter_changed: AtomicBool,
+    // counters for sent and received packets and errors (see stats.rs)
+    pub(crate) stats: Ne2000Stats,
 }
 
 // =============================================================================
@@ -194,7 +242,8 @@ impl Page0 {
             dcr_port: Port::new(base_address + P0_DCR),
             crda_0_p0: Port::new(base_address + P0_CRDA0),
             crda_1_p0: Port::new(base_address + P0_CRDA1),
-            rsr_port: Port::new(base_address + P0_RSR),
+            tsr_port: Port::new(base_address + P0_TSR),
+            ncr_port: Port::new(base_address + P0_NCR),
         }
     }
 }
@@ -202,7 +251,6 @@ impl Page0 {
 impl Page1 {
     pub fn new(base_address: u16) -> Self {
         Self {
-            par: core::array::from_fn(|i| Mutex::new(Port::new(base_address + P1_PAR0 + i as u16))),
             mar: core::array::from_fn(|i| Port::new(base_address + P1_MAR0 + i as u16)),
             current_port: Port::new(base_address + P1_CURR),
         }
@@ -219,10 +267,6 @@ impl Registers {
             // command Port for controlling the CR Register
             //(starting, stopping the nic, switching between pages)
             command_port: Port::new(base_address + COMMAND),
-            // Interrupt Status Register
-            isr_port: Mutex::new(Port::new(base_address + P0_ISR)),
-            // Interrupt Mask Register
-            imr_port: Mutex::new(Port::new(base_address + P0_IMR)),
             // data port (or i/o port for reading received data)
             data_port: Port::new(base_address + DATA),
             data_port_u16: Port::new(base_address + DATA),
@@ -231,6 +275,20 @@ impl Registers {
             page1: Page1::new(base_address),
         }
     }
+}
+
+impl SharedRegisters {
+    pub fn new(base_address: u16) -> Self {
+        Self {
+            // Interrupt Status Register
+            isr_port: Mutex::new(Port::new(base_address + P0_ISR)),
+            // Interrupt Mask Register
+            imr_port: Mutex::new(Port::new(base_address + P0_IMR)),
+            rsr_port: Mutex::new(Port::new(base_address + P0_RSR)),
+            cntr_ports: Mutex::new(core::array::from_fn(|i| Port::new(base_address + P0_CNTR0 + i as u16))),
+            par: core::array::from_fn(|i| Mutex::new(Port::new(base_address + P1_PAR0 + i as u16))),
+        }
+    }
 
     // helper functions for reading isr and imr registers
     fn read_isr(&self) -> u8 {
@@ -239,6 +297,14 @@ impl Registers {
     pub fn write_imr(&self, val: u8) {
         unsafe { self.imr_port.lock().write(val) }
     }
+    // read (and thereby clear) the tally counters CNTR0-2
+    fn read_tally_counters(&self) -> [u8; 3] {
+        let mut counters = self.cntr_ports.lock();
+        core::array::from_fn(|i| unsafe { counters[i].read() })
+    }
+    fn read_rsr(&self) -> u8 {
+        unsafe { self.rsr_port.lock().read() }
+    }
 }
 
 // EXAMPLE for a sender and receiver
@@ -325,15 +391,23 @@ impl Ne2000 {
 
         // construct the ne2000 and return it at the end of the
         // initialization
-        let mut ne2000 = Self {
-            registers: Registers::new(base_address),
-            base_address: base_address,
+        let ne2000 = Self {
+            registers: Mutex::new(Registers::new(base_address)),
+            shared_registers: SharedRegisters::new(base_address),
             // wrap Sender and Receiver in a Mutex
             send_queue: (Mutex::new(send_queue.0), send_queue.1),
             receive_buffers_empty: recv_buffers, // save freshly allocated buffers with size of RECV_QUEUE_CAP in the struct
             receive_messages: mpmc::bounded::scq::queue(consts::RECV_QUEUE_CAP),
             interrupt,
             check_interrupts: check_interrupts,
+            next_page_pointer: AtomicU8::new(0),
+            tx_slot_length: [const { AtomicU16::new(0) }; TRANSMIT_SLOTS],
+            tx_next_slot: AtomicUsize::new(0),
+            tx_active_slot: AtomicUsize::new(TRANSMIT_IDLE),
+            multicast_filter: [const { AtomicU8::new(0xFF) }; 8],
+            promiscuous: AtomicBool::new(false),
+            receive_filter_changed: AtomicBool::new(false),
+            stats: Ne2000Stats::default(),
         };
 
         info!("Powering on device");
@@ -354,12 +428,13 @@ impl Ne2000 {
             // this ensures, that the Registers are cleared and no undefined behavior can happen
             // just doing the read operation enables the reset, a write is not necessary, but the bits dont get set correctly
             // Reference: https://wiki.osdev.org/Ne2000#Initialization_and_MAC_Address
-            let reset_value = ne2000.registers.reset_port.read();
-            ne2000.registers.reset_port.write(reset_value);
+            let mut registers = ne2000.registers.lock();
+            let reset_value = registers.reset_port.read();
+            registers.reset_port.write(reset_value);
 
             // bitwise and operation, checks if highest bit is set
             // if register content equals 0, reset was successful
-            while (ne2000.registers.read_isr() & InterruptStatusRegister::ISR_RST.bits()) == 0 {
+            while (ne2000.shared_registers.read_isr() & InterruptStatusRegister::ISR_RST.bits()) == 0 {
                 info!("Reset in Progress");
                 scheduler().sleep(1);
             }
@@ -370,7 +445,7 @@ impl Ne2000 {
             //=== STEP 1 ===//
             // Initialize CR Register
             // Switch to Page0 , stop DMA and set the NIC in Stop mode
-            ne2000.registers.command_port.write((CR::STOP_DMA | CR::STP | CR::PAGE_0).bits());
+            registers.command_port.write((CR::STOP_DMA | CR::STP | CR::PAGE_0).bits());
 
             //=== STEP 2 ===//
             // Initialize DCR Register
@@ -379,8 +454,7 @@ impl Ne2000 {
             // establish FIFO threshholds. The DCR must be initialized prior to loading the Remote Byte Count Registers.
             // Reference: p.22, https://web.archive.org/web/20010612150713/http://www.national.com/ds/DP/DP8390D.pdf
             // Command Register at Page 0 at this point
-            ne2000
-                .registers
+            registers
                 .page0
                 .dcr_port
                 .write((DataConfigurationRegister::DCR_AR | DataConfigurationRegister::DCR_FT1 | DataConfigurationRegister::DCR_LS).bits());
@@ -389,50 +463,42 @@ impl Ne2000 {
             // clear RBCR1,0
             //RBCR0,1 : indicates the length of the block in bytes
             // MAC address has length of 6 Bytes
-            ne2000.registers.page0.rbcr_0_port.write(0);
-            ne2000.registers.page0.rbcr_1_port.write(0);
+            registers.page0.rbcr_0_port.write(0);
+            registers.page0.rbcr_1_port.write(0);
 
             //=== STEP 4 ===//
             // initialize RCR
             // determines the operations of the NIC during reception of a packet
             // and is used to program what types of packets to accept.
-            // RCR_AR : allow RUNT Packets (Packets < 64 Btytes)
-            // RCR_AB : allow Broadcast Packets
-            // RCR_AM : allow Multicast Packets
-            ne2000
-                .registers
-                .page0
-                .rcr_port
-                .write((ReceiveConfigurationRegister::RCR_AR | ReceiveConfigurationRegister::RCR_AB | ReceiveConfigurationRegister::RCR_AM).bits());
+            // (see receive_configuration())
+            let receive_configuration = ne2000.receive_configuration();
+            registers.page0.rcr_port.write(receive_configuration.bits());
 
             //=== STEP 5 ===//
             // Place the NIC in Loopback Mode (Mode 0)
-            ne2000.registers.page0.tcr_port.write(TransmitConfigurationRegister::TCR_LB0.bits());
+            registers.page0.tcr_port.write(TransmitConfigurationRegister::TCR_LB0.bits());
 
             //=== STEP 6 ===//
             // initialize the NIC's receive buffer
             // pstart and pstop define the size of the receive buffer (pstop - pstart = buffer size )
-            ne2000.registers.page0.tpsr_port.write(TRANSMIT_START_PAGE);
-            ne2000.registers.page0.pstart_port.write(RECEIVE_START_PAGE);
-            ne2000.registers.page0.bnry_port.write(RECEIVE_START_PAGE + 1);
-            ne2000.registers.page0.pstop_port.write(RECEIVE_STOP_PAGE);
+            registers.page0.tpsr_port.write(TRANSMIT_START_PAGE);
+            registers.page0.pstart_port.write(RECEIVE_START_PAGE);
+            registers.page0.bnry_port.write(RECEIVE_START_PAGE + 1);
+            registers.page0.pstop_port.write(RECEIVE_STOP_PAGE);
 
             //=== STEP 7 ===//
             //  Clear ISR
-            ne2000.registers.isr_port.lock().write(0xFF);
+            ne2000.shared_registers.isr_port.lock().write(0xFF);
 
             //=== STEP 8 ===//
             // Initialize IMR
             // enables, disables interrupts
-            // enable PacketReceived, PacketTransmit and Overwrite
-            ne2000.registers.imr_port.lock().write(
-                (InterruptMaskRegister::IMR_PRXE | InterruptMaskRegister::IMR_PTXE | InterruptMaskRegister::IMR_OVWE).bits(),
-                //InterruptMaskRegister::IMR_OVWE.bits(),
-            );
+            // enable PacketReceived, PacketTransmit, Overwrite and the error interrupts
+            ne2000.shared_registers.imr_port.lock().write(ENABLED_INTERRUPTS.bits());
 
             //=== STEP 9 ===//
             // Switch to P1, disable DMA and Stop the NIC
-            ne2000.registers.command_port.write((CR::STOP | CR::PAGE_1).bits());
+            registers.command_port.write((CR::STOP | CR::PAGE_1).bits());
 
             // define array for saving the MAC Address
             let mut mac = [0u8; 6];
@@ -440,7 +506,7 @@ impl Ne2000 {
             // i) Initialize the Physical Address Registers: PAR0-PAR5
             // iterate through the ports to get the mac address
             // borrow the value
-            let par = &ne2000.registers.page1.par;
+            let par = &ne2000.shared_registers.par;
             for (i, guard) in par.iter().enumerate() {
                 let mut port = guard.lock();
                 mac[i] = port.read();
@@ -454,24 +520,28 @@ impl Ne2000 {
 
             // located on Page 1
             // ii) Initialize Multicast Address Register: MAR0-MAR7 with 0xFF
-            for port in ne2000.registers.page1.mar.iter_mut() {
+            // (accept all multicast frames, until the network stack sets the filter, see set_multicast_filter())
+            for port in registers.page1.mar.iter_mut() {
                 port.write(0xFF);
             }
             // p.156 http://www.bitsavers.org/components/national/_dataBooks/1988_National_Data_Communications_Local_Area_Networks_UARTs_Handbook.pdf#page=156
             //CURRENT_NEXT_PAGE_POINTER = RECEIVE_START_PAGE + 1;
-            CURRENT_NEXT_PAGE_POINTER.store(RECEIVE_START_PAGE + 1, Ordering::Relaxed);
+            ne2000.next_page_pointer.store(RECEIVE_START_PAGE + 1, Ordering::Relaxed);
 
             // iii) Initialize Current Pointer
-            ne2000.registers.page1.current_port.write(CURRENT_NEXT_PAGE_POINTER.load(Ordering::Relaxed));
+            registers.page1.current_port.write(ne2000.next_page_pointer.load(Ordering::Relaxed));
             //.write(0x47);
 
             //=== STEP 10 ===//
             // Start the NIC
-            ne2000.registers.command_port.write((CR::STOP_DMA | CR::STA | CR::PAGE_0).bits());
+            registers.command_port.write((CR::STOP_DMA | CR::STA | CR::PAGE_0).bits());
 
             //=== STEP 11 ===//
             // Initialize TCR(Transmit Configuration Register) by writing a 0 to it
-            ne2000.registers.page0.tcr_port.write(0);
+            registers.page0.tcr_port.write(0);
+
+            // get_mac() locks the registers as well
+            drop(registers);
 
             info!("\x1b[1;31mFinished Initialization");
             // print an ascii banner to the log screen
@@ -509,19 +579,45 @@ impl Ne2000 {
      *   |                     |------------------------------> |  frame on the wire
      */
     // =============================================================================
+    // the nic has two transmit slots (see TRANSMIT_SLOTS in consts.rs)
+    // frames are uploaded alternately into slot 0 and slot 1 (ping-pong)
+    // while frame N is on the wire, frame N+1 gets uploaded into the other slot
+    // per remote dma, so uploading and transmitting overlap
+    // frame N+1 gets started by finish_transmit(), after the PTX interrupt of frame N
+    //
+    //    slot 0: | upload N |   wire N   | upload N+2 |  wire N+2 |
+    //    slot 1:            | upload N+1 |  wire N+1  |
+    //
+    // send_packet() only waits, if both slots are taken
+    // note: send_packet(), finish_transmit() and receive_packet() lock the registers,
+    // so the remote dma and the command register are never used concurrently
+    // (they are only called by the network thread, so the lock is not contended)
+    // send_packet() releases the lock, while it sleeps, so no other thread spins on it
+    // meanwhile; the remote dma in progress stays intact, because the network thread
+    // is the one sleeping and nobody else starts a remote dma
+    // =============================================================================
 
-    pub fn send_packet(&mut self, packet: &[u8]) {
+    pub fn send_packet(&self, packet: &[u8]) {
+        let mut registers = self.registers.lock();
+        // slot, into which this frame gets uploaded
+        let slot = self.tx_next_slot.load(Ordering::Relaxed);
         unsafe {
-            // check, if the nic is ready for transmit
-            // if the TXP Bit is still set, the NIC is still sending another packet
-            // wait until Bit gets unset
-            while CR::from_bits_retain(self.registers.command_port.read()).contains(CR::TXP) {
-                scheduler().sleep(1);
+            // check, if the slot is free
+            // if it still holds a frame, that frame is on the wire and the frame
+            // in the other slot waits for it -> wait until the transmission has finished
+            // (finish_transmit() frees the slot and starts the waiting frame)
+            while self.tx_slot_length[slot].load(Ordering::Relaxed) != 0 {
+                self.finish_transmit_locked(&mut registers);
+                if self.tx_slot_length[slot].load(Ordering::Relaxed) != 0 {
+                    drop(registers);
+                    scheduler().sleep(1);
+                    registers = self.registers.lock();
+                }
             }
 
             //==== STEP 1 ====//
             // switch to page 0, enable nic, stop dma
-            self.registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_0).bits());
+            registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_0).bits());
 
             // =============================================================================
             // start dummy_read
@@ -532,21 +628,23 @@ impl Ne2000 {
             // =============================================================================
 
             // Save CRDA bit (Current Remote DMA Address)
-            let old_crda: u16 = self.registers.page0.crda_0_p0.read() as u16 | ((self.registers.page0.crda_1_p0.read() as u16) << 8);
+            let old_crda: u16 = registers.page0.crda_0_p0.read() as u16 | ((registers.page0.crda_1_p0.read() as u16) << 8);
 
             // Set RBCR > 0
-            self.registers.page0.rbcr_0_port.write(0x01);
-            self.registers.page0.rbcr_1_port.write(0x00);
+            registers.page0.rbcr_0_port.write(0x01);
+            registers.page0.rbcr_1_port.write(0x00);
             // Set RSAR to unused address
-            self.registers.page0.rsar_0_port.write(TRANSMIT_START_PAGE);
-            self.registers.page0.rsar_1_port.write(0);
+            registers.page0.rsar_0_port.write(TRANSMIT_START_PAGE);
+            registers.page0.rsar_1_port.write(0);
             // Issue Dummy Remote READ Command
-            self.registers.command_port.write((CR::STA | CR::REMOTE_READ | CR::PAGE_0).bits());
+            registers.command_port.write((CR::STA | CR::REMOTE_READ | CR::PAGE_0).bits());
 
             // Mandatory Delay between Dummy Read and Write to ensure dummy read was successful
             // Wait until crda value has changed
-            while old_crda == self.registers.page0.crda_0_p0.read() as u16 | ((self.registers.page0.crda_1_p0.read() as u16) << 8) {
+            while old_crda == registers.page0.crda_0_p0.read() as u16 | ((registers.page0.crda_1_p0.read() as u16) << 8) {
+                drop(registers);
                 scheduler().sleep(1);
+                registers = self.registers.lock();
             }
 
             // =============================================================================
@@ -561,33 +659,34 @@ impl Ne2000 {
             // get the higher 8 Bit of the length
             let high = (packet_length >> 8) as u8;
 
-            self.registers.page0.rbcr_0_port.write(low);
-            self.registers.page0.rbcr_1_port.write(high);
+            registers.page0.rbcr_0_port.write(low);
+            registers.page0.rbcr_1_port.write(high);
 
             //==== STEP 3 ====//
             // Remote DMA complete ?
             // ref: https://wiki.osdev.org/Ne2000#Sending_a_Packet
             // Clear RDC Interrupt
-            self.registers.isr_port.lock().write(InterruptStatusRegister::ISR_RDC.bits());
+            self.shared_registers.isr_port.lock().write(InterruptStatusRegister::ISR_RDC.bits());
 
             //==== STEP 4 ====//
             // Load RSAR with 0 (low bits) and Page Number (high bits)
             // tell the nic, on which page it should start to write the packet
-            self.registers.page0.rsar_0_port.write(0);
-            self.registers.page0.rsar_1_port.write(TRANSMIT_START_PAGE);
+            // (the first page of the free transmit slot)
+            registers.page0.rsar_0_port.write(0);
+            registers.page0.rsar_1_port.write(Self::transmit_slot_page(slot));
 
             //==== STEP 5 ====//
             // Set Bits in COMMAND Register to remote write
-            self.registers.command_port.write((CR::STA | CR::REMOTE_WRITE | CR::PAGE_0).bits());
+            registers.command_port.write((CR::STA | CR::REMOTE_WRITE | CR::PAGE_0).bits());
 
             //==== STEP 6 ====//
             // Write packet to remote DMA (host writes data to the local buffer memory of the nic)
-            let data_port = &mut self.registers.data_port;
+            let data_port = &mut registers.data_port;
             for &data in packet {
                 data_port.write(data);
             }
 
-            /*let data_port_u16 = &mut self.registers.data_port_u16;
+            /*let data_port_u16 = &mut registers.data_port_u16;
             // Write data in 16-bit words
             for chunk in packet.chunks_exact(2) {
                 let word = u16::from_le_bytes([chunk[0], chunk[1]]);
@@ -596,35 +695,109 @@ impl Ne2000 {
             // Handle leftover byte if packet length is odd
             if let Some(&last_byte) = packet.chunks_exact(2).remainder().first() {
                 // use bytewise dataport
-                let mut byte_port = self.registers.data_port;
+                let mut byte_port = registers.data_port;
                 byte_port.write(last_byte);
             }*/
 
             //==== STEP 7 ====//
             // Poll ISR until remote DMA Bit is set
             // remote dma write ends, when byte count in RBCR is 0
-            while (self.registers.read_isr() & InterruptStatusRegister::ISR_RDC.bits()) == 0 {
+            while (self.shared_registers.read_isr() & InterruptStatusRegister::ISR_RDC.bits()) == 0 {
+                drop(registers);
                 scheduler().sleep(1);
+                registers = self.registers.lock();
             }
 
             // Clear ISR RDC Interrupt Bit
-            self.registers.isr_port.lock().write(InterruptStatusRegister::ISR_RDC.bits());
+            self.shared_registers.isr_port.lock().write(InterruptStatusRegister::ISR_RDC.bits());
+
+            // the frame is now in the local buffer of the nic
+            // the next frame goes into the other slot
+            self.tx_slot_length[slot].store(packet_length as u16, Ordering::Relaxed);
+            self.tx_next_slot.store((slot + 1) % TRANSMIT_SLOTS, Ordering::Relaxed);
 
             //==== STEP 8 ====//
+            // if the frame on the wire has already been sent, the transmitter
+            // is idle now -> free its slot (the PTX interrupt may not have been handled yet)
+            self.finish_transmit_locked(&mut registers);
+
+            //==== STEP 9 ====//
+            // transmitter idle -> send the frame right away
+            // otherwise finish_transmit() sends it after the PTX interrupt of the current frame
+            if self.tx_active_slot.load(Ordering::Relaxed) == TRANSMIT_IDLE {
+                self.start_transmit(&mut registers, slot);
+            }
+        }
+    }
+
+    // =============================================================================
+    // ==== FUNCTION transmit_slot_page
+    // =============================================================================
+    // first page of a transmit slot in the local buffer of the nic
+    // =============================================================================
+    fn transmit_slot_page(slot: usize) -> u8 {
+        TRANSMIT_START_PAGE + slot as u8 * TRANSMIT_SLOT_PAGES
+    }
+
+    // =============================================================================
+    // ==== FUNCTION start_transmit
+    // =============================================================================
+    // tell the nic to send the frame, which has been uploaded into the given slot
+    // =============================================================================
+    fn start_transmit(&self, registers: &mut Registers, slot: usize) {
+        let packet_length = self.tx_slot_length[slot].load(Ordering::Relaxed);
+        self.tx_active_slot.store(slot, Ordering::Relaxed);
+        unsafe {
             // Set TBCR Bits before Transmit and TPSR Bit
             // TBCR0,1 : registers indicate the length of the packet
             // to be transmitted in bytes
             // TPSR: Transmit Page Start Register,
             // points to the assembled packet to be transmitted
-            self.registers.page0.tbcr_0_port_p0.write(low);
-            self.registers.page0.tbcr_1_port_p0.write(high);
-            self.registers.page0.tpsr_port.write(TRANSMIT_START_PAGE);
+            registers.page0.tbcr_0_port_p0.write((packet_length & 0xFF) as u8);
+            registers.page0.tbcr_1_port_p0.write((packet_length >> 8) as u8);
+            registers.page0.tpsr_port.write(Self::transmit_slot_page(slot));
 
-            //==== STEP 9 ====//
             // disable remote read, start nic, set TXP Bit in CR to send packet
             // during transmission the nic writes data into the fifo
             // transmit serializer reads the data from the fifo and transmits it
-            self.registers.command_port.write((CR::STA | CR::TXP | CR::STOP_DMA | CR::PAGE_0).bits());
+            registers.command_port.write((CR::STA | CR::TXP | CR::STOP_DMA | CR::PAGE_0).bits());
+        }
+    }
+
+    // =============================================================================
+    // ==== FUNCTION finish_transmit
+    // =============================================================================
+    // gets called by the network thread after a PTX interrupt (see process_interrupts())
+    // and by send_packet()
+    // if the frame on the wire has been sent (TXP Bit cleared), free its slot,
+    // count it (or the error of the transmission) and start the frame, which waits in the other slot
+    // =============================================================================
+    pub fn finish_transmit(&self) {
+        self.finish_transmit_locked(&mut self.registers.lock());
+    }
+
+    // finish_transmit() for callers, which hold the lock of the registers already
+    fn finish_transmit_locked(&self, registers: &mut Registers) {
+        let slot = self.tx_active_slot.load(Ordering::Relaxed);
+        if slot == TRANSMIT_IDLE {
+            return;
+        }
+        // the TXP Bit stays set until the transmission has been completed or aborted
+        if unsafe { CR::from_bits_retain(registers.command_port.read()).contains(CR::TXP) } {
+            return;
+        }
+
+        let packet_length = self.tx_slot_length[slot].swap(0, Ordering::Relaxed);
+        self.tx_active_slot.store(TRANSMIT_IDLE, Ordering::Relaxed);
+        // the TSR and NCR describe the last transmission, until the next one is started
+        let (status, collisions) = unsafe { (registers.page0.tsr_port.read(), registers.page0.ncr_port.read()) };
+        self.stats
+            .transmit_status(TransmitStatusRegister::from_bits_retain(status), collisions, packet_length as usize);
+
+        // frames are uploaded alternately, so a waiting frame is always in the next slot
+        let next = (slot + 1) % TRANSMIT_SLOTS;
+        if self.tx_slot_length[next].load(Ordering::Relaxed) != 0 {
+            self.start_transmit(registers, next);
         }
     }
 
@@ -675,35 +848,45 @@ impl Ne2000 {
     //            │                              │                                  │
     //
     // =============================================================================
-    pub fn receive_packet(&mut self) {
+    pub fn receive_packet(&self) {
+        self.receive_packet_locked(&mut self.registers.lock());
+    }
+
+    // receive_packet() for callers, which hold the lock of the registers already
+    fn receive_packet_locked(&self, registers: &mut Registers) {
         unsafe {
             //==== Step 1 ===================================================================//
             // Read the CURR Register and save the value in the variable current
             // switch to page 1 to read curr register
+            // interrupts are disabled while page 1 is selected, because the interrupt handler
+            // expects page 0 (see apply_receive_filter)
             //===============================================================================//
-            self.registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_1).bits());
+            let mut current = interrupts::without_interrupts(|| {
+                registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_1).bits());
 
-            // Read current register to prepare for the next packet
-            let mut current = self.registers.page1.current_port.read();
+                // Read current register to prepare for the next packet
+                let current = registers.page1.current_port.read();
 
-            // switch back to Page 0
-            self.registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_0).bits());
+                // switch back to Page 0
+                registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_0).bits());
+                current
+            });
 
             //==== Step 2 ===================================================================//
             // set rbcr and rsar registers for read operation of the header,
             //===============================================================================//
             // as long as packets are there to be processed, loop
-            while current != CURRENT_NEXT_PAGE_POINTER.load(Ordering::Relaxed) {
+            while current != self.next_page_pointer.load(Ordering::Relaxed) {
                 // write size of header
-                self.registers.page0.rbcr_0_port.write(mem::size_of::<PacketHeader>() as u8);
-                self.registers.page0.rbcr_1_port.write(0);
+                registers.page0.rbcr_0_port.write(mem::size_of::<PacketHeader>() as u8);
+                registers.page0.rbcr_1_port.write(0);
 
                 // set rsar to address of the next page
-                self.registers.page0.rsar_0_port.write(0);
-                self.registers.page0.rsar_1_port.write(CURRENT_NEXT_PAGE_POINTER.load(Ordering::Relaxed));
+                registers.page0.rsar_0_port.write(0);
+                registers.page0.rsar_1_port.write(self.next_page_pointer.load(Ordering::Relaxed));
 
                 // enable remote Read
-                self.registers.command_port.write((CR::STA | CR::REMOTE_READ | CR::PAGE_0).bits());
+                registers.command_port.write((CR::STA | CR::REMOTE_READ | CR::PAGE_0).bits());
 
                 // build the PacketHeader struct from the buffer ring
                 // the nic always stores a packet header at the beginning of the first
@@ -712,14 +895,14 @@ impl Ne2000 {
                 // Reference: p.8, Section "Beginning of Reception",
                 // https://web.archive.org/web/20010612150713/http://www.national.com/ds/DP/DP8390D.pdf
                 let packet_header = PacketHeader {
-                    receive_status: self.registers.data_port.read() as u8,
-                    next_packet: self.registers.data_port.read() as u8,
+                    receive_status: registers.data_port.read() as u8,
+                    next_packet: registers.data_port.read() as u8,
                     length: {
                         // Read the first byte (u8)
-                        let low_byte = self.registers.data_port.read() as u16;
+                        let low_byte = registers.data_port.read() as u16;
 
                         // Read the second byte (u8), shift it by 8 bits to form the higher part of the length
-                        let high_byte = self.registers.data_port.read() as u16;
+                        let high_byte = registers.data_port.read() as u16;
 
                         // Combine the two bytes to form the full length (in u16)
                         let length_u16 = (high_byte << 8) | low_byte;
@@ -734,6 +917,17 @@ impl Ne2000 {
                     },
                 };
 
+                // a next packet pointer outside of the receive ring means the header is garbage
+                // (e.g. the ring has been overwritten), reading further would hand corrupted frames to smoltcp
+                // -> drop everything up to CURR and continue with an empty ring
+                if !(RECEIVE_START_PAGE..RECEIVE_STOP_PAGE).contains(&packet_header.next_packet) {
+                    self.stats.receive_error();
+                    self.next_page_pointer.store(current, Ordering::Relaxed);
+                    let bnry = if current == RECEIVE_START_PAGE { RECEIVE_STOP_PAGE - 1 } else { current - 1 };
+                    registers.page0.bnry_port.write(bnry);
+                    break;
+                }
+
                 //==== Step 3 ===================================================================//
                 // check received packet
                 // rust doesn't treat integers as boolean in an if clause, so a comparison has to be made
@@ -759,47 +953,59 @@ impl Ne2000 {
                     // get an empty packet from the receive_buffers_empty queue for
                     // saving the data
                     // 0 is the Receiver
-                    let mut packet = self.receive_buffers_empty.0.try_dequeue().expect("Error dequeuing");
-
-                    let packet_length: u16 = packet_header.length as u16;
-
-                    // Write packet length into RBCR
-                    // size of the length field in the PacketHeader is u16
-                    // rbcr0 + rbcr1 = 16 Byte
-                    // split in low and high order bytes
-                    // 16 Bit value 0 - 65 535
-                    // if counter reaches 0 -> set RDC bit in ISR
-                    // mask off the lower 8 bits of packet_length and store them in RBCR0.
-                    self.registers.page0.rbcr_0_port.write((packet_length & 0xFF) as u8);
-
-                    //self.registers.rbcr1.write(packet_header.length >> 8);
-                    // shift 8 bits to the right, only the high byte remains
-                    // fix overflow warning
-                    self.registers.page0.rbcr_1_port.write((packet_length >> 8) as u8);
-
-                    // Remote Start Address Register: points to the
-                    // start of the block of data to be transferred
-                    // load nic header length into the registers to skip the
-                    // packet header during the read operation
-                    self.registers.page0.rsar_0_port.write(size_of::<PacketHeader>() as u8);
-
-                    self.registers.page0.rsar_1_port.write(CURRENT_NEXT_PAGE_POINTER.load(Ordering::Relaxed));
-
-                    //==== Step 4 ===================================================================//
-                    // issue remote read operation for reading the packet from the nics local buffer
-                    //===============================================================================//
-                    self.registers.command_port.write((CR::STA | CR::REMOTE_READ | CR::PAGE_0).bits());
-
-                    // Read Packet Data from I/O Port and write it into packet
-                    //self.registers.data_port.read() as u8;
-                    for i in 0..packet_header.length {
-                        // slice indices must be of type usize
-                        packet[i as usize] = self.registers.data_port.read();
+                    // if there is none left, smoltcp doesn't keep up -> drop the packet
+                    match self.receive_buffers_empty.0.try_dequeue() {
+                        Ok(mut packet) => {
+                            let packet_length: u16 = packet_header.length as u16;
+
+                            // Write packet length into RBCR
+                            // size of the length field in the PacketHeader is u16
+                            // rbcr0 + rbcr1 = 16 Byte
+                            // split in low and high order bytes
+                            // 16 Bit value 0 - 65 535
+                            // if counter reaches 0 -> set RDC bit in ISR
+                            // mask off the lower 8 bits of packet_length and store them in RBCR0.
+                            registers.page0.rbcr_0_port.write((packet_length & 0xFF) as u8);
+
+                            //registers.rbcr1.write(packet_header.length >> 8);
+                            // shift 8 bits to the right, only the high byte remains
+                            // fix overflow warning
+                            registers.page0.rbcr_1_port.write((packet_length >> 8) as u8);
+
+                            // Remote Start Address Register: points to the
+                            // start of the block of data to be transferred
+                            // load nic header length into the registers to skip the
+                            // packet header during the read operation
+                            registers.page0.rsar_0_port.write(size_of::<PacketHeader>() as u8);
+
+                            registers.page0.rsar_1_port.write(self.next_page_pointer.load(Ordering::Relaxed));
+
+                            //==== Step 4 ===================================================================//
+                            // issue remote read operation for reading the packet from the nics local buffer
+                            //===============================================================================//
+                            registers.command_port.write((CR::STA | CR::REMOTE_READ | CR::PAGE_0).bits());
+
+                            // Read Packet Data from I/O Port and write it into packet
+                            //registers.data_port.read() as u8;
+                            for i in 0..packet_header.length {
+                                // slice indices must be of type usize
+                                packet[i as usize] = registers.data_port.read();
+                            }
+
+                            // the byte count in the header includes 4 bytes, which don't belong to the frame
+                            // (saturating, so that a garbage count can't overflow)
+                            let frame_length = (packet_length as usize).saturating_sub(size_of::<PacketHeader>());
+
+                            // enqueue the packet in the receive_messages queue,
+                            //this queue gets processed by receive in smoltcp
+                            self.receive_messages.1.try_enqueue((packet, frame_length)).expect("Error enqueuing packet");
+                            self.stats.received(frame_length);
+                        }
+                        Err(_) => self.stats.dropped(),
                     }
-
-                    // enqueue the packet in the receive_messages queue,
-                    //this queue gets processed by receive in smoltcp
-                    self.receive_messages.1.try_enqueue(packet).expect("Error enqueuing packet");
+                } else {
+                    // packet is damaged or too large, discard it
+                    self.stats.receive_error();
                 }
 
                 //==== Step 5 ===================================================================//
@@ -808,44 +1014,47 @@ impl Ne2000 {
 
                 // Avoid buffer overflow (case where BNRY = CURR), ring full
                 // updates the pointer to the next packet, which has to be processed
-                CURRENT_NEXT_PAGE_POINTER.store(packet_header.next_packet, Ordering::Relaxed);
+                self.next_page_pointer.store(packet_header.next_packet, Ordering::Relaxed);
                 // wrap around case : next_packet == pstart, make sure bnry points to a page inside the receive buffer
                 // PSTOP -1 : last valid page
                 // FIX Overflow when subtracting
                 let prev_page = packet_header.next_packet.wrapping_sub(1);
                 if (prev_page) < RECEIVE_START_PAGE {
-                    self.registers.page0.bnry_port.write(RECEIVE_STOP_PAGE - 1);
+                    registers.page0.bnry_port.write(RECEIVE_STOP_PAGE - 1);
                 } else {
                     // update the Boundary Pointer, points to the first packet in the ring not yet read by
                     // indicates that the previous packet has been read
                     // the host
                     // normal case: update bndy to point to one page behind the packet the nic will fill next
                     // tells the nic that the pages which have just been processed are free
-                    let cur_value = CURRENT_NEXT_PAGE_POINTER.load(Ordering::Relaxed);
+                    let cur_value = self.next_page_pointer.load(Ordering::Relaxed);
                     let bnry = if cur_value == RECEIVE_START_PAGE {
                         RECEIVE_STOP_PAGE - 1
                     } else {
                         cur_value - 1 // safe: next > RECEIVE_START_PAGE
                     };
-                    self.registers.page0.bnry_port.write(bnry);
+                    registers.page0.bnry_port.write(bnry);
                 }
 
                 // update the current variable for the next page to be written to
-                //switch to Page 1, stop dma operations, start nic
-                self.registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_1).bits());
-
-                // read new current value
-                // points to the first buffer used to store store a packet
-                current = self.registers.page1.current_port.read();
-
-                // go back to page 0
-                self.registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_0).bits());
+                //switch to Page 1, stop dma operations, start nic (without interrupts, see Step 1)
+                current = interrupts::without_interrupts(|| {
+                    registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_1).bits());
+
+                    // read new current value
+                    // points to the first buffer used to store store a packet
+                    let current = registers.page1.current_port.read();
+
+                    // go back to page 0
+                    registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_0).bits());
+                    current
+                });
             }
 
             //==== Step 6 ===================================================================//
             // clear the RDC Interrupt Bit in the ISR (Remote DMA Operation has been completed)
             //===============================================================================//
-            self.registers.isr_port.lock().write(InterruptStatusRegister::ISR_RDC.bits());
+            self.shared_registers.isr_port.lock().write(InterruptStatusRegister::ISR_RDC.bits());
         }
     }
 
@@ -871,29 +1080,95 @@ impl Ne2000 {
         // Both byte and word accesses are allowed.
         // The first 16 bytes contains the MAC address at even locations,
 
-        unsafe {
-            // switch to page 1 to access PAR 0..5
-            // stop the nic
-            // disable remote dma
-            let mut registers = Registers::new(self.base_address);
+        // switch to page 1 to access PAR 0..5
+        // stop the nic
+        // disable remote dma
+        // interrupts are disabled while page 1 is selected, because the interrupt handler
+        // expects page 0 (see apply_receive_filter)
+        let mut registers = self.registers.lock();
+        interrupts::without_interrupts(|| unsafe {
             registers.command_port.write((CR::STOP | CR::PAGE_1).bits());
 
             // read 6 bytes (MAC address)
             // save the values of the PAR Registers in mac
-            for (i, guard) in self.registers.page1.par.iter().enumerate() {
+            for (i, guard) in self.shared_registers.par.iter().enumerate() {
                 let mut port = guard.lock();
                 mac[i] = port.read();
             }
 
             // start the nic
             registers.command_port.write((CR::STA | CR::PAGE_0).bits());
-        }
+        });
         // convert the data in the mac array to type EthernetAddress
         let mac_address = EthernetAddress::from_bytes(&mac);
         // return the actual MAC Address
         mac_address
     }
 
+    // =============================================================================
+    // ==== FUNCTION receive_configuration
+    // =============================================================================
+    // value for the RCR
+    // RCR_AR : allow RUNT Packets (Packets < 64 Btytes)
+    // RCR_AB : allow Broadcast Packets
+    // RCR_AM : allow Multicast Packets, which pass the hash filter in MAR0-MAR7
+    // RCR_PRO : allow all Packets (promiscuous mode)
+    // =============================================================================
+    fn receive_configuration(&self) -> ReceiveConfigurationRegister {
+        let mut configuration = ReceiveConfigurationRegister::RCR_AR | ReceiveConfigurationRegister::RCR_AB | ReceiveConfigurationRegister::RCR_AM;
+        if self.promiscuous.load(Ordering::Relaxed) {
+            configuration |= ReceiveConfigurationRegister::RCR_PRO;
+        }
+        configuration
+    }
+
+    // =============================================================================
+    // ==== FUNCTIONS set_multicast_filter, set_promiscuous
+    // =============================================================================
+    // only accept multicast frames for the given addresses / accept all frames
+    // the registers can't be written here, because the network thread might be using
+    // the nic at the same time, so the filter is written by apply_receive_filter(),
+    // which is called by the network thread (see process_interrupts())
+    // =============================================================================
+    pub fn set_multicast_filter(&self, addresses: &[EthernetAddress]) {
+        for (register, value) in self.multicast_filter.iter().zip(multicast_filter(addresses)) {
+            register.store(value, Ordering::Relaxed);
+        }
+        self.receive_filter_changed.store(true, Ordering::Release);
+    }
+
+    pub fn set_promiscuous(&self, enabled: bool) {
+        self.promiscuous.store(enabled, Ordering::Relaxed);
+        self.receive_filter_changed.store(true, Ordering::Release);
+    }
+
+    // =============================================================================
+    // ==== FUNCTION apply_receive_filter
+    // =============================================================================
+    // write the filter from set_multicast_filter() and set_promiscuous() into the RCR (page 0) and MAR0-MAR7 (page 1)
+    // interrupts are disabled while page 1 is selected, because the interrupt handler
+    // expects page 0
+    // =============================================================================
+    pub fn apply_receive_filter(&self) {
+        if !self.receive_filter_changed.swap(false, Ordering::Acquire) {
+            return;
+        }
+        let receive_configuration = self.receive_configuration();
+        let filter: [u8; 8] = core::array::from_fn(|i| self.multicast_filter[i].load(Ordering::Relaxed));
+
+        let mut registers = self.registers.lock();
+        interrupts::without_interrupts(|| unsafe {
+            registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_0).bits());
+            registers.page0.rcr_port.write(receive_configuration.bits());
+
+            registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_1).bits());
+            for (port, value) in registers.page1.mar.iter_mut().zip(filter) {
+                port.write(value);
+            }
+            registers.command_port.write((CR::STA | CR::STOP_DMA | CR::PAGE_0).bits());
+        });
+    }
+
     // =============================================================================
     // ==== FUNCTION handle_overflow
     // =============================================================================
@@ -916,11 +1191,11 @@ impl Ne2000 {
      *    |  Step 2: CR = STOP | PAGE_0             |
      *    |------------------------------>          | Stop DMA + NIC
      *    |                                         |
-     *    |  Step 3: wait 1.6ms                     |
+     *    |  Step 3: busy wait 1.6ms (PIT)          |
      *    |  Step 4: RBCR0 = 0, RBCR1 = 0           |
      *    |                                         |
      *    |  Step 5: check resend logic             |
-     *    |   └─ if TXP == 1 and !PTX/TXE → resend  |
+     *    |   └─ if TXP set and !PTX/TXE → resend   |
      *    |                                         |
      *    |  Step 6: set loopback mode              |
      *    |  Step 7: CR = START                     |
@@ -930,36 +1205,43 @@ impl Ne2000 {
      *    |  Step 9: clear ISR.OVW                  |
      *    |  Step 10: clear loopback (TCR = 0)      |
      *    |                                         |
-     *    |  Step 11: if resend == 1:               |
+     *    |  Step 11: if resend:                    |
      *    |   └─ CR = STA | TXP | STOP_DMA          |
      *    |------------------------------>          | Reissue transmit
      *    |                                         |
-     *    |  return from ISR                        |
+     *    |  Step 12: unmask interrupts (IMR)       |
      */
+    // Note: this runs on the network thread (see process_interrupts()), never in the ISR.
+    // The ISR only sets the ovw flag and leaves the interrupts of the nic masked, until the ring has been
+    // recovered. Step 3 used to call scheduler().sleep(1600), which sleeps 1.6 s instead of 1.6 ms,
+    // and step 5 compared the masked TXP bit against 1, so an interrupted transmission was never resent.
     // =============================================================================
-    pub fn handle_overflow(&mut self) {
+    pub fn handle_overflow(&self) {
+        let mut registers = self.registers.lock();
         unsafe {
             //==== Step 1 ===================================================================//
             // save the value of the TXP Bit in CR
             //===============================================================================//
-            let txp_bit = self.registers.command_port.read() & CR::TXP.bits();
+            let transmitting = registers.command_port.read() & CR::TXP.bits() != 0;
 
             //==== Step 2 ===================================================================//
             // Issue stop command, stop NIC and DMA
             //===============================================================================//
-            self.registers.command_port.write((CR::STOP | CR::PAGE_0).bits());
+            registers.command_port.write((CR::STOP | CR::PAGE_0).bits());
 
             //==== Step 3 ===================================================================//
             // wait for at least 1.6 ms according to the documentation,
             // until transmit or receive operation has ended
+            // busy wait on the PIT counter, sleep() only has a resolution of milliseconds
+            // and could return early or far too late
             //===============================================================================//
-            scheduler().sleep(1600);
+            timer().wait_us(STOP_COMPLETION_DELAY_US);
 
             //==== Step 4 ===================================================================//
             // Clear RBCR0 and RBCR1
             //===============================================================================//
-            self.registers.page0.rbcr_0_port.write(0);
-            self.registers.page0.rbcr_1_port.write(0);
+            registers.page0.rbcr_0_port.write(0);
+            registers.page0.rbcr_1_port.write(0);
 
             //==== Step 5 ===================================================================//
             // read value of TXP bit, check if there was a
@@ -969,50 +1251,48 @@ impl Ne2000 {
             //      if PTX or TXE = 1 -> resend = 0
             //      else resend 1
             //===============================================================================//
-            let mut resend = 0;
-            if txp_bit == 0 {
-                resend = 0;
-            }
-
-            if txp_bit == 1 {
-                if self.registers.read_isr() & (InterruptStatusRegister::ISR_PTX | InterruptStatusRegister::ISR_TXE).bits() != 0 {
-                    resend = 0
-                } else {
-                    resend = 1;
-                }
-            }
+            let resend = transmitting
+                && self.shared_registers.read_isr() & (InterruptStatusRegister::ISR_PTX | InterruptStatusRegister::ISR_TXE).bits() == 0;
 
             //==== Step 6 ===================================================================//
             // Place the nic in loopback mode 0
             //===============================================================================//
-            self.registers.page0.tcr_port.write(TransmitConfigurationRegister::TCR_LB0.bits());
+            registers.page0.tcr_port.write(TransmitConfigurationRegister::TCR_LB0.bits());
 
             //==== Step 7 ===================================================================//
             // Issue start command
             //===============================================================================//
-            self.registers.command_port.write((CR::STA | CR::PAGE_0).bits());
+            registers.command_port.write((CR::STA | CR::PAGE_0).bits());
 
             //==== Step 8 ===================================================================//
             // remove packets in the buffer
             //===============================================================================//
-            self.receive_packet();
+            self.receive_packet_locked(&mut registers);
 
             //==== Step 9 ===================================================================//
             // Reset Overwrite warning (OVW)
             //===============================================================================//
-            self.registers.isr_port.lock().write(InterruptStatusRegister::ISR_OVW.bits());
+            self.shared_registers.isr_port.lock().write(InterruptStatusRegister::ISR_OVW.bits());
+            self.stats.overflow();
 
             //==== Step 10 ===================================================================//
             // take nic out of loopback
             //===============================================================================//
-            self.registers.page0.tcr_port.write(0);
+            registers.page0.tcr_port.write(0);
 
             //==== Step 11 ===================================================================//
             // if resend = 1, reset variable, reissue transmit command
+            // (TPSR and TBCR still point to the frame in the active transmit slot)
             //===============================================================================//
-            if resend == 1 {
-                self.registers.command_port.write((CR::STA | CR::TXP | CR::STOP_DMA | CR::PAGE_0).bits());
+            if resend {
+                registers.command_port.write((CR::STA | CR::TXP | CR::STOP_DMA | CR::PAGE_0).bits());
             }
+
+            //==== Step 12 ===================================================================//
+            // unmask the interrupts again, the ISR left them disabled after the overflow
+            // interrupts which occured in the meantime are still pending in the ISR and fire now
+            //===============================================================================//
+            self.shared_registers.write_imr(ENABLED_INTERRUPTS.bits());
         }
     }
 
@@ -1052,17 +1332,17 @@ impl InterruptHandler for Ne2000InterruptHandler {
     fn trigger(&self) {
         // a mutex is required for IMR and ISR, because these registers are also used by the
         // transmit and receive function, and Init routine
-        if self.device.registers.isr_port.is_locked() {
+        if self.device.shared_registers.isr_port.is_locked() {
             //scheduler().sleep(1);
             panic!("Interrupt status register is locked during interrupt!");
         }
 
         // clear Interrupt Mask Register
         // disables interrupts
-        self.device.registers.write_imr(0);
+        self.device.shared_registers.write_imr(0);
 
         // Read interrupt status register (Each bit corresponds to an interrupt type or error)
-        let status_reg = self.device.registers.read_isr();
+        let status_reg = self.device.shared_registers.read_isr();
         let status = InterruptStatusRegister::from_bits_retain(status_reg);
 
         //////////////////////////////////////////////////////////////////////////
@@ -1081,7 +1361,7 @@ impl InterruptHandler for Ne2000InterruptHandler {
             // see : https://www.os2museum.com/wp/was-the-ne2000-really-that-bad/?utm_source=chatgpt.com
             unsafe {
                 // acknowledge the interrupt
-                self.device.registers.isr_port.lock().write(InterruptStatusRegister::ISR_PRX.bits());
+                self.device.shared_registers.isr_port.lock().write(InterruptStatusRegister::ISR_PRX.bits());
                 // set prx to true, this triggers the check() function in network/mod.rs
                 // which calls the receive_packets function and resets the value at the end
                 self.device.check_interrupts.prx.store(true, Ordering::Relaxed);
@@ -1096,9 +1376,11 @@ impl InterruptHandler for Ne2000InterruptHandler {
                 // which calls the receive_packets function and resets the value at the end
                 self.device.check_interrupts.ptx.store(true, Ordering::Relaxed);
                 // acknowledge the interrupt
-                self.device.registers.isr_port.lock().write(InterruptStatusRegister::ISR_PTX.bits());
+                self.device.shared_registers.isr_port.lock().write(InterruptStatusRegister::ISR_PTX.bits());
             }
             // free the allocated memory after sending the packet
+            // (the frames have already been copied into the transmit slots of the nic)
+            // the network thread starts the next waiting frame (see finish_transmit())
             // 0 : Receiver, manages the dequeuing and freeing of the buffers
             // 1 : Senders, write to the queue new packets
             let mut queue = self.device.send_queue.0.lock();
@@ -1111,20 +1393,90 @@ impl InterruptHandler for Ne2000InterruptHandler {
             }
         }
 
+        // check for a transmit error (TXE), the frame has been aborted
+        // this completes the transmission as well, the network thread reads the TSR
+        // and starts the next frame (see finish_transmit())
+        if status.contains(InterruptStatusRegister::ISR_TXE) {
+            self.device.check_interrupts.ptx.store(true, Ordering::Relaxed);
+            unsafe { self.device.shared_registers.isr_port.lock().write(InterruptStatusRegister::ISR_TXE.bits()) };
+        }
+
+        // check for a receive error (RXE) or a counter overflow (CNT)
+        // crc errors, frame alignment errors and missed packets are counted by the tally counters,
+        // read them before they saturate (reading clears them and the CNT condition)
+        if status.intersects(InterruptStatusRegister::ISR_RXE | InterruptStatusRegister::ISR_CNT) {
+            if status.contains(InterruptStatusRegister::ISR_RXE) {
+                let receive_status = ReceiveStatusRegister::from_bits_retain(self.device.shared_registers.read_rsr());
+                self.device.stats.receive_status(receive_status);
+            }
+            let [frame_errors, crc_errors, missed] = self.device.shared_registers.read_tally_counters();
+            self.device.stats.tally(frame_errors, crc_errors, missed);
+            unsafe {
+                self.device
+                    .shared_registers
+                    .isr_port
+                    .lock()
+                    .write(status_reg & (InterruptStatusRegister::ISR_RXE | InterruptStatusRegister::ISR_CNT).bits())
+            };
+        }
+
         // check for a buffer overflow, OVW Bit set ?
+        // OVW is only acknowledged by handle_overflow(), so the interrupts stay masked
+        // until the network thread has recovered the ring, otherwise the interrupt would fire again immediately
         if status.contains(InterruptStatusRegister::ISR_OVW) {
             // set ovw to true, this triggers the check() function in network/mod.rs
             // which calls the handle_overflow function and resets the value at the end
             self.device.check_interrupts.ovw.store(true, Ordering::Relaxed);
+        } else {
+            // re-enable Interrupts (22.07.2025)
+            unsafe {
+                self.device
+                    .shared_registers
+                    .imr_port
+                    .lock()
+                    .write(ENABLED_INTERRUPTS.bits());
+            }
         }
 
-        // re-enable Interrupts (22.07.2025)
-        unsafe {
-            self.device
-                .registers
-                .imr_port
-                .lock()
-                .write((InterruptMaskRegister::IMR_PRXE | InterruptMaskRegister::IMR_PTXE | InterruptMaskRegister::IMR_OVWE).bits());
+        // the flags set above are handled by the network thread (see process_interrupts())
+        network::notify_worker();
+    }
+}
+
+// =============================================================================
+// ==== FUNCTION multicast_filter
+// =============================================================================
+// compute the values for MAR0-MAR7 for a list of multicast addresses
+// the nic computes the crc of the destination address of every multicast frame,
+// the upper 6 bits of the crc select one of the 64 bits in MAR0-MAR7
+// (bits 31-29: register, bits 28-26: bit in the register)
+// the frame is accepted, if this bit is set
+// Reference: p.17, Section "Multicast Address Registers",
+// https://web.archive.org/web/20010612150713/http://www.national.com/ds/DP/DP8390D.pdf
+// =============================================================================
+fn multicast_filter(addresses: &[EthernetAddress]) -> [u8; 8] {
+    let mut filter = [0u8; 8];
+    for address in addresses {
+        let crc = multicast_crc(address);
+        filter[(crc >> 29) as usize] |= 1 << ((crc >> 26) & 0x07);
+    }
+    filter
+}
+
+// CRC-32 of the address, shifted in least significant bit first (like the nic does it)
+fn multicast_crc(address: &EthernetAddress) -> u32 {
+    const POLYNOMIAL: u32 = 0x04C1_1DB7;
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in address.as_bytes() {
+        let mut octet = byte;
+        for _ in 0..8 {
+            let carry = (crc >> 31) ^ (octet & 0x01) as u32;
+            crc <<= 1;
+            if carry != 0 {
+                crc ^= POLYNOMIAL;
+            }
+            octet >>= 1;
         }
     }
+    crc
 }
@@ -0,0 +1,151 @@
+// =============================================================================
+// FILE        : stats.rs
+// AUTHOR      : Johann Spenrath <johann.spenrath@hhu.de>
+// DESCRIPTION : traffic and error counters of the NE2000
+// =============================================================================
+// NOTES:
+// - the common counters (packets, bytes, errors, drops) are kept in a
+//   StatsCounters struct, like in the other drivers (see device/nic.rs)
+// - additionally the errors, which the DP8390 reports through the
+//   tally counters (CNTR0-2), TSR and RSR and the receive buffer
+//   overflows are counted
+// - the counters are atomic, because they are updated by the interrupt
+//   handler and by the network thread
+// Reference: p.24-26, https://web.archive.org/web/20010612150713/http://www.national.com/ds/DP/DP8390D.pdf
+// =============================================================================
+// DEPENDENCIES:
+// =============================================================================
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::consts::{ReceiveStatusRegister, TransmitStatusRegister};
+use crate::device::nic::{NetworkStats, StatsCounters};
+
+// =============================================================================
+// ==== STRUCT Ne2000Stats
+// =============================================================================
+#[derive(Default)]
+pub struct Ne2000Stats {
+    // packets, bytes, errors and drops
+    common: StatsCounters,
+    // OVW interrupts (receive ring full)
+    rx_overflows: AtomicU64,
+    // CNTR1
+    rx_crc_errors: AtomicU64,
+    // CNTR0
+    rx_frame_errors: AtomicU64,
+    // RSR_FO
+    rx_fifo_errors: AtomicU64,
+    // CNTR2, packets which didn't fit into the receive ring
+    rx_missed: AtomicU64,
+    // NCR, number of collisions during transmission
+    tx_collisions: AtomicU64,
+    // TSR_ABT, aborted after 16 collisions
+    tx_aborted: AtomicU64,
+    // TSR_FU
+    tx_fifo_errors: AtomicU64,
+    // TSR_CRS
+    tx_carrier_errors: AtomicU64,
+}
+
+impl Ne2000Stats {
+    // a packet has been copied out of the receive ring
+    pub fn received(&self, len: usize) {
+        self.common.received(len);
+    }
+
+    // a damaged packet has been found in the receive ring
+    pub fn receive_error(&self) {
+        self.common.receive_error();
+    }
+
+    // a packet has been received, but there was no empty buffer left for it
+    pub fn dropped(&self) {
+        self.common.dropped();
+    }
+
+    // the receive ring has been full (OVW)
+    pub fn overflow(&self) {
+        self.rx_overflows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // =============================================================================
+    // ==== FUNCTION tally
+    // =============================================================================
+    // add the values of the tally counters CNTR0 (frame alignment errors),
+    // CNTR1 (crc errors) and CNTR2 (missed packets)
+    // the counters are cleared by the nic when they are read
+    // =============================================================================
+    pub fn tally(&self, frame_errors: u8, crc_errors: u8, missed: u8) {
+        self.rx_frame_errors.fetch_add(frame_errors as u64, Ordering::Relaxed);
+        self.rx_crc_errors.fetch_add(crc_errors as u64, Ordering::Relaxed);
+        self.rx_missed.fetch_add(missed as u64, Ordering::Relaxed);
+    }
+
+    // =============================================================================
+    // ==== FUNCTION receive_status
+    // =============================================================================
+    // gets called after a receive error interrupt (RXE) with the value of the RSR
+    // crc and frame alignment errors are already counted by the tally counters,
+    // only the fifo overrun has no counter
+    // =============================================================================
+    pub fn receive_status(&self, status: ReceiveStatusRegister) {
+        if status.contains(ReceiveStatusRegister::RSR_FO) {
+            self.rx_fifo_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // =============================================================================
+    // ==== FUNCTION transmit_status
+    // =============================================================================
+    // gets called after the nic has finished a frame with the value of the TSR
+    // and the number of collisions (NCR)
+    // =============================================================================
+    pub fn transmit_status(&self, status: TransmitStatusRegister, collisions: u8, len: usize) {
+        self.tx_collisions.fetch_add(collisions as u64, Ordering::Relaxed);
+        if status.contains(TransmitStatusRegister::TSR_PTX) {
+            self.common.transmitted(len);
+            return;
+        }
+
+        // the frame has not been sent
+        self.common.transmit_error();
+        if status.contains(TransmitStatusRegister::TSR_ABT) {
+            self.tx_aborted.fetch_add(1, Ordering::Relaxed);
+        }
+        if status.contains(TransmitStatusRegister::TSR_FU) {
+            self.tx_fifo_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if status.contains(TransmitStatusRegister::TSR_CRS) {
+            self.tx_carrier_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // =============================================================================
+    // ==== FUNCTION snapshot
+    // =============================================================================
+    // crc, frame and fifo errors count as receive errors,
+    // missed packets count as dropped packets
+    // =============================================================================
+    pub fn snapshot(&self) -> NetworkStats {
+        let common = self.common.snapshot();
+        let rx_crc_errors = self.rx_crc_errors.load(Ordering::Relaxed);
+        let rx_frame_errors = self.rx_frame_errors.load(Ordering::Relaxed);
+        let rx_fifo_errors = self.rx_fifo_errors.load(Ordering::Relaxed);
+        let rx_missed = self.rx_missed.load(Ordering::Relaxed);
+
+        NetworkStats {
+            rx_errors: common.rx_errors + rx_crc_errors + rx_frame_errors + rx_fifo_errors,
+            rx_dropped: common.rx_dropped + rx_missed,
+            rx_overflows: self.rx_overflows.load(Ordering::Relaxed),
+            rx_crc_errors,
+            rx_frame_errors,
+            rx_fifo_errors,
+            rx_missed,
+            tx_collisions: self.tx_collisions.load(Ordering::Relaxed),
+            tx_aborted: self.tx_aborted.load(Ordering::Relaxed),
+            tx_fifo_errors: self.tx_fifo_errors.load(Ordering::Relaxed),
+            tx_carrier_errors: self.tx_carrier_errors.load(Ordering::Relaxed),
+            ..common
+        }
+    }
+}
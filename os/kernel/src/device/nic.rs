@@ -0,0 +1,327 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU64, Ordering};
+use log::info;
+use pci_types::EndpointHeader;
+use smoltcp::phy;
+use smoltcp::phy::{DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+use smoltcp::wire::EthernetAddress;
+use spin::RwLock;
+use x86_64::PhysAddr;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::structures::paging::frame::PhysFrameRange;
+
+use crate::device::e1000;
+use crate::device::e1000::E1000;
+use crate::device::ne2k;
+use crate::device::ne2k::ne2000::Ne2000;
+use crate::device::rtl8139::Rtl8139;
+use crate::device::virtio_net;
+use crate::device::virtio_net::VirtioNet;
+use crate::memory::{PAGE_SIZE, vmm};
+use crate::network::capture;
+use crate::network::packet;
+use crate::pci_bus;
+
+/// Common interface of all network card drivers.
+///
+/// The network stack only talks to network cards through this trait,
+/// so adding a new card only requires implementing it and adding an entry to `DRIVERS`.
+pub trait NetworkDevice: Send + Sync {
+    fn mac_address(&self) -> EthernetAddress;
+
+    /// Returns whether a cable is plugged in (cards without link detection always report `true`).
+    fn link_up(&self) -> bool {
+        true
+    }
+
+    fn stats(&self) -> NetworkStats;
+
+    fn capabilities(&self) -> DeviceCapabilities;
+
+    /// Only accept multicast frames, that are sent to one of `addresses` (broadcasts are always accepted).
+    /// Cards without a multicast filter keep accepting all multicast frames.
+    fn set_multicast_filter(&self, _addresses: &[EthernetAddress]) {}
+
+    /// Accept all frames, regardless of their destination (e.g. for capturing packets).
+    /// Returns `false`, if the card doesn't support this.
+    fn set_promiscuous(&self, _enabled: bool) -> bool {
+        false
+    }
+
+    /// Register the interrupt handler of the card.
+    fn plugin(self: Arc<Self>);
+
+    /// Do the work, that the interrupt handler has deferred (e.g. copying packets out of the card).
+    /// This is called regularly by the network thread, outside of interrupt context.
+    fn process_interrupts(&self) {}
+
+    /// Take the next received frame out of the receive queue.
+    /// Returns the buffer together with the length of the frame in it (the buffer may be larger).
+    fn receive(&self) -> Option<(Vec<u8, PacketAllocator>, usize)>;
+
+    /// Give a buffer returned by `receive()` back to the driver, after the frame has been processed.
+    fn recycle(&self, buffer: Vec<u8, PacketAllocator>);
+
+    /// Send a frame of `len` bytes. `fill` is called with the transmit buffer and writes the frame into it.
+    fn transmit(&self, len: usize, fill: &mut dyn FnMut(&mut [u8]));
+}
+
+/// Traffic counters of a network card.
+///
+/// The detailed error counters (starting with `rx_overflows`) are only filled by cards,
+/// that report them (currently the NE2000), all other cards leave them at 0.
+/// The layout is handed to user space as is (see `sys_get_network_stats`), so it must match library/network.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct NetworkStats {
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub rx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    pub tx_errors: u64,
+    /// Receive buffer overflows
+    pub rx_overflows: u64,
+    pub rx_crc_errors: u64,
+    pub rx_frame_errors: u64,
+    pub rx_fifo_errors: u64,
+    /// Frames, that the card had no room for
+    pub rx_missed: u64,
+    pub tx_collisions: u64,
+    pub tx_aborted: u64,
+    pub tx_fifo_errors: u64,
+    pub tx_carrier_errors: u64,
+}
+
+/// Counters, which drivers can update from any context (including interrupt handlers).
+#[derive(Default)]
+pub struct StatsCounters {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    rx_errors: AtomicU64,
+    rx_dropped: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+    tx_errors: AtomicU64,
+}
+
+impl StatsCounters {
+    pub fn received(&self, len: usize) {
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        self.rx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub fn receive_error(&self) {
+        self.rx_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A packet has been received correctly, but there was no buffer left to store it.
+    pub fn dropped(&self) {
+        self.rx_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn transmitted(&self, len: usize) {
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        self.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub fn transmit_error(&self) {
+        self.tx_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> NetworkStats {
+        NetworkStats {
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            rx_errors: self.rx_errors.load(Ordering::Relaxed),
+            rx_dropped: self.rx_dropped.load(Ordering::Relaxed),
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+            tx_errors: self.tx_errors.load(Ordering::Relaxed),
+            ..NetworkStats::default()
+        }
+    }
+}
+
+/// Entry in the driver table.
+pub struct NicDriver {
+    pub name: &'static str,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub init: fn(&RwLock<EndpointHeader>) -> Arc<dyn NetworkDevice>,
+}
+
+/// All supported network cards, identified by their PCI vendor and device ID.
+static DRIVERS: &[NicDriver] = &[
+    NicDriver {
+        name: "Realtek RTL8139",
+        vendor_id: 0x10ec,
+        device_id: 0x8139,
+        init: |pci_device| Arc::new(Rtl8139::new(pci_device)),
+    },
+    // References:
+    // - https://en.wikibooks.org/wiki/QEMU/Devices/Network -> the nic model
+    // - https://theretroweb.com/chips/4692 -> device id and vendor id
+    NicDriver {
+        name: "Realtek 8029 (NE2000)",
+        vendor_id: ne2k::consts::VENDOR_ID,
+        device_id: ne2k::consts::DEVICE_ID,
+        init: |pci_device| Arc::new(Ne2000::new(pci_device)),
+    },
+    NicDriver {
+        name: "virtio-net",
+        vendor_id: virtio_net::VENDOR_ID,
+        device_id: virtio_net::DEVICE_ID,
+        init: |pci_device| Arc::new(VirtioNet::new(pci_device)),
+    },
+    NicDriver {
+        name: "Intel 82540EM (e1000)",
+        vendor_id: e1000::VENDOR_ID,
+        device_id: e1000::DEVICE_ID,
+        init: |pci_device| Arc::new(E1000::new(pci_device)),
+    },
+];
+
+/// Search the PCI bus for supported network cards, initialize them and register their interrupt handlers.
+/// Every card is returned together with the name of its driver.
+pub fn probe() -> Vec<(&'static str, Arc<dyn NetworkDevice>)> {
+    let mut devices = Vec::new();
+    for driver in DRIVERS {
+        for pci_device in pci_bus().search_by_ids(driver.vendor_id, driver.device_id) {
+            info!("Found {} network controller", driver.name);
+            let device = (driver.init)(pci_device);
+            info!("{} MAC address: [{}]", driver.name, device.mac_address());
+
+            Arc::clone(&device).plugin();
+            devices.push((driver.name, device));
+        }
+    }
+
+    devices
+}
+
+/// Allocator for receive buffers, which consist of exactly one page frame.
+/// Drivers create these buffers with `Vec::from_raw_parts_in()`, so allocating is not supported.
+#[derive(Default)]
+pub struct PacketAllocator;
+
+unsafe impl Allocator for PacketAllocator {
+    fn allocate(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        panic!("PacketAllocator does not support allocate!");
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != PAGE_SIZE {
+            panic!("PacketAllocator may only be used with page frames!");
+        }
+
+        let start = PhysFrame::from_start_address(PhysAddr::new(ptr.as_ptr() as u64)).expect("PacketAllocator may only be used with page frames!");
+        unsafe {
+            vmm::free_frames(PhysFrameRange { start, end: start + 1 });
+        }
+    }
+}
+
+/// Lets smoltcp use any `NetworkDevice`.
+///
+/// All frames, that are received or sent through it, are offered to the packet capture (see `network::capture`),
+/// received Ethernet frames also to the packet sockets (see `network::packet`).
+pub struct SmoltcpDevice<'a> {
+    /// Name of the driver (see `DRIVERS`)
+    name: &'static str,
+    /// `Medium::Ip`, if the device sends plain IP packets instead of Ethernet frames (like the loopback device)
+    medium: Medium,
+    device: &'a dyn NetworkDevice,
+}
+
+pub struct NicTxToken<'a> {
+    name: &'static str,
+    medium: Medium,
+    device: &'a dyn NetworkDevice,
+}
+
+pub struct NicRxToken<'a> {
+    name: &'static str,
+    medium: Medium,
+    buffer: Vec<u8, PacketAllocator>,
+    /// Length of the frame in `buffer`
+    len: usize,
+    device: &'a dyn NetworkDevice,
+}
+
+impl<'a> SmoltcpDevice<'a> {
+    pub fn new(name: &'static str, device: &'a dyn NetworkDevice) -> Self {
+        Self { name, medium: device.capabilities().medium, device }
+    }
+}
+
+impl phy::TxToken for NicTxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut f = Some(f);
+        let mut result = None;
+        self.device.transmit(len, &mut |buffer| {
+            let f = f.take().expect("Transmit buffer has been filled twice!");
+            result = Some(f(buffer));
+            match self.medium {
+                Medium::Ip => capture::record_ip(self.name, buffer),
+                _ => capture::record(self.name, buffer),
+            }
+        });
+
+        result.expect("Driver did not fill the transmit buffer!")
+    }
+}
+
+impl phy::RxToken for NicRxToken<'_> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        let frame = &self.buffer[..self.len];
+        match self.medium {
+            Medium::Ip => capture::record_ip(self.name, frame),
+            _ => {
+                capture::record(self.name, frame);
+                packet::deliver(self.name, frame);
+            }
+        }
+        let result = f(frame);
+        self.device.recycle(self.buffer);
+        result
+    }
+}
+
+impl phy::Device for SmoltcpDevice<'_> {
+    type RxToken<'a>
+        = NicRxToken<'a>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = NicTxToken<'a>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let (buffer, len) = self.device.receive()?;
+        Some((
+            NicRxToken { name: self.name, medium: self.medium, buffer, len, device: self.device },
+            NicTxToken { name: self.name, medium: self.medium, device: self.device },
+        ))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(NicTxToken { name: self.name, medium: self.medium, device: self.device })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.device.capabilities()
+    }
+}
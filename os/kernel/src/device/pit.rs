@@ -162,7 +162,13 @@ impl Timer {
     }
 
     pub fn wait(&self, wait_time_ms: usize) {
-        let wait_time_ns = wait_time_ms * 1000000;
+        self.wait_us(wait_time_ms * 1000);
+    }
+
+    // Busy wait for the given number of microseconds by polling the PIT counter.
+    // Does not depend on timer interrupts, so it may be used with interrupts disabled.
+    pub fn wait_us(&self, wait_time_us: usize) {
+        let wait_time_ns = wait_time_us * 1000;
         let mut elapsed_time_ns = 0;
         let mut last_timer_value = self.read_timer();
 
@@ -2,29 +2,26 @@ use alloc::boxed::Box;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::bitflags;
-use core::alloc::{AllocError, Allocator, Layout};
 use core::ops::BitOr;
-use core::ptr::NonNull;
 use core::sync::atomic::{AtomicU8, Ordering};
-use core::{ptr, slice};
+use core::slice;
 use log::info;
 use nolock::queues::{mpmc, mpsc};
 use pci_types::{CommandRegister, EndpointHeader};
-use smoltcp::phy;
 use smoltcp::phy::{DeviceCapabilities, Medium};
-use smoltcp::time::Instant;
 use smoltcp::wire::EthernetAddress;
 use spin::{Mutex, RwLock};
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 use x86_64::structures::paging::frame::PhysFrameRange;
 use x86_64::structures::paging::page::PageRange;
-use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
-use x86_64::{PhysAddr, VirtAddr};
+use x86_64::VirtAddr;
+use x86_64::structures::paging::{Page, PageTableFlags};
 
+use crate::device::nic::{NetworkDevice, NetworkStats, PacketAllocator, StatsCounters};
 use crate::interrupt::interrupt_dispatcher::InterruptVector;
 use crate::interrupt::interrupt_handler::InterruptHandler;
 use crate::memory::{PAGE_SIZE, vmm};
-use crate::{apic, interrupt_dispatcher, pci_bus, process_manager, scheduler};
+use crate::{apic, interrupt_dispatcher, network, pci_bus, process_manager, scheduler};
 
 const BUFFER_SIZE: usize = 8 * 1024 + 16 + 1500;
 const BUFFER_PAGES: usize = if BUFFER_SIZE % PAGE_SIZE == 0 {
@@ -33,6 +30,8 @@ const BUFFER_PAGES: usize = if BUFFER_SIZE % PAGE_SIZE == 0 {
     BUFFER_SIZE / PAGE_SIZE + 1
 };
 const RECV_QUEUE_CAP: usize = 16;
+/// The card stores the CRC behind every received frame (it is counted in the length of the packet header).
+const CRC_SIZE: usize = 4;
 
 bitflags! {
     pub struct Command: u8 {
@@ -58,6 +57,12 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct MediaStatus: u8 {
+        const LINK_FAILED = 0x04;
+    }
+}
+
 bitflags! {
     pub struct ReceiveFlag: u32 {
         const ACCEPT_ALL = 0x0001;
@@ -132,6 +137,7 @@ struct Registers {
     interrupt_status: Mutex<Port<u16>>,
     receive_configuration: PortWriteOnly<u32>,
     config1: PortWriteOnly<u8>,
+    media_status: Mutex<PortReadOnly<u8>>,
 }
 
 pub struct Rtl8139 {
@@ -144,10 +150,12 @@ pub struct Rtl8139 {
         mpmc::bounded::scq::Receiver<Vec<u8, PacketAllocator>>,
         mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>,
     ),
+    /// Received frames together with their length (without CRC)
     recv_messages: (
-        mpmc::bounded::scq::Receiver<Vec<u8, PacketAllocator>>,
-        mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>,
+        mpmc::bounded::scq::Receiver<(Vec<u8, PacketAllocator>, usize)>,
+        mpmc::bounded::scq::Sender<(Vec<u8, PacketAllocator>, usize)>,
     ),
+    stats: StatsCounters,
 }
 
 pub struct Rtl8139InterruptHandler {
@@ -178,6 +186,7 @@ impl Registers {
             interrupt_status: Mutex::new(Port::new(base_address + 0x3e)),
             receive_configuration: PortWriteOnly::new(base_address + 0x44),
             config1: PortWriteOnly::new(base_address + 0x52),
+            media_status: Mutex::new(PortReadOnly::new(base_address + 0x58)),
         }
     }
 }
@@ -209,52 +218,46 @@ impl ReceiveBuffer {
     }
 }
 
-#[derive(Default)]
-pub struct PacketAllocator;
-
-unsafe impl Allocator for PacketAllocator {
-    fn allocate(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        panic!("PacketAllocator does not support allocate!");
+impl NetworkDevice for Rtl8139 {
+    fn mac_address(&self) -> EthernetAddress {
+        self.read_mac_address()
     }
 
-    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
-        if layout.size() != PAGE_SIZE {
-            panic!("PacketAllocator may only be used with page frames!");
-        }
+    fn link_up(&self) -> bool {
+        // LINKB is set, when the link has failed
+        let status = MediaStatus::from_bits_retain(unsafe { self.registers.media_status.lock().read() });
+        !status.contains(MediaStatus::LINK_FAILED)
+    }
 
-        let start = PhysFrame::from_start_address(PhysAddr::new(ptr.as_ptr() as u64)).expect("PacketAllocator may only be used with page frames!");
-        unsafe {
-            vmm::free_frames(PhysFrameRange { start, end: start + 1 });
-        }
+    fn stats(&self) -> NetworkStats {
+        self.stats.snapshot()
     }
-}
 
-pub struct Rtl8139TxToken<'a> {
-    device: &'a Rtl8139,
-}
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1536;
+        caps.max_burst_size = Some(1);
+        caps.medium = Medium::Ethernet;
 
-pub struct Rtl8139RxToken<'a> {
-    buffer: Vec<u8, PacketAllocator>,
-    device: &'a Rtl8139,
-}
+        caps
+    }
 
-impl<'a> Rtl8139TxToken<'a> {
-    pub fn new(device: &'a Rtl8139) -> Self {
-        Self { device }
+    fn plugin(self: Arc<Self>) {
+        Rtl8139::plugin(self);
     }
-}
 
-impl<'a> Rtl8139RxToken<'a> {
-    pub fn new(buffer: Vec<u8, PacketAllocator>, device: &'a Rtl8139) -> Self {
-        Self { buffer, device }
+    fn receive(&self) -> Option<(Vec<u8, PacketAllocator>, usize)> {
+        self.recv_messages.0.try_dequeue().ok()
     }
-}
 
-impl<'a> phy::TxToken for Rtl8139TxToken<'a> {
-    fn consume<R, F>(self, len: usize, f: F) -> R
-    where
-        F: FnOnce(&mut [u8]) -> R,
-    {
+    fn recycle(&self, buffer: Vec<u8, PacketAllocator>) {
+        self.recv_buffers_empty
+            .1
+            .try_enqueue(buffer)
+            .expect("Failed to enqueue used receive buffer!");
+    }
+
+    fn transmit(&self, len: usize, fill: &mut dyn FnMut(&mut [u8])) {
         if len > PAGE_SIZE {
             panic!("Packet length may not exceed page size!");
         }
@@ -273,15 +276,15 @@ impl<'a> phy::TxToken for Rtl8139TxToken<'a> {
             .set_flags(pages, PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE);
 
         // Queue physical memory for deallocation after transmission
-        self.device.send_queue.1.enqueue(phys_buffer).expect("Failed to enqueue physical buffer!");
+        self.send_queue.1.enqueue(phys_buffer).expect("Failed to enqueue physical buffer!");
 
         // Let smoltcp write the packet data to the buffer
         let buffer = unsafe { slice::from_raw_parts_mut(phys_buffer.start.start_address().as_u64() as *mut u8, len) };
-        let result = f(buffer);
+        fill(buffer);
 
         // Get current transmit descriptor
-        let index = self.device.next_transmit_descriptor();
-        let mut descriptor = self.device.registers.transmit_descriptors[index].lock();
+        let index = self.next_transmit_descriptor();
+        let mut descriptor = self.registers.transmit_descriptors[index].lock();
 
         // Wait for current descriptor to be available
         while !descriptor.available() {
@@ -293,57 +296,7 @@ impl<'a> phy::TxToken for Rtl8139TxToken<'a> {
             descriptor.address.write(phys_buffer.start.start_address().as_u64() as u32);
             descriptor.status.write(buffer.len() as u32);
         }
-
-        result
-    }
-}
-
-impl<'a> phy::RxToken for Rtl8139RxToken<'a> {
-    fn consume<R, F>(mut self, f: F) -> R
-    where
-        F: FnOnce(&[u8]) -> R,
-    {
-        let result = f(&mut self.buffer);
-        self.device
-            .recv_buffers_empty
-            .1
-            .try_enqueue(self.buffer)
-            .expect("Failed to enqueue used receive buffer!");
-
-        result
-    }
-}
-
-impl phy::Device for Rtl8139 {
-    type RxToken<'a>
-        = Rtl8139RxToken<'a>
-    where
-        Self: 'a;
-    type TxToken<'a>
-        = Rtl8139TxToken<'a>
-    where
-        Self: 'a;
-
-    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        let device = unsafe { ptr::from_ref(self).as_ref()? };
-        match self.recv_messages.0.try_dequeue() {
-            Ok(recv_buf) => Some((Rtl8139RxToken::new(recv_buf, device), Rtl8139TxToken::new(device))),
-            Err(_) => None,
-        }
-    }
-
-    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
-        let device = unsafe { ptr::from_ref(self).as_ref()? };
-        Some(Rtl8139TxToken::new(device))
-    }
-
-    fn capabilities(&self) -> DeviceCapabilities {
-        let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = 1536;
-        caps.max_burst_size = Some(1);
-        caps.medium = Medium::Ethernet;
-
-        caps
+        self.stats.transmitted(len);
     }
 }
 
@@ -394,6 +347,9 @@ impl InterruptHandler for Rtl8139InterruptHandler {
         if status.contains(Interrupt::RECEIVE_OK) {
             self.device.process_received_packet();
         }
+
+        // Let the network thread pass received packets to smoltcp
+        network::notify_worker();
     }
 }
 
@@ -448,6 +404,7 @@ impl Rtl8139 {
             send_queue: (Mutex::new(send_queue.0), send_queue.1),
             recv_buffers_empty: recv_buffers,
             recv_messages: mpmc::bounded::scq::queue(RECV_QUEUE_CAP),
+            stats: StatsCounters::default(),
         };
 
         unsafe {
@@ -542,8 +499,14 @@ impl Rtl8139 {
                     let src = &recv_buffer.data[msg_start..msg_end];
                     target[0..src.len()].copy_from_slice(src);
 
-                    let _ = self.recv_messages.1.try_enqueue(target);
+                    let length = src.len().saturating_sub(CRC_SIZE);
+                    let _ = self.recv_messages.1.try_enqueue((target, length));
+                    self.stats.received(length);
+                } else {
+                    self.stats.dropped();
                 }
+            } else {
+                self.stats.receive_error();
             }
         } else {
             panic!("Receive buffer is locked during packet processing!");
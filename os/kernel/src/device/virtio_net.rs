@@ -0,0 +1,456 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::ops::BitOr;
+use core::ptr;
+use core::sync::atomic::{Ordering, fence};
+use log::info;
+use nolock::queues::mpmc;
+use pci_types::{CommandRegister, EndpointHeader};
+use smoltcp::phy::{DeviceCapabilities, Medium};
+use smoltcp::wire::EthernetAddress;
+use spin::{Mutex, RwLock};
+use x86_64::instructions::port::{Port, PortReadOnly};
+use x86_64::structures::paging::frame::PhysFrameRange;
+
+use crate::device::nic::{NetworkDevice, NetworkStats, PacketAllocator, StatsCounters};
+use crate::interrupt::interrupt_dispatcher::InterruptVector;
+use crate::interrupt::interrupt_handler::InterruptHandler;
+use crate::memory::{PAGE_SIZE, vmm};
+use crate::{apic, interrupt_dispatcher, network, pci_bus, scheduler};
+
+// Driver for virtio network cards (legacy interface, as described in
+// section 4.1.4.8 and 5.1 of https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.pdf).
+// QEMU provides a compatible card with `-nic user,model=virtio-net-pci`.
+
+pub const VENDOR_ID: u16 = 0x1af4;
+/// Transitional virtio-net device (supports the legacy interface)
+pub const DEVICE_ID: u16 = 0x1000;
+
+const RECEIVE_QUEUE: u16 = 0;
+const TRANSMIT_QUEUE: u16 = 1;
+/// Number of buffers per queue (the device may offer more descriptors, which stay unused)
+const QUEUE_BUFFERS: u16 = 64;
+const RECV_QUEUE_CAP: usize = 64;
+/// Size of `virtio_net_hdr` without VIRTIO_NET_F_MRG_RXBUF
+const NET_HEADER_SIZE: usize = 10;
+/// Legacy virtqueues are aligned to 4 KiB
+const QUEUE_ALIGN: usize = 4096;
+
+bitflags! {
+    pub struct DeviceStatus: u8 {
+        const ACKNOWLEDGE = 0x01;
+        const DRIVER = 0x02;
+        const DRIVER_OK = 0x04;
+        const FAILED = 0x80;
+    }
+}
+
+bitflags! {
+    pub struct Features: u32 {
+        const MAC = 1 << 5;
+        const STATUS = 1 << 16;
+    }
+}
+
+bitflags! {
+    pub struct DescriptorFlags: u16 {
+        const NEXT = 0x01;
+        const WRITE = 0x02;
+    }
+}
+
+bitflags! {
+    pub struct NetStatus: u16 {
+        const LINK_UP = 0x01;
+    }
+}
+
+#[repr(C)]
+struct Descriptor {
+    address: u64,
+    length: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct UsedElement {
+    id: u32,
+    length: u32,
+}
+
+struct Registers {
+    device_features: Mutex<PortReadOnly<u32>>,
+    driver_features: Mutex<Port<u32>>,
+    queue_address: Mutex<Port<u32>>,
+    queue_size: Mutex<PortReadOnly<u16>>,
+    queue_select: Mutex<Port<u16>>,
+    device_status: Mutex<Port<u8>>,
+    isr_status: Mutex<PortReadOnly<u8>>,
+    mac: Mutex<[PortReadOnly<u8>; 6]>,
+    net_status: Mutex<PortReadOnly<u16>>,
+}
+
+/// A split virtqueue in the legacy layout: descriptor table, available ring and (page aligned) used ring.
+/// Every descriptor owns one page frame as buffer.
+struct VirtQueue {
+    index: u16,
+    size: u16,
+    memory: PhysFrameRange,
+    descriptors: *mut Descriptor,
+    available: *mut u16,
+    used: *mut u16,
+    buffers: Vec<PhysFrameRange>,
+    /// Descriptors, that are currently not owned by the device
+    free: Vec<u16>,
+    last_used: u16,
+    notify: Port<u16>,
+}
+
+// The raw pointers point to memory, which is exclusively owned by the queue
+unsafe impl Send for VirtQueue {}
+
+pub struct VirtioNet {
+    registers: Registers,
+    features: Features,
+    interrupt: InterruptVector,
+    receive_queue: Mutex<VirtQueue>,
+    transmit_queue: Mutex<VirtQueue>,
+    recv_buffers_empty: (
+        mpmc::bounded::scq::Receiver<Vec<u8, PacketAllocator>>,
+        mpmc::bounded::scq::Sender<Vec<u8, PacketAllocator>>,
+    ),
+    stats: StatsCounters,
+}
+
+pub struct VirtioNetInterruptHandler {
+    device: Arc<VirtioNet>,
+}
+
+impl Registers {
+    fn new(base_address: u16) -> Self {
+        Self {
+            device_features: Mutex::new(PortReadOnly::new(base_address)),
+            driver_features: Mutex::new(Port::new(base_address + 0x04)),
+            queue_address: Mutex::new(Port::new(base_address + 0x08)),
+            queue_size: Mutex::new(PortReadOnly::new(base_address + 0x0c)),
+            queue_select: Mutex::new(Port::new(base_address + 0x0e)),
+            device_status: Mutex::new(Port::new(base_address + 0x12)),
+            isr_status: Mutex::new(PortReadOnly::new(base_address + 0x13)),
+            mac: Mutex::new([
+                PortReadOnly::new(base_address + 0x14),
+                PortReadOnly::new(base_address + 0x15),
+                PortReadOnly::new(base_address + 0x16),
+                PortReadOnly::new(base_address + 0x17),
+                PortReadOnly::new(base_address + 0x18),
+                PortReadOnly::new(base_address + 0x19),
+            ]),
+            net_status: Mutex::new(PortReadOnly::new(base_address + 0x1a)),
+        }
+    }
+
+    fn set_status(&self, status: DeviceStatus) {
+        let mut port = self.device_status.lock();
+        unsafe {
+            let current = port.read();
+            port.write(current | status.bits());
+        }
+    }
+}
+
+impl VirtQueue {
+    fn new(index: u16, size: u16, base_address: u16) -> Self {
+        let size_usize = size as usize;
+        let available_offset = size_usize * size_of::<Descriptor>();
+        let used_offset = (available_offset + 6 + 2 * size_usize).next_multiple_of(QUEUE_ALIGN);
+        let queue_bytes = used_offset + 6 + size_of::<UsedElement>() * size_usize;
+
+        let memory = unsafe { vmm::alloc_frames(queue_bytes.div_ceil(PAGE_SIZE)) };
+        let start = memory.start.start_address().as_u64() as *mut u8;
+        unsafe { ptr::write_bytes(start, 0, queue_bytes) };
+
+        let buffer_count = size.min(QUEUE_BUFFERS);
+        let buffers = (0..buffer_count).map(|_| unsafe { vmm::alloc_frames(1) }).collect();
+
+        unsafe {
+            Self {
+                index,
+                size,
+                memory,
+                descriptors: start as *mut Descriptor,
+                available: start.add(available_offset) as *mut u16,
+                used: start.add(used_offset) as *mut u16,
+                buffers,
+                free: (0..buffer_count).rev().collect(),
+                last_used: 0,
+                notify: Port::new(base_address + 0x10),
+            }
+        }
+    }
+
+    fn page_number(&self) -> u32 {
+        (self.memory.start.start_address().as_u64() / QUEUE_ALIGN as u64) as u32
+    }
+
+    fn buffer(&mut self, id: u16) -> &mut [u8] {
+        let address = self.buffers[id as usize].start.start_address().as_u64();
+        unsafe { core::slice::from_raw_parts_mut(address as *mut u8, PAGE_SIZE) }
+    }
+
+    /// Hand the buffer of descriptor `id` to the device.
+    fn submit(&mut self, id: u16, length: usize, flags: DescriptorFlags) {
+        let address = self.buffers[id as usize].start.start_address().as_u64();
+        unsafe {
+            self.descriptors.add(id as usize).write_volatile(Descriptor {
+                address,
+                length: length as u32,
+                flags: flags.bits(),
+                next: 0,
+            });
+
+            // available ring: flags, idx, ring[size]
+            let idx = self.available.add(1).read_volatile();
+            self.available.add(2 + (idx % self.size) as usize).write_volatile(id);
+            // the device must see the ring entry before the new index
+            fence(Ordering::SeqCst);
+            self.available.add(1).write_volatile(idx.wrapping_add(1));
+        }
+    }
+
+    fn notify(&mut self) {
+        fence(Ordering::SeqCst);
+        unsafe { self.notify.write(self.index) };
+    }
+
+    /// Get the next descriptor, which the device has finished, together with the number of bytes written.
+    fn pop_used(&mut self) -> Option<(u16, usize)> {
+        // used ring: flags, idx, ring[size] (8 bytes per element)
+        let idx = unsafe { self.used.add(1).read_volatile() };
+        if idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+
+        let element = unsafe { (self.used.add(2) as *const UsedElement).add((self.last_used % self.size) as usize).read_volatile() };
+        self.last_used = self.last_used.wrapping_add(1);
+        Some((element.id as u16, element.length as usize))
+    }
+
+    /// Get a descriptor, which is not owned by the device, after collecting all finished transmissions.
+    fn take_free(&mut self) -> Option<u16> {
+        while let Some((id, _)) = self.pop_used() {
+            self.free.push(id);
+        }
+        self.free.pop()
+    }
+}
+
+impl NetworkDevice for VirtioNet {
+    fn mac_address(&self) -> EthernetAddress {
+        self.read_mac_address()
+    }
+
+    fn link_up(&self) -> bool {
+        if !self.features.contains(Features::STATUS) {
+            return true;
+        }
+
+        let status = NetStatus::from_bits_retain(unsafe { self.registers.net_status.lock().read() });
+        status.contains(NetStatus::LINK_UP)
+    }
+
+    fn stats(&self) -> NetworkStats {
+        self.stats.snapshot()
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1514;
+        caps.max_burst_size = None;
+        caps.medium = Medium::Ethernet;
+
+        caps
+    }
+
+    fn plugin(self: Arc<Self>) {
+        VirtioNet::plugin(self);
+    }
+
+    fn receive(&self) -> Option<(Vec<u8, PacketAllocator>, usize)> {
+        let mut queue = self.receive_queue.lock();
+        loop {
+            let (id, length) = queue.pop_used()?;
+            let frame_length = length.saturating_sub(NET_HEADER_SIZE);
+
+            // Copy frame (without the virtio header) to a new buffer and give the descriptor back to the device
+            let target = match self.recv_buffers_empty.0.try_dequeue() {
+                Ok(mut target) => {
+                    target[0..frame_length].copy_from_slice(&queue.buffer(id)[NET_HEADER_SIZE..NET_HEADER_SIZE + frame_length]);
+                    self.stats.received(frame_length);
+                    Some((target, frame_length))
+                }
+                Err(_) => {
+                    self.stats.dropped();
+                    None
+                }
+            };
+
+            queue.submit(id, PAGE_SIZE, DescriptorFlags::WRITE);
+            queue.notify();
+
+            if target.is_some() {
+                return target;
+            }
+        }
+    }
+
+    fn recycle(&self, buffer: Vec<u8, PacketAllocator>) {
+        self.recv_buffers_empty
+            .1
+            .try_enqueue(buffer)
+            .expect("Failed to enqueue used receive buffer!");
+    }
+
+    fn transmit(&self, len: usize, fill: &mut dyn FnMut(&mut [u8])) {
+        if len + NET_HEADER_SIZE > PAGE_SIZE {
+            panic!("Packet length may not exceed page size!");
+        }
+
+        // Wait for the device to finish one of the previous transmissions
+        let id = loop {
+            if let Some(id) = self.transmit_queue.lock().take_free() {
+                break id;
+            }
+            scheduler().switch_thread_no_interrupt();
+        };
+
+        let mut queue = self.transmit_queue.lock();
+        let buffer = queue.buffer(id);
+        // No checksum offloading or segmentation, so the header is all zeros
+        buffer[0..NET_HEADER_SIZE].fill(0);
+        fill(&mut buffer[NET_HEADER_SIZE..NET_HEADER_SIZE + len]);
+
+        queue.submit(id, NET_HEADER_SIZE + len, DescriptorFlags::empty());
+        queue.notify();
+        self.stats.transmitted(len);
+    }
+}
+
+impl VirtioNetInterruptHandler {
+    pub fn new(device: Arc<VirtioNet>) -> Self {
+        Self { device }
+    }
+}
+
+impl InterruptHandler for VirtioNetInterruptHandler {
+    fn trigger(&self) {
+        // Reading the ISR status register acknowledges the interrupt.
+        // Used buffers are collected by the network thread (see `receive()` and `transmit()`).
+        unsafe { self.device.registers.isr_status.lock().read() };
+        network::notify_worker();
+    }
+}
+
+impl VirtioNet {
+    pub fn new(pci_device: &RwLock<EndpointHeader>) -> Self {
+        info!("Configuring PCI registers");
+        let pci_config_space = pci_bus().config_space();
+        let mut pci_device = pci_device.write();
+
+        // Make sure bus master and I/O space are enabled (the legacy interface uses port I/O)
+        pci_device.update_command(pci_config_space, |command| {
+            command.bitor(CommandRegister::BUS_MASTER_ENABLE | CommandRegister::IO_ENABLE)
+        });
+
+        // Read register base address from BAR0
+        let bar0 = pci_device.bar(0, pci_config_space).expect("Failed to read base address!");
+        let base_address = bar0.unwrap_io() as u16;
+        info!("virtio-net base address: [0x{base_address:x}]");
+
+        let interrupt = InterruptVector::try_from(pci_device.interrupt(pci_config_space).1 + 32).unwrap();
+        let registers = Registers::new(base_address);
+
+        info!("Resetting device");
+        unsafe { registers.device_status.lock().write(0) };
+        registers.set_status(DeviceStatus::ACKNOWLEDGE);
+        registers.set_status(DeviceStatus::DRIVER);
+
+        // We only need the MAC address and the link status, everything else (checksum offloading, etc.) stays disabled
+        let device_features = Features::from_bits_truncate(unsafe { registers.device_features.lock().read() });
+        let features = device_features & (Features::MAC | Features::STATUS);
+        unsafe { registers.driver_features.lock().write(features.bits()) };
+
+        info!("Setting up virtqueues");
+        let receive_queue = Self::setup_queue(&registers, RECEIVE_QUEUE, base_address);
+        let transmit_queue = Self::setup_queue(&registers, TRANSMIT_QUEUE, base_address);
+
+        let recv_buffers = mpmc::bounded::scq::queue(RECV_QUEUE_CAP);
+        for _ in 0..RECV_QUEUE_CAP {
+            let phys_frame = unsafe { vmm::alloc_frames(1) };
+            let buffer = unsafe {
+                Vec::from_raw_parts_in(
+                    phys_frame.start.start_address().as_u64() as *mut u8,
+                    PAGE_SIZE,
+                    PAGE_SIZE,
+                    PacketAllocator::default(),
+                )
+            };
+            recv_buffers.1.try_enqueue(buffer).expect("Failed to enqueue receive buffer!");
+        }
+
+        let virtio_net = Self {
+            registers,
+            features,
+            interrupt,
+            receive_queue: Mutex::new(receive_queue),
+            transmit_queue: Mutex::new(transmit_queue),
+            recv_buffers_empty: recv_buffers,
+            stats: StatsCounters::default(),
+        };
+
+        info!("Enabling device");
+        virtio_net.registers.set_status(DeviceStatus::DRIVER_OK);
+
+        // Give all receive buffers to the device
+        let mut queue = virtio_net.receive_queue.lock();
+        while let Some(id) = queue.free.pop() {
+            queue.submit(id, PAGE_SIZE, DescriptorFlags::WRITE);
+        }
+        queue.notify();
+        drop(queue);
+
+        virtio_net
+    }
+
+    fn setup_queue(registers: &Registers, index: u16, base_address: u16) -> VirtQueue {
+        unsafe { registers.queue_select.lock().write(index) };
+        let size = unsafe { registers.queue_size.lock().read() };
+        if size == 0 {
+            registers.set_status(DeviceStatus::FAILED);
+            panic!("virtio-net: Queue {index} is not available!");
+        }
+
+        let queue = VirtQueue::new(index, size, base_address);
+        unsafe { registers.queue_address.lock().write(queue.page_number()) };
+
+        queue
+    }
+
+    pub fn plugin(device: Arc<VirtioNet>) {
+        let interrupt = device.interrupt;
+        interrupt_dispatcher().assign(device.interrupt, Box::new(VirtioNetInterruptHandler::new(device)));
+        apic().allow(interrupt);
+    }
+
+    pub fn read_mac_address(&self) -> EthernetAddress {
+        let mut mac_registers = self.registers.mac.lock();
+        let mut mac = [0u8; 6];
+        for (byte, port) in mac.iter_mut().zip(mac_registers.iter_mut()) {
+            *byte = unsafe { port.read() };
+        }
+
+        EthernetAddress::from_bytes(&mac)
+    }
+}
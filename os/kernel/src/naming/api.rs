@@ -22,11 +22,12 @@ use spin::{Mutex, Once};
 
 use super::traits::FileSystem;
 use super::lookup;
+use super::netfs;
 use super::open_objects;
 use super::stat::Mode;
 use super::tmpfs;
 
-use naming::shared_types::{OpenOptions, RawDirent, SeekOrigin};
+use naming::shared_types::{OpenOptions, PollFlags, RawDirent, SeekOrigin};
 use syscall::return_vals::Errno;
 use crate::initrd;
 
@@ -49,6 +50,10 @@ pub fn init() {
             }
         }
 
+        if tmpfs.mount("/dev/net", Arc::new(netfs::NetDir)).is_err() {
+            warn!("Failed to mount network connections at /dev/net");
+        }
+
         Arc::new(tmpfs)
     });
     open_objects::open_object_table_init();
@@ -88,6 +93,12 @@ pub fn seek(object_handle: usize, offset: usize, origin: SeekOrigin) -> Result<u
     open_objects::seek(object_handle, offset, origin)
 }
 
+/// Check for which operations the named object referenced by `object_handle` is ready. \
+/// Returns `Ok(flags)` or `Err`.
+pub fn poll(object_handle: usize) -> Result<PollFlags, Errno> {
+    open_objects::poll(object_handle)
+}
+
 /// Close the named object referenced by `object_handle`.
 /// Returns `Ok(0)` or `Err(errno)`
 pub fn close(object_handle: usize) -> Result<usize, Errno> {
@@ -38,13 +38,9 @@ pub(super) fn lookup_named_object(path: &str) -> Result<NamedObject, Errno> {
         // get root directory and open the desired file
         let mut current_dir = ROOT.get().unwrap().root_dir();
         let mut len = components.len();
-        let mut found;
         for component in &components {
-            found = current_dir.lookup(component);
-            if found.is_err() {
-                return Err(Errno::ENOENT);
-            }
-            found_named_object = found.unwrap();
+            // e.g. ENOENT, or the error of a network connection under /dev/net
+            found_named_object = current_dir.lookup(component)?;
 
             // if not last component, this must be a directory
             if len > 1 {
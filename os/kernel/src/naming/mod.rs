@@ -3,5 +3,6 @@ pub mod stat;
 
 mod open_objects;
 mod tmpfs;
+mod netfs;
 mod lookup;
 mod traits;
\ No newline at end of file
@@ -0,0 +1,345 @@
+/* ╔═════════════════════════════════════════════════════════════════════════╗
+   ║ Module: netfs                                                           ║
+   ╟─────────────────────────────────────────────────────────────────────────╢
+   ║ Network connections as named objects, mounted at /dev/net:              ║
+   ║   - /dev/net/tcp/<host>/<port>  connected TCP stream                    ║
+   ║   - /dev/net/udp/<host>/<port>  UDP socket, that talks to host:port     ║
+   ║ Looking a path up only checks host and port, opening it connects.       ║
+   ║ read/write/poll use the socket and closing the handle closes it.        ║
+   ╚═════════════════════════════════════════════════════════════════════════╝
+*/
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Formatter};
+use core::net::Ipv4Addr;
+use core::str::FromStr;
+use log::info;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::{tcp, udp};
+use smoltcp::wire::{IpAddress, IpEndpoint};
+
+use super::stat::{Mode, Stat, MODE_DIR};
+use super::traits::{DirectoryObject, FileObject, NamedObject};
+use crate::network::resolver::{self, AddressFamily, ResolveError};
+use crate::network::{self, Interest, SocketType};
+use crate::{process_manager, timer};
+use naming::shared_types::{DirEntry, FileType, OpenOptions, PollFlags};
+use syscall::return_vals::Errno;
+
+/// Connecting a TCP stream is given up after this many ms
+const CONNECT_TIMEOUT: usize = 10000;
+
+/// Directories in /dev/net
+const PROTOCOLS: [(&str, SocketType); 2] = [("tcp", SocketType::Tcp), ("udp", SocketType::Udp)];
+
+/// The directory /dev/net (see `api::init`), which has a directory for every protocol
+pub struct NetDir;
+
+/// /dev/net/tcp or /dev/net/udp, which has a directory for every host (they can't be listed)
+struct ProtocolDir(SocketType);
+
+/// /dev/net/<protocol>/<host>, which has a file for every port
+struct HostDir {
+    protocol: SocketType,
+    host: String,
+    addresses: Vec<IpAddress>,
+}
+
+/// /dev/net/<protocol>/<host>/<port>, every handle for it is a new connection (see `FileObject::open`)
+struct PortFile {
+    protocol: SocketType,
+    addresses: Vec<IpAddress>,
+    port: u16,
+}
+
+/// A socket, that has been connected by opening /dev/net/<protocol>/<host>/<port>
+struct SocketFile {
+    handle: SocketHandle,
+    protocol: SocketType,
+    /// Id of the process, that has opened the path (no other process can use the socket)
+    owner: usize,
+    remote: IpEndpoint,
+}
+
+fn dir_stat() -> Stat {
+    Stat::new(Mode::new(MODE_DIR), 0)
+}
+
+impl DirectoryObject for NetDir {
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        PROTOCOLS
+            .iter()
+            .find(|(protocol, _)| *protocol == name)
+            .map(|(_, socket_type)| (Arc::new(ProtocolDir(*socket_type)) as Arc<dyn DirectoryObject>).into())
+            .ok_or(Errno::ENOENT)
+    }
+
+    fn create_file(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn create_dir(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(dir_stat())
+    }
+
+    fn readdir(&self, index: usize) -> Result<Option<DirEntry>, Errno> {
+        Ok(PROTOCOLS.get(index).map(|(name, _)| DirEntry {
+            file_type: FileType::Directory,
+            name: name.to_string(),
+        }))
+    }
+}
+
+impl DirectoryObject for ProtocolDir {
+    /// Resolve the host (addresses are taken as they are).
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        let addresses = resolver::resolve(name, AddressFamily::Any, resolver::DEFAULT_TIMEOUT_MS, resolver::DEFAULT_RETRIES)
+            .map_err(|error| match error {
+                // there is no such host
+                ResolveError::InvalidName | ResolveError::NotFound => Errno::ENOENT,
+                error => Errno::from(error),
+            })?;
+        let host = HostDir { protocol: self.0, host: name.to_string(), addresses };
+        Ok((Arc::new(host) as Arc<dyn DirectoryObject>).into())
+    }
+
+    fn create_file(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn create_dir(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(dir_stat())
+    }
+
+    fn readdir(&self, _index: usize) -> Result<Option<DirEntry>, Errno> {
+        Ok(None)
+    }
+}
+
+impl DirectoryObject for HostDir {
+    fn lookup(&self, name: &str) -> Result<NamedObject, Errno> {
+        let port = u16::from_str(name).ok().filter(|port| *port != 0).ok_or(Errno::ENOENT)?;
+        let file = PortFile { protocol: self.protocol, addresses: self.addresses.clone(), port };
+        Ok((Arc::new(file) as Arc<dyn FileObject>).into())
+    }
+
+    fn create_file(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn create_dir(&self, _name: &str, _mode: Mode) -> Result<NamedObject, Errno> {
+        Err(Errno::ERDONLY)
+    }
+
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(dir_stat())
+    }
+
+    fn readdir(&self, _index: usize) -> Result<Option<DirEntry>, Errno> {
+        Ok(None)
+    }
+}
+
+impl FileObject for PortFile {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::zeroed())
+    }
+
+    /// Connect to the port. For TCP, all addresses of the host are tried in turn.
+    fn open(&self, _options: OpenOptions) -> Result<Option<Arc<dyn FileObject>>, Errno> {
+        let file = SocketFile::connect(self.protocol, &self.addresses, self.port)?;
+        Ok(Some(Arc::new(file)))
+    }
+
+    // only the handles can be read and written (they are `SocketFile`s)
+}
+
+impl SocketFile {
+    fn connect(protocol: SocketType, addresses: &[IpAddress], port: u16) -> Result<SocketFile, Errno> {
+        let mut result = Err(Errno::ENOENT);
+        for &address in addresses {
+            info!("connecting to {address}:{port} for /dev/net");
+            result = match protocol {
+                SocketType::Tcp => connect_tcp(address, port),
+                SocketType::Udp => open_udp(),
+                _ => Err(Errno::ENOTSUP),
+            }
+            .map(|handle| SocketFile {
+                handle,
+                protocol,
+                owner: process_manager().read().current_process().id(),
+                remote: IpEndpoint::new(address, port),
+            });
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    /// Only the process, that has opened the path, can use the socket (and only until it exits).
+    fn check_owner(&self) -> Result<(), Errno> {
+        if process_manager().read().current_process().id() != self.owner || !network::owns_socket(self.handle) {
+            return Err(Errno::EBADF);
+        }
+        Ok(())
+    }
+}
+
+/// Open a TCP socket and wait, until it is connected to `address`:`port`.
+fn connect_tcp(address: IpAddress, port: u16) -> Result<SocketHandle, Errno> {
+    let handle = network::open_tcp();
+    let deadline = Some(timer().systime_ms() + CONNECT_TIMEOUT);
+    let result = network::connect_tcp(handle, address, port).map_err(Errno::from).and_then(|_| loop {
+        match network::tcp_connected(handle) {
+            Some(true) => break Ok(()),
+            // the remote host refused the connection
+            Some(false) => break Err(Errno::ECONNRESET),
+            None if !network::wait_for(handle, Interest::Connection, deadline) => break Err(Errno::ETIMEDOUT),
+            None => {}
+        }
+    });
+    if let Err(errno) = result {
+        network::close_socket(handle);
+        return Err(errno);
+    }
+    Ok(handle)
+}
+
+/// Open a UDP socket on an ephemeral port.
+fn open_udp() -> Result<SocketHandle, Errno> {
+    let handle = network::open_udp();
+    // 0.0.0.0 binds to all addresses (IPv6 too)
+    if let Err(error) = network::bind_udp(handle, IpAddress::Ipv4(Ipv4Addr::UNSPECIFIED), 0) {
+        network::close_socket(handle);
+        return Err(error.into());
+    }
+    Ok(handle)
+}
+
+impl FileObject for SocketFile {
+    fn stat(&self) -> Result<Stat, Errno> {
+        Ok(Stat::zeroed())
+    }
+
+    /// Read from the stream (0 means, that the remote host has closed it) or receive a datagram from the remote host.
+    fn read(&self, buf: &mut [u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        self.check_owner()?;
+        let handle = self.handle;
+        if self.protocol == SocketType::Tcp {
+            return match network::block_on(handle, Interest::Receive, || match network::receive_tcp(handle, buf) {
+                // no data yet
+                Ok(0) if !buf.is_empty() => None,
+                result => Some(result),
+            })? {
+                Ok(len) => Ok(len),
+                // the remote host closed the connection, this is the end of the stream
+                Err(tcp::RecvError::Finished) => Ok(0),
+                Err(tcp::RecvError::InvalidState) => Err(Errno::ECONNRESET),
+            };
+        }
+        loop {
+            match network::block_on(handle, Interest::Receive, || match network::receive_datagram(handle, buf) {
+                Err(udp::RecvError::Exhausted) => None,
+                result => Some(result),
+            })? {
+                Ok((len, metadata)) if metadata.endpoint == self.remote => return Ok(len),
+                // datagrams from other hosts are dropped, like on a connected socket
+                Ok(_) => {}
+                // returning 0 would look like the end of the file, so truncated datagrams are dropped, too
+                Err(udp::RecvError::Truncated) => {}
+                Err(udp::RecvError::Exhausted) => unreachable!(),
+            }
+        }
+    }
+
+    /// Write into the stream or send `buf` as one datagram to the remote host.
+    fn write(&self, buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
+        self.check_owner()?;
+        let handle = self.handle;
+        if self.protocol == SocketType::Tcp {
+            return match network::block_on(handle, Interest::Send, || match network::send_tcp(handle, buf) {
+                // the send buffer is full
+                Ok(0) if !buf.is_empty() => None,
+                result => Some(result),
+            })? {
+                Ok(len) => Ok(len),
+                Err(tcp::SendError::InvalidState) => Err(Errno::ECONNRESET),
+            };
+        }
+        // broadcasts have to be allowed with `SocketOption::Broadcast`, which files don't have
+        if network::is_broadcast(&self.remote.addr) {
+            return Err(Errno::EACCES);
+        }
+        match network::block_on(handle, Interest::Send, || {
+            match network::send_datagram(handle, self.remote.addr, self.remote.port, buf) {
+                Err(udp::SendError::BufferFull) => None,
+                result => Some(result),
+            }
+        })? {
+            Ok(()) => Ok(buf.len()),
+            Err(udp::SendError::Unaddressable) => Err(Errno::EINVAL),
+            Err(udp::SendError::BufferFull) => unreachable!(),
+        }
+    }
+
+    fn poll(&self, _options: OpenOptions) -> Result<PollFlags, Errno> {
+        self.check_owner()?;
+        let mut ready = PollFlags::empty();
+        for (flag, interest) in [(PollFlags::READABLE, Interest::Receive), (PollFlags::WRITABLE, Interest::Send)] {
+            if network::socket_ready(self.handle, interest).ok_or(Errno::EBADF)? {
+                ready |= flag;
+            }
+        }
+        Ok(ready)
+    }
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        // closing the handle closes the socket (unless its process has exited and closed it already)
+        if self.check_owner().is_ok() {
+            network::close_socket(self.handle);
+        }
+    }
+}
+
+impl Debug for NetDir {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetFsDir").finish()
+    }
+}
+
+impl Debug for ProtocolDir {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetFsProtocolDir").field("protocol", &self.0).finish()
+    }
+}
+
+impl Debug for HostDir {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetFsHostDir").field("protocol", &self.protocol).field("host", &self.host).finish()
+    }
+}
+
+impl Debug for PortFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetFsPort").field("protocol", &self.protocol).field("port", &self.port).finish()
+    }
+}
+
+impl Debug for SocketFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetFsSocket").field("protocol", &self.protocol).field("remote", &self.remote).finish()
+    }
+}
@@ -17,7 +17,7 @@ use spin::{Mutex, Once};
 
 use super::traits::NamedObject;
 use super::lookup;
-use naming::shared_types::{DirEntry, OpenOptions, SeekOrigin};
+use naming::shared_types::{DirEntry, OpenOptions, PollFlags, SeekOrigin};
 use syscall::return_vals::{Errno, SyscallResult};
 
 
@@ -43,11 +43,7 @@ pub(super) fn open_object_table_init() {
 
 pub(super) fn open(path: &str, flags: OpenOptions) -> Result<usize, Errno> {
     // try to open the named object for the given path
-    let result = lookup::lookup_named_object(path);
-    if result.is_err() {
-        return Err(Errno::ENOENT);
-    }
-    let found_named_object: NamedObject = result.unwrap();
+    let found_named_object: NamedObject = lookup::lookup_named_object(path)?;
 
     // check if path is a directory and this was requested
     if flags.contains(OpenOptions::DIRECTORY) {
@@ -56,6 +52,15 @@ pub(super) fn open(path: &str, flags: OpenOptions) -> Result<usize, Errno> {
         }
     }
 
+    // some files hand out a new object for every handle (e.g. a connection under /dev/net)
+    let found_named_object = match found_named_object.as_file() {
+        Ok(file) => match file.open(flags)? {
+            Some(opened) => NamedObject::FileObject(opened),
+            None => found_named_object,
+        },
+        Err(_) => found_named_object,
+    };
+
     // try to allocate an new handle
     get_open_object_table()
         .lock()
@@ -67,35 +72,27 @@ pub(super) fn open(path: &str, flags: OpenOptions) -> Result<usize, Errno> {
 }
 
 pub(super) fn write(fh: usize, buf: &[u8]) -> Result<usize, Errno> {
-    get_open_object_table()
-        .lock()
-        .lookup_opened_object(fh)
-        .and_then(|opened_object| {
-            // Make `opened_object` mutable here
-            opened_object.named_object.as_file().and_then(|file| {
-                let pos = opened_object.pos.load(Ordering::SeqCst);
-                let bytes_written = file.write(buf, pos, opened_object.options)?;
-                opened_object
-                    .pos
-                    .store(pos + bytes_written, Ordering::SeqCst);
-                Ok(bytes_written) // Return the bytes written
-            })
-        })
+    // don't hold the table lock while writing, as some files block (e.g. sockets)
+    let opened_object = lookup_opened_object(fh)?;
+    opened_object.named_object.as_file().and_then(|file| {
+        let pos = opened_object.pos.load(Ordering::SeqCst);
+        let bytes_written = file.write(buf, pos, opened_object.options)?;
+        opened_object
+            .pos
+            .store(pos + bytes_written, Ordering::SeqCst);
+        Ok(bytes_written) // Return the bytes written
+    })
 }
 
 pub(super) fn read(fh: usize, buf: &mut [u8]) -> Result<usize, Errno> {
-    get_open_object_table()
-        .lock()
-        .lookup_opened_object(fh)
-        .and_then(|opened_object| {
-            // Make `opened_object` mutable here
-            opened_object.named_object.as_file().and_then(|file| {
-                let pos = opened_object.pos.load(Ordering::SeqCst);
-                let bytes_read = file.read(buf, pos, opened_object.options)?;
-                opened_object.pos.store(pos + bytes_read, Ordering::SeqCst);
-                Ok(bytes_read) // Return the bytes read
-            })
-        })
+    // don't hold the table lock while reading, as some files block (e.g. sockets)
+    let opened_object = lookup_opened_object(fh)?;
+    opened_object.named_object.as_file().and_then(|file| {
+        let pos = opened_object.pos.load(Ordering::SeqCst);
+        let bytes_read = file.read(buf, pos, opened_object.options)?;
+        opened_object.pos.store(pos + bytes_read, Ordering::SeqCst);
+        Ok(bytes_read) // Return the bytes read
+    })
 }
 
 pub fn seek(fh: usize, offset: usize, origin: SeekOrigin) -> Result<usize, Errno> {
@@ -131,10 +128,28 @@ pub(super) fn readdir(fh: usize) -> Result<Option<DirEntry>, Errno> {
         })
 }
 
+pub(super) fn poll(fh: usize) -> Result<PollFlags, Errno> {
+    let opened_object = lookup_opened_object(fh)?;
+    match opened_object.named_object.as_file() {
+        Ok(file) => file.poll(opened_object.options),
+        // readdir never blocks
+        Err(_) => Ok(PollFlags::READABLE),
+    }
+}
+
 pub(super) fn close(handle: usize) -> Result<usize, Errno> {
     get_open_object_table().lock().free_handle(handle)
 }
 
+/// Helper function returning the opened object for the given handle,
+/// the table is only locked while looking it up
+fn lookup_opened_object(fh: usize) -> Result<Arc<OpenedObject>, Errno> {
+    get_open_object_table()
+        .lock()
+        .lookup_opened_object(fh)
+        .map(|opened_object| opened_object.clone())
+}
+
 /*pub(super) fn dump() {
     get_open_object_table().lock().dump();
 }*/
@@ -31,6 +31,19 @@ impl TmpFs {
     }
 
     pub fn create_static_file(&self, path: &str, buffer: &'static [u8]) -> Result<NamedObject, Errno> {
+        let (dir, filename) = self.create_parent_dirs(path)?;
+        dir.create_static_file(filename, buffer)
+    }
+
+    /// Mount the directory `mounted` (e.g. of another file system) at `path`.
+    pub fn mount(&self, path: &str, mounted: Arc<dyn DirectoryObject>) -> Result<(), Errno> {
+        let (dir, name) = self.create_parent_dirs(path)?;
+        dir.mount(name, mounted)
+    }
+
+    /// Helper function creating all directories of `path`, except for the last component. \
+    /// Returns the parent directory and the last component.
+    fn create_parent_dirs<'a>(&self, path: &'a str) -> Result<(&Dir, &'a str), Errno> {
         let mut dir = self.root_dir.as_ref();
 
         let (path, filename) = match path.rsplit_once("/") {
@@ -49,7 +62,7 @@ impl TmpFs {
             dir = unsafe { (ptr::from_ref(new_dir.as_dir()?.as_ref()) as *const Dir).as_ref().unwrap() };
         }
 
-        dir.create_static_file(filename, buffer)
+        Ok((dir, filename))
     }
 }
 
@@ -62,6 +75,8 @@ impl FileSystem for TmpFs {
 enum TmpFsINode {
     File(Arc<dyn FileObject>),
     Directory(Arc<Dir>),
+    /// Directory of another file system (see `TmpFs::mount`)
+    Mount(Arc<dyn DirectoryObject>),
 }
 
 struct DirInner {
@@ -97,6 +112,18 @@ impl Dir {
         // Return the created file as a NamedObject
         Ok((inode as Arc<dyn FileObject>).into())
     }
+
+    pub fn mount(&self, name: &str, mounted: Arc<dyn DirectoryObject>) -> Result<(), Errno> {
+        let mut dir_lock = self.0.write();
+
+        // Check if a file or directory with the same name already exists
+        if dir_lock.files.iter().any(|(file_name, _)| file_name == name) {
+            return Err(Errno::EEXIST);
+        }
+
+        dir_lock.files.push((name.to_string(), TmpFsINode::Mount(mounted)));
+        Ok(())
+    }
 }
 
 impl DirectoryObject for Dir {
@@ -108,6 +135,7 @@ impl DirectoryObject for Dir {
             match tmpfs_inode {
                 TmpFsINode::File(file) => Ok(file.clone().into()), // Clone and convert to NamedObject
                 TmpFsINode::Directory(dir) => Ok((dir.clone() as Arc<dyn DirectoryObject>).into()), // Clone and cast directory
+                TmpFsINode::Mount(dir) => Ok(dir.clone().into()),
             }
         } else {
             Err(Errno::ENOENT) // Return error if the file is not found
@@ -168,7 +196,7 @@ impl DirectoryObject for Dir {
         };
 
         let entry = match inode {
-            TmpFsINode::Directory(_dir) => DirEntry {
+            TmpFsINode::Directory(_) | TmpFsINode::Mount(_) => DirEntry {
                 file_type: FileType::Directory,
                 name: name.clone(),
             },
@@ -17,7 +17,7 @@ use core::fmt::{self, Debug};
 use core::result::Result;
 
 use super::stat::{Mode, Stat};
-use naming::shared_types::{OpenOptions, DirEntry};
+use naming::shared_types::{OpenOptions, DirEntry, PollFlags};
 use syscall::return_vals::Errno;
 
 /// FileSystem operations
@@ -27,6 +27,12 @@ pub trait FileSystem: Send + Sync {
 
 /// File object operations
 pub trait FileObject: Debug + Send + Sync {
+    /// Called by `open` before a handle is allocated. Returns the object, that the handle refers to,
+    /// if it is not this file (e.g. a new connection). Regular files return `None`.
+    fn open(&self, _options: OpenOptions) -> Result<Option<Arc<dyn FileObject>>, Errno> {
+        Ok(None)
+    }
+
     fn stat(&self) -> Result<Stat, Errno> {
         Err(Errno::EBADF)
     }
@@ -38,6 +44,12 @@ pub trait FileObject: Debug + Send + Sync {
     fn write(&self, _buf: &[u8], _offset: usize, _options: OpenOptions) -> Result<usize, Errno> {
         Err(Errno::EBADF)
     }
+
+    /// Return for which operations `read` and `write` would not block.
+    /// Regular files never block, so they are always ready.
+    fn poll(&self, _options: OpenOptions) -> Result<PollFlags, Errno> {
+        Ok(PollFlags::READABLE | PollFlags::WRITABLE)
+    }
 }
 
 
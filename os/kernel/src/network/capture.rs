@@ -0,0 +1,254 @@
+//! Packet capture inside the kernel.
+//!
+//! Every frame, that passes the smoltcp adapter of a network card (see `nic::SmoltcpDevice`),
+//! is offered to `record()`. While a capture is running, matching frames are copied into a ring buffer
+//! together with a timestamp. User space reads them as libpcap records (see `sys_capture_read`)
+//! and only has to put the pcap file header in front of them.
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use smoltcp::wire::{EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, Ipv6Packet, TcpPacket, UdpPacket};
+use spin::Mutex;
+
+use super::{INTERFACES, notify_worker};
+use crate::timer;
+
+/// Frames are cut off after this many bytes (enough for the largest Ethernet frame with a VLAN tag).
+pub const SNAPSHOT_LENGTH: usize = 1518;
+/// Size of the header in front of every libpcap record
+/// (timestamp in seconds and microseconds, captured length and original length, 4 bytes each).
+pub const RECORD_HEADER_SIZE: usize = 16;
+/// The ring buffer holds at most this many bytes of frame data.
+/// If it is full, the oldest frames are dropped.
+const CAPTURE_BUFFER_SIZE: usize = 256 * 1024;
+const ETHERNET_HEADER_SIZE: usize = 14;
+
+/// Checked for every frame, so that `record()` doesn't need the lock, while no capture is running.
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+static CAPTURE: Mutex<Capture> = Mutex::new(Capture::new());
+
+/// Which frames should be captured. Every field, that is set, has to match.
+#[derive(Debug, Default, Clone)]
+pub struct CaptureFilter {
+    /// Only capture frames of the interfaces, whose driver name contains this (e.g. "NE2000").
+    pub interface: Option<String>,
+    /// Only capture frames with this EtherType (e.g. 0x0800 for IPv4).
+    pub ether_type: Option<u16>,
+    /// Only capture IP packets with this protocol (e.g. 17 for UDP).
+    pub ip_protocol: Option<u8>,
+    /// Only capture TCP and UDP packets, whose source or destination port is this.
+    pub port: Option<u16>,
+}
+
+struct CapturedFrame {
+    /// `timer().systime_ms()` at the time the frame has been received or sent
+    timestamp: usize,
+    /// Length of the frame on the wire, the data may have been cut off (see `SNAPSHOT_LENGTH`)
+    length: usize,
+    data: Vec<u8>,
+}
+
+struct Capture {
+    filter: CaptureFilter,
+    /// Put the matching cards into promiscuous mode for this capture
+    promiscuous: bool,
+    frames: VecDeque<CapturedFrame>,
+    /// Bytes of frame data in `frames`
+    buffered: usize,
+    /// Frames, that have been dropped, because nobody has read them in time
+    dropped: usize,
+}
+
+impl Capture {
+    const fn new() -> Self {
+        Self {
+            filter: CaptureFilter { interface: None, ether_type: None, ip_protocol: None, port: None },
+            promiscuous: false,
+            frames: VecDeque::new(),
+            buffered: 0,
+            dropped: 0,
+        }
+    }
+}
+
+impl CaptureFilter {
+    fn matches(&self, interface: &str, frame: &[u8]) -> bool {
+        if let Some(name) = &self.interface
+            && !interface.contains(name.as_str())
+        {
+            return false;
+        }
+        if self.ether_type.is_none() && self.ip_protocol.is_none() && self.port.is_none() {
+            return true;
+        }
+
+        let Ok(frame) = EthernetFrame::new_checked(frame) else {
+            return false;
+        };
+        if let Some(ether_type) = self.ether_type
+            && frame.ethertype() != EthernetProtocol::from(ether_type)
+        {
+            return false;
+        }
+        if self.ip_protocol.is_none() && self.port.is_none() {
+            return true;
+        }
+
+        // extension headers of IPv6 are not followed
+        let (protocol, payload) = match frame.ethertype() {
+            EthernetProtocol::Ipv4 => match Ipv4Packet::new_checked(frame.payload()) {
+                Ok(packet) => (packet.next_header(), packet.payload()),
+                Err(_) => return false,
+            },
+            EthernetProtocol::Ipv6 => match Ipv6Packet::new_checked(frame.payload()) {
+                Ok(packet) => (packet.next_header(), packet.payload()),
+                Err(_) => return false,
+            },
+            _ => return false,
+        };
+        if let Some(ip_protocol) = self.ip_protocol
+            && protocol != IpProtocol::from(ip_protocol)
+        {
+            return false;
+        }
+
+        match self.port {
+            None => true,
+            Some(port) => {
+                let ports = match protocol {
+                    IpProtocol::Tcp => TcpPacket::new_checked(payload).map(|packet| (packet.src_port(), packet.dst_port())),
+                    IpProtocol::Udp => UdpPacket::new_checked(payload).map(|packet| (packet.src_port(), packet.dst_port())),
+                    _ => return false,
+                };
+                ports.is_ok_and(|(source, destination)| source == port || destination == port)
+            }
+        }
+    }
+}
+
+/// Start capturing the frames, that match `filter`. Frames of an earlier capture, that haven't been read yet, are discarded.
+///
+/// If `promiscuous` is set, the matching cards also accept frames, that are not meant for this host.
+/// Returns `false`, if one of them doesn't support this (it is captured anyway).
+pub fn start(filter: CaptureFilter, promiscuous: bool) -> bool {
+    stop();
+
+    let mut supported = true;
+    if promiscuous {
+        for interface in INTERFACES.read().iter() {
+            if filter.interface.as_ref().is_none_or(|name| interface.name.contains(name.as_str())) {
+                supported &= interface.device.set_promiscuous(true);
+            }
+        }
+        notify_worker();
+    }
+
+    let mut capture = CAPTURE.lock();
+    capture.filter = filter;
+    capture.promiscuous = promiscuous;
+    capture.frames.clear();
+    capture.buffered = 0;
+    capture.dropped = 0;
+    CAPTURING.store(true, Ordering::Release);
+
+    supported
+}
+
+/// Stop capturing. The frames, that have been captured, can still be read.
+///
+/// Returns the number of frames, that have been dropped, because the ring buffer was full.
+pub fn stop() -> usize {
+    CAPTURING.store(false, Ordering::Release);
+
+    let (filter, promiscuous, dropped) = {
+        let mut capture = CAPTURE.lock();
+        let promiscuous = core::mem::replace(&mut capture.promiscuous, false);
+        (capture.filter.clone(), promiscuous, capture.dropped)
+    };
+    if promiscuous {
+        for interface in INTERFACES.read().iter() {
+            if filter.interface.as_ref().is_none_or(|name| interface.name.contains(name.as_str())) {
+                interface.device.set_promiscuous(false);
+            }
+        }
+        notify_worker();
+    }
+
+    dropped
+}
+
+/// Offer a frame, that has been received or sent by `interface`, to the running capture.
+pub fn record(interface: &str, frame: &[u8]) {
+    if !CAPTURING.load(Ordering::Acquire) {
+        return;
+    }
+
+    let mut capture = CAPTURE.lock();
+    if !capture.filter.matches(interface, frame) {
+        return;
+    }
+
+    let data = frame[..frame.len().min(SNAPSHOT_LENGTH)].to_vec();
+    capture.buffered += data.len();
+    capture.frames.push_back(CapturedFrame { timestamp: timer().systime_ms(), length: frame.len(), data });
+    while capture.buffered > CAPTURE_BUFFER_SIZE {
+        let oldest = capture.frames.pop_front().expect("Capture buffer is empty, but not all bytes have been freed!");
+        capture.buffered -= oldest.data.len();
+        capture.dropped += 1;
+    }
+}
+
+/// Offer an IP packet of an interface without link layer (e.g. the loopback interface) to the running capture.
+///
+/// The packet is captured with an Ethernet header of zero addresses in front of it, so that it fits into the same file.
+pub fn record_ip(interface: &str, packet: &[u8]) {
+    if !CAPTURING.load(Ordering::Acquire) {
+        return;
+    }
+
+    let ether_type = match packet.first().map(|byte| byte >> 4) {
+        Some(4) => EthernetProtocol::Ipv4,
+        Some(6) => EthernetProtocol::Ipv6,
+        _ => return,
+    };
+    let mut frame = vec![0u8; ETHERNET_HEADER_SIZE];
+    frame[12..ETHERNET_HEADER_SIZE].copy_from_slice(&u16::from(ether_type).to_be_bytes());
+    frame.extend_from_slice(packet);
+
+    record(interface, &frame);
+}
+
+/// Move as many captured frames as fit into `buf` as libpcap records (in the byte order of the host).
+///
+/// Records are never split, so `buf` should have room for at least `RECORD_HEADER_SIZE + SNAPSHOT_LENGTH` bytes.
+/// Returns the number of bytes written (0, if there are no frames).
+pub fn read(buf: &mut [u8]) -> usize {
+    let mut capture = CAPTURE.lock();
+    let mut written = 0;
+    while let Some(frame) = capture.frames.front() {
+        let end = written + RECORD_HEADER_SIZE + frame.data.len();
+        if end > buf.len() {
+            break;
+        }
+
+        let header = [
+            (frame.timestamp / 1000) as u32,
+            (frame.timestamp % 1000 * 1000) as u32,
+            frame.data.len() as u32,
+            frame.length as u32,
+        ];
+        for (i, field) in header.iter().enumerate() {
+            buf[written + i * 4..written + (i + 1) * 4].copy_from_slice(&field.to_ne_bytes());
+        }
+        buf[written + RECORD_HEADER_SIZE..end].copy_from_slice(&frame.data);
+        written = end;
+
+        let frame = capture.frames.pop_front().unwrap();
+        capture.buffered -= frame.data.len();
+    }
+
+    written
+}
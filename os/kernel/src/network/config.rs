@@ -0,0 +1,180 @@
+//! Static configuration of the network interfaces.
+//!
+//! By default, every network card gets its IPv4 address, gateway and DNS servers via DHCP.
+//! This can be changed per interface with entries of the form
+//!
+//! ```text
+//! ip=<interface>:dhcp
+//! ip=<interface>:off
+//! ip=<interface>:<address>/<prefix>[:<gateway>[:<dns server>,<dns server>,...]]
+//! ```
+//!
+//! `<interface>` is the driver name of the card or a part of it (e.g. `NE2000`).
+//! `off` turns DHCP off, without assigning an address (e.g. to add one later with the `ip` app).
+//! The entries are read from `etc/network.conf` in the initial ramdisk (one per line, `#` starts a comment)
+//! and from the kernel command line, which has the last word, if both configure the same interface.
+//!
+//! Example for a TAP device without DHCP server: `ip=NE2000:10.0.2.15/24:10.0.2.2:10.0.2.3`
+//!
+//! `ports=<first>-<last>` changes the range of the ephemeral ports (see `ports`), e.g. `ports=32768-60999`.
+//!
+//! `raw=<app>,<app>,...` lets these applications open packet and raw IP sockets (see `packet`), capture frames
+//! and change the addresses, routes and neighbor cache, `raw=*` lets all of them.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use core::str::FromStr;
+use log::{info, warn};
+use smoltcp::wire::{IpAddress, IpCidr};
+
+use crate::initrd;
+
+/// Path of the configuration file in the initial ramdisk
+pub const CONFIG_FILE: &str = "etc/network.conf";
+
+/// How an interface gets its addresses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Addressing {
+    /// Request an IPv4 address, gateway and DNS servers via DHCP (the default).
+    Dhcp,
+    /// Neither DHCP nor a static address.
+    Off,
+    Static {
+        address: IpCidr,
+        /// The default route goes through this router.
+        gateway: Option<IpAddress>,
+        dns_servers: Vec<IpAddress>,
+    },
+}
+
+/// Configuration of the interfaces, whose driver name contains `interface`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceConfig {
+    pub interface: String,
+    pub addressing: Addressing,
+}
+
+impl FromStr for InterfaceConfig {
+    type Err = ();
+
+    /// Parse the value of an `ip=` entry (without `ip=`).
+    fn from_str(entry: &str) -> Result<Self, ()> {
+        let mut fields = entry.split(':');
+        let interface = fields.next().filter(|interface| !interface.is_empty()).ok_or(())?.to_string();
+        let addressing = match fields.next().ok_or(())? {
+            "dhcp" => Addressing::Dhcp,
+            "off" => Addressing::Off,
+            address => {
+                let address = IpCidr::from_str(address)?;
+                let gateway = match fields.next() {
+                    None | Some("") => None,
+                    Some(gateway) => Some(IpAddress::from_str(gateway)?),
+                };
+                let dns_servers = match fields.next() {
+                    None | Some("") => Vec::new(),
+                    Some(servers) => servers.split(',').map(IpAddress::from_str).collect::<Result<_, _>>()?,
+                };
+                Addressing::Static { address, gateway, dns_servers }
+            }
+        };
+        if fields.next().is_some() {
+            return Err(());
+        }
+
+        Ok(Self { interface, addressing })
+    }
+}
+
+/// Everything, that is read from the configuration file and the kernel command line.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NetworkConfig {
+    pub interfaces: Vec<InterfaceConfig>,
+    /// Range of the ephemeral ports (`None` means the default)
+    pub ephemeral_ports: Option<RangeInclusive<u16>>,
+    /// Applications, that may open packet and raw IP sockets and capture frames (`None` means none)
+    pub privileged_apps: Option<Vec<String>>,
+}
+
+/// Parse the value of a `ports=` entry (without `ports=`). Port 0 can't be part of the range.
+fn parse_port_range(entry: &str) -> Option<RangeInclusive<u16>> {
+    let (first, last) = entry.split_once('-')?;
+    let (first, last) = (u16::from_str(first).ok()?, u16::from_str(last).ok()?);
+    (first != 0 && first <= last).then_some(first..=last)
+}
+
+/// Get the words of `text`, without comments.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        // everything after a '#' is a comment
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(str::split_whitespace)
+}
+
+/// Get the last valid `ports=` entry in `text`.
+pub fn parse_ports(text: &str) -> Option<RangeInclusive<u16>> {
+    words(text)
+        .filter_map(|word| word.strip_prefix("ports="))
+        .filter_map(|entry| {
+            let range = parse_port_range(entry);
+            if range.is_none() {
+                warn!("Ignoring invalid port range [ports={entry}]");
+            }
+            range
+        })
+        .last()
+}
+
+/// Get the applications of the last `raw=` entry in `text`.
+pub fn parse_privileged_apps(text: &str) -> Option<Vec<String>> {
+    words(text)
+        .filter_map(|word| word.strip_prefix("raw="))
+        .last()
+        .map(|apps| apps.split(',').filter(|app| !app.is_empty()).map(ToString::to_string).collect())
+}
+
+/// Get all `ip=` entries in `text`. Other words are ignored, so that this works for the kernel command line too.
+pub fn parse(text: &str) -> Vec<InterfaceConfig> {
+    words(text)
+        .filter_map(|word| word.strip_prefix("ip="))
+        .filter_map(|entry| match InterfaceConfig::from_str(entry) {
+            Ok(config) => Some(config),
+            Err(()) => {
+                warn!("Ignoring invalid network configuration [ip={entry}]");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Read the configuration file from the initial ramdisk and then the kernel command line.
+/// Later entries override earlier ones for the same interface.
+pub fn load(command_line: &str) -> NetworkConfig {
+    let mut entries = Vec::new();
+    let mut ephemeral_ports = None;
+    let mut privileged_apps = None;
+    if let Some(entry) = initrd().entries().find(|entry| entry.filename().as_str() == Ok(CONFIG_FILE)) {
+        match core::str::from_utf8(entry.data()) {
+            Ok(text) => {
+                entries.extend(parse(text));
+                ephemeral_ports = parse_ports(text);
+                privileged_apps = parse_privileged_apps(text);
+            }
+            Err(_) => warn!("{CONFIG_FILE} is not valid UTF-8"),
+        }
+    }
+    entries.extend(parse(command_line));
+    ephemeral_ports = parse_ports(command_line).or(ephemeral_ports);
+    privileged_apps = parse_privileged_apps(command_line).or(privileged_apps);
+
+    let mut configs: Vec<InterfaceConfig> = Vec::new();
+    for entry in entries {
+        configs.retain(|config| config.interface != entry.interface);
+        configs.push(entry);
+    }
+
+    for config in configs.iter() {
+        info!("Network configuration for [{}]: {:?}", config.interface, config.addressing);
+    }
+    NetworkConfig { interfaces: configs, ephemeral_ports, privileged_apps }
+}
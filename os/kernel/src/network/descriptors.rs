@@ -0,0 +1,115 @@
+//! Socket descriptors, that user programs use instead of smoltcp's socket handles.
+//!
+//! Every process has its own table, that maps small numbers (the lowest free one first) to a socket handle,
+//! the type, that the socket has been opened with, and how far it has been set up. The system calls
+//! look up every descriptor, so a program can neither reach the sockets of other processes
+//! nor use a socket as a different type (e.g. a UDP socket for `SockAccept`).
+//!
+//! Descriptors are separate from the handles of the naming service, both start at 0.
+//! Sockets inside the kernel (e.g. the one of the resolver) don't have descriptors.
+
+use alloc::collections::btree_map::BTreeMap;
+use smoltcp::iface::SocketHandle;
+use spin::Mutex;
+
+use super::SocketType;
+use crate::process_manager;
+
+/// A process can't have more sockets open than this.
+const MAX_DESCRIPTORS: usize = 1024;
+
+/// The descriptor tables of all processes, that have sockets (by process id)
+static TABLES: Mutex<BTreeMap<usize, BTreeMap<usize, Descriptor>>> = Mutex::new(BTreeMap::new());
+
+/// How far a socket has been set up by its program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketState {
+    Opened,
+    /// UDP and ICMP sockets, that have been bound to a port (or ident)
+    Bound,
+    /// TCP sockets, that have been bound and accept connections
+    Listening,
+    /// TCP sockets, that have connected or have been accepted
+    Connected,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Descriptor {
+    pub handle: SocketHandle,
+    pub socket_type: SocketType,
+    pub state: SocketState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorError {
+    /// The process has no socket with this descriptor.
+    BadDescriptor,
+    /// The socket has a different type, than the system call expects.
+    WrongType,
+    /// The process has `MAX_DESCRIPTORS` sockets open already.
+    TableFull,
+}
+
+/// This must be called before locking `TABLES`, because exiting processes lock it, while the process manager is locked.
+fn current_process_id() -> usize {
+    process_manager().read().current_process().id()
+}
+
+/// Give the socket `handle` a descriptor in the table of the current process.
+pub fn insert(handle: SocketHandle, socket_type: SocketType, state: SocketState) -> Result<usize, DescriptorError> {
+    let id = current_process_id();
+    let mut tables = TABLES.lock();
+    let table = tables.entry(id).or_default();
+    // the table is ordered, so the first gap is the lowest free descriptor
+    let descriptor = table.keys().enumerate().find(|(free, used)| free != *used).map_or(table.len(), |(free, _)| free);
+    if descriptor >= MAX_DESCRIPTORS {
+        return Err(DescriptorError::TableFull);
+    }
+    table.insert(descriptor, Descriptor { handle, socket_type, state });
+    Ok(descriptor)
+}
+
+/// Look up a descriptor of the current process.
+pub fn get(descriptor: usize) -> Result<Descriptor, DescriptorError> {
+    let id = current_process_id();
+    TABLES
+        .lock()
+        .get(&id)
+        .and_then(|table| table.get(&descriptor))
+        .copied()
+        .ok_or(DescriptorError::BadDescriptor)
+}
+
+/// Look up a descriptor of the current process, that has to belong to a socket of `socket_type`.
+pub fn get_typed(descriptor: usize, socket_type: SocketType) -> Result<Descriptor, DescriptorError> {
+    let found = get(descriptor)?;
+    if found.socket_type != socket_type {
+        return Err(DescriptorError::WrongType);
+    }
+    Ok(found)
+}
+
+/// Remember, that the socket behind `descriptor` has been bound, connected, etc.
+pub fn set_state(descriptor: usize, state: SocketState) {
+    let id = current_process_id();
+    if let Some(found) = TABLES.lock().get_mut(&id).and_then(|table| table.get_mut(&descriptor)) {
+        found.state = state;
+    }
+}
+
+/// Remove a descriptor of the current process. Returns what it has referred to, so that the caller can close the socket.
+pub fn remove(descriptor: usize) -> Result<Descriptor, DescriptorError> {
+    let id = current_process_id();
+    let mut tables = TABLES.lock();
+    let table = tables.get_mut(&id).ok_or(DescriptorError::BadDescriptor)?;
+    let removed = table.remove(&descriptor).ok_or(DescriptorError::BadDescriptor)?;
+    if table.is_empty() {
+        tables.remove(&id);
+    }
+    Ok(removed)
+}
+
+/// Drop the table of a process, that is exiting (its sockets are closed by `close_sockets_for_process()`).
+pub(crate) fn close_for_process(process_id: usize) {
+    TABLES.lock().remove(&process_id);
+}
@@ -1,213 +1,479 @@
-use crate::device::ne2k::consts::{DEVICE_ID, VENDOR_ID};
-use crate::device::rtl8139::Rtl8139;
-use alloc::collections::btree_map::BTreeMap;
-// add the N2000 driver
-use crate::device::ne2k::ne2000::Ne2000;
+pub mod capture;
+pub mod config;
+pub mod descriptors;
+pub mod packet;
+pub mod ports;
+pub mod resolver;
+pub mod slaac;
+
+use crate::device::loopback::{self, Loopback};
+use crate::device::nic;
+use crate::device::nic::{NetworkDevice, SmoltcpDevice};
+use crate::network::config::Addressing;
+use crate::network::ports::{PortError, Protocol};
+use crate::network::slaac::Slaac;
 use crate::process::process::Process;
 use crate::process::thread::Thread;
-use crate::{pci_bus, process_manager, scheduler, timer};
+use crate::{process_manager, scheduler, timer};
+use alloc::collections::btree_map::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::mem;
 use core::net::{Ipv4Addr, Ipv6Addr};
-use core::ops::Deref;
-use core::ptr;
-use core::sync::atomic::Ordering;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, Ordering};
 use log::{info, warn};
-use smoltcp::iface::{self, Interface, PollResult, SocketHandle, SocketSet};
-use smoltcp::socket::dns::GetQueryResultError;
-use smoltcp::socket::{dhcpv4, dns, icmp, tcp, udp};
-use smoltcp::time::Instant;
-use smoltcp::wire::{DnsQueryType, HardwareAddress, IpAddress, IpCidr, IpEndpoint};
-use spin::{Once, RwLock};
-
-static RTL8139: Once<Arc<Rtl8139>> = Once::new();
-// ensure that the driver is only initialized once
-static NE2000: Once<Arc<Ne2000>> = Once::new();
-
-static INTERFACES: RwLock<Vec<Interface>> = RwLock::new(Vec::new());
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use smoltcp::iface::{self, Interface, PollResult, Route, SocketHandle, SocketSet};
+use smoltcp::socket::{dhcpv4, icmp, raw, tcp, udp, Socket};
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{
+    EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint, IpListenEndpoint, IpProtocol, IpVersion, Ipv4Cidr,
+    Ipv6Cidr, IPV4_MULTICAST_ALL_SYSTEMS, IPV6_LINK_LOCAL_ALL_NODES,
+};
+use spin::{Mutex, Once, RwLock};
+use syscall::return_vals::Errno;
+
+/// All network cards, that have been brought up as smoltcp interfaces, followed by the loopback interface.
+/// They are all polled by the same thread (in this order) and share one socket set,
+/// but every interface only sends the packets, that are routed through it (see `RoutingTable`).
+static INTERFACES: RwLock<Vec<NetworkInterface>> = RwLock::new(Vec::new());
 static SOCKETS: Once<RwLock<SocketSet>> = Once::new();
 /// This maps sockets to the respective process.
 /// We use this to check whether a process can access a particular socket.
 /// We can't just create a SocketSet per process because smoltcp drops all
 /// packets for non-existing sockets when polling.
 static SOCKET_PROCESS: RwLock<BTreeMap<SocketHandle, Arc<Process>>> = RwLock::new(BTreeMap::new());
-static DNS_SOCKET: Once<SocketHandle> = Once::new();
-static DHCP_SOCKET: Once<SocketHandle> = Once::new();
-
-#[derive(Debug)]
+/// Set by interrupt handlers and socket operations, to wake up the network thread.
+static WORKER_NOTIFIED: Once<Arc<AtomicBool>> = Once::new();
+/// Blocking behaviour of the sockets (sockets without an entry use the defaults).
+static SOCKET_OPTIONS: RwLock<BTreeMap<SocketHandle, SocketOptions>> = RwLock::new(BTreeMap::new());
+/// Listening TCP sockets (see `bind_tcp()`).
+static LISTENERS: RwLock<BTreeMap<SocketHandle, Listener>> = RwLock::new(BTreeMap::new());
+/// Closed TCP connections, that are still sending their remaining data, and when to give up on them.
+/// They have no owner anymore and are removed by the network thread (see `reap_closed_sockets()`).
+static CLOSING: Mutex<Vec<(SocketHandle, usize)>> = Mutex::new(Vec::new());
+/// Multicast groups, that UDP sockets have joined, together with the interface (driver name), on which they have joined them.
+/// An interface stays in a group, as long as one socket is a member (see `join_multicast_group()`).
+static MEMBERSHIPS: Mutex<BTreeMap<SocketHandle, Vec<(&'static str, IpAddress)>>> = Mutex::new(BTreeMap::new());
+/// Threads waiting for a socket to become ready (see `wait_for()`).
+/// The network thread sets the flag of a waiting thread, when its socket is ready.
+static WAIT_QUEUES: Mutex<BTreeMap<SocketHandle, Vec<(Interest, Arc<AtomicBool>)>>> = Mutex::new(BTreeMap::new());
+/// The network thread sleeps at most this long, even if smoltcp has nothing to do.
+const MAX_POLL_DELAY: Duration = Duration::from_millis(1000);
+/// Sleep time, if polling failed because a socket operation holds the locks.
+const RETRY_POLL_DELAY: Duration = Duration::from_millis(1);
+/// Threads waiting for a socket check it at least this often (in ms), in case a wakeup got lost.
+const MAX_WAIT_TIME: usize = 1000;
+/// Number of datagrams, that fit into the buffers of a UDP socket
+const UDP_PACKET_SLOTS: usize = 3000;
+/// Default size of the buffers of a UDP socket (in bytes)
+const UDP_BUFFER_SIZE: usize = UDP_PACKET_SLOTS * 10000;
+/// Default size of the buffers of a TCP socket (in bytes)
+const TCP_BUFFER_SIZE: usize = 65535;
+/// Closed TCP connections are reset after this many ms, if the remote host hasn't taken all data by then
+const LINGER_TIME: usize = 30000;
+/// Number of connections, that a listening TCP socket can have pending, if the program doesn't specify it
+const DEFAULT_BACKLOG: usize = 8;
+/// Listening TCP sockets can't have more pending connections than this
+const MAX_BACKLOG: usize = 64;
+/// Socket buffers can't be bigger than this (in bytes), so that they always fit into the kernel heap
+const MAX_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 #[non_exhaustive]
 pub enum SocketType {
     Udp,
     Tcp,
     Icmp,
+    /// Whole IP packets of one protocol, including the IP header (only for privileged applications, see `packet`)
+    Raw,
 }
 
-pub fn init() {
-    SOCKETS.call_once(|| RwLock::new(SocketSet::new(Vec::new())));
+/// What a thread is waiting for, when it blocks on a socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interest {
+    /// Data can be received (or the connection has been closed).
+    Receive,
+    /// Data can be sent (or the connection has been closed).
+    Send,
+    /// A TCP connection has been established (or has failed).
+    Connection,
+}
+
+/// Per socket settings, that smoltcp doesn't know about.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SocketOptions {
+    /// Return immediately, instead of waiting for the socket to become ready.
+    pub nonblocking: bool,
+    /// Reading has been shut down, so all received data is discarded.
+    pub read_shut_down: bool,
+    /// Give up waiting to receive (or for a connection) after this many milliseconds (`None` waits forever).
+    pub read_timeout: Option<usize>,
+    /// Give up waiting to send after this many milliseconds (`None` waits forever).
+    pub write_timeout: Option<usize>,
+    /// Allow sending UDP datagrams to broadcast addresses.
+    pub broadcast: bool,
+}
 
-    let enable_rtl8139 = false;
-    let enable_ne2k = true;
+impl SocketOptions {
+    /// The timeout for waiting on `interest`.
+    pub fn timeout(&self, interest: Interest) -> Option<usize> {
+        match interest {
+            Interest::Send => self.write_timeout,
+            Interest::Receive | Interest::Connection => self.read_timeout,
+        }
+    }
+}
 
-    if enable_rtl8139 {
-        let devices = pci_bus().search_by_ids(0x10ec, 0x8139);
-        if !devices.is_empty() {
-            RTL8139.call_once(|| {
-                info!("Found Realtek RTL8139 network controller");
-                let rtl8139 = Arc::new(Rtl8139::new(devices[0]));
-                info!("RTL8139 MAC address: [{}]", rtl8139.read_mac_address());
+/// Options that user programs can change with `SockSetOpt` and read with `SockGetOpt`.
+///
+/// The numbers are part of the system call interface and must match `library/network`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(usize)]
+pub enum SocketOption {
+    /// Return EAGAIN instead of blocking (0 or 1).
+    Nonblocking = 0,
+    /// Timeout for receiving, accepting and connecting in ms (0 means none, at most `isize::MAX`).
+    ReadTimeout = 1,
+    /// Timeout for sending in ms (0 means none, at most `isize::MAX`).
+    WriteTimeout = 2,
+    /// Size of the receive buffer in bytes (fixed, once a TCP socket is connected).
+    RecvBufferSize = 3,
+    /// Size of the send buffer in bytes (fixed, once a TCP socket is connected).
+    SendBufferSize = 4,
+    /// Collect small TCP segments before sending them (Nagle's algorithm, 0 or 1).
+    Nagle = 5,
+    /// Interval of TCP keep-alive packets in ms (0 disables them, at most `isize::MAX`).
+    KeepAlive = 6,
+    /// Hop limit (TTL) of outgoing packets (0 means the default).
+    HopLimit = 7,
+    /// Allow sending UDP datagrams to broadcast addresses (0 or 1).
+    Broadcast = 8,
+}
 
-                Rtl8139::plugin(Arc::clone(&rtl8139));
-                rtl8139
-            });
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketOptionError {
+    /// The option doesn't exist for this type of socket.
+    Unsupported,
+    /// The value is out of range.
+    InvalidValue,
+    /// The buffers of a connected TCP socket can't be resized.
+    Connected,
+    /// The buffers can't be resized, because they still hold data, that would be lost.
+    Busy,
+}
+
+/// How to shut down a TCP connection (see `shutdown_tcp()`).
+///
+/// The numbers are part of the system call interface and must match `library/network`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(usize)]
+pub enum Shutdown {
+    /// Discard all data, that is received from now on.
+    Read = 0,
+    /// Send a FIN, once all queued data has been sent.
+    Write = 1,
+    /// Both of the above.
+    Both = 2,
+    /// Reset the connection right away, discarding all queued data.
+    Abort = 3,
+}
+
+/// A listening TCP socket.
+///
+/// smoltcp knows no backlog, a listening socket just becomes the connection.
+/// So we keep a pool of sockets listening on the same endpoint and replace
+/// every socket, that has been connected, with a fresh one when accepting.
+struct Listener {
+    endpoint: IpListenEndpoint,
+    /// the first handle is the one of the listener itself
+    backlog: Vec<SocketHandle>,
+}
+
+/// A network card together with its smoltcp interface.
+///
+/// Every interface has its own addresses, routes and DHCP client (unless it has been configured statically, see `config`).
+/// Network cards also configure their IPv6 addresses themselves (see `slaac`).
+struct NetworkInterface {
+    /// Name of the driver (see `nic::DRIVERS`)
+    name: &'static str,
+    device: Arc<dyn NetworkDevice>,
+    interface: Interface,
+    /// The DHCP socket is only added to the socket set while this interface is polled.
+    /// Otherwise, every interface would send the DHCP requests of all other interfaces
+    /// and the socket would ignore the replies (the client hardware address doesn't match).
+    dhcp_socket: Option<dhcpv4::Socket<'static>>,
+    /// The address assigned by DHCP. Only this one is replaced, when the lease changes,
+    /// addresses added with `add_ip_address` are kept.
+    dhcp_address: Option<Ipv4Cidr>,
+    /// IPv6 autoconfiguration (`None` for the loopback interface)
+    slaac: Option<Slaac>,
+    /// Multicast groups, that have been joined with `join_multicast_group`
+    multicast_groups: Vec<IpAddress>,
+}
+
+impl NetworkInterface {
+    fn new(name: &'static str, device: Arc<dyn NetworkDevice>) -> Self {
+        let time = timer().systime_ms();
+        let mut conf = iface::Config::new(HardwareAddress::from(device.mac_address()));
+        conf.random_seed = time as u64;
+
+        let mut interface = Interface::new(conf, &mut SmoltcpDevice::new(name, device.as_ref()), Instant::from_millis(time as i64));
+
+        // the link-local address is needed for neighbor discovery and for asking the routers for a prefix
+        let slaac = Slaac::new(device.mac_address());
+        interface.update_ip_addrs(|addrs| {
+            addrs.push(IpCidr::Ipv6(slaac.link_local_address())).expect("failed to add link-local address");
+        });
+        info!("IPv6 link-local address: {}", slaac.link_local_address());
+
+        // request an IP address via DHCP
+        let interface = Self {
+            name,
+            device,
+            interface,
+            dhcp_socket: Some(dhcpv4::Socket::new()),
+            dhcp_address: None,
+            slaac: Some(slaac),
+            multicast_groups: Vec::new(),
+        };
+        interface.update_multicast_filter();
+        interface
+    }
+
+    /// Create the loopback interface with the addresses 127.0.0.1/8 and ::1/128.
+    fn new_loopback() -> Self {
+        let time = timer().systime_ms();
+        let device: Arc<dyn NetworkDevice> = Arc::new(Loopback::new());
+        let mut conf = iface::Config::new(HardwareAddress::Ip);
+        conf.random_seed = time as u64;
+
+        let mut interface = Interface::new(conf, &mut SmoltcpDevice::new(loopback::NAME, device.as_ref()), Instant::from_millis(time as i64));
+        interface.update_ip_addrs(|addrs| {
+            addrs.push(IpCidr::Ipv4(Ipv4Cidr::new(Ipv4Addr::LOCALHOST, 8))).unwrap();
+            addrs.push(IpCidr::Ipv6(Ipv6Cidr::new(Ipv6Addr::LOCALHOST, 128))).unwrap();
+        });
+
+        // the addresses are fixed, so there is no DHCP client
+        Self { name: loopback::NAME, device, interface, dhcp_socket: None, dhcp_address: None, slaac: None, multicast_groups: Vec::new() }
+    }
+
+    /// Tell the card, which multicast frames it should accept.
+    ///
+    /// Besides the joined groups, smoltcp is always a member of the all-systems/all-nodes groups
+    /// and of the solicited-node groups of our IPv6 addresses (needed for neighbor discovery).
+    /// This has to be called whenever the joined groups or the addresses change.
+    fn update_multicast_filter(&self) {
+        let mut groups = vec![IpAddress::Ipv4(IPV4_MULTICAST_ALL_SYSTEMS), IpAddress::Ipv6(IPV6_LINK_LOCAL_ALL_NODES)];
+        for cidr in self.interface.ip_addrs() {
+            if let IpAddress::Ipv6(address) = cidr.address() {
+                let [.., b13, b14, b15] = address.octets();
+                groups.push(IpAddress::Ipv6(Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xff00 | b13 as u16, u16::from_be_bytes([b14, b15]))));
+            }
         }
+        groups.extend(self.multicast_groups.iter().copied());
 
-        if let Some(rtl8139) = RTL8139.get() {
-            extern "sysv64" fn poll() {
-                loop {
-                    poll_sockets();
-                    //scheduler().sleep(50);
-                }
+        let addresses: Vec<_> = groups.iter().map(multicast_ethernet_address).collect();
+        self.device.set_multicast_filter(&addresses);
+    }
+
+    /// Process incoming and outgoing packets of this interface and check the DHCP and SLAAC status.
+    ///
+    /// `index` is the position of this interface in `routing`. Packets, that are routed through another interface,
+    /// stay in their sockets, until that interface is polled.
+    fn poll(&mut self, time: Instant, sockets: &mut SocketSet<'static>, routing: &RoutingTable, index: usize) -> PollResult {
+        // interfaces without DHCP client (e.g. the loopback interface) have no socket of their own
+        let dhcp_handle = self.dhcp_socket.take().map(|dhcp_socket| sockets.add(dhcp_socket));
+        let slaac_handle = self.slaac.as_mut().map(|slaac| slaac.attach(time, sockets));
+
+        packet::transmit_queued(self.name, self.device.as_ref());
+        let mut device = SmoltcpDevice::new(self.name, self.device.as_ref());
+        // the DHCP and SLAAC sockets belong to this interface, no matter where their packets go
+        let result = self.interface.poll_filtered(time, &mut device, sockets, &mut |handle, destination| {
+            Some(handle) == dhcp_handle || Some(handle) == slaac_handle || routing.interface_index(destination) == index
+        });
+
+        if let Some(dhcp_handle) = dhcp_handle {
+            self.check_dhcp(sockets, dhcp_handle);
+            self.restore_dhcp_socket(sockets, dhcp_handle);
+        }
+        if let Some(slaac_handle) = slaac_handle {
+            let slaac = self.slaac.as_mut().expect("SLAAC has been removed while polling");
+            if slaac.detach(time, &mut self.interface, sockets, slaac_handle) {
+                // the addresses have changed
+                self.update_multicast_filter();
             }
-            scheduler().ready(Thread::new_kernel_thread(poll, "RTL8139"));
-
-            //scheduler().ready(Thread::new_kernel_thread(poll_interrupts, "check_interrupts"));
-
-            // Set up network interface
-            let time = timer().systime_ms();
-            let mut conf = iface::Config::new(HardwareAddress::from(rtl8139.read_mac_address()));
-            conf.random_seed = time as u64;
-
-            // The Smoltcp interface struct wants a mutable reference to the device.
-            // However, the RTL8139 driver is designed to work with shared references.
-            // Since smoltcp does not actually store the mutable reference anywhere,
-            // we can safely cast the shared reference to a mutable one.
-            // (Actually, I am not sure why the smoltcp interface wants a mutable reference to the device,
-            // since it does not modify the device itself.)
-            let device = unsafe { ptr::from_ref(rtl8139.deref()).cast_mut().as_mut().unwrap() };
-            add_interface(Interface::new(conf, device, Instant::from_millis(time as i64)));
-
-            let sockets = SOCKETS.get().expect("Socket set not initialized!");
-            let current_process = process_manager().read().current_process();
-            let mut process_map = SOCKET_PROCESS.write();
-            // setup DNS
-            DNS_SOCKET.call_once(|| {
-                let dns_socket = dns::Socket::new(&[], Vec::new());
-                let dns_handle = sockets.write().add(dns_socket);
-                process_map
-                    .try_insert(dns_handle, current_process.clone())
-                    .expect("failed to insert socket into socket-process map");
-                dns_handle
-            });
-            // request an IP address via DHCP
-            DHCP_SOCKET.call_once(|| {
-                let dhcp_socket = dhcpv4::Socket::new();
-                let dhcp_handle = sockets.write().add(dhcp_socket);
-                process_map
-                    .try_insert(dhcp_handle, current_process)
-                    .expect("failed to insert socket into socket-process map");
-                dhcp_handle
-            });
         }
+        result
     }
 
-    // =============================================================================
-    // Register the Ne2000 card here
-    // wrap into Arc for shared ownership
-    // Scans PCI bus for Ne2000 cards or similar by looking at the device id and vendor id.
-    // References:
-    // - https://en.wikibooks.org/wiki/QEMU/Devices/Network -> the nic model
-    // - https://theretroweb.com/chips/4692 -> device id and vendor id
-    // =============================================================================
-    if enable_ne2k {
-        // get the EndpointHeader
-        // the endpoint header contains essential information about the device,
-        // such as the Vendor ID (VID), Device ID (DID), and other configuration parameters
-        let device_ne2k = pci_bus().search_by_ids(VENDOR_ID, DEVICE_ID);
-        if device_ne2k.len() > 0 {
-            // perform the initialization only once!
-            NE2000.call_once(|| {
-                info!("\x1b[1;31mFound Realtek 8029 network controller");
-                // initialize the driver
-                let device = Ne2000::new(device_ne2k[0]);
-                // wrap the instance in an Arc for sharing in a multithreaded context
-                let ne2k = Arc::new(device);
-
-                //read the mac address
-                info!("\x1b[1;31mNe2000 MAC address: [{}]", ne2k.get_mac());
-                //enable interrupt handler
-                Ne2000::assign(Arc::clone(&ne2k));
-                info!("assigned Interrupt handler");
-
-                extern "sysv64" fn poll_interrupts() {
-                    loop {
-                        check_interrupts();
+    /// Check whether a DHCP lease has been acquired or lost.
+    fn check_dhcp(&mut self, sockets: &mut SocketSet<'static>, dhcp_handle: SocketHandle) {
+        // DHCP handling is based on https://github.com/smoltcp-rs/smoltcp/blob/main/examples/dhcp_client.rs
+        // Johann Spenrath on 05.09.2025:
+        // check dhcp status (lease acquired or lost)
+        let dhcp_socket = sockets.get_mut::<dhcpv4::Socket>(dhcp_handle);
+        if let Some(event) = dhcp_socket.poll() {
+            let interface = &mut self.interface;
+            // remove the address of the previous lease
+            if let Some(address) = self.dhcp_address.take() {
+                interface.update_ip_addrs(|addrs| addrs.retain(|cidr| *cidr != IpCidr::Ipv4(address)));
+            }
+            match event {
+                dhcpv4::Event::Deconfigured => {
+                    info!("lost DHCP lease");
+                    interface.routes_mut().remove_default_ipv4_route();
+                }
+                dhcpv4::Event::Configured(config) => {
+                    info!("acquired DHCP lease:");
+                    info!("IP address: {}", config.address);
+                    interface.update_ip_addrs(|addrs| {
+                        addrs.push(IpCidr::Ipv4(config.address)).expect("too many IP addresses");
+                    });
+                    self.dhcp_address = Some(config.address);
+
+                    if let Some(router) = config.router {
+                        info!("default gateway: {router}");
+                        interface.routes_mut().add_default_ipv4_route(router).unwrap();
+                    } else {
+                        info!("no default gateway");
+                        interface.routes_mut().remove_default_ipv4_route();
                     }
+                    info!("DNS servers: {:?}", config.dns_servers);
+                    let dns_servers: Vec<_> = config.dns_servers.iter().map(|ip| IpAddress::Ipv4(*ip)).collect();
+                    resolver::set_servers(&dns_servers);
                 }
-                scheduler().ready(Thread::new_kernel_thread(poll_interrupts, "NE2K: check_interrupts"));
+            }
+            // the addresses have changed
+            self.update_multicast_filter();
+        }
+    }
 
-                ne2k
-            });
+    /// Get the time until this interface needs to be polled again (`None` means it has nothing to do).
+    fn poll_delay(&mut self, time: Instant, sockets: &mut SocketSet<'static>) -> Option<Duration> {
+        // the DHCP client has its own timers (e.g. for renewing the lease)
+        let dhcp_handle = self.dhcp_socket.take().map(|dhcp_socket| sockets.add(dhcp_socket));
+        let delay = self.interface.poll_delay(time, sockets);
+        if let Some(dhcp_handle) = dhcp_handle {
+            self.restore_dhcp_socket(sockets, dhcp_handle);
         }
-        // =============================================================================
-        // create new thread which polls for the fields rcv and ovw
-        // if the trigger method gets called by an packet received
-        // or receive buffer overwrite interrupt, the check method
-        // calls the corresponding method to handle the interrupt
-        // =============================================================================
-        if let Some(ne2k) = NE2000.get() {
-            extern "sysv64" fn poll() {
-                loop {
-                    poll_sockets();
-                    //scheduler().sleep(1);
-                    //scheduler().switch_thread_no_interrupt();
+
+        // so has SLAAC (router solicitations and address lifetimes)
+        let slaac_delay = self.slaac.as_ref().and_then(|slaac| slaac.poll_delay(time));
+        [delay, slaac_delay].into_iter().flatten().min()
+    }
+
+    fn restore_dhcp_socket(&mut self, sockets: &mut SocketSet<'static>, dhcp_handle: SocketHandle) {
+        match sockets.remove(dhcp_handle) {
+            Socket::Dhcpv4(dhcp_socket) => self.dhcp_socket = Some(dhcp_socket),
+            _ => panic!("DHCP socket has been replaced"),
+        }
+    }
+
+    /// Interfaces without link layer (e.g. the loopback interface) have no neighbors.
+    fn has_neighbors(&self) -> bool {
+        matches!(self.interface.hardware_addr(), HardwareAddress::Ethernet(_))
+    }
+
+    /// Apply a configuration from the kernel command line or the configuration file (see `config`).
+    fn configure(&mut self, addressing: &Addressing) {
+        match addressing {
+            Addressing::Dhcp => {
+                if self.dhcp_socket.is_none() {
+                    self.dhcp_socket = Some(dhcpv4::Socket::new());
                 }
             }
-            scheduler().ready(Thread::new_kernel_thread(poll, "NE2000"));
-            // return the instance
-            //ne2k
-            // Set up network interface
-            // if NE2000 is initialized, start a new thread,
-            // which calls poll_ne2000 in an infinite loop
-            // the method checks for any outgoing or incoming packages in the buffers of
-            // the device or in the buffers of the sockets
-            let time = timer().systime_ms();
-            let mut conf = iface::Config::new(HardwareAddress::from(ne2k.get_mac()));
-            conf.random_seed = time as u64;
-
-            // The Smoltcp interface struct wants a mutable reference to the device.
-            // However, the RTL8139 driver is designed to work with shared references.
-            // Since smoltcp does not actually store the mutable reference anywhere,
-            // we can safely cast the shared reference to a mutable one.
-            // (Actually, I am not sure why the smoltcp interface wants a mutable reference to the device,
-            // since it does not modify the device itself.)
-            let device = unsafe { ptr::from_ref(ne2k.deref()).cast_mut().as_mut().unwrap() };
-            add_interface(Interface::new(conf, device, Instant::from_millis(time as i64)));
-
-            let sockets = SOCKETS.get().expect("Socket set not initialized!");
-            let current_process = process_manager().read().current_process();
-            let mut process_map = SOCKET_PROCESS.write();
-            // setup DNS
-            DNS_SOCKET.call_once(|| {
-                let dns_socket = dns::Socket::new(&[], Vec::new());
-                let dns_handle = sockets.write().add(dns_socket);
-                process_map
-                    .try_insert(dns_handle, current_process.clone())
-                    .expect("failed to insert socket into socket-process map");
-                dns_handle
-            });
-            // request an IP address via DHCP
-            DHCP_SOCKET.call_once(|| {
-                let dhcp_socket = dhcpv4::Socket::new();
-                let dhcp_handle = sockets.write().add(dhcp_socket);
-                process_map
-                    .try_insert(dhcp_handle, current_process)
-                    .expect("failed to insert socket into socket-process map");
-                dhcp_handle
-            });
+            Addressing::Off => self.dhcp_socket = None,
+            Addressing::Static { address, gateway, dns_servers } => {
+                self.dhcp_socket = None;
+                self.interface.update_ip_addrs(|addrs| {
+                    if !addrs.contains(address) {
+                        addrs.push(*address).expect("too many IP addresses");
+                    }
+                });
+                match gateway {
+                    Some(IpAddress::Ipv4(router)) => {
+                        self.interface.routes_mut().add_default_ipv4_route(*router).expect("too many routes");
+                    }
+                    Some(IpAddress::Ipv6(router)) => {
+                        self.interface.routes_mut().add_default_ipv6_route(*router).expect("too many routes");
+                    }
+                    None => {}
+                }
+                if !dns_servers.is_empty() {
+                    resolver::set_servers(dns_servers);
+                }
+                self.update_multicast_filter();
+            }
+        }
+    }
+}
+
+/// Bring up all network cards and the loopback interface and start the network thread.
+///
+/// Interfaces are configured via DHCP, unless `command_line` or the configuration file say otherwise (see `config`).
+pub fn init(command_line: &str) {
+    SOCKETS.call_once(|| RwLock::new(SocketSet::new(Vec::new())));
+    WORKER_NOTIFIED.call_once(|| Arc::new(AtomicBool::new(false)));
+
+    // search the PCI bus for all network cards, we have a driver for
+    for (name, device) in nic::probe() {
+        add_interface(name, device);
+    }
+    // the loopback interface is always there, even without network cards
+    INTERFACES.write().push(NetworkInterface::new_loopback());
+
+    // setup DNS
+    resolver::init();
+
+    // static addresses, DHCP opt-outs, the ephemeral ports and who may use packet sockets
+    let config = config::load(command_line);
+    if let Some(range) = config.ephemeral_ports {
+        ports::set_ephemeral_range(range);
+    }
+    if let Some(apps) = config.privileged_apps {
+        packet::set_privileged_apps(apps);
+    }
+    {
+        let mut interfaces = INTERFACES.write();
+        for config in config.interfaces {
+            let mut found = false;
+            for interface in interfaces.iter_mut().filter(|interface| interface.name.contains(config.interface.as_str())) {
+                interface.configure(&config.addressing);
+                found = true;
+            }
+            if !found {
+                warn!("No network interface matches [{}]", config.interface);
+            }
+        }
+    }
+
+    // one thread polls all interfaces
+    // the method checks for any outgoing or incoming packages in the buffers of
+    // the devices or in the buffers of the sockets
+    // between two polls, the thread sleeps until an interrupt handler or a socket operation
+    // wakes it up, or until smoltcp needs to be polled again (e.g. for TCP retransmissions)
+    extern "sysv64" fn poll() {
+        // the set of cards doesn't change after init, so collect them only once
+        let devices: Vec<_> = INTERFACES.read().iter().map(|interface| Arc::clone(&interface.device)).collect();
+        let notified = WORKER_NOTIFIED.get().expect("Network thread flag not initialized!");
+        loop {
+            // everything, that happens from now on, is handled by this iteration
+            notified.store(false, Ordering::SeqCst);
+
+            // handle what the interrupt handlers have left for us (e.g. received packets)
+            for device in devices.iter() {
+                device.process_interrupts();
+            }
+            let delay = poll_sockets().unwrap_or(RETRY_POLL_DELAY);
+
+            scheduler().sleep_until_notified(delay.total_millis() as usize, notified);
         }
     }
+    scheduler().ready(Thread::new_kernel_thread(poll, "network"));
 }
 
 fn check_ownership(handle: SocketHandle) {
@@ -228,128 +494,761 @@ macro_rules! get_socket_for_current_process {
     };
 }
 
-pub fn rtl8139() -> Option<Arc<Rtl8139>> {
-    match RTL8139.get() {
-        Some(rtl8139) => Some(Arc::clone(rtl8139)),
-        None => None,
+/// Wake up the network thread, so that it polls all interfaces.
+///
+/// This only sets a flag and may be called from interrupt handlers.
+pub fn notify_worker() {
+    if let Some(notified) = WORKER_NOTIFIED.get() {
+        notified.store(true, Ordering::SeqCst);
     }
 }
 
-// =============================================================================
-// add ne2000 function
-// =============================================================================
-// safely share access to global, reference-counted nic
-// Once get method : Returns a reference to the inner value if the Once has been initialized.
-// Pattern matching : if some, return the cloned pointer of Ne2000
-// else none
-// =============================================================================
-pub fn ne2000() -> Option<Arc<Ne2000>> {
-    match NE2000.get() {
-        Some(ne2000) => Some(Arc::clone(ne2000)),
-        None => None,
+pub fn socket_options(handle: SocketHandle) -> SocketOptions {
+    check_ownership(handle);
+    SOCKET_OPTIONS.read().get(&handle).copied().unwrap_or_default()
+}
+
+/// Read an option of a socket (see `SocketOption` for the encoding of the values).
+pub fn get_socket_option(handle: SocketHandle, option: SocketOption) -> Result<usize, SocketOptionError> {
+    let options = socket_options(handle);
+    let sockets = SOCKETS.get().expect("Socket set not initialized!").read();
+    let (_, socket) = sockets.iter().find(|(h, _)| *h == handle).expect("process tried accessing non-existent socket");
+    let millis = |duration: Option<Duration>| duration.map_or(0, |duration| duration.total_millis() as usize);
+    match (option, socket) {
+        (SocketOption::Nonblocking, _) => Ok(options.nonblocking.into()),
+        (SocketOption::ReadTimeout, _) => Ok(options.read_timeout.unwrap_or(0)),
+        (SocketOption::WriteTimeout, _) => Ok(options.write_timeout.unwrap_or(0)),
+        (SocketOption::Broadcast, Socket::Udp(_)) => Ok(options.broadcast.into()),
+        (SocketOption::RecvBufferSize, Socket::Udp(socket)) => Ok(socket.payload_recv_capacity()),
+        (SocketOption::RecvBufferSize, Socket::Tcp(socket)) => Ok(socket.recv_capacity()),
+        (SocketOption::SendBufferSize, Socket::Udp(socket)) => Ok(socket.payload_send_capacity()),
+        (SocketOption::SendBufferSize, Socket::Tcp(socket)) => Ok(socket.send_capacity()),
+        (SocketOption::Nagle, Socket::Tcp(socket)) => Ok(socket.nagle_enabled().into()),
+        (SocketOption::KeepAlive, Socket::Tcp(socket)) => Ok(millis(socket.keep_alive())),
+        (SocketOption::HopLimit, Socket::Udp(socket)) => Ok(socket.hop_limit().unwrap_or(0).into()),
+        (SocketOption::HopLimit, Socket::Tcp(socket)) => Ok(socket.hop_limit().unwrap_or(0).into()),
+        (SocketOption::HopLimit, Socket::Icmp(socket)) => Ok(socket.hop_limit().unwrap_or(0).into()),
+        _ => Err(SocketOptionError::Unsupported),
     }
 }
 
-// =============================================================================
-// function check_interrupts()
-// check for overflow interrupt and receive packet interrupt
-// the trigger function sets the booleans , this function calls
-// the appropriate function to handle the interrupt
-// =============================================================================
-pub fn check_interrupts() {
-    let ne2000 = NE2000.get().expect("NE2000 not initialized");
-    let device = unsafe { ptr::from_ref(ne2000.deref()).cast_mut().as_mut().unwrap() };
-    // check if interrupt occured
-    // Packet received ?
-    if device.check_interrupts.prx.load(Ordering::Relaxed) {
-        // reset the AtomicBool after handling the interrupt
-        device.check_interrupts.prx.store(false, Ordering::Relaxed);
-        device.receive_packet();
+/// Change an option of a socket (see `SocketOption` for the encoding of the values).
+pub fn set_socket_option(handle: SocketHandle, option: SocketOption, value: usize) -> Result<(), SocketOptionError> {
+    // all values have to fit into the return value of `SockGetOpt`
+    if value > isize::MAX as usize {
+        return Err(SocketOptionError::InvalidValue);
+    }
+    let mut options = socket_options(handle);
+    let timeout = if value == 0 { None } else { Some(value) };
+    match option {
+        SocketOption::Nonblocking => options.nonblocking = value != 0,
+        SocketOption::ReadTimeout => options.read_timeout = timeout,
+        SocketOption::WriteTimeout => options.write_timeout = timeout,
+        SocketOption::Broadcast if is_udp_socket(handle) => options.broadcast = value != 0,
+        _ => {
+            // the sockets in the backlog of a listener become its connections, so they need the same settings
+            for handle in backlog(handle) {
+                set_smoltcp_option(handle, option, value)?;
+            }
+            // keep-alive packets and hop limits affect when and what the network thread sends
+            notify_worker();
+            return Ok(());
+        }
+    }
+    SOCKET_OPTIONS.write().insert(handle, options);
+    Ok(())
+}
+
+fn is_udp_socket(handle: SocketHandle) -> bool {
+    let sockets = SOCKETS.get().expect("Socket set not initialized!").read();
+    sockets.iter().any(|(h, socket)| h == handle && matches!(socket, Socket::Udp(_)))
+}
+
+/// Change an option, that is stored in the smoltcp socket itself.
+fn set_smoltcp_option(handle: SocketHandle, option: SocketOption, value: usize) -> Result<(), SocketOptionError> {
+    let hop_limit = || match value {
+        0 => Ok(None),
+        1..=255 => Ok(Some(value as u8)),
+        _ => Err(SocketOptionError::InvalidValue),
+    };
+    let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+    let (_, socket) = sockets.iter_mut().find(|(h, _)| *h == handle).expect("process tried accessing non-existent socket");
+    match (option, socket) {
+        (SocketOption::RecvBufferSize, Socket::Udp(socket)) => {
+            let tx_size = socket.payload_send_capacity();
+            resize_udp(socket, value, tx_size)?;
+        }
+        (SocketOption::SendBufferSize, Socket::Udp(socket)) => {
+            let rx_size = socket.payload_recv_capacity();
+            resize_udp(socket, rx_size, value)?;
+        }
+        (SocketOption::RecvBufferSize, Socket::Tcp(socket)) => {
+            let tx_size = socket.send_capacity();
+            resize_tcp(socket, value, tx_size)?;
+        }
+        (SocketOption::SendBufferSize, Socket::Tcp(socket)) => {
+            let rx_size = socket.recv_capacity();
+            resize_tcp(socket, rx_size, value)?;
+        }
+        (SocketOption::Nagle, Socket::Tcp(socket)) => socket.set_nagle_enabled(value != 0),
+        (SocketOption::KeepAlive, Socket::Tcp(socket)) => {
+            socket.set_keep_alive((value != 0).then(|| Duration::from_millis(value as u64)))
+        }
+        (SocketOption::HopLimit, Socket::Udp(socket)) => socket.set_hop_limit(hop_limit()?),
+        (SocketOption::HopLimit, Socket::Tcp(socket)) => socket.set_hop_limit(hop_limit()?),
+        (SocketOption::HopLimit, Socket::Icmp(socket)) => socket.set_hop_limit(hop_limit()?),
+        _ => return Err(SocketOptionError::Unsupported),
+    }
+    Ok(())
+}
+
+/// Replace the buffers of a UDP socket, keeping its binding and hop limit.
+///
+/// This is only possible, as long as there are no datagrams in the buffers.
+fn resize_udp(socket: &mut udp::Socket<'static>, rx_size: usize, tx_size: usize) -> Result<(), SocketOptionError> {
+    if !(1..=MAX_BUFFER_SIZE).contains(&rx_size) || !(1..=MAX_BUFFER_SIZE).contains(&tx_size) {
+        return Err(SocketOptionError::InvalidValue);
+    }
+    if socket.recv_queue() > 0 || socket.send_queue() > 0 {
+        return Err(SocketOptionError::Busy);
+    }
+    let mut new_socket = new_udp_socket(rx_size, tx_size);
+    new_socket.set_hop_limit(socket.hop_limit());
+    if socket.is_open() {
+        new_socket.bind(socket.endpoint()).expect("failed to bind resized socket");
+    }
+    *socket = new_socket;
+    Ok(())
+}
+
+/// Replace the buffers of a TCP socket, keeping its settings and whether it's listening.
+///
+/// This is only possible as long as there is no connection and no data of a previous one in the buffers.
+fn resize_tcp(socket: &mut tcp::Socket<'static>, rx_size: usize, tx_size: usize) -> Result<(), SocketOptionError> {
+    if !(1..=MAX_BUFFER_SIZE).contains(&rx_size) || !(1..=MAX_BUFFER_SIZE).contains(&tx_size) {
+        return Err(SocketOptionError::InvalidValue);
     }
-    // Receive Buffer Overwrite?
-    if device.check_interrupts.ovw.load(Ordering::Relaxed) {
-        // reset the AtomicBool after handling the interrupt
-        device.check_interrupts.ovw.store(false, Ordering::Relaxed);
-        device.handle_overflow();
+    if socket.recv_queue() > 0 || socket.send_queue() > 0 {
+        return Err(SocketOptionError::Busy);
     }
-    // Packet send?
-    if device.check_interrupts.ptx.load(Ordering::Relaxed) {
-        device.check_interrupts.ptx.store(false, Ordering::Relaxed);
+    let mut new_socket = new_tcp_socket(rx_size, tx_size);
+    copy_tcp_settings(socket, &mut new_socket);
+    match socket.state() {
+        tcp::State::Closed => {}
+        tcp::State::Listen => new_socket.listen(socket.listen_endpoint()).expect("failed to listen on resized socket"),
+        _ => return Err(SocketOptionError::Connected),
     }
+    *socket = new_socket;
+    Ok(())
+}
+
+/// Apply the settings of one TCP socket (not its state) to another one.
+fn copy_tcp_settings(from: &tcp::Socket, to: &mut tcp::Socket) {
+    to.set_nagle_enabled(from.nagle_enabled());
+    to.set_keep_alive(from.keep_alive());
+    to.set_hop_limit(from.hop_limit());
+    to.set_timeout(from.timeout());
+    to.set_ack_delay(from.ack_delay());
 }
 
-pub fn add_interface(interface: Interface) {
-    INTERFACES.write().push(interface);
+/// Check whether the socket exists and belongs to the current process (`check_ownership()` panics instead).
+pub fn owns_socket(handle: SocketHandle) -> bool {
+    SOCKET_PROCESS.read().get(&handle) == Some(&process_manager().read().current_process())
 }
 
-/// Get IP addresses for a host.
+/// Check whether an operation with the given interest would not block on this socket.
+fn is_ready(socket: &Socket, interest: Interest) -> bool {
+    match (socket, interest) {
+        // a listening socket becomes readable, when a connection can be accepted
+        (Socket::Tcp(socket), Interest::Receive) if matches!(socket.state(), tcp::State::Listen | tcp::State::SynReceived) => false,
+        (Socket::Tcp(socket), Interest::Receive) => socket.can_recv() || !socket.may_recv(),
+        (Socket::Tcp(socket), Interest::Send) => socket.can_send() || !socket.may_send(),
+        (Socket::Tcp(socket), Interest::Connection) => {
+            !matches!(socket.state(), tcp::State::Listen | tcp::State::SynSent | tcp::State::SynReceived)
+        }
+        (Socket::Udp(socket), Interest::Receive) => socket.can_recv(),
+        (Socket::Udp(socket), Interest::Send) => socket.can_send(),
+        (Socket::Icmp(socket), Interest::Receive) => socket.can_recv(),
+        (Socket::Icmp(socket), Interest::Send) => socket.can_send(),
+        (Socket::Raw(socket), Interest::Receive) => socket.can_recv(),
+        (Socket::Raw(socket), Interest::Send) => socket.can_send(),
+        // nothing to wait for
+        _ => true,
+    }
+}
+
+/// Check whether an operation with the given interest would not block on this socket.
 ///
-/// If host is none, get the addresses of the current host.
-pub fn get_ip_addresses(host: Option<&str>) -> Vec<IpAddress> {
-    let handle = DNS_SOCKET.get().expect("DNS socket does not exist yet");
-    if let Some(host) = host {
-        // first, start the queries
-        let mut query_handles: Vec<_> = {
-            let mut interfaces = INTERFACES.write();
-            let interface = interfaces.get_mut(0).expect("network interface is missing");
-            let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
-            let socket = sockets.get_mut::<dns::Socket>(*handle);
-            [DnsQueryType::Aaaa, DnsQueryType::A, DnsQueryType::Cname]
-                .into_iter()
-                .filter_map(|ty| {
-                    socket
-                        .start_query(interface.context(), host, ty)
-                        .map_err(|e| {
-                            warn!("DNS query for {host} {ty:?} failed: {e:?}");
-                            e
-                        })
-                        .ok()
-                })
-                .collect()
-        };
-        // then, see if they've returned something
-        let mut resulting_ips = Vec::new();
-        loop {
-            {
-                let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
-                let socket = sockets.get_mut::<dns::Socket>(*handle);
-                let mut remaining: Vec<_> = query_handles
-                    .drain(..)
-                    .filter(|query| match socket.get_query_result(*query) {
-                        // it's finished, get the results
-                        Ok(ips) => {
-                            // TODO: does a cname query really return an IP?
-                            resulting_ips.extend_from_slice(&ips);
-                            false
-                        }
-                        // if failed, log and and ignore
-                        Err(GetQueryResultError::Failed) => {
-                            warn!("DNS query for {host} failed");
-                            false
-                        }
-                        // it's still ongoing
-                        Err(GetQueryResultError::Pending) => true,
-                    })
-                    .collect();
-                if remaining.is_empty() {
-                    // we're done!
-                    break;
+/// This returns `None`, if the socket doesn't exist or belongs to a different process.
+pub fn socket_ready(handle: SocketHandle, interest: Interest) -> Option<bool> {
+    if !owns_socket(handle) {
+        return None;
+    }
+    let targets = wait_targets(handle, interest);
+    let sockets = SOCKETS.get().expect("Socket set not initialized!").read();
+    if !sockets.iter().any(|(h, _)| h == handle) {
+        return None;
+    }
+    Some(sockets.iter().any(|(h, socket)| {
+        targets.iter().any(|(target, interest)| *target == h && is_ready(socket, *interest))
+    }))
+}
+
+/// The sockets and interests to check, when waiting on `handle` for `interest`.
+///
+/// This is the socket itself, except for listeners: They are readable, as soon as
+/// any socket of their backlog has a connection, that can be accepted.
+pub fn wait_targets(handle: SocketHandle, interest: Interest) -> Vec<(SocketHandle, Interest)> {
+    match (LISTENERS.read().get(&handle), interest) {
+        (Some(listener), Interest::Receive | Interest::Connection) => {
+            listener.backlog.iter().map(|handle| (*handle, Interest::Connection)).collect()
+        }
+        _ => vec![(handle, interest)],
+    }
+}
+
+/// All sockets, that make up `handle` (more than one for listeners).
+fn backlog(handle: SocketHandle) -> Vec<SocketHandle> {
+    LISTENERS.read().get(&handle).map_or_else(|| vec![handle], |listener| listener.backlog.clone())
+}
+
+/// Repeat `op` until it doesn't return `None` (which means, that it would block).
+///
+/// Between two tries, the calling thread sleeps until the socket is ready for `interest`.
+/// Nonblocking sockets return EAGAIN instead and sockets with a timeout return ETIMEDOUT.
+pub fn block_on<T>(handle: SocketHandle, interest: Interest, mut op: impl FnMut() -> Option<T>) -> Result<T, Errno> {
+    let options = socket_options(handle);
+    let deadline = options.timeout(interest).map(|timeout| timer().systime_ms().saturating_add(timeout));
+    loop {
+        if let Some(result) = op() {
+            return Ok(result);
+        }
+        if options.nonblocking {
+            return Err(Errno::EAGAIN);
+        }
+        if !wait_for(handle, interest, deadline) {
+            return Err(Errno::ETIMEDOUT);
+        }
+    }
+}
+
+/// Block the calling thread until the socket is ready for `interest` or `deadline` (system time in ms) has passed.
+///
+/// This returns false, if the deadline has passed. The thread may also be woken up,
+/// before the socket is ready, so callers need to retry their operation in a loop.
+pub fn wait_for(handle: SocketHandle, interest: Interest, deadline: Option<usize>) -> bool {
+    wait_for_any(&wait_targets(handle, interest), deadline)
+}
+
+/// Block the calling thread until any of the sockets is ready for its interest or `deadline` has passed.
+///
+/// See `wait_for`.
+pub fn wait_for_any(waits: &[(SocketHandle, Interest)], deadline: Option<usize>) -> bool {
+    let notified = Arc::new(AtomicBool::new(false));
+    {
+        let mut wait_queues = WAIT_QUEUES.lock();
+        for (handle, interest) in waits {
+            wait_queues.entry(*handle).or_default().push((*interest, Arc::clone(&notified)));
+        }
+    }
+
+    // check only after registering, otherwise we might miss the wakeup
+    let ready = {
+        let sockets = SOCKETS.get().expect("Socket set not initialized!").read();
+        waits.iter().any(|(handle, interest)| {
+            sockets
+                .iter()
+                .find(|(h, _)| h == handle)
+                .is_none_or(|(_, socket)| is_ready(socket, *interest))
+        })
+    };
+
+    if !ready {
+        let now = timer().systime_ms();
+        let wait_time = deadline.map_or(MAX_WAIT_TIME, |deadline| deadline.saturating_sub(now).min(MAX_WAIT_TIME));
+        if wait_time > 0 {
+            scheduler().sleep_until_notified(wait_time, &notified);
+        }
+    }
+
+    let mut wait_queues = WAIT_QUEUES.lock();
+    for (handle, _) in waits {
+        if let Some(waiters) = wait_queues.get_mut(handle) {
+            waiters.retain(|(_, flag)| !Arc::ptr_eq(flag, &notified));
+            if waiters.is_empty() {
+                wait_queues.remove(handle);
+            }
+        }
+    }
+
+    deadline.is_none_or(|deadline| timer().systime_ms() < deadline)
+}
+
+/// Wake up all threads, whose sockets have become ready.
+fn wake_waiters(sockets: &SocketSet) {
+    let mut wait_queues = WAIT_QUEUES.lock();
+    if wait_queues.is_empty() {
+        return;
+    }
+
+    for (handle, socket) in sockets.iter() {
+        if let Some(waiters) = wait_queues.get_mut(&handle) {
+            waiters.retain(|(interest, notified)| {
+                if is_ready(socket, *interest) {
+                    notified.store(true, Ordering::SeqCst);
+                    false
+                } else {
+                    true
                 }
-                // else, check for the remaining ones
-                query_handles.clear();
-                query_handles.append(&mut remaining);
+            });
+        }
+    }
+    wait_queues.retain(|_, waiters| !waiters.is_empty());
+}
+
+/// Bring up a smoltcp interface for a network card and start requesting an address via DHCP.
+pub fn add_interface(name: &'static str, device: Arc<dyn NetworkDevice>) {
+    INTERFACES.write().push(NetworkInterface::new(name, device));
+}
+
+/// Get the first IPv4 address of the interface, whose driver name contains `driver` (e.g. "e1000").
+/// Returns `None`, if there is no such interface or it has not been configured yet.
+pub fn interface_ipv4_address(driver: &str) -> Option<Ipv4Addr> {
+    INTERFACES
+        .read()
+        .iter()
+        .filter(|interface| interface.name.contains(driver))
+        .find_map(|interface| interface.interface.ipv4_addr())
+}
+
+/// Get the traffic counters of the interface, whose driver name contains `driver` (e.g. "NE2000").
+pub fn interface_stats(driver: &str) -> Option<nic::NetworkStats> {
+    INTERFACES
+        .read()
+        .iter()
+        .find(|interface| interface.name.contains(driver))
+        .map(|interface| interface.device.stats())
+}
+
+/// Get the driver name and the traffic counters of every interface.
+pub fn all_interface_stats() -> Vec<(&'static str, nic::NetworkStats)> {
+    INTERFACES
+        .read()
+        .iter()
+        .map(|interface| (interface.name, interface.device.stats()))
+        .collect()
+}
+
+/// Map a multicast IP address to the Ethernet address, that frames for it are sent to.
+fn multicast_ethernet_address(group: &IpAddress) -> EthernetAddress {
+    match group {
+        IpAddress::Ipv4(address) => {
+            let [_, b1, b2, b3] = address.octets();
+            EthernetAddress([0x01, 0x00, 0x5e, b1 & 0x7f, b2, b3])
+        }
+        IpAddress::Ipv6(address) => {
+            let [.., b12, b13, b14, b15] = address.octets();
+            EthernetAddress([0x33, 0x33, b12, b13, b14, b15])
+        }
+    }
+}
+
+/// Find the interface for a multicast group: the one, whose driver name contains `selector` or that has the address `selector`.
+/// Without `selector`, it is the interface, through which the group is routed.
+fn multicast_interface<'a>(
+    interfaces: &'a mut [NetworkInterface], group: &IpAddress, selector: Option<&str>,
+) -> Result<&'a mut NetworkInterface, ConfigError> {
+    match selector.map(|selector| (selector, IpAddress::from_str(selector))) {
+        None => interface_for(interfaces, group).ok_or(ConfigError::NoInterface),
+        Some((_, Ok(address))) => interfaces
+            .iter_mut()
+            .find(|interface| interface.interface.ip_addrs().iter().any(|cidr| cidr.address() == address))
+            .ok_or(ConfigError::NoInterface),
+        Some((driver, Err(_))) => interface_named(interfaces, driver),
+    }
+}
+
+/// Let a UDP socket join a multicast group (see `multicast_interface()` for `interface`).
+///
+/// The card of the interface is told to accept frames for the group. The interface stays in the group,
+/// until all sockets, that have joined it, have left it or have been closed.
+pub fn join_multicast_group(handle: SocketHandle, group: IpAddress, interface: Option<&str>) -> Result<(), ConfigError> {
+    check_ownership(handle);
+    let mut interfaces = INTERFACES.write();
+    let interface = multicast_interface(&mut interfaces, &group, interface)?;
+    let mut memberships = MEMBERSHIPS.lock();
+    if memberships.get(&handle).is_some_and(|joined| joined.contains(&(interface.name, group))) {
+        return Err(ConfigError::Exists);
+    }
+    if !interface.multicast_groups.contains(&group) {
+        interface.interface.join_multicast_group(group).map_err(|_| ConfigError::Full)?;
+        interface.multicast_groups.push(group);
+        interface.update_multicast_filter();
+        // send the membership report
+        notify_worker();
+    }
+    memberships.entry(handle).or_default().push((interface.name, group));
+    Ok(())
+}
+
+/// Let a UDP socket leave a multicast group, that it has joined with `join_multicast_group`.
+pub fn leave_multicast_group(handle: SocketHandle, group: IpAddress, interface: Option<&str>) -> Result<(), ConfigError> {
+    check_ownership(handle);
+    let mut interfaces = INTERFACES.write();
+    let mut memberships = MEMBERSHIPS.lock();
+    let joined = memberships.get_mut(&handle).ok_or(ConfigError::NotFound)?;
+    let name = match interface {
+        Some(_) => multicast_interface(&mut interfaces, &group, interface)?.name,
+        // the group may have been joined on any interface
+        None => joined.iter().find(|(_, joined)| *joined == group).ok_or(ConfigError::NotFound)?.0,
+    };
+    let index = joined.iter().position(|membership| *membership == (name, group)).ok_or(ConfigError::NotFound)?;
+    joined.remove(index);
+    if joined.is_empty() {
+        memberships.remove(&handle);
+    }
+    drop_membership(&mut interfaces, &memberships, name, group);
+    Ok(())
+}
+
+/// Let a socket leave all multicast groups, before it is closed.
+fn leave_all_multicast_groups(handle: SocketHandle) {
+    let mut interfaces = INTERFACES.write();
+    let mut memberships = MEMBERSHIPS.lock();
+    for (name, group) in memberships.remove(&handle).unwrap_or_default() {
+        drop_membership(&mut interfaces, &memberships, name, group);
+    }
+}
+
+/// Let the interface `name` leave `group`, if no socket is a member anymore.
+fn drop_membership(
+    interfaces: &mut [NetworkInterface], memberships: &BTreeMap<SocketHandle, Vec<(&'static str, IpAddress)>>, name: &str,
+    group: IpAddress,
+) {
+    if memberships.values().flatten().any(|membership| *membership == (name, group)) {
+        return;
+    }
+    if let Some(interface) = interfaces.iter_mut().find(|interface| interface.name == name) {
+        // this only fails for addresses, that are no multicast groups
+        let _ = interface.interface.leave_multicast_group(group);
+        interface.multicast_groups.retain(|joined| *joined != group);
+        interface.update_multicast_filter();
+        notify_worker();
+    }
+}
+
+/// Check whether datagrams to `address` are broadcasts (to 255.255.255.255 or the broadcast address of a subnet).
+pub fn is_broadcast(address: &IpAddress) -> bool {
+    let IpAddress::Ipv4(address) = address else {
+        return false;
+    };
+    address.is_broadcast()
+        || INTERFACES.read().iter().flat_map(|interface| interface.interface.ip_addrs()).any(|cidr| match cidr {
+            IpCidr::Ipv4(cidr) => cidr.broadcast() == Some(*address),
+            _ => false,
+        })
+}
+
+/// Let the card of the interface, whose driver name contains `driver`, accept all frames
+/// (e.g. for capturing packets). Returns `false`, if there is no such card or it doesn't support this.
+pub fn set_promiscuous(driver: &str, enabled: bool) -> bool {
+    let supported = INTERFACES
+        .read()
+        .iter()
+        .find(|interface| interface.name.contains(driver))
+        .is_some_and(|interface| interface.device.set_promiscuous(enabled));
+    notify_worker();
+    supported
+}
+
+/// Errors of `add_ip_address`, `remove_ip_address`, `add_route`, `remove_route` and the neighbor functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// There is no such interface (or none, through which the gateway or the neighbor can be reached).
+    NoInterface,
+    /// The address, route or neighbor doesn't exist.
+    NotFound,
+    /// The address, route or permanent neighbor has already been added.
+    Exists,
+    /// The interface can't have any more addresses, routes or permanent neighbors.
+    Full,
+}
+
+/// Find the interface, whose driver name contains `driver`.
+fn interface_named<'a>(interfaces: &'a mut [NetworkInterface], driver: &str) -> Result<&'a mut NetworkInterface, ConfigError> {
+    interfaces
+        .iter_mut()
+        .find(|interface| interface.name.contains(driver))
+        .ok_or(ConfigError::NoInterface)
+}
+
+/// Add an address to the interface, whose driver name contains `driver`.
+pub fn add_ip_address(driver: &str, address: IpCidr) -> Result<(), ConfigError> {
+    let mut interfaces = INTERFACES.write();
+    let interface = interface_named(&mut interfaces, driver)?;
+    let mut result = Ok(());
+    interface.interface.update_ip_addrs(|addrs| {
+        result = if addrs.iter().any(|cidr| cidr.address() == address.address()) {
+            Err(ConfigError::Exists)
+        } else {
+            addrs.push(address).map_err(|_| ConfigError::Full)
+        };
+    });
+    interface.update_multicast_filter();
+    notify_worker();
+    result
+}
+
+/// Remove an address from the interface, whose driver name contains `driver`.
+pub fn remove_ip_address(driver: &str, address: IpAddress) -> Result<(), ConfigError> {
+    let mut interfaces = INTERFACES.write();
+    let interface = interface_named(&mut interfaces, driver)?;
+    let mut result = Err(ConfigError::NotFound);
+    interface.interface.update_ip_addrs(|addrs| {
+        if let Some(index) = addrs.iter().position(|cidr| cidr.address() == address) {
+            addrs.remove(index);
+            result = Ok(());
+        }
+    });
+    if interface.dhcp_address.is_some_and(|cidr| IpAddress::Ipv4(cidr.address()) == address) {
+        interface.dhcp_address = None;
+    }
+    interface.update_multicast_filter();
+    result
+}
+
+/// Route the packets for `destination` through `gateway`.
+///
+/// The route is added to the interface, whose driver name contains `driver`,
+/// or (if `driver` is `None`) to the interface, that is directly attached to the network of `gateway`.
+pub fn add_route(driver: Option<&str>, destination: IpCidr, gateway: IpAddress) -> Result<(), ConfigError> {
+    let mut interfaces = INTERFACES.write();
+    let interface = match driver {
+        Some(driver) => interface_named(&mut interfaces, driver)?,
+        None => interfaces
+            .iter_mut()
+            .find(|interface| interface.interface.ip_addrs().iter().any(|cidr| cidr.contains_addr(&gateway)))
+            .ok_or(ConfigError::NoInterface)?,
+    };
+    let mut result = Ok(());
+    interface.interface.routes_mut().update(|routes| {
+        result = if routes.iter().any(|route| route.cidr == destination) {
+            Err(ConfigError::Exists)
+        } else {
+            let route = Route { cidr: destination, via_router: gateway, preferred_until: None, expires_at: None };
+            routes.push(route).map_err(|_| ConfigError::Full)
+        };
+    });
+    notify_worker();
+    result
+}
+
+/// Remove the route for `destination` from the interface, whose driver name contains `driver`
+/// (or from all interfaces, if `driver` is `None`).
+pub fn remove_route(driver: Option<&str>, destination: IpCidr) -> Result<(), ConfigError> {
+    let mut interfaces = INTERFACES.write();
+    let mut result = Err(ConfigError::NotFound);
+    for interface in interfaces.iter_mut().filter(|interface| driver.is_none_or(|driver| interface.name.contains(driver))) {
+        interface.interface.routes_mut().update(|routes| {
+            let count = routes.len();
+            routes.retain(|route| route.cidr != destination);
+            if routes.len() < count {
+                result = Ok(());
             }
-            // release the locks and sleep
-            scheduler().sleep(50);
+        });
+    }
+    if result.is_err() && driver.is_some_and(|driver| !interfaces.iter().any(|interface| interface.name.contains(driver))) {
+        result = Err(ConfigError::NoInterface);
+    }
+    result
+}
+
+/// Get the routes of all interfaces (together with the driver name).
+pub fn all_routes() -> Vec<(&'static str, Route)> {
+    let mut interfaces = INTERFACES.write();
+    let mut all = Vec::new();
+    for interface in interfaces.iter_mut() {
+        // smoltcp only gives access to the routes through `update()`
+        interface.interface.routes_mut().update(|routes| all.extend(routes.iter().map(|route| (interface.name, *route))));
+    }
+    all
+}
+
+/// Link layer properties of an interface.
+pub struct LinkInfo {
+    /// `None` for the loopback interface
+    pub mac: Option<EthernetAddress>,
+    /// Largest IP packet, that can be sent
+    pub mtu: usize,
+    pub up: bool,
+}
+
+/// Get the MAC address, MTU and link state of every interface (together with the driver name).
+pub fn all_interface_links() -> Vec<(&'static str, LinkInfo)> {
+    INTERFACES
+        .read()
+        .iter()
+        .map(|interface| {
+            let mac = match interface.interface.hardware_addr() {
+                HardwareAddress::Ethernet(mac) => Some(mac),
+                _ => None,
+            };
+            let link = LinkInfo { mac, mtu: interface.device.capabilities().ip_mtu(), up: interface.device.link_up() };
+            (interface.name, link)
+        })
+        .collect()
+}
+
+/// State of a neighbor cache entry (the numbers are handed to user space).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive)]
+#[repr(u8)]
+pub enum NeighborState {
+    /// The entry has been learned and is used for sending.
+    Reachable = 0,
+    /// The entry has been learned, but it is too old, so the address will be resolved again before sending.
+    Expired = 1,
+    /// The entry has been added with `add_neighbor` and never expires.
+    Permanent = 2,
+}
+
+/// An entry of smoltcp's neighbor cache.
+pub struct Neighbor {
+    pub address: IpAddress,
+    pub mac: EthernetAddress,
+    pub state: NeighborState,
+    /// `None` for permanent entries
+    pub expires_at: Option<Instant>,
+}
+
+/// Get the neighbor cache entries of all interfaces (together with the driver name).
+pub fn all_neighbors() -> Vec<(&'static str, Neighbor)> {
+    let time = Instant::from_millis(timer().systime_ms() as i64);
+    let interfaces = INTERFACES.read();
+    let mut all = Vec::new();
+    for interface in interfaces.iter() {
+        for (address, neighbor) in interface.interface.neighbors() {
+            let HardwareAddress::Ethernet(mac) = neighbor.hardware_addr() else {
+                continue;
+            };
+            let (state, expires_at) = if neighbor.is_permanent() {
+                (NeighborState::Permanent, None)
+            } else if neighbor.expires_at() > time {
+                (NeighborState::Reachable, Some(neighbor.expires_at()))
+            } else {
+                (NeighborState::Expired, Some(neighbor.expires_at()))
+            };
+            all.push((interface.name, Neighbor { address: *address, mac, state, expires_at }));
         }
-        resulting_ips
-    } else {
-        INTERFACES.read().iter().flat_map(Interface::ip_addrs).map(IpCidr::address).collect()
     }
+    all
 }
 
-pub fn open_udp() -> SocketHandle {
-    let sockets = SOCKETS.get().expect("Socket set not initialized!");
+/// Find the interface with a neighbor cache, whose driver name contains `driver`.
+fn neighbors_of<'a>(interfaces: &'a mut [NetworkInterface], driver: &str) -> Result<&'a mut NetworkInterface, ConfigError> {
+    interfaces
+        .iter_mut()
+        .find(|interface| interface.has_neighbors() && interface.name.contains(driver))
+        .ok_or(ConfigError::NoInterface)
+}
+
+/// Add a permanent neighbor cache entry to the interface, whose driver name contains `driver`,
+/// so that packets for `address` are sent to `mac` without resolving it first.
+///
+/// `address` must be in the network of one of the addresses of the interface (otherwise smoltcp would never look it up).
+/// A learned entry for `address` is replaced, a permanent one is not.
+pub fn add_neighbor(driver: &str, address: IpAddress, mac: EthernetAddress) -> Result<(), ConfigError> {
+    let mut interfaces = INTERFACES.write();
+    let interface = neighbors_of(&mut interfaces, driver)?;
+    if !interface.interface.ip_addrs().iter().any(|cidr| cidr.contains_addr(&address)) {
+        return Err(ConfigError::NoInterface);
+    }
+    if interface.interface.neighbors().any(|(existing, neighbor)| *existing == address && neighbor.is_permanent()) {
+        return Err(ConfigError::Exists);
+    }
+    interface.interface.add_static_neighbor(address, HardwareAddress::Ethernet(mac)).map_err(|_| ConfigError::Full)?;
+
+    // packets, that are waiting for the address to be resolved, can be sent now
+    notify_worker();
+    Ok(())
+}
+
+/// Remove the (permanent or learned) neighbor cache entry for `address` from the interface, whose driver name contains `driver`.
+pub fn remove_neighbor(driver: &str, address: IpAddress) -> Result<(), ConfigError> {
+    let mut interfaces = INTERFACES.write();
+    let interface = neighbors_of(&mut interfaces, driver)?;
+    interface.interface.remove_neighbor(&address).map(|_| ()).ok_or(ConfigError::NotFound)
+}
+
+/// Forget the learned neighbor cache entries of the interface, whose driver name contains `driver`
+/// (or of all interfaces, if `driver` is `None`). Permanent entries are kept.
+pub fn flush_neighbors(driver: Option<&str>) -> Result<(), ConfigError> {
+    let mut interfaces = INTERFACES.write();
+    let mut found = false;
+    for interface in interfaces
+        .iter_mut()
+        .filter(|interface| interface.has_neighbors() && driver.is_none_or(|driver| interface.name.contains(driver)))
+    {
+        interface.interface.flush_neighbors();
+        found = true;
+    }
+    if found || driver.is_none() { Ok(()) } else { Err(ConfigError::NoInterface) }
+}
+
+/// Find the interface through which `host` can be reached (see `RoutingTable`).
+fn interface_for<'a>(interfaces: &'a mut [NetworkInterface], host: &IpAddress) -> Option<&'a mut NetworkInterface> {
+    let index = RoutingTable::new(interfaces).interface_index(host);
+    interfaces.get_mut(index)
+}
+
+/// The networks of all interfaces and their routes, to decide through which interface packets leave.
+///
+/// Directly attached networks are preferred over routes.
+/// If neither matches, the first interface is used.
+struct RoutingTable {
+    /// the addresses of every interface (together with their prefix), in the order of the interfaces
+    networks: Vec<Vec<IpCidr>>,
+    /// the destinations of the routes of every interface
+    routes: Vec<Vec<IpCidr>>,
+}
+
+impl RoutingTable {
+    fn new(interfaces: &mut [NetworkInterface]) -> Self {
+        let networks = interfaces.iter().map(|interface| interface.interface.ip_addrs().to_vec()).collect();
+        let routes = interfaces
+            .iter_mut()
+            .map(|interface| {
+                let mut routes = Vec::new();
+                // smoltcp only gives access to the routes through `update()`
+                interface.interface.routes_mut().update(|table| routes.extend(table.iter().map(|route| route.cidr)));
+                routes
+            })
+            .collect();
+        Self { networks, routes }
+    }
+
+    /// The index of the interface, through which `host` can be reached.
+    fn interface_index(&self, host: &IpAddress) -> usize {
+        let matches = |cidrs: &Vec<IpCidr>| cidrs.iter().any(|cidr| cidr.contains_addr(host));
+        self.networks
+            .iter()
+            .position(matches)
+            .or_else(|| self.routes.iter().position(matches))
+            .unwrap_or(0)
+    }
+}
+
+/// Get the IP addresses of this host (use `resolver::resolve` for other hosts).
+pub fn get_ip_addresses() -> Vec<IpAddress> {
+    INTERFACES
+        .read()
+        .iter()
+        .flat_map(|interface| interface.interface.ip_addrs())
+        .map(IpCidr::address)
+        .collect()
+}
+
+/// Create a UDP socket with `rx_size` and `tx_size` bytes of buffers.
+fn new_udp_socket(rx_size: usize, tx_size: usize) -> udp::Socket<'static> {
     // =============================================================================
     // changed transmit and receive buffer size to tx_size and rx_size
     // IMPORTANT//
@@ -361,18 +1260,22 @@ pub fn open_udp() -> SocketHandle {
     // Problem:  enqueue faster than poll() can transmit,
     // hit whichever limit comes first and get BufferFull
     // =============================================================================
-    //let rx_size = 3000;
-    let rx_size = 3000;
-    //let rx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; rx_size], vec![0; 100 * rx_size]);
-    let rx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; rx_size], vec![0; rx_size * 10000]);
-
-    //let tx_size = 3000;
-    let tx_size = 3000;
+    let rx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; UDP_PACKET_SLOTS], vec![0; rx_size]);
     // todo check with debugger, when doing receive benchmark memory allocation error with great values
-    //let tx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; tx_size], vec![0; 100 * tx_size]);
-    let tx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; tx_size], vec![0; tx_size * 10000]);
+    let tx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; UDP_PACKET_SLOTS], vec![0; tx_size]);
+    udp::Socket::new(rx_buffer, tx_buffer)
+}
+
+/// Create a TCP socket with `rx_size` and `tx_size` bytes of buffers.
+fn new_tcp_socket(rx_size: usize, tx_size: usize) -> tcp::Socket<'static> {
+    let rx_buffer = tcp::SocketBuffer::new(vec![0; rx_size]);
+    let tx_buffer = tcp::SocketBuffer::new(vec![0; tx_size]);
+    tcp::Socket::new(rx_buffer, tx_buffer)
+}
 
-    let handle = sockets.write().add(udp::Socket::new(rx_buffer, tx_buffer));
+pub fn open_udp() -> SocketHandle {
+    let sockets = SOCKETS.get().expect("Socket set not initialized!");
+    let handle = sockets.write().add(new_udp_socket(UDP_BUFFER_SIZE, UDP_BUFFER_SIZE));
     SOCKET_PROCESS
         .write()
         .try_insert(handle, process_manager().read().current_process())
@@ -382,10 +1285,7 @@ pub fn open_udp() -> SocketHandle {
 
 pub fn open_tcp() -> SocketHandle {
     let sockets = SOCKETS.get().expect("Socket set not initialized!");
-    let rx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
-    let tx_buffer = tcp::SocketBuffer::new(vec![0; 65535]);
-
-    let handle = sockets.write().add(tcp::Socket::new(rx_buffer, tx_buffer));
+    let handle = sockets.write().add(new_tcp_socket(TCP_BUFFER_SIZE, TCP_BUFFER_SIZE));
     SOCKET_PROCESS
         .write()
         .try_insert(handle, process_manager().read().current_process())
@@ -407,35 +1307,208 @@ pub fn open_icmp() -> SocketHandle {
     handle
 }
 
+/// Open a raw socket, that receives copies of all IP packets with `version` and `protocol`
+/// and sends packets, that already have an IP header. The caller has to check `packet::privileged()`.
+pub fn open_raw(version: IpVersion, protocol: IpProtocol) -> SocketHandle {
+    let sockets = SOCKETS.get().expect("Socket set not initialized!");
+
+    let rx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 8], vec![0; 65535]);
+    let tx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 8], vec![0; 65535]);
+
+    let handle = sockets.write().add(raw::Socket::new(version, protocol, rx_buffer, tx_buffer));
+    SOCKET_PROCESS
+        .write()
+        .try_insert(handle, process_manager().read().current_process())
+        .expect("failed to insert socket into socket-process map");
+    handle
+}
+
 pub fn close_socket(handle: SocketHandle) {
     let sockets = SOCKETS.get().expect("Socket set not initialized!");
     check_ownership(handle);
-    SOCKET_PROCESS.write().remove(&handle).unwrap();
-    sockets.write().remove(handle);
+    // a listener takes its backlog with it
+    let handles = backlog(handle);
+    LISTENERS.write().remove(&handle);
+    leave_all_multicast_groups(handle);
+    for handle in handles {
+        SOCKET_PROCESS.write().remove(&handle).unwrap();
+        SOCKET_OPTIONS.write().remove(&handle);
+        release_socket(&mut sockets.write(), handle);
+    }
+    notify_worker();
+}
+
+/// Remove a socket from the set, after its owner has closed it.
+///
+/// TCP connections are closed gracefully instead: They stay in the set, until the remote host
+/// has received all queued data and our FIN (see `reap_closed_sockets()`).
+fn release_socket(sockets: &mut SocketSet<'static>, handle: SocketHandle) {
+    let lingering = sockets.iter_mut().find(|(h, _)| *h == handle).is_some_and(|(_, socket)| match socket {
+        Socket::Tcp(socket) if !matches!(socket.state(), tcp::State::Closed | tcp::State::Listen | tcp::State::TimeWait) => {
+            socket.close();
+            true
+        }
+        _ => false,
+    });
+    if lingering {
+        CLOSING.lock().push((handle, timer().systime_ms() + LINGER_TIME));
+    } else {
+        sockets.remove(handle);
+        ports::release(handle);
+    }
+}
+
+/// Remove the closed TCP connections, that have finished sending their remaining data.
+///
+/// Connections, that are still lingering after `LINGER_TIME`, are reset.
+fn reap_closed_sockets(sockets: &mut SocketSet<'static>) {
+    let mut closing = CLOSING.lock();
+    if closing.is_empty() {
+        return;
+    }
+    let now = timer().systime_ms();
+    closing.retain(|(handle, deadline)| {
+        let socket = sockets.get_mut::<tcp::Socket>(*handle);
+        match socket.state() {
+            tcp::State::Closed | tcp::State::TimeWait => {
+                sockets.remove(*handle);
+                ports::release(*handle);
+                false
+            }
+            _ if now > *deadline => {
+                warn!("resetting connection, that didn't close within {LINGER_TIME} ms");
+                socket.abort();
+                true
+            }
+            _ => true,
+        }
+    });
+}
+
+/// Shut down one or both directions of a TCP connection, or reset it.
+pub fn shutdown_tcp(handle: SocketHandle, how: Shutdown) {
+    if matches!(how, Shutdown::Read | Shutdown::Both) {
+        let mut options = socket_options(handle);
+        options.read_shut_down = true;
+        SOCKET_OPTIONS.write().insert(handle, options);
+    }
+    get_socket_for_current_process!(socket, handle, tcp::Socket);
+    match how {
+        Shutdown::Read => {}
+        Shutdown::Write | Shutdown::Both => socket.close(),
+        Shutdown::Abort => socket.abort(),
+    }
+    notify_worker();
+}
+
+/// Errors of `bind_udp`, `bind_tcp` and `connect_tcp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindError {
+    /// The socket is bound or connected already.
+    InvalidState,
+    /// The address or port can't be used (or there is no route to the remote host).
+    Unaddressable,
+    /// The port is bound by another socket (or there is no ephemeral port left).
+    AddressInUse,
+}
+
+impl From<PortError> for BindError {
+    fn from(_: PortError) -> Self {
+        BindError::AddressInUse
+    }
+}
+
+impl From<udp::BindError> for BindError {
+    fn from(error: udp::BindError) -> Self {
+        match error {
+            udp::BindError::InvalidState => BindError::InvalidState,
+            udp::BindError::Unaddressable => BindError::Unaddressable,
+        }
+    }
+}
+
+impl From<tcp::ListenError> for BindError {
+    fn from(error: tcp::ListenError) -> Self {
+        match error {
+            tcp::ListenError::InvalidState => BindError::InvalidState,
+            tcp::ListenError::Unaddressable => BindError::Unaddressable,
+        }
+    }
 }
 
-pub fn bind_udp(handle: SocketHandle, addr: IpAddress, port: u16) -> Result<(), udp::BindError> {
+impl From<tcp::ConnectError> for BindError {
+    fn from(error: tcp::ConnectError) -> Self {
+        match error {
+            tcp::ConnectError::InvalidState => BindError::InvalidState,
+            tcp::ConnectError::Unaddressable => BindError::Unaddressable,
+        }
+    }
+}
+
+/// Bind a UDP socket to `port` (or to an ephemeral port, if it is 0).
+pub fn bind_udp(handle: SocketHandle, addr: IpAddress, port: u16) -> Result<(), BindError> {
     get_socket_for_current_process!(socket, handle, udp::Socket);
-    let port = pick_port(port);
-    match addr {
+    if socket.is_open() {
+        return Err(BindError::InvalidState);
+    }
+    let port = ports::reserve(Protocol::Udp, port, handle)?;
+    let result = match addr {
         // binding to 0.0.0.0 or :: means listening to all requests
         // but smoltcp doesn't understand it that way
         IpAddress::Ipv4(Ipv4Addr::UNSPECIFIED) | IpAddress::Ipv6(Ipv6Addr::UNSPECIFIED) => socket.bind(port),
         // else, bind to the specified address
         _ => socket.bind((addr, port)),
+    };
+    if result.is_err() {
+        ports::unreserve(Protocol::Udp, port);
     }
+    Ok(result?)
 }
 
-pub fn bind_tcp(handle: SocketHandle, addr: IpAddress, port: u16) -> Result<(), tcp::ListenError> {
-    get_socket_for_current_process!(socket, handle, tcp::Socket);
-    let port = pick_port(port);
-    match addr {
+/// Let the socket listen for connections.
+///
+/// Up to `backlog` clients (0 means the default) can connect, before they are accepted.
+pub fn bind_tcp(handle: SocketHandle, addr: IpAddress, port: u16, backlog: usize) -> Result<(), BindError> {
+    let backlog = if backlog == 0 { DEFAULT_BACKLOG } else { backlog.min(MAX_BACKLOG) };
+    check_ownership(handle);
+    if LISTENERS.read().contains_key(&handle) {
+        return Err(BindError::InvalidState);
+    }
+    let port = ports::reserve(Protocol::Tcp, port, handle)?;
+    let endpoint = match addr {
         // binding to 0.0.0.0 or :: means listening to all requests
         // but smoltcp doesn't understand it that way
-        IpAddress::Ipv4(Ipv4Addr::UNSPECIFIED) | IpAddress::Ipv6(Ipv6Addr::UNSPECIFIED) => socket.listen(port),
+        IpAddress::Ipv4(Ipv4Addr::UNSPECIFIED) | IpAddress::Ipv6(Ipv6Addr::UNSPECIFIED) => IpListenEndpoint::from(port),
         // else, bind to the specified address
-        _ => socket.listen((addr, port)),
+        _ => IpListenEndpoint::from((addr, port)),
+    };
+
+    let mut handles = vec![handle];
+    {
+        let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+        let socket = sockets.get_mut::<tcp::Socket>(handle);
+        if let Err(error) = socket.listen(endpoint) {
+            ports::unreserve(Protocol::Tcp, port);
+            return Err(error.into());
+        }
+        // the other sockets of the backlog listen on the same endpoint
+        let mut others = Vec::new();
+        for _ in 1..backlog {
+            let mut other = new_tcp_socket(socket.recv_capacity(), socket.send_capacity());
+            copy_tcp_settings(socket, &mut other);
+            other.listen(endpoint).expect("failed to listen on backlog socket");
+            others.push(other);
+        }
+        handles.extend(others.into_iter().map(|other| sockets.add(other)));
+    }
+
+    let process = process_manager().read().current_process();
+    let mut socket_process = SOCKET_PROCESS.write();
+    for other in &handles[1..] {
+        socket_process.insert(*other, Arc::clone(&process));
     }
+    LISTENERS.write().insert(handle, Listener { endpoint, backlog: handles });
+    Ok(())
 }
 
 pub fn bind_icmp(handle: SocketHandle, ident: u16) -> Result<(), icmp::BindError> {
@@ -443,30 +1516,61 @@ pub fn bind_icmp(handle: SocketHandle, ident: u16) -> Result<(), icmp::BindError
     socket.bind(icmp::Endpoint::Ident(ident))
 }
 
-pub fn accept_tcp(handle: SocketHandle) -> Result<IpEndpoint, tcp::ConnectError> {
-    // TODO: smoltcp knows no backlog
-    // all but the first connection will fail
-    loop {
-        // this extra block is needed so that we don't block all sockets
-        {
-            get_socket_for_current_process!(socket, handle, tcp::Socket);
-            if socket.is_active() {
-                break;
-            }
+/// Take a connection from the backlog of a listening socket (`None` means, that there is none yet).
+///
+/// The connection gets a new handle and its place in the backlog is taken by a fresh listening socket.
+pub fn accept_tcp(handle: SocketHandle) -> Option<Result<(SocketHandle, IpEndpoint), tcp::ConnectError>> {
+    check_ownership(handle);
+    let Some((endpoint, backlog)) = LISTENERS.read().get(&handle).map(|listener| (listener.endpoint, listener.backlog.clone())) else {
+        return Some(Err(tcp::ConnectError::InvalidState));
+    };
+
+    let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+    for pending in backlog {
+        let socket = sockets.get_mut::<tcp::Socket>(pending);
+        if matches!(socket.state(), tcp::State::Listen | tcp::State::SynReceived) {
+            continue;
+        }
+        let mut fresh = new_tcp_socket(socket.recv_capacity(), socket.send_capacity());
+        copy_tcp_settings(socket, &mut fresh);
+        fresh.listen(endpoint).expect("failed to listen on backlog socket");
+        let connection = mem::replace(socket, fresh);
+        // the connection may have been reset, before we could accept it
+        if let Some(remote) = connection.remote_endpoint() {
+            let accepted = sockets.add(connection);
+            drop(sockets);
+            SOCKET_PROCESS.write().insert(accepted, process_manager().read().current_process());
+            return Some(Ok((accepted, remote)));
         }
-        scheduler().sleep(100);
     }
+    None
+}
+
+/// Check whether a TCP connection has been established (`None` means it is still being set up).
+pub fn tcp_connected(handle: SocketHandle) -> Option<bool> {
     get_socket_for_current_process!(socket, handle, tcp::Socket);
-    Ok(socket.remote_endpoint().unwrap())
+    match socket.state() {
+        tcp::State::SynSent | tcp::State::SynReceived => None,
+        state => Some(state == tcp::State::Established),
+    }
 }
 
-pub fn connect_tcp(handle: SocketHandle, host: IpAddress, port: u16) -> Result<IpEndpoint, tcp::ConnectError> {
+/// Connect a TCP socket to `host`, from an ephemeral port.
+pub fn connect_tcp(handle: SocketHandle, host: IpAddress, port: u16) -> Result<IpEndpoint, BindError> {
     get_socket_for_current_process!(socket, handle, tcp::Socket);
     let mut interfaces = INTERFACES.write();
-    let interface = interfaces.get_mut(0).ok_or(tcp::ConnectError::InvalidState)?;
-    let local_port = pick_port(0);
+    let interface = interface_for(&mut interfaces, &host).ok_or(BindError::Unaddressable)?;
+    if socket.is_open() {
+        return Err(BindError::InvalidState);
+    }
+    let local_port = ports::reserve(Protocol::Tcp, 0, handle)?;
 
-    socket.connect(interface.context(), (host, port), local_port)?;
+    if let Err(error) = socket.connect(interface.interface.context(), (host, port), local_port) {
+        ports::unreserve(Protocol::Tcp, local_port);
+        return Err(error.into());
+    }
+    // send the SYN right away
+    notify_worker();
     Ok(socket.local_endpoint().unwrap())
 }
 
@@ -474,26 +1578,43 @@ pub fn send_datagram(handle: SocketHandle, destination: IpAddress, port: u16, da
     get_socket_for_current_process!(socket, handle, udp::Socket);
     // packets don't hit the wire when calling send_slice, poll() transmits them
     // if poll is to slow, socket tx and rx buffer limit will be reached
+    notify_worker();
     socket.send_slice(data, (destination, port))
 }
 
 pub fn send_tcp(handle: SocketHandle, data: &[u8]) -> Result<usize, tcp::SendError> {
     get_socket_for_current_process!(socket, handle, tcp::Socket);
+    notify_worker();
     socket.send_slice(data)
 }
 
 pub fn send_icmp(handle: SocketHandle, destination: IpAddress, data: &[u8]) -> Result<(), icmp::SendError> {
     get_socket_for_current_process!(socket, handle, icmp::Socket);
+    notify_worker();
     socket.send_slice(data, destination)
 }
 
+pub fn send_raw(handle: SocketHandle, packet: &[u8]) -> Result<(), raw::SendError> {
+    get_socket_for_current_process!(socket, handle, raw::Socket);
+    notify_worker();
+    socket.send_slice(packet)
+}
+
 pub fn receive_datagram(handle: SocketHandle, data: &mut [u8]) -> Result<(usize, udp::UdpMetadata), udp::RecvError> {
     get_socket_for_current_process!(socket, handle, udp::Socket);
     socket.recv_slice(data)
 }
 
 pub fn receive_tcp(handle: SocketHandle, data: &mut [u8]) -> Result<usize, tcp::RecvError> {
+    let read_shut_down = socket_options(handle).read_shut_down;
     get_socket_for_current_process!(socket, handle, tcp::Socket);
+    // reading opens the receive window, which the remote host should learn about
+    notify_worker();
+    if read_shut_down {
+        // throw away whatever has arrived, so that the remote host doesn't get stuck
+        while socket.recv(|buffer| (buffer.len(), buffer.len())).is_ok_and(|len| len > 0) {}
+        return Err(tcp::RecvError::Finished);
+    }
     socket.recv_slice(data)
 }
 
@@ -501,23 +1622,32 @@ pub fn receive_icmp(handle: SocketHandle, data: &mut [u8]) -> Result<(usize, IpA
     get_socket_for_current_process!(socket, handle, icmp::Socket);
     socket.recv_slice(data)
 }
-// =============================================================================
-// function poll_sockets for the ne2k (Johann Spenrath)
-// check in a poll loop for new packets on the sockets
-// =============================================================================
-pub fn poll_sockets() -> Option<()> {
-    let ne2k = NE2000.get().expect("NE2000 not initialized");
+
+pub fn receive_raw(handle: SocketHandle, data: &mut [u8]) -> Result<usize, raw::RecvError> {
+    get_socket_for_current_process!(socket, handle, raw::Socket);
+    socket.recv_slice(data)
+}
+/// Try to poll all sockets on all interfaces.
+///
+/// This returns the time until the interfaces need to be polled again
+/// (at most `MAX_POLL_DELAY`) or None, if it failed to get all needed locks.
+/// This is needed, because we otherwise might get a deadlock, because an
+/// application has the lock on `sockets` while we have the lock on `interfaces`.
+pub fn poll_sockets() -> Option<Duration> {
     let mut interfaces = INTERFACES.try_write()?;
     let mut sockets = SOCKETS.get().expect("Socket set not initialized!").try_write()?;
     let time = Instant::from_millis(timer().systime_ms() as i64);
 
-    // Smoltcp expects a mutable reference to the device, but the RTL8139 driver is built
-    // to work with a shared reference. We can safely cast the shared reference to a mutable.
-    let device = unsafe { ptr::from_ref(ne2k.deref()).cast_mut().as_mut().unwrap() };
-
     // process both incoming and outgoing network packets using smoltcp
-    let interface = interfaces.get_mut(0).expect("failed to get interface");
-    let _res = interface.poll(time, device, &mut sockets);
+    let routing = RoutingTable::new(&mut interfaces);
+    let mut changed = false;
+    for (index, interface) in interfaces.iter_mut().enumerate() {
+        changed |= interface.poll(time, &mut sockets, &routing, index) == PollResult::SocketStateChanged;
+    }
+    if changed {
+        wake_waiters(&sockets);
+    }
+    reap_closed_sockets(&mut sockets);
 
     // Johann Spenrath on 05.09.2025:
     // if socket state changed, hand over CPU control to other threads
@@ -525,49 +1655,14 @@ pub fn poll_sockets() -> Option<()> {
     //    scheduler().switch_thread_no_interrupt();
     //}
 
-    // DHCP handling is based on https://github.com/smoltcp-rs/smoltcp/blob/main/examples/dhcp_client.rs
-    // Johann Spenrath on 05.09.2025:
-    // check dhcp status (lease acquired or lost)
-    let dhcp_handle = DHCP_SOCKET.get().expect("DHCP socket does not exist yet");
-    let dhcp_socket = sockets.get_mut::<dhcpv4::Socket>(*dhcp_handle);
-    if let Some(event) = dhcp_socket.poll() {
-        match event {
-            dhcpv4::Event::Deconfigured => {
-                info!("lost DHCP lease");
-                interface.update_ip_addrs(|addrs| addrs.clear());
-                interface.routes_mut().remove_default_ipv4_route();
-            }
-            dhcpv4::Event::Configured(config) => {
-                info!("acquired DHCP lease:");
-                info!("IP address: {}", config.address);
-                interface.update_ip_addrs(|addrs| {
-                    addrs.clear();
-                    addrs.push(IpCidr::Ipv4(config.address)).unwrap();
-                });
-
-                if let Some(router) = config.router {
-                    info!("default gateway: {router}");
-                    interface.routes_mut().add_default_ipv4_route(router).unwrap();
-                } else {
-                    info!("no default gateway");
-                    interface.routes_mut().remove_default_ipv4_route();
-                }
-                info!("DNS servers: {:?}", config.dns_servers);
-                let dns_servers: Vec<_> = config.dns_servers.iter().map(|ip| IpAddress::Ipv4(*ip)).collect();
-                let dns_handle = DNS_SOCKET.get().expect("DNS socket does not exist yet");
-                let dns_socket = sockets.get_mut::<dns::Socket>(*dns_handle);
-                dns_socket.update_servers(&dns_servers);
-            }
-        }
-    }
-    Some(())
+    let time = Instant::from_millis(timer().systime_ms() as i64);
+    let delay = interfaces
+        .iter_mut()
+        .filter_map(|interface| interface.poll_delay(time, &mut sockets))
+        .fold(MAX_POLL_DELAY, |min, delay| min.min(delay));
+    Some(delay)
 }
 
-/// Try to poll all sockets.
-///
-/// This returns None, if it failed to get all needed locks.
-/// This is needed, because we otherwise might get a deadlock, because an
-/// application has the lock on `sockets` while we have the lock on `interfaces`.
 /*fn poll_sockets() -> Option<()> {
     let rtl8139 = RTL8139.get().expect("RTL8139 not initialized");
     let mut interfaces = INTERFACES.try_write()?;
@@ -618,27 +1713,25 @@ pub fn poll_sockets() -> Option<()> {
 
 pub(crate) fn close_sockets_for_process(process: &mut Process) {
     let mut lock = SOCKET_PROCESS.write();
-    let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
     let handles: Vec<_> = lock
         .iter()
         .filter(|(_handle, proc)| ***proc == *process)
         .map(|(handle, _proc)| handle)
         .copied()
         .collect();
+    // this needs the interfaces, so it has to happen before locking the sockets
+    for handle in &handles {
+        leave_all_multicast_groups(*handle);
+    }
+    let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
     for handle in handles {
         lock.remove(&handle).unwrap();
-        sockets.remove(handle);
-    }
-}
-
-/// Pick a random port if port == 0, else just use the passed port.
-fn pick_port(port: u16) -> u16 {
-    if port == 0 {
-        // TODO: make sure that this isn't used yet
-        timer().systime_ms() as u16
-    } else {
-        port
+        SOCKET_OPTIONS.write().remove(&handle);
+        LISTENERS.write().remove(&handle);
+        release_socket(&mut sockets, handle);
     }
+    packet::close_for_process(process);
+    descriptors::close_for_process(process.id);
 }
 
 // =============================================================================
@@ -0,0 +1,255 @@
+//! Packet sockets, that send and receive whole Ethernet frames (e.g. for ARP, LLDP or custom EtherType tools),
+//! and the privilege check for them and for raw IP sockets.
+//!
+//! Every frame, that a card receives, is offered to `deliver()` (see `nic::SmoltcpDevice`).
+//! Packet sockets get a copy of the frames of their interface, that have their EtherType,
+//! smoltcp still processes all of them. Frames, that are sent, are queued for their interface and
+//! handed to the card by the network thread (see `transmit_queued()`), because only that thread may use the card.
+//! They are captured, but smoltcp doesn't see them.
+//!
+//! Only the applications listed with `raw=<app>,<app>,...` (see `config`) may open packet and raw IP sockets
+//! (and change the addresses, routes and neighbor cache), `raw=*` allows all of them. There are no users, so the name of the application is all we can check.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use log::info;
+use smoltcp::phy::Medium;
+use smoltcp::wire::{EthernetFrame, EthernetProtocol};
+use spin::{Mutex, RwLock};
+
+use super::{capture, notify_worker, INTERFACES, MAX_WAIT_TIME};
+use crate::device::nic::NetworkDevice;
+use crate::process::process::Process;
+use crate::{process_manager, scheduler, timer};
+
+/// Received frames, that haven't been read yet, are dropped beyond this (the oldest first).
+/// Sending fails with `WouldBlock`, while this many frames are waiting for the network thread.
+const MAX_QUEUED_FRAMES: usize = 64;
+
+/// Checked for every frame, so that `deliver()` doesn't need the lock, while there are no packet sockets.
+static OPEN: AtomicBool = AtomicBool::new(false);
+static PACKET_SOCKETS: Mutex<BTreeMap<usize, PacketSocket>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+/// Frames, that have been sent, but not transmitted yet (by driver name of the interface)
+static TX_QUEUES: Mutex<BTreeMap<&'static str, VecDeque<Vec<u8>>>> = Mutex::new(BTreeMap::new());
+/// Applications, that may open packet and raw IP sockets ("*" means all)
+static PRIVILEGED_APPS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketError {
+    /// The application may not open packet sockets.
+    NotPermitted,
+    /// There is no such interface or it has no link layer.
+    NoSuchInterface,
+    /// There is no such socket or it belongs to a different process.
+    NoSuchSocket,
+    /// The frame is shorter than an Ethernet header or longer than the MTU.
+    InvalidFrame,
+    /// Receiving would have to wait or the send queue of the interface is full.
+    WouldBlock,
+    TimedOut,
+}
+
+struct PacketSocket {
+    owner: Arc<Process>,
+    /// Driver name of the interface
+    interface: &'static str,
+    /// Only receive frames with this EtherType (`None` means all).
+    ether_type: Option<u16>,
+    frames: VecDeque<Vec<u8>>,
+    /// Frames, that have been dropped, because nobody has read them in time
+    dropped: usize,
+    /// Threads, that are waiting in `receive()`
+    waiters: Vec<Arc<AtomicBool>>,
+}
+
+/// Set the applications, that may open packet and raw IP sockets and capture frames.
+pub fn set_privileged_apps(apps: Vec<String>) {
+    info!("Applications allowed to use packet and raw sockets and to capture: {apps:?}");
+    *PRIVILEGED_APPS.write() = apps;
+}
+
+/// Check whether the current process may open packet and raw IP sockets, capture frames (see `capture`)
+/// and change the addresses, routes and neighbor cache of the interfaces.
+///
+/// The kernel always may, applications only, if they are listed in the configuration.
+pub fn privileged() -> bool {
+    let manager = process_manager().read();
+    let process = manager.current_process();
+    if manager.kernel_process().is_some_and(|kernel| kernel == process) {
+        return true;
+    }
+    PRIVILEGED_APPS.read().iter().any(|app| app == "*" || *app == process.name)
+}
+
+/// Open a packet socket on the interface, whose driver name contains `interface`.
+///
+/// It receives the frames with `ether_type` (or all frames, if it is `None`). Returns the id of the socket.
+pub fn open(interface: &str, ether_type: Option<u16>) -> Result<usize, PacketError> {
+    if !privileged() {
+        return Err(PacketError::NotPermitted);
+    }
+    let interface = INTERFACES
+        .read()
+        .iter()
+        .filter(|candidate| candidate.device.capabilities().medium == Medium::Ethernet)
+        .find(|candidate| candidate.name.contains(interface))
+        .map(|candidate| candidate.name)
+        .ok_or(PacketError::NoSuchInterface)?;
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let owner = process_manager().read().current_process();
+    PACKET_SOCKETS.lock().insert(
+        id,
+        PacketSocket { owner, interface, ether_type, frames: VecDeque::new(), dropped: 0, waiters: Vec::new() },
+    );
+    OPEN.store(true, Ordering::Release);
+    Ok(id)
+}
+
+/// Close a packet socket of the current process.
+pub fn close(id: usize) -> Result<(), PacketError> {
+    let process = process_manager().read().current_process();
+    let mut sockets = PACKET_SOCKETS.lock();
+    if sockets.get(&id).is_none_or(|socket| socket.owner != process) {
+        return Err(PacketError::NoSuchSocket);
+    }
+    let socket = sockets.remove(&id).unwrap();
+    if socket.dropped > 0 {
+        info!("Packet socket [{id}] has dropped {} frames", socket.dropped);
+    }
+    OPEN.store(!sockets.is_empty(), Ordering::Release);
+    Ok(())
+}
+
+/// Close all packet sockets of a process, that is exiting.
+pub(crate) fn close_for_process(process: &Process) {
+    let mut sockets = PACKET_SOCKETS.lock();
+    sockets.retain(|_, socket| *socket.owner != *process);
+    OPEN.store(!sockets.is_empty(), Ordering::Release);
+}
+
+/// Send `frame` (including the Ethernet header) on the interface of the socket.
+///
+/// The frame is only queued here, the network thread transmits it (see `transmit_queued()`).
+pub fn send(id: usize, frame: &[u8]) -> Result<(), PacketError> {
+    let name = {
+        let process = process_manager().read().current_process();
+        let sockets = PACKET_SOCKETS.lock();
+        match sockets.get(&id) {
+            Some(socket) if socket.owner == process => socket.interface,
+            _ => return Err(PacketError::NoSuchSocket),
+        }
+    };
+
+    // smoltcp counts the Ethernet header into the MTU
+    let mtu = INTERFACES
+        .read()
+        .iter()
+        .find(|interface| interface.name == name)
+        .map(|interface| interface.device.capabilities().max_transmission_unit)
+        .ok_or(PacketError::NoSuchInterface)?;
+    if EthernetFrame::new_checked(frame).is_err() || frame.len() > mtu {
+        return Err(PacketError::InvalidFrame);
+    }
+
+    {
+        let mut queues = TX_QUEUES.lock();
+        let queue = queues.entry(name).or_default();
+        if queue.len() >= MAX_QUEUED_FRAMES {
+            return Err(PacketError::WouldBlock);
+        }
+        queue.push_back(frame.to_vec());
+    }
+    notify_worker();
+    Ok(())
+}
+
+/// Hand the frames, that have been sent on `interface`, to its card.
+///
+/// This is called by the network thread, before it polls the interface.
+pub(super) fn transmit_queued(interface: &'static str, device: &dyn NetworkDevice) {
+    // take the frames first, so that `send()` doesn't wait for the card
+    let Some(frames) = TX_QUEUES.lock().remove(interface) else {
+        return;
+    };
+    for frame in frames {
+        device.transmit(frame.len(), &mut |buffer| {
+            buffer[..frame.len()].copy_from_slice(&frame);
+            capture::record(interface, &frame);
+        });
+    }
+}
+
+/// Take the next received frame out of the socket and copy it into `buf`.
+///
+/// Frames, that don't fit, are cut off. Returns the number of bytes copied.
+/// If there is none, this waits up to `timeout` ms (`None` means forever, `Some(0)` returns right away).
+pub fn receive(id: usize, buf: &mut [u8], timeout: Option<usize>) -> Result<usize, PacketError> {
+    let process = process_manager().read().current_process();
+    let deadline = timeout.map(|timeout| timer().systime_ms().saturating_add(timeout));
+    loop {
+        let notified = Arc::new(AtomicBool::new(false));
+        {
+            let mut sockets = PACKET_SOCKETS.lock();
+            let socket = match sockets.get_mut(&id) {
+                Some(socket) if socket.owner == process => socket,
+                _ => return Err(PacketError::NoSuchSocket),
+            };
+            if let Some(frame) = socket.frames.pop_front() {
+                let len = frame.len().min(buf.len());
+                buf[..len].copy_from_slice(&frame[..len]);
+                return Ok(len);
+            }
+            if timeout == Some(0) {
+                return Err(PacketError::WouldBlock);
+            }
+            if deadline.is_some_and(|deadline| timer().systime_ms() >= deadline) {
+                return Err(PacketError::TimedOut);
+            }
+            socket.waiters.push(Arc::clone(&notified));
+        }
+
+        let now = timer().systime_ms();
+        let wait_time = deadline.map_or(MAX_WAIT_TIME, |deadline| deadline.saturating_sub(now).min(MAX_WAIT_TIME));
+        if wait_time > 0 {
+            scheduler().sleep_until_notified(wait_time, &notified);
+        }
+
+        if let Some(socket) = PACKET_SOCKETS.lock().get_mut(&id) {
+            socket.waiters.retain(|waiter| !Arc::ptr_eq(waiter, &notified));
+        }
+    }
+}
+
+/// Offer a frame, that has been received by `interface`, to the packet sockets.
+pub fn deliver(interface: &str, frame: &[u8]) {
+    if !OPEN.load(Ordering::Acquire) {
+        return;
+    }
+    let Ok(ethernet) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    let ether_type = ethernet.ethertype();
+
+    let mut sockets = PACKET_SOCKETS.lock();
+    for socket in sockets.values_mut() {
+        if socket.interface != interface
+            || socket.ether_type.is_some_and(|wanted| EthernetProtocol::from(wanted) != ether_type)
+        {
+            continue;
+        }
+        if socket.frames.len() >= MAX_QUEUED_FRAMES {
+            socket.frames.pop_front();
+            socket.dropped += 1;
+        }
+        socket.frames.push_back(frame.to_vec());
+        for waiter in socket.waiters.drain(..) {
+            waiter.store(true, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Local ports of the UDP and TCP sockets.
+//!
+//! Every port can only be bound by one socket per protocol (a listening TCP socket and its backlog count as one,
+//! accepted connections share the port of their listener). Sockets, that are bound to port 0,
+//! and outgoing TCP connections get an unused port from the ephemeral range.
+//! The range can be changed with `ports=<first>-<last>` (see `config`).
+//!
+//! A port is released, when its socket is removed from the socket set
+//! (closed TCP connections keep it, until they have finished sending).
+
+use alloc::collections::btree_map::BTreeMap;
+use core::ops::RangeInclusive;
+use log::info;
+use smoltcp::iface::SocketHandle;
+use spin::Mutex;
+
+use crate::timer;
+
+/// Ports for clients, as suggested by the IANA (RFC 6335)
+pub const DEFAULT_EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
+
+static PORTS: Mutex<Ports> = Mutex::new(Ports { ephemeral: DEFAULT_EPHEMERAL_PORTS, next: None, bound: BTreeMap::new() });
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Protocol {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortError {
+    /// The port is bound by another socket.
+    InUse,
+    /// All ephemeral ports are in use.
+    Exhausted,
+}
+
+struct Ports {
+    ephemeral: RangeInclusive<u16>,
+    /// The next ephemeral port to try (`None` until the first one is handed out)
+    next: Option<u16>,
+    /// The socket, that has bound each port
+    bound: BTreeMap<(Protocol, u16), SocketHandle>,
+}
+
+impl Ports {
+    /// Find an unused ephemeral port, starting after the one, that has been handed out last.
+    fn ephemeral_port(&mut self, protocol: Protocol) -> Option<u16> {
+        let first = *self.ephemeral.start();
+        let count = self.ephemeral.len() as u32;
+        if count == 0 {
+            return None;
+        }
+        // start at a random port, so that a restarted system doesn't reuse the ports of the old connections
+        let next = self.next.unwrap_or_else(|| first + (timer().systime_ms() as u32 % count) as u16);
+        let offset = next.wrapping_sub(first) as u32 % count;
+        let port = (0..count)
+            .map(|i| first + ((offset + i) % count) as u16)
+            .find(|port| !self.bound.contains_key(&(protocol, *port)))?;
+        self.next = Some(if port == *self.ephemeral.end() { first } else { port + 1 });
+        Some(port)
+    }
+}
+
+/// Change the range of the ephemeral ports (ports, that are bound already, are kept).
+pub fn set_ephemeral_range(range: RangeInclusive<u16>) {
+    info!("Ephemeral ports: {}-{}", range.start(), range.end());
+    let mut ports = PORTS.lock();
+    ports.ephemeral = range;
+    ports.next = None;
+}
+
+/// Bind `port` for `handle` or an ephemeral port, if `port` is 0. Returns the bound port.
+pub fn reserve(protocol: Protocol, port: u16, handle: SocketHandle) -> Result<u16, PortError> {
+    let mut ports = PORTS.lock();
+    let port = if port == 0 {
+        ports.ephemeral_port(protocol).ok_or(PortError::Exhausted)?
+    } else if ports.bound.contains_key(&(protocol, port)) {
+        return Err(PortError::InUse);
+    } else {
+        port
+    };
+    ports.bound.insert((protocol, port), handle);
+    Ok(port)
+}
+
+/// Give a single port back (e.g. because binding the socket has failed after all).
+pub fn unreserve(protocol: Protocol, port: u16) {
+    PORTS.lock().bound.remove(&(protocol, port));
+}
+
+/// Give all ports of a socket back.
+pub fn release(handle: SocketHandle) {
+    PORTS.lock().bound.retain(|_, owner| *owner != handle);
+}
@@ -0,0 +1,418 @@
+//! DNS resolver for A and AAAA records.
+//!
+//! Names are looked up in this order:
+//! 1. `etc/hosts` in the initial ramdisk (`<address> <name> [<alias> ...]` per line, `#` starts a comment)
+//! 2. the cache, where answers stay as long as their TTL allows
+//! 3. the DNS servers, that have been configured via DHCP or statically (see `config`)
+//!
+//! Every server is asked up to `retries + 1` times, each time waiting `timeout_ms` for an answer.
+//! The servers are only asked for A and AAAA records, CNAME records are followed by the servers themselves
+//! (the addresses of the canonical name are part of the answer).
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicU16, Ordering};
+use log::{info, warn};
+use num_enum::TryFromPrimitive;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::udp;
+use smoltcp::wire::{
+    DnsFlags, DnsOpcode, DnsPacket, DnsQueryType, DnsQuestion, DnsRcode, DnsRecord, DnsRecordData, DnsRepr, IpAddress,
+    IpEndpoint,
+};
+use spin::{Mutex, Once, RwLock};
+
+use crate::network::ports::{self, Protocol};
+use crate::network::{notify_worker, wait_for, Interest, SOCKETS};
+use crate::{initrd, timer};
+
+/// Path of the hosts file in the initial ramdisk
+pub const HOSTS_FILE: &str = "etc/hosts";
+/// Time to wait for an answer of a server, before asking again (in ms)
+pub const DEFAULT_TIMEOUT_MS: usize = 1000;
+/// How often a server is asked again, if it doesn't answer in time
+pub const DEFAULT_RETRIES: usize = 2;
+const DNS_PORT: u16 = 53;
+/// Answers and queries are small, because only A and AAAA records are requested
+const DNS_BUFFER_SIZE: usize = 1500;
+const MAX_CACHE_ENTRIES: usize = 64;
+/// Answers are cached at most this long (in s), even if their TTL is longer
+const MAX_TTL: u32 = 3600;
+/// A name must not be longer than this (in bytes, RFC 1035)
+const MAX_NAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+
+/// The DNS servers, that are asked in this order
+static SERVERS: RwLock<Vec<IpAddress>> = RwLock::new(Vec::new());
+/// Static entries from the hosts file (address and lower case name)
+static HOSTS: Once<Vec<(IpAddress, String)>> = Once::new();
+static CACHE: Mutex<BTreeMap<(String, RecordType), CacheEntry>> = Mutex::new(BTreeMap::new());
+/// Counter for the transaction IDs of the queries
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+/// Which addresses should be looked up (the numbers are used by the `Resolve` syscall).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(usize)]
+pub enum AddressFamily {
+    /// IPv6 and IPv4 addresses (in this order)
+    Any = 0,
+    Ipv4 = 1,
+    Ipv6 = 2,
+}
+
+impl AddressFamily {
+    fn records(self) -> &'static [RecordType] {
+        match self {
+            AddressFamily::Any => &[RecordType::Aaaa, RecordType::A],
+            AddressFamily::Ipv4 => &[RecordType::A],
+            AddressFamily::Ipv6 => &[RecordType::Aaaa],
+        }
+    }
+
+    fn contains(self, address: &IpAddress) -> bool {
+        matches!(
+            (self, address),
+            (AddressFamily::Any, _) | (AddressFamily::Ipv4, IpAddress::Ipv4(_)) | (AddressFamily::Ipv6, IpAddress::Ipv6(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RecordType {
+    A,
+    Aaaa,
+}
+
+impl RecordType {
+    fn query_type(self) -> DnsQueryType {
+        match self {
+            RecordType::A => DnsQueryType::A,
+            RecordType::Aaaa => DnsQueryType::Aaaa,
+        }
+    }
+
+    fn matches(self, address: &IpAddress) -> bool {
+        matches!((self, address), (RecordType::A, IpAddress::Ipv4(_)) | (RecordType::Aaaa, IpAddress::Ipv6(_)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveError {
+    /// The name is not a valid host name.
+    InvalidName,
+    /// The name doesn't exist (NXDOMAIN) or has no addresses.
+    NotFound,
+    /// No server has answered in time.
+    TimedOut,
+    /// There is no DNS server to ask.
+    NoServers,
+    /// There is no free port for the queries.
+    NoPort,
+}
+
+struct CacheEntry {
+    addresses: Vec<IpAddress>,
+    /// System time in ms
+    expires_at: usize,
+}
+
+/// Answer of a server for one record type
+enum Answer {
+    /// The addresses and the smallest TTL of their records (in s)
+    Found(Vec<IpAddress>, u32),
+    /// The name doesn't exist
+    NxDomain,
+}
+
+/// Read the hosts file from the initial ramdisk.
+pub fn init() {
+    HOSTS.call_once(|| {
+        let Some(entry) = initrd().entries().find(|entry| entry.filename().as_str() == Ok(HOSTS_FILE)) else {
+            return Vec::new();
+        };
+        match core::str::from_utf8(entry.data()) {
+            Ok(text) => parse_hosts(text),
+            Err(_) => {
+                warn!("{HOSTS_FILE} is not valid UTF-8");
+                Vec::new()
+            }
+        }
+    });
+}
+
+fn parse_hosts(text: &str) -> Vec<(IpAddress, String)> {
+    let mut hosts = Vec::new();
+    for line in text.lines() {
+        // everything after a '#' is a comment
+        let mut words = line.split('#').next().unwrap_or_default().split_whitespace();
+        let Some(address) = words.next() else {
+            continue;
+        };
+        match IpAddress::from_str(address) {
+            Ok(address) => hosts.extend(words.map(|name| (address, name.to_ascii_lowercase()))),
+            Err(()) => warn!("Ignoring invalid entry in {HOSTS_FILE}: [{line}]"),
+        }
+    }
+    hosts
+}
+
+/// Replace the DNS servers (and forget the cached answers of the old ones).
+pub fn set_servers(servers: &[IpAddress]) {
+    let mut current = SERVERS.write();
+    if current.as_slice() != servers {
+        info!("DNS servers: {servers:?}");
+        *current = servers.to_vec();
+        CACHE.lock().clear();
+    }
+}
+
+/// Get the addresses of `host`.
+///
+/// IP addresses are returned as they are. A name without any addresses results in `NotFound`.
+pub fn resolve(host: &str, family: AddressFamily, timeout_ms: usize, retries: usize) -> Result<Vec<IpAddress>, ResolveError> {
+    if let Ok(address) = IpAddress::from_str(host) {
+        return Ok(vec![address]);
+    }
+    let name = host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase();
+
+    let static_addresses: Vec<_> = HOSTS
+        .get()
+        .into_iter()
+        .flatten()
+        .filter(|(address, host)| *host == name && family.contains(address))
+        .map(|(address, _)| *address)
+        .collect();
+    if !static_addresses.is_empty() {
+        return Ok(static_addresses);
+    }
+
+    let mut answers: BTreeMap<RecordType, Vec<IpAddress>> = BTreeMap::new();
+    let mut missing = Vec::new();
+    for record in family.records() {
+        match cached(&name, *record) {
+            Some(addresses) => {
+                answers.insert(*record, addresses);
+            }
+            None => missing.push(*record),
+        }
+    }
+
+    if !missing.is_empty() {
+        match query(&name, &missing, timeout_ms, retries) {
+            Ok(results) => {
+                for (record, answer) in results {
+                    if let Answer::Found(addresses, ttl) = answer {
+                        cache(&name, record, &addresses, ttl);
+                        answers.insert(record, addresses);
+                    }
+                }
+            }
+            // the other record type may still be cached
+            Err(error) if answers.is_empty() => return Err(error),
+            Err(_) => {}
+        }
+    }
+
+    let addresses: Vec<_> = family.records().iter().filter_map(|record| answers.remove(record)).flatten().collect();
+    if addresses.is_empty() { Err(ResolveError::NotFound) } else { Ok(addresses) }
+}
+
+fn cached(name: &str, record: RecordType) -> Option<Vec<IpAddress>> {
+    let mut cache = CACHE.lock();
+    let key = (name.to_string(), record);
+    let entry = cache.get(&key)?;
+    if entry.expires_at <= timer().systime_ms() {
+        cache.remove(&key);
+        return None;
+    }
+    Some(entry.addresses.clone())
+}
+
+fn cache(name: &str, record: RecordType, addresses: &[IpAddress], ttl: u32) {
+    if ttl == 0 || addresses.is_empty() {
+        return;
+    }
+    let now = timer().systime_ms();
+    let mut cache = CACHE.lock();
+    if cache.len() >= MAX_CACHE_ENTRIES {
+        cache.retain(|_, entry| entry.expires_at > now);
+    }
+    if cache.len() >= MAX_CACHE_ENTRIES {
+        // still full, throw away the entry, that would expire next
+        let first = cache.iter().min_by_key(|(_, entry)| entry.expires_at).map(|(key, _)| key.clone());
+        if let Some(key) = first {
+            cache.remove(&key);
+        }
+    }
+    let expires_at = now + ttl.min(MAX_TTL) as usize * 1000;
+    cache.insert((name.to_string(), record), CacheEntry { addresses: addresses.to_vec(), expires_at });
+}
+
+/// Encode a name as a sequence of labels, each prefixed with its length.
+fn encode_name(name: &str) -> Result<Vec<u8>, ResolveError> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(ResolveError::InvalidName);
+    }
+    let mut encoded = Vec::with_capacity(name.len() + 2);
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > MAX_LABEL_LEN {
+            return Err(ResolveError::InvalidName);
+        }
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    Ok(encoded)
+}
+
+/// Ask the servers for the `records` of `name`. Returns the answer of the first server, that knows it.
+fn query(name: &str, records: &[RecordType], timeout_ms: usize, retries: usize) -> Result<Vec<(RecordType, Answer)>, ResolveError> {
+    let servers = SERVERS.read().clone();
+    if servers.is_empty() {
+        return Err(ResolveError::NoServers);
+    }
+    let encoded = encode_name(name)?;
+
+    let handle = open_socket()?;
+    let mut result = Err(ResolveError::TimedOut);
+    'servers: for server in servers {
+        for _ in 0..=retries {
+            match ask(handle, server, &encoded, records, timeout_ms) {
+                Some(Ok(answers)) => {
+                    result = Ok(answers);
+                    break 'servers;
+                }
+                // the server has failed (e.g. SERVFAIL), try the next one
+                Some(Err(())) => continue 'servers,
+                // no answer in time, try again
+                None => {}
+            }
+        }
+        warn!("DNS server {server} hasn't answered the query for {name}");
+    }
+    SOCKETS.get().expect("Socket set not initialized!").write().remove(handle);
+    ports::release(handle);
+
+    let answers = result?;
+    if answers.iter().all(|(_, answer)| matches!(answer, Answer::NxDomain)) {
+        return Err(ResolveError::NotFound);
+    }
+    Ok(answers)
+}
+
+/// Create the UDP socket for the queries. It belongs to the kernel, not to a process.
+fn open_socket() -> Result<SocketHandle, ResolveError> {
+    let rx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 4 * DNS_BUFFER_SIZE]);
+    let tx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 2], vec![0; 2 * DNS_BUFFER_SIZE]);
+    let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+    let handle = sockets.add(udp::Socket::new(rx_buffer, tx_buffer));
+    let Ok(port) = ports::reserve(Protocol::Udp, 0, handle) else {
+        sockets.remove(handle);
+        return Err(ResolveError::NoPort);
+    };
+    sockets.get_mut::<udp::Socket>(handle).bind(port).expect("failed to bind DNS socket");
+    Ok(handle)
+}
+
+/// Send one query per record type to `server` and wait up to `timeout_ms` for all answers.
+///
+/// Returns `None`, if the server hasn't answered in time, and `Some(Err(()))`, if it has failed.
+fn ask(
+    handle: SocketHandle, server: IpAddress, name: &[u8], records: &[RecordType], timeout_ms: usize,
+) -> Option<Result<Vec<(RecordType, Answer)>, ()>> {
+    let server = IpEndpoint::new(server, DNS_PORT);
+    let mut pending: Vec<(u16, RecordType)> = Vec::new();
+    {
+        let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+        let socket = sockets.get_mut::<udp::Socket>(handle);
+        // drop late answers to earlier queries
+        while socket.recv().is_ok() {}
+        for record in records {
+            let id = (timer().systime_ms() as u16).wrapping_mul(31).wrapping_add(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+            let repr = DnsRepr {
+                transaction_id: id,
+                opcode: DnsOpcode::Query,
+                flags: DnsFlags::RECURSION_DESIRED,
+                question: DnsQuestion { name, type_: record.query_type() },
+            };
+            let mut packet = vec![0; repr.buffer_len()];
+            repr.emit(&mut DnsPacket::new_unchecked(packet.as_mut_slice()));
+            if socket.send_slice(&packet, server).is_err() {
+                warn!("failed to send DNS query to {server}");
+                return None;
+            }
+            pending.push((id, *record));
+        }
+    }
+    notify_worker();
+
+    let deadline = timer().systime_ms().saturating_add(timeout_ms);
+    let mut answers = Vec::new();
+    let mut buffer = vec![0; DNS_BUFFER_SIZE];
+    loop {
+        {
+            let mut sockets = SOCKETS.get().expect("Socket set not initialized!").write();
+            let socket = sockets.get_mut::<udp::Socket>(handle);
+            while let Ok((len, meta)) = socket.recv_slice(&mut buffer) {
+                if meta.endpoint != server {
+                    continue;
+                }
+                let Some((id, rcode, addresses, ttl)) = parse_response(&buffer[..len]) else {
+                    continue;
+                };
+                let Some(index) = pending.iter().position(|(pending_id, _)| *pending_id == id) else {
+                    continue;
+                };
+                let (_, record) = pending.remove(index);
+                match rcode {
+                    DnsRcode::NoError => {
+                        let addresses = addresses.into_iter().filter(|address| record.matches(address)).collect();
+                        answers.push((record, Answer::Found(addresses, ttl)));
+                    }
+                    DnsRcode::NXDomain => answers.push((record, Answer::NxDomain)),
+                    rcode => {
+                        warn!("DNS server {server} has failed: {rcode:?}");
+                        return Some(Err(()));
+                    }
+                }
+            }
+        }
+        if pending.is_empty() {
+            return Some(Ok(answers));
+        }
+        if !wait_for(handle, Interest::Receive, Some(deadline)) {
+            return None;
+        }
+    }
+}
+
+/// Get the transaction ID, the response code and the addresses (with the smallest TTL) of a response.
+fn parse_response(data: &[u8]) -> Option<(u16, DnsRcode, Vec<IpAddress>, u32)> {
+    let packet = DnsPacket::new_checked(data).ok()?;
+    if !packet.flags().contains(DnsFlags::RESPONSE) || packet.opcode() != DnsOpcode::Query {
+        return None;
+    }
+
+    let mut rest = packet.payload();
+    for _ in 0..packet.question_count() {
+        (rest, _) = DnsQuestion::parse(rest).ok()?;
+    }
+    let mut addresses = Vec::new();
+    let mut ttl = u32::MAX;
+    for _ in 0..packet.answer_record_count() {
+        let (remaining, record) = DnsRecord::parse(rest).ok()?;
+        rest = remaining;
+        let address = match record.data {
+            DnsRecordData::A(address) => IpAddress::Ipv4(address),
+            DnsRecordData::Aaaa(address) => IpAddress::Ipv6(address),
+            _ => continue,
+        };
+        addresses.push(address);
+        ttl = ttl.min(record.ttl);
+    }
+
+    Some((packet.transaction_id(), packet.rcode(), addresses, ttl))
+}
@@ -0,0 +1,232 @@
+//! IPv6 stateless address autoconfiguration (RFC 4862).
+//!
+//! Every network card gets a link-local address (fe80::/64), derived from its MAC address.
+//! Then, it asks the routers on the link for their prefixes (router solicitation)
+//! and derives a global address for every prefix, that it gets (router advertisement).
+//! The router, that sent the advertisement, becomes the default IPv6 gateway.
+//!
+//! smoltcp answers neighbor solicitations, but it ignores router advertisements,
+//! so they are received with a raw ICMPv6 socket here.
+//! There is no duplicate address detection, the addresses are used right away.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use log::{info, warn};
+use smoltcp::iface::{Interface, Route, SocketHandle, SocketSet};
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::raw;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{
+    EthernetAddress, Icmpv6Packet, Icmpv6Repr, IpCidr, IpProtocol, IpVersion, Ipv6Address, Ipv6Cidr,
+    Ipv6Packet, Ipv6Repr, NdiscPrefixInfoFlags, NdiscRepr, RawHardwareAddress, IPV6_LINK_LOCAL_ALL_ROUTERS,
+};
+
+/// Number of router solicitations, that are sent, if no router answers (MAX_RTR_SOLICITATIONS in RFC 4861)
+const MAX_SOLICITATIONS: usize = 3;
+/// Time between two router solicitations (RTR_SOLICITATION_INTERVAL in RFC 4861)
+const SOLICITATION_INTERVAL: Duration = Duration::from_secs(4);
+/// Neighbor discovery packets must have this hop limit, so that they can't come from outside the link
+const NDISC_HOP_LIMIT: u8 = 255;
+/// A lifetime of 0xffffffff seconds means, that the prefix doesn't expire
+const INFINITE_LIFETIME: Duration = Duration::from_secs(0xffff_ffff);
+/// SLAAC only works for prefixes of this length (64 bits prefix, 64 bits interface identifier)
+const PREFIX_LEN: u8 = 64;
+
+/// Autoconfiguration of the IPv6 addresses of one network card.
+pub struct Slaac {
+    mac: EthernetAddress,
+    /// The raw socket is only added to the socket set while the interface is polled (like the DHCP socket).
+    socket: Option<raw::Socket<'static>>,
+    /// Number of router solicitations, that have been sent so far
+    solicitations: usize,
+    next_solicitation: Instant,
+    /// Whether a router has answered, so no more solicitations are needed
+    configured: bool,
+    /// Addresses, that have been derived from a prefix, and when they expire (`None` means never)
+    addresses: Vec<(Ipv6Cidr, Option<Instant>)>,
+}
+
+impl Slaac {
+    pub fn new(mac: EthernetAddress) -> Self {
+        let rx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 4], vec![0; 4096]);
+        let tx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 1], vec![0; 256]);
+        Self {
+            mac,
+            socket: Some(raw::Socket::new(IpVersion::Ipv6, IpProtocol::Icmpv6, rx_buffer, tx_buffer)),
+            solicitations: 0,
+            next_solicitation: Instant::ZERO,
+            configured: false,
+            addresses: Vec::new(),
+        }
+    }
+
+    /// The modified EUI-64 interface identifier (RFC 4291, appendix A): the MAC address with ff:fe in the middle
+    /// and the universal/local bit flipped.
+    fn interface_identifier(&self) -> [u8; 8] {
+        let [m0, m1, m2, m3, m4, m5] = self.mac.0;
+        [m0 ^ 0x02, m1, m2, 0xff, 0xfe, m3, m4, m5]
+    }
+
+    /// Combine the first 64 bits of `prefix` with the interface identifier.
+    fn address_for(&self, prefix: &Ipv6Address) -> Ipv6Address {
+        let mut octets = prefix.octets();
+        octets[8..].copy_from_slice(&self.interface_identifier());
+        Ipv6Address::from(octets)
+    }
+
+    /// The link-local address (fe80::/64) of the card.
+    pub fn link_local_address(&self) -> Ipv6Cidr {
+        let prefix = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0);
+        Ipv6Cidr::new(self.address_for(&prefix), PREFIX_LEN)
+    }
+
+    /// Add the raw socket to the socket set, before the interface is polled.
+    ///
+    /// If a router solicitation is due, it is queued, so that the poll sends it.
+    pub fn attach(&mut self, time: Instant, sockets: &mut SocketSet<'static>) -> SocketHandle {
+        let mut socket = self.socket.take().expect("SLAAC socket is already attached");
+        if !self.configured && self.solicitations < MAX_SOLICITATIONS && time >= self.next_solicitation {
+            match socket.send_slice(&self.router_solicitation()) {
+                Ok(()) => {
+                    self.solicitations += 1;
+                    self.next_solicitation = time + SOLICITATION_INTERVAL;
+                }
+                Err(error) => warn!("failed to send router solicitation: {error:?}"),
+            }
+        }
+        sockets.add(socket)
+    }
+
+    /// Process the router advertisements, that have been received by the poll, remove expired addresses
+    /// and take the raw socket out of the socket set again.
+    ///
+    /// Returns `true`, if the addresses of the interface have changed.
+    pub fn detach(&mut self, time: Instant, interface: &mut Interface, sockets: &mut SocketSet<'static>, handle: SocketHandle) -> bool {
+        let mut socket = match sockets.remove(handle) {
+            smoltcp::socket::Socket::Raw(socket) => socket,
+            _ => panic!("SLAAC socket has been replaced"),
+        };
+
+        let mut changed = false;
+        while let Ok(packet) = socket.recv() {
+            changed |= self.process_packet(time, interface, packet);
+        }
+
+        // remove the addresses, whose prefix hasn't been advertised in time
+        let (expired, valid): (Vec<_>, Vec<_>) =
+            self.addresses.drain(..).partition(|(_, valid_until)| valid_until.is_some_and(|valid_until| valid_until <= time));
+        self.addresses = valid;
+        for (address, _) in expired {
+            info!("IPv6 address {address} has expired");
+            interface.update_ip_addrs(|addrs| addrs.retain(|cidr| *cidr != IpCidr::Ipv6(address)));
+            changed = true;
+        }
+
+        self.socket = Some(socket);
+        changed
+    }
+
+    /// Get the time until the next router solicitation or until the next address expires.
+    pub fn poll_delay(&self, time: Instant) -> Option<Duration> {
+        let solicitation = (!self.configured && self.solicitations < MAX_SOLICITATIONS).then_some(self.next_solicitation);
+        self.addresses
+            .iter()
+            .filter_map(|(_, valid_until)| *valid_until)
+            .chain(solicitation)
+            .min()
+            .map(|deadline| if deadline > time { deadline - time } else { Duration::ZERO })
+    }
+
+    /// Build a router solicitation (an IPv6 packet, that is sent to all routers on the link).
+    fn router_solicitation(&self) -> Vec<u8> {
+        let source = self.link_local_address().address();
+        let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit { lladdr: Some(RawHardwareAddress::from(self.mac)) });
+        let ip_repr = Ipv6Repr {
+            src_addr: source,
+            dst_addr: IPV6_LINK_LOCAL_ALL_ROUTERS,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit: NDISC_HOP_LIMIT,
+        };
+
+        let mut buffer = vec![0; ip_repr.buffer_len() + icmp_repr.buffer_len()];
+        let mut packet = Ipv6Packet::new_unchecked(&mut buffer);
+        ip_repr.emit(&mut packet);
+        let mut icmp_packet = Icmpv6Packet::new_unchecked(packet.payload_mut());
+        icmp_repr.emit(&source, &IPV6_LINK_LOCAL_ALL_ROUTERS, &mut icmp_packet, &ChecksumCapabilities::default());
+        buffer
+    }
+
+    /// Handle an ICMPv6 packet. Everything except router advertisements is ignored (smoltcp handles it).
+    fn process_packet(&mut self, time: Instant, interface: &mut Interface, packet: &[u8]) -> bool {
+        let Ok(packet) = Ipv6Packet::new_checked(packet) else {
+            return false;
+        };
+        let Ok(ip_repr) = Ipv6Repr::parse(&packet) else {
+            return false;
+        };
+        // routers send advertisements from their link-local address
+        if ip_repr.next_header != IpProtocol::Icmpv6 || ip_repr.hop_limit != NDISC_HOP_LIMIT || !ip_repr.src_addr.is_unicast_link_local() {
+            return false;
+        }
+        let Ok(icmp_packet) = Icmpv6Packet::new_checked(packet.payload()) else {
+            return false;
+        };
+        let Ok(Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert { router_lifetime, prefix_info, .. })) =
+            Icmpv6Repr::parse(&ip_repr.src_addr, &ip_repr.dst_addr, &icmp_packet, &ChecksumCapabilities::default())
+        else {
+            return false;
+        };
+
+        self.configured = true;
+        let router = ip_repr.src_addr;
+        interface.routes_mut().update(|routes| {
+            routes.retain(|route| route.cidr != IpCidr::Ipv6(Ipv6Cidr::new(Ipv6Address::UNSPECIFIED, 0)));
+            // a lifetime of 0 means, that the router is no default gateway (anymore)
+            if router_lifetime != Duration::ZERO {
+                let route = Route::new_ipv6_gateway(router);
+                let route = Route { expires_at: Some(time + router_lifetime), ..route };
+                if routes.push(route).is_err() {
+                    warn!("failed to add IPv6 default gateway {router}: too many routes");
+                }
+            }
+        });
+
+        let Some(prefix) = prefix_info else {
+            return false;
+        };
+        if !prefix.flags.contains(NdiscPrefixInfoFlags::ADDRCONF) || prefix.prefix_len != PREFIX_LEN || prefix.prefix.is_unicast_link_local() {
+            return false;
+        }
+        let address = Ipv6Cidr::new(self.address_for(&prefix.prefix), PREFIX_LEN);
+        let valid_until = (prefix.valid_lifetime != INFINITE_LIFETIME).then(|| time + prefix.valid_lifetime);
+        if let Some(entry) = self.addresses.iter_mut().find(|(known, _)| *known == address) {
+            // the prefix has been advertised again, which extends the lifetime
+            entry.1 = valid_until;
+            return false;
+        }
+        if prefix.valid_lifetime == Duration::ZERO {
+            return false;
+        }
+
+        // the address may already have been added by hand, then it isn't managed here
+        let mut result = Ok(false);
+        interface.update_ip_addrs(|addrs| {
+            if !addrs.contains(&IpCidr::Ipv6(address)) {
+                result = addrs.push(IpCidr::Ipv6(address)).map(|()| true);
+            }
+        });
+        match result {
+            Ok(true) => {
+                info!("IPv6 address {address} (router {router})");
+                self.addresses.push((address, valid_until));
+                true
+            }
+            Ok(false) => false,
+            Err(_) => {
+                warn!("failed to add IPv6 address {address}: too many addresses");
+                false
+            }
+        }
+    }
+}
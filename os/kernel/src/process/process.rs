@@ -6,6 +6,7 @@
    ║ Author: Fabian Ruhland, HHU                                             ║
    ╚═════════════════════════════════════════════════════════════════════════╝
 */
+use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::sync::atomic::AtomicUsize;
@@ -23,13 +24,15 @@ fn next_process_id() -> usize {
 
 pub struct Process {
     pub id: usize,
+    /// Name of the application (e.g. "shell") or "kernel"
+    pub name: String,
     pub virtual_address_space: VirtualAddressSpace,
 }
 
 
 impl Process {
-    pub fn new(page_tables: Arc<Paging>) -> Self {
-        Self { id: next_process_id(), virtual_address_space: VirtualAddressSpace::new(page_tables) }
+    pub fn new(page_tables: Arc<Paging>, name: &str) -> Self {
+        Self { id: next_process_id(), name: name.to_string(), virtual_address_space: VirtualAddressSpace::new(page_tables) }
     }
 
     /// Return the id of the process
@@ -69,7 +72,7 @@ impl PartialEq for Process {
 
 impl core::fmt::Debug for Process {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("Process").field("id", &self.id).finish()
+        f.debug_struct("Process").field("id", &self.id).field("name", &self.name).finish()
     }
 }
 
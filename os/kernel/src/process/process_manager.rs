@@ -31,11 +31,11 @@ impl ProcessManager {
         }
     }
 
-    /// Create a new process
-    pub fn create_process(&mut self) -> Arc<Process> {
+    /// Create a new process for the application `name`
+    pub fn create_process(&mut self, name: &str) -> Arc<Process> {
         let kernel_process = self.kernel_process().expect("No kernel process found!");
         let paging = vmm::clone_address_space(&(kernel_process.virtual_address_space));
-        let process = Arc::new(Process::new(paging));
+        let process = Arc::new(Process::new(paging, name));
         self.active_processes.push(Arc::clone(&process));
         process
     }
@@ -48,7 +48,7 @@ impl ProcessManager {
         }
 
         let paging = vmm::create_kernel_address_space();
-        let kernel_process = Arc::new(Process::new(paging));
+        let kernel_process = Arc::new(Process::new(paging, "kernel"));
         self.active_processes.push(Arc::clone(&kernel_process));
 
         // TODO: adjust this when removing 1:1 mapping
@@ -12,8 +12,8 @@ use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::ptr;
-use core::sync::atomic::AtomicUsize;
-use core::sync::atomic::Ordering::Relaxed;
+use core::sync::atomic::{AtomicBool, AtomicUsize};
+use core::sync::atomic::Ordering::{Relaxed, SeqCst};
 use smallmap::Map;
 use spin::{Mutex, MutexGuard};
 
@@ -42,10 +42,13 @@ impl ReadyState {
     }
 }
 
+/// A sleeping thread, its wakeup time and an optional flag, which wakes it up early when set
+type SleepEntry = (Arc<Thread>, usize, Option<Arc<AtomicBool>>);
+
 /// Main struct of the scheduler
 pub struct Scheduler {
     ready_state: Mutex<ReadyState>,
-    sleep_list: Mutex<Vec<(Arc<Thread>, usize)>>,
+    sleep_list: Mutex<Vec<SleepEntry>>,
     join_map: Mutex<Map<usize, Vec<Arc<Thread>>>>, // manage which threads are waiting for a thread-id to terminate
 }
 
@@ -151,13 +154,47 @@ impl Scheduler {
             {
                 // Execute in own block, so that the lock is released automatically (block() does not return)
                 let mut sleep_list = self.sleep_list.lock();
-                sleep_list.push((thread, wakeup_time));
+                sleep_list.push((thread, wakeup_time, None));
             }
 
             self.block(&mut state);
         }
     }
 
+    ///
+    /// Description: Put calling thread to sleep for at most `ms` milliseconds.
+    ///              The thread is woken up early, as soon as `notified` is set.
+    ///              Setting the flag does not need any locks, so it may be done by interrupt handlers.
+    ///              The flag is not reset, this is up to the caller.
+    ///
+    /// Parameters: `ms` maximum sleep time
+    ///             `notified` flag to wait for
+    ///
+    pub fn sleep_until_notified(&self, ms: usize, notified: &Arc<AtomicBool>) {
+        let mut state = self.get_ready_state();
+
+        if !state.initialized {
+            // Scheduler is not initialized yet, so this function has been called during the boot process
+            timer().wait(ms);
+            return;
+        }
+
+        let thread = Scheduler::current(&state);
+        let wakeup_time = timer().systime_ms() + ms;
+
+        {
+            // Execute in own block, so that the lock is released automatically (block() does not return)
+            let mut sleep_list = self.sleep_list.lock();
+            // If the flag is set after this check, `check_sleep_list()` will see it
+            if notified.load(SeqCst) {
+                return;
+            }
+            sleep_list.push((thread, wakeup_time, Some(Arc::clone(notified))));
+        }
+
+        self.block(&mut state);
+    }
+
     /// 
     /// Description: Switch from current to next thread (from ready queue)
     /// 
@@ -335,11 +372,12 @@ impl Scheduler {
         Arc::clone(state.current_thread.as_ref().expect("Trying to access current thread before initialization!"))
     }
 
-    fn check_sleep_list(state: &mut ReadyState, sleep_list: &mut Vec<(Arc<Thread>, usize)>) {
+    fn check_sleep_list(state: &mut ReadyState, sleep_list: &mut Vec<SleepEntry>) {
         let time = timer().systime_ms();
 
         sleep_list.retain(|entry| {
-            if time >= entry.1 {
+            let notified = entry.2.as_ref().is_some_and(|flag| flag.load(SeqCst));
+            if time >= entry.1 || notified {
                 state.ready_queue.push_front(Arc::clone(&entry.0));
                 false
             } else {
@@ -139,7 +139,7 @@ impl Thread {
     /// Returns the main thread of the application which is not yet registered in the scheduler.
     pub fn load_application(elf_buffer: &[u8], name: &str, args: &Vec<&str>) -> Arc<Thread> {
         let current_process = process_manager().read().current_process();
-        let new_process = process_manager().write().create_process();
+        let new_process = process_manager().write().create_process(name);
         let pid = new_process.id();
         let tid = scheduler::next_thread_id();
 
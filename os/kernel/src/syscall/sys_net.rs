@@ -1,15 +1,27 @@
 use core::str::FromStr;
 
-use alloc::{ffi::CString, string::ToString};
+use alloc::{ffi::CString, string::{String, ToString}, vec::Vec};
 use log::{debug, info, warn};
-use smoltcp::{iface::SocketHandle, socket::{icmp, tcp, udp}, wire::IpAddress};
+use num_enum::TryFromPrimitive;
+use smoltcp::{socket::{icmp, raw, tcp, udp}, time::Instant, wire::{EthernetAddress, IpAddress, IpCidr, IpProtocol, IpVersion, Ipv4Packet, Ipv6Packet}};
+use naming::shared_types::{PollEntry, PollFlags, PollKind};
 use syscall::return_vals::Errno;
 
-use crate::{network::{accept_tcp, bind_icmp, bind_tcp, bind_udp, close_socket, connect_tcp, get_ip_addresses, open_icmp, open_tcp, open_udp, receive_datagram, receive_icmp, receive_tcp, send_datagram, send_icmp, send_tcp, SocketType}, syscall::sys_naming::ptr_to_string};
+use crate::{device::nic::NetworkStats, network::{capture::{self, CaptureFilter}, descriptors::{self, Descriptor, DescriptorError, SocketState}, packet::{self, PacketError}, resolver::{self, AddressFamily, ResolveError}, accept_tcp, add_ip_address, block_on, BindError, add_neighbor, add_route, remove_ip_address, remove_neighbor, remove_route, flush_neighbors, ConfigError, all_interface_links, all_interface_stats, all_neighbors, all_routes, bind_icmp, bind_tcp, bind_udp, close_socket, connect_tcp, get_ip_addresses, is_broadcast, join_multicast_group, leave_multicast_group, open_icmp, open_raw, open_tcp, open_udp, receive_datagram, receive_icmp, receive_raw, receive_tcp, send_datagram, send_icmp, send_raw, send_tcp, shutdown_tcp, get_socket_option, set_socket_option, socket_options, socket_ready, tcp_connected, wait_for_any, wait_targets, Interest, Shutdown, SocketOption, SocketOptionError, SocketType}, naming::api, syscall::sys_naming::ptr_to_string, timer};
 
 /// This module contains all network-related system calls.
 
-pub fn sys_sock_open(protocol: SocketType) -> isize {
+/// Look up the socket behind `descriptor`, that must have been opened as `protocol` (`None` accepts all types).
+fn lookup(descriptor: usize, protocol: Option<SocketType>) -> Result<Descriptor, Errno> {
+    match protocol {
+        Some(protocol) => descriptors::get_typed(descriptor, protocol),
+        None => descriptors::get(descriptor),
+    }
+    .map_err(Errno::from)
+}
+
+/// Open a socket and return its descriptor. `ip_version` (4 or 6) and `ip_protocol` are only used by raw sockets.
+pub fn sys_sock_open(protocol: SocketType, ip_version: usize, ip_protocol: usize) -> isize {
     info!("opening a {protocol:?} socket");
     // TODO: what happens when we get a type thats not in the enum?
     #[allow(unreachable_patterns)]
@@ -17,37 +29,66 @@ pub fn sys_sock_open(protocol: SocketType) -> isize {
         SocketType::Udp => open_udp(),
         SocketType::Tcp => open_tcp(),
         SocketType::Icmp => open_icmp(),
+        SocketType::Raw => {
+            if !packet::privileged() {
+                return Errno::EACCES.into();
+            }
+            let version = match ip_version {
+                4 => IpVersion::Ipv4,
+                6 => IpVersion::Ipv6,
+                _ => return Errno::EINVAL.into(),
+            };
+            let Ok(ip_protocol) = u8::try_from(ip_protocol) else {
+                return Errno::EINVAL.into();
+            };
+            open_raw(version, IpProtocol::from(ip_protocol))
+        }
         _ => return Errno::ENOTSUP.into(),
     };
-    // handle.0 is private, sadly, so just hope this works
-    unsafe { core::mem::transmute::<SocketHandle, usize>(handle) }.try_into().unwrap()
+    match descriptors::insert(handle, protocol, SocketState::Opened) {
+        Ok(descriptor) => descriptor.try_into().unwrap(),
+        Err(error) => {
+            close_socket(handle);
+            Errno::from(error).into()
+        }
+    }
 }
 
+/// Bind a socket to an address and port (or an ident for ICMP).
+///
+/// For TCP, this starts listening and `backlog` is the number of clients, that can connect
+/// before they are accepted (0 means the default). It is ignored for the other protocols.
 pub unsafe fn sys_sock_bind(
-    handle: SocketHandle, protocol: SocketType, addr_ptr: *const u8, port: u16,
+    descriptor: usize, protocol: SocketType, addr_ptr: *const u8, port: u16, backlog: usize,
 ) -> isize {
-    // TODO: somehow check that the protocol is correct for handle?
+    let handle = match lookup(descriptor, Some(protocol)) {
+        Ok(found) => found.handle,
+        Err(errno) => return errno.into(),
+    };
     if let Ok(addr_str) = unsafe { ptr_to_string(addr_ptr) } && let Ok(addr) = IpAddress::from_str(&addr_str) {
         info!("binding {handle:?} to {addr:?}:{port}");
         #[allow(unreachable_patterns)]
         match protocol {
             SocketType::Udp => match bind_udp(handle, addr, port) {
-                Ok(()) => 0,
-                // socket has already been opened
-                Err(udp::BindError::InvalidState) => Errno::EEXIST.into(),
-                // port is zero
-                Err(udp::BindError::Unaddressable) => Errno::EINVAL.into(),
+                Ok(()) => {
+                    descriptors::set_state(descriptor, SocketState::Bound);
+                    0
+                },
+                Err(error) => Errno::from(error).into(),
             },
-            SocketType::Tcp => match bind_tcp(handle, addr, port) {
-                Ok(()) => 0,
-                // socket has already been opened
-                Err(tcp::ListenError::InvalidState) => Errno::EEXIST.into(),
-                // port is zero
-                Err(tcp::ListenError::Unaddressable) => Errno::EINVAL.into(),
+            SocketType::Tcp => match bind_tcp(handle, addr, port, backlog) {
+                Ok(()) => {
+                    descriptors::set_state(descriptor, SocketState::Listening);
+                    0
+                },
+                Err(error) => Errno::from(error).into(),
             },
             // port is actually the ident here
             SocketType::Icmp => match bind_icmp(handle, port) {
-                Ok(()) => 0,
+                Ok(()) => {
+                    descriptors::set_state(descriptor, SocketState::Bound);
+                    0
+                },
                 // socket has already been opened
                 Err(icmp::BindError::InvalidState) => Errno::EEXIST.into(),
                 // ident is missing
@@ -60,15 +101,34 @@ pub unsafe fn sys_sock_bind(
     }
 }
 
+/// Accept a connection on a listening TCP socket.
+///
+/// Returns the descriptor of the connection together with the remote port.
 pub unsafe fn sys_sock_accept(
-    handle: SocketHandle,
+    descriptor: usize,
     protocol: SocketType,
     addr_buf: *mut u8,
 ) -> isize {
+    let found = match lookup(descriptor, Some(protocol)) {
+        Ok(found) => found,
+        Err(errno) => return errno.into(),
+    };
+    let handle = found.handle;
     if matches!(protocol, SocketType::Tcp) {
+        // the socket has to be bound first
+        if found.state != SocketState::Listening {
+            return Errno::EINVAL.into();
+        }
         info!("accepting connections on {handle:?}");
-        match accept_tcp(handle) {
-            Ok(endpoint) => {
+        match block_on(handle, Interest::Connection, || accept_tcp(handle)) {
+            Ok(Ok((accepted, endpoint))) => {
+                let accepted = match descriptors::insert(accepted, SocketType::Tcp, SocketState::Connected) {
+                    Ok(accepted) => accepted,
+                    Err(error) => {
+                        close_socket(accepted);
+                        return Errno::from(error).into();
+                    }
+                };
                 let addr_str = CString::new(
                     endpoint.addr.to_string().as_bytes()
                 ).unwrap();
@@ -76,9 +136,14 @@ pub unsafe fn sys_sock_accept(
                 unsafe { addr_buf.copy_from_nonoverlapping(
                     addr_bytes.as_ptr(), addr_bytes.len(),
                 ) };
-                endpoint.port.try_into().unwrap()
+                // return the descriptor of the connection together with the remote port
+                let mut val = isize::try_from(accepted << 16).unwrap();
+                val |= isize::try_from(endpoint.port).unwrap();
+                val
             },
-            Err(e) => panic!("failed to accept: {e:?}"),
+            // the client has reset the connection
+            Ok(Err(_)) => Errno::ECONNRESET.into(),
+            Err(errno) => errno.into(),
         }
     } else {
         Errno::ENOTSUP.into()
@@ -86,17 +151,29 @@ pub unsafe fn sys_sock_accept(
 }
 
 pub unsafe fn sys_sock_connect(
-    handle: SocketHandle,
+    descriptor: usize,
     protocol: SocketType,
     remote_addr_ptr: *const u8,
     port: u16,
     local_addr_ptr: *mut u8,
 ) -> isize {
+    let handle = match lookup(descriptor, Some(protocol)) {
+        Ok(found) => found.handle,
+        Err(errno) => return errno.into(),
+    };
     if matches!(protocol, SocketType::Tcp) {
         if let Ok(addr_str) = unsafe { ptr_to_string(remote_addr_ptr) } && let Ok(addr) = IpAddress::from_str(&addr_str) {
             info!("connecting to {addr:?}:{port}");
             match connect_tcp(handle, addr, port) {
                 Ok(endpoint) => {
+                    // wait for the handshake (nonblocking sockets return right away)
+                    match block_on(handle, Interest::Connection, || tcp_connected(handle)) {
+                        Ok(true) | Err(Errno::EAGAIN) => {},
+                        // the remote host refused the connection
+                        Ok(false) => return Errno::ECONNRESET.into(),
+                        Err(errno) => return errno.into(),
+                    }
+                    descriptors::set_state(descriptor, SocketState::Connected);
                     let addr_str = CString::new(
                         endpoint.addr.to_string().as_bytes()
                     ).unwrap();
@@ -106,7 +183,9 @@ pub unsafe fn sys_sock_connect(
                     ) };
                     endpoint.port.try_into().unwrap()
                 },
-                Err(e) => panic!("failed to accept: {e:?}"),
+                // the socket is connected already
+                Err(BindError::InvalidState) => Errno::EISCONN.into(),
+                Err(error) => Errno::from(error).into(),
             }
         } else {
             Errno::EINVAL.into()
@@ -117,66 +196,105 @@ pub unsafe fn sys_sock_connect(
 }
 
 pub unsafe fn sys_sock_send(
-    handle: SocketHandle,
+    descriptor: usize,
     protocol: SocketType,
     data: *const u8,
     len: usize,
     addr_ptr: *const u8,
     port: u16,
 ) -> isize {
+    let handle = match lookup(descriptor, Some(protocol)) {
+        Ok(found) => found.handle,
+        Err(errno) => return errno.into(),
+    };
     let data = unsafe { core::slice::from_raw_parts(data, len) };
     debug!("sending {len} bytes on {handle:?}");
     #[allow(unreachable_patterns)]
     match protocol {
         SocketType::Udp => {
             if let Ok(addr_str) = unsafe { ptr_to_string(addr_ptr) } && let Ok(addr) = IpAddress::from_str(&addr_str) {
-                match send_datagram(handle, addr, port, data) {
-                    Ok(()) => data.len().try_into().unwrap(),
+                // like in Linux, broadcasts have to be allowed first
+                if is_broadcast(&addr) && !socket_options(handle).broadcast {
+                    return Errno::EACCES.into();
+                }
+                let result = block_on(handle, Interest::Send, || match send_datagram(handle, addr, port, data) {
+                    // wait until the network thread has sent some of the queued packets
+                    Err(udp::SendError::BufferFull) => None,
+                    result => Some(result),
+                });
+                match result {
+                    Ok(Ok(())) => data.len().try_into().unwrap(),
                     // host or port are missing or zero
-                    Err(udp::SendError::Unaddressable) => Errno::EINVAL.into(),
-                    // TODO: drop? return 0?
-                    Err(udp::SendError::BufferFull) => Errno::EBUSY.into(),
+                    Ok(Err(udp::SendError::Unaddressable)) => Errno::EINVAL.into(),
+                    Ok(Err(udp::SendError::BufferFull)) => unreachable!(),
+                    Err(errno) => errno.into(),
                 }
             } else {
                 Errno::EINVAL.into()
             }
         },
-        SocketType::Tcp => match send_tcp(handle, data) {
-            Ok(len) => len.try_into().unwrap(),
+        SocketType::Tcp => match block_on(handle, Interest::Send, || match send_tcp(handle, data) {
+            // the send buffer is full
+            Ok(0) if !data.is_empty() => None,
+            result => Some(result),
+        }) {
+            Ok(Ok(len)) => len.try_into().unwrap(),
             // socket can't send (yet)
-            Err(tcp::SendError::InvalidState) => Errno::EINVAL.into(),
+            Ok(Err(tcp::SendError::InvalidState)) => Errno::EINVAL.into(),
+            Err(errno) => errno.into(),
         },
         SocketType::Icmp => {
             if let Ok(addr_str) = unsafe { ptr_to_string(addr_ptr) } && let Ok(addr) = IpAddress::from_str(&addr_str) {
-                match send_icmp(handle, addr, data) {
-                    Ok(()) => 0,
+                let result = block_on(handle, Interest::Send, || match send_icmp(handle, addr, data) {
+                    Err(icmp::SendError::BufferFull) => None,
+                    result => Some(result),
+                });
+                match result {
+                    Ok(Ok(())) => 0,
                     // ip address missing
-                    Err(icmp::SendError::Unaddressable) => Errno::EINVAL.into(),
-                    // TODO: drop? return 0?
-                    Err(icmp::SendError::BufferFull) => Errno::EBUSY.into(),
+                    Ok(Err(icmp::SendError::Unaddressable)) => Errno::EINVAL.into(),
+                    Ok(Err(icmp::SendError::BufferFull)) => unreachable!(),
+                    Err(errno) => errno.into(),
                 }
             } else {
                 Errno::EINVAL.into()
             }
         }
+        // the packet already contains the destination
+        SocketType::Raw => match block_on(handle, Interest::Send, || match send_raw(handle, data) {
+            Err(raw::SendError::BufferFull) => None,
+            result => Some(result),
+        }) {
+            Ok(Ok(())) => data.len().try_into().unwrap(),
+            Ok(Err(raw::SendError::BufferFull)) => unreachable!(),
+            Err(errno) => errno.into(),
+        },
         _ => Errno::ENOTSUP.into(),
     }
 }
 
 pub unsafe fn sys_sock_receive(
-    handle: SocketHandle,
+    descriptor: usize,
     protocol: SocketType,
     data_ptr: *mut u8,
     data_len: usize,
     addr_buf: *mut u8,
 ) -> isize {
+    let handle = match lookup(descriptor, Some(protocol)) {
+        Ok(found) => found.handle,
+        Err(errno) => return errno.into(),
+    };
     let data = unsafe { core::slice::from_raw_parts_mut(data_ptr, data_len) };
     debug!("receiving up to {data_len} bytes on {handle:?}");
     #[allow(unreachable_patterns)]
     match protocol {
-        SocketType::Udp => match receive_datagram(handle, data) {
+        SocketType::Udp => match block_on(handle, Interest::Receive, || match receive_datagram(handle, data) {
+            // no datagram yet
+            Err(udp::RecvError::Exhausted) => None,
+            result => Some(result),
+        }) {
             // TODO: also pass the metadata
-            Ok((len, metadata)) => {
+            Ok(Ok((len, metadata))) => {
                 let addr_str = CString::new(
                     metadata.endpoint.addr.to_string().as_bytes()
                 ).unwrap();
@@ -189,24 +307,30 @@ pub unsafe fn sys_sock_receive(
                 val
             },
             // discard truncated packet
-            Err(udp::RecvError::Truncated) => {
+            Ok(Err(udp::RecvError::Truncated)) => {
                 warn!("discarding truncated incoming packet");
                 0
             },
-            // if we got no data, that is okay
-            Err(udp::RecvError::Exhausted) => 0,
+            Ok(Err(udp::RecvError::Exhausted)) => unreachable!(),
+            Err(errno) => errno.into(),
         },
-        SocketType::Tcp => match receive_tcp(handle, data) {
-            Ok(len) => len.try_into().unwrap(),
-            Err(tcp::RecvError::InvalidState) => {
-                warn!("TCP socket is in an invalid state");
-                Errno::EINVALH.into()
-            },
-            // the remote host closed the connection
-            Err(tcp::RecvError::Finished) => Errno::ECONNRESET.into(),
+        SocketType::Tcp => match block_on(handle, Interest::Receive, || match receive_tcp(handle, data) {
+            // no data yet
+            Ok(0) if !data.is_empty() => None,
+            result => Some(result),
+        }) {
+            Ok(Ok(len)) => len.try_into().unwrap(),
+            // the connection has been reset (or has never been established)
+            Ok(Err(tcp::RecvError::InvalidState)) => Errno::ECONNRESET.into(),
+            // the remote host closed the connection, this is the end of the stream
+            Ok(Err(tcp::RecvError::Finished)) => 0,
+            Err(errno) => errno.into(),
         },
-        SocketType::Icmp => match receive_icmp(handle, data) {
-            Ok((len, address)) => {
+        SocketType::Icmp => match block_on(handle, Interest::Receive, || match receive_icmp(handle, data) {
+            Err(icmp::RecvError::Exhausted) => None,
+            result => Some(result),
+        }) {
+            Ok(Ok((len, address))) => {
                 let addr_str = CString::new(
                     address.to_string().as_bytes()
                 ).unwrap();
@@ -217,44 +341,770 @@ pub unsafe fn sys_sock_receive(
                 len.try_into().unwrap()
             },
             // discard truncated packet
-            Err(icmp::RecvError::Truncated) => {
+            Ok(Err(icmp::RecvError::Truncated)) => {
+                warn!("discarding truncated incoming packet");
+                0
+            },
+            Ok(Err(icmp::RecvError::Exhausted)) => unreachable!(),
+            Err(errno) => errno.into(),
+        },
+        // the packet starts with the IP header, the source address is copied out of it
+        SocketType::Raw => match block_on(handle, Interest::Receive, || match receive_raw(handle, data) {
+            Err(raw::RecvError::Exhausted) => None,
+            result => Some(result),
+        }) {
+            Ok(Ok(len)) => {
+                let source = match data[..len].first().map(|byte| byte >> 4) {
+                    Some(4) => Ipv4Packet::new_checked(&data[..len]).map(|packet| IpAddress::Ipv4(packet.src_addr())),
+                    _ => Ipv6Packet::new_checked(&data[..len]).map(|packet| IpAddress::Ipv6(packet.src_addr())),
+                };
+                if let Ok(source) = source {
+                    let addr_str = CString::new(source.to_string().as_bytes()).unwrap();
+                    let addr_bytes = addr_str.as_bytes_with_nul();
+                    unsafe { addr_buf.copy_from_nonoverlapping(addr_bytes.as_ptr(), addr_bytes.len()) };
+                }
+                len.try_into().unwrap()
+            },
+            // discard truncated packet
+            Ok(Err(raw::RecvError::Truncated)) => {
                 warn!("discarding truncated incoming packet");
                 0
             },
-            // if we got no data, that is okay
-            Err(icmp::RecvError::Exhausted) => 0,
+            Ok(Err(raw::RecvError::Exhausted)) => unreachable!(),
+            Err(errno) => errno.into(),
         },
         _ => Errno::ENOTSUP.into(),
     }
 }
 
-pub fn sys_sock_close(handle: SocketHandle) -> isize {
-    info!("closing {handle} socket");
-    close_socket(handle);
+pub fn sys_sock_close(descriptor: usize) -> isize {
+    match descriptors::remove(descriptor) {
+        Ok(removed) => {
+            info!("closing {} socket", removed.handle);
+            close_socket(removed.handle);
+            0
+        },
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Shut down one or both directions of a TCP connection, see `Shutdown` for the values of `how`.
+pub fn sys_sock_shutdown(descriptor: usize, how: usize) -> isize {
+    let handle = match lookup(descriptor, None) {
+        Ok(found) if found.socket_type == SocketType::Tcp => found.handle,
+        Ok(_) => return Errno::ENOTSUP.into(),
+        Err(errno) => return errno.into(),
+    };
+    let Ok(how) = Shutdown::try_from_primitive(how) else {
+        return Errno::EINVAL.into();
+    };
+    info!("shutting down {handle}: {how:?}");
+    shutdown_tcp(handle, how);
     0
 }
 
+/// Change an option of a socket, see `SocketOption` for the meaning of `value`.
+pub fn sys_sock_set_opt(descriptor: usize, option: usize, value: usize) -> isize {
+    let handle = match lookup(descriptor, None) {
+        Ok(found) => found.handle,
+        Err(errno) => return errno.into(),
+    };
+    let Ok(option) = SocketOption::try_from_primitive(option) else {
+        return Errno::ENOTSUP.into();
+    };
+    info!("setting {option:?} of {handle} to {value}");
+    match set_socket_option(handle, option, value) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Let a UDP socket join the multicast group `group_ptr`.
+///
+/// `interface_ptr` is the driver name or an address of the interface. If it is null,
+/// the group is joined on the interface, through which it is routed.
+pub unsafe fn sys_sock_join_multicast(descriptor: usize, protocol: SocketType, group_ptr: *const u8, interface_ptr: *const u8) -> isize {
+    let handle = match lookup(descriptor, Some(protocol)) {
+        Ok(found) => found.handle,
+        Err(errno) => return errno.into(),
+    };
+    if !matches!(protocol, SocketType::Udp) {
+        return Errno::ENOTSUP.into();
+    }
+    let (Ok(group_str), Ok(interface)) = (unsafe { ptr_to_string(group_ptr) }, unsafe { optional_string(interface_ptr) }) else {
+        return Errno::EINVAL.into();
+    };
+    let Ok(group) = IpAddress::from_str(&group_str) else {
+        return Errno::EINVAL.into();
+    };
+    if !group.is_multicast() {
+        return Errno::EINVAL.into();
+    }
+    info!("{handle} joins multicast group {group}");
+    match join_multicast_group(handle, group, interface.as_deref()) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Let a UDP socket leave a multicast group, that it has joined with `sys_sock_join_multicast`.
+pub unsafe fn sys_sock_leave_multicast(descriptor: usize, protocol: SocketType, group_ptr: *const u8, interface_ptr: *const u8) -> isize {
+    let handle = match lookup(descriptor, Some(protocol)) {
+        Ok(found) => found.handle,
+        Err(errno) => return errno.into(),
+    };
+    if !matches!(protocol, SocketType::Udp) {
+        return Errno::ENOTSUP.into();
+    }
+    let (Ok(group_str), Ok(interface)) = (unsafe { ptr_to_string(group_ptr) }, unsafe { optional_string(interface_ptr) }) else {
+        return Errno::EINVAL.into();
+    };
+    let Ok(group) = IpAddress::from_str(&group_str) else {
+        return Errno::EINVAL.into();
+    };
+    info!("{handle} leaves multicast group {group}");
+    match leave_multicast_group(handle, group, interface.as_deref()) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Read an option of a socket, see `SocketOption` for the meaning of the returned value.
+pub fn sys_sock_get_opt(descriptor: usize, option: usize) -> isize {
+    let handle = match lookup(descriptor, None) {
+        Ok(found) => found.handle,
+        Err(errno) => return errno.into(),
+    };
+    let Ok(option) = SocketOption::try_from_primitive(option) else {
+        return Errno::ENOTSUP.into();
+    };
+    match get_socket_option(handle, option) {
+        // `set_socket_option` doesn't accept larger values
+        Ok(value) => isize::try_from(value).unwrap_or(Errno::EINVAL.into()),
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Wait until at least one of the `count` entries is ready for its interest.
+///
+/// Entries are either sockets or handles of the naming service. For every entry,
+/// the flags that are ready are written back. A `timeout` of 0 only checks the entries,
+/// `usize::MAX` waits forever. Returns the number of ready entries (0 on timeout).
+pub unsafe fn sys_poll(entries: *mut PollEntry, count: usize, timeout: usize) -> isize {
+    if entries.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+    let entries = if count == 0 { &mut [] } else { unsafe { core::slice::from_raw_parts_mut(entries, count) } };
+    let deadline = if timeout == usize::MAX { None } else { Some(timer().systime_ms().saturating_add(timeout)) };
+    loop {
+        let mut ready_count = 0;
+        let mut waits = Vec::new();
+        for entry in entries.iter_mut() {
+            let ready = match entry.kind() {
+                PollKind::Object => match api::poll(entry.handle) {
+                    Ok(flags) => flags,
+                    Err(errno) => return errno.into(),
+                },
+                PollKind::Socket => {
+                    let handle = match lookup(entry.handle, None) {
+                        Ok(found) => found.handle,
+                        Err(errno) => return errno.into(),
+                    };
+                    let mut ready = PollFlags::empty();
+                    for (flag, interest) in [(PollFlags::READABLE, Interest::Receive), (PollFlags::WRITABLE, Interest::Send)] {
+                        if !entry.interest().contains(flag) {
+                            continue;
+                        }
+                        match socket_ready(handle, interest) {
+                            Some(true) => ready |= flag,
+                            Some(false) => waits.extend(wait_targets(handle, interest)),
+                            None => return Errno::EINVALH.into(),
+                        }
+                    }
+                    ready
+                },
+            } & entry.interest();
+            entry.ready = ready.bits();
+            if !ready.is_empty() {
+                ready_count += 1;
+            }
+        }
+        if ready_count > 0 || timeout == 0 {
+            return ready_count;
+        }
+        if !wait_for_any(&waits, deadline) {
+            return 0;
+        }
+    }
+}
+
 /// Return a \0 seperated list of IP addresses for a given hostname.
 /// 
 /// If the hostname is missing, the addresses of the current host will be returned.
+/// Otherwise, the hostname is resolved with the default timeout and retries (see `sys_resolve`).
 pub unsafe fn sys_get_ip_adresses(ptr: *mut u8, len: usize, host_ptr: *const u8) -> isize {
-    let host = if host_ptr.is_null() {
-        None
+    let addresses = if host_ptr.is_null() {
+        get_ip_addresses()
     } else {
-        match unsafe { ptr_to_string(host_ptr) } {
-            Ok(host) => Some(host),
+        let host = match unsafe { ptr_to_string(host_ptr) } {
+            Ok(host) => host,
             Err(errno) => return errno.into(),
+        };
+        info!("resolving host {host}");
+        match resolver::resolve(&host, AddressFamily::Any, resolver::DEFAULT_TIMEOUT_MS, resolver::DEFAULT_RETRIES) {
+            Ok(addresses) => addresses,
+            Err(error) => return Errno::from(error).into(),
         }
     };
-    info!("resolving host {host:?}");
+    match unsafe { write_addresses(ptr, len, &addresses) } {
+        Ok(()) => 0,
+        Err(errno) => errno.into(),
+    }
+}
+
+/// Resolve a hostname and write its addresses as a \0 seperated list to `buf`.
+///
+/// `family` selects the record types (see `AddressFamily`). A `timeout_ms` of 0 and `retries` of `usize::MAX`
+/// select the defaults. Returns the number of addresses or ENOENT, if the name doesn't exist,
+/// and ETIMEDOUT, if no DNS server has answered.
+pub unsafe fn sys_resolve(host_ptr: *const u8, family: usize, timeout_ms: usize, retries: usize, buf: *mut u8, len: usize) -> isize {
+    let host = match unsafe { ptr_to_string(host_ptr) } {
+        Ok(host) => host,
+        Err(errno) => return errno.into(),
+    };
+    let Ok(family) = AddressFamily::try_from(family) else {
+        return Errno::EINVAL.into();
+    };
+    let timeout_ms = if timeout_ms == 0 { resolver::DEFAULT_TIMEOUT_MS } else { timeout_ms };
+    let retries = if retries == usize::MAX { resolver::DEFAULT_RETRIES } else { retries };
+    info!("resolving host {host} ({family:?})");
+    let addresses = match resolver::resolve(&host, family, timeout_ms, retries) {
+        Ok(addresses) => addresses,
+        Err(error) => return Errno::from(error).into(),
+    };
+    match unsafe { write_addresses(buf, len, &addresses) } {
+        Ok(()) => addresses.len() as isize,
+        Err(errno) => errno.into(),
+    }
+}
+
+impl From<DescriptorError> for Errno {
+    fn from(error: DescriptorError) -> Self {
+        match error {
+            DescriptorError::BadDescriptor => Errno::EBADF,
+            // e.g. a UDP socket has been passed to a TCP system call
+            DescriptorError::WrongType => Errno::ENOTSOCK,
+            DescriptorError::TableFull => Errno::ENOHANDLES,
+        }
+    }
+}
+
+impl From<BindError> for Errno {
+    fn from(error: BindError) -> Self {
+        match error {
+            // socket has already been opened
+            BindError::InvalidState => Errno::EEXIST,
+            BindError::Unaddressable => Errno::EINVAL,
+            BindError::AddressInUse => Errno::EADDRINUSE,
+        }
+    }
+}
+
+impl From<ResolveError> for Errno {
+    fn from(error: ResolveError) -> Self {
+        match error {
+            ResolveError::InvalidName => Errno::EINVAL,
+            ResolveError::NotFound => Errno::ENOENT,
+            ResolveError::TimedOut => Errno::ETIMEDOUT,
+            ResolveError::NoServers => Errno::ENETUNREACH,
+            ResolveError::NoPort => Errno::EADDRINUSE,
+        }
+    }
+}
+
+/// Write `addresses` as a \0 seperated list to `ptr`. Fails with ENOSPC, if the buffer is too small.
+unsafe fn write_addresses(ptr: *mut u8, len: usize, addresses: &[IpAddress]) -> Result<(), Errno> {
+    if ptr.is_null() {
+        return Err(Errno::EINVAL);
+    }
     let target = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
     let mut idx = 0;
-    for ip in get_ip_addresses(host.as_deref()) {
-        info!("{host:?} has address {ip:?}");
+    for ip in addresses {
+        debug!("writing address {ip}");
         let text = ip.to_string();
+        if idx + text.len() >= len {
+            return Err(Errno::ENOSPC);
+        }
         target[idx..idx+text.len()].copy_from_slice(text.as_bytes());
         target[idx+text.len()] = 0;
         idx += text.len() + 1;
     }
-    0
+    // an empty entry terminates the list
+    if idx < len {
+        target[idx] = 0;
+    }
+    Ok(())
+}
+
+/// Name and traffic counters of an interface, as they are handed to user space.
+/// The layout must match `InterfaceStats` in library/network.
+#[repr(C)]
+pub struct InterfaceStats {
+    /// driver name, padded with 0 bytes
+    name: [u8; 16],
+    stats: NetworkStats,
+}
+
+/// Write the traffic counters of up to `count` interfaces to `buf`.
+///
+/// Returns the number of interfaces, that have been written.
+pub unsafe fn sys_get_network_stats(buf: *mut InterfaceStats, count: usize) -> isize {
+    if buf.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+    let mut written = 0;
+    for (name, stats) in all_interface_stats().into_iter().take(count) {
+        let entry = InterfaceStats { name: padded_name(name), stats };
+        unsafe { buf.add(written).write(entry) };
+        written += 1;
+    }
+    written as isize
+}
+
+/// Settings of a packet capture, as they are passed from user space.
+/// The layout must match `CaptureOptions` in library/network.
+#[repr(C)]
+pub struct CaptureOptions {
+    /// only capture the interfaces, whose driver name contains this (padded with 0 bytes, empty means all)
+    interface: [u8; 16],
+    /// EtherType to capture (0 means any)
+    ether_type: u16,
+    /// TCP or UDP port to capture (0 means any)
+    port: u16,
+    /// IP protocol to capture (0 means any)
+    ip_protocol: u8,
+    /// put the cards into promiscuous mode (0 or 1)
+    promiscuous: u8,
+}
+
+/// Start capturing frames (see `network::capture`). Like packet sockets, this is only for privileged applications.
+///
+/// Returns 1, if the cards are in promiscuous mode (or it hasn't been requested), 0 otherwise.
+pub unsafe fn sys_capture_start(options: *const CaptureOptions) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    let Some(options) = (unsafe { options.as_ref() }) else {
+        return Errno::EINVAL.into();
+    };
+    let len = options.interface.iter().position(|&byte| byte == 0).unwrap_or(options.interface.len());
+    let Ok(interface) = str::from_utf8(&options.interface[..len]) else {
+        return Errno::EINVAL.into();
+    };
+    let filter = CaptureFilter {
+        interface: (!interface.is_empty()).then(|| String::from(interface)),
+        ether_type: (options.ether_type != 0).then_some(options.ether_type),
+        ip_protocol: (options.ip_protocol != 0).then_some(options.ip_protocol),
+        port: (options.port != 0).then_some(options.port),
+    };
+    info!("starting packet capture: {filter:?}");
+    capture::start(filter, options.promiscuous != 0) as isize
+}
+
+/// Stop capturing frames. Returns the number of frames, that have been dropped, because nobody has read them in time.
+pub fn sys_capture_stop() -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    capture::stop() as isize
+}
+
+/// Read captured frames as libpcap records into `buf`.
+///
+/// `buf` must have room for at least one complete record.
+/// Returns the number of bytes written, which is 0, if no frames have been captured since the last call.
+pub unsafe fn sys_capture_read(buf: *mut u8, len: usize) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    if buf.is_null() || len < capture::RECORD_HEADER_SIZE + capture::SNAPSHOT_LENGTH {
+        return Errno::EINVAL.into();
+    }
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+    capture::read(buf) as isize
+}
+
+impl From<SocketOptionError> for Errno {
+    fn from(error: SocketOptionError) -> Self {
+        match error {
+            SocketOptionError::Unsupported => Errno::ENOTSUP,
+            SocketOptionError::InvalidValue => Errno::EINVAL,
+            SocketOptionError::Connected => Errno::EISCONN,
+            SocketOptionError::Busy => Errno::EBUSY,
+        }
+    }
+}
+
+impl From<ConfigError> for Errno {
+    fn from(error: ConfigError) -> Self {
+        match error {
+            ConfigError::NoInterface | ConfigError::NotFound => Errno::ENOENT,
+            ConfigError::Exists => Errno::EEXIST,
+            ConfigError::Full => Errno::ENOSPC,
+        }
+    }
+}
+
+/// Read a string argument, that may be a null pointer.
+unsafe fn optional_string(ptr: *const u8) -> Result<Option<String>, Errno> {
+    if ptr.is_null() {
+        Ok(None)
+    } else {
+        unsafe { ptr_to_string(ptr) }.map(Some)
+    }
+}
+
+/// Add an address (e.g. "10.0.2.15/24") to the interface, whose driver name contains `interface_ptr`.
+/// Like all changes of the addresses, routes and neighbors, this is only for privileged applications.
+pub unsafe fn sys_add_ip_address(interface_ptr: *const u8, cidr_ptr: *const u8) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    let (Ok(interface), Ok(cidr_str)) = (unsafe { ptr_to_string(interface_ptr) }, unsafe { ptr_to_string(cidr_ptr) }) else {
+        return Errno::EINVAL.into();
+    };
+    let Ok(cidr) = IpCidr::from_str(&cidr_str) else {
+        return Errno::EINVAL.into();
+    };
+    info!("adding {cidr} to {interface}");
+    match add_ip_address(&interface, cidr) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Remove an address (without prefix length) from the interface, whose driver name contains `interface_ptr`.
+pub unsafe fn sys_remove_ip_address(interface_ptr: *const u8, addr_ptr: *const u8) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    let (Ok(interface), Ok(addr_str)) = (unsafe { ptr_to_string(interface_ptr) }, unsafe { ptr_to_string(addr_ptr) }) else {
+        return Errno::EINVAL.into();
+    };
+    let Ok(addr) = IpAddress::from_str(&addr_str) else {
+        return Errno::EINVAL.into();
+    };
+    info!("removing {addr} from {interface}");
+    match remove_ip_address(&interface, addr) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Route the packets for `cidr_ptr` (e.g. "0.0.0.0/0" for the default route) through `gateway_ptr`.
+///
+/// `interface_ptr` may be null, then the interface is chosen by the gateway.
+pub unsafe fn sys_add_route(interface_ptr: *const u8, cidr_ptr: *const u8, gateway_ptr: *const u8) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    let (Ok(interface), Ok(cidr_str), Ok(gateway_str)) =
+        (unsafe { optional_string(interface_ptr) }, unsafe { ptr_to_string(cidr_ptr) }, unsafe { ptr_to_string(gateway_ptr) })
+    else {
+        return Errno::EINVAL.into();
+    };
+    let (Ok(cidr), Ok(gateway)) = (IpCidr::from_str(&cidr_str), IpAddress::from_str(&gateway_str)) else {
+        return Errno::EINVAL.into();
+    };
+    info!("adding route to {cidr} via {gateway}");
+    match add_route(interface.as_deref(), cidr, gateway) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Remove the route for `cidr_ptr`. If `interface_ptr` is null, it is removed from all interfaces.
+pub unsafe fn sys_remove_route(interface_ptr: *const u8, cidr_ptr: *const u8) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    let (Ok(interface), Ok(cidr_str)) = (unsafe { optional_string(interface_ptr) }, unsafe { ptr_to_string(cidr_ptr) }) else {
+        return Errno::EINVAL.into();
+    };
+    let Ok(cidr) = IpCidr::from_str(&cidr_str) else {
+        return Errno::EINVAL.into();
+    };
+    info!("removing route to {cidr}");
+    match remove_route(interface.as_deref(), cidr) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Pad a driver name with 0 bytes (longer names are cut off).
+fn padded_name(name: &str) -> [u8; 16] {
+    let mut padded = [0; 16];
+    let len = name.len().min(padded.len());
+    padded[..len].copy_from_slice(&name.as_bytes()[..len]);
+    padded
+}
+
+/// Get the bytes of an address (IPv4 addresses use the first 4 bytes) and whether it is an IPv6 address.
+fn address_bytes(address: IpAddress) -> ([u8; 16], bool) {
+    let mut bytes = [0; 16];
+    match address {
+        IpAddress::Ipv4(address) => {
+            bytes[..4].copy_from_slice(&address.octets());
+            (bytes, false)
+        }
+        IpAddress::Ipv6(address) => (address.octets(), true),
+    }
+}
+
+/// Time from now until `time` in ms (0, if it has passed already, and `u64::MAX` for `None`).
+fn millis_until(time: Option<Instant>) -> u64 {
+    let now = Instant::from_millis(timer().systime_ms() as i64);
+    match time {
+        Some(time) if time > now => (time - now).total_millis(),
+        Some(_) => 0,
+        None => u64::MAX,
+    }
+}
+
+/// MAC address, MTU and link state of an interface, as they are handed to user space.
+/// The layout must match `InterfaceLink` in library/network.
+#[repr(C)]
+pub struct InterfaceLink {
+    /// driver name, padded with 0 bytes
+    name: [u8; 16],
+    mac: [u8; 6],
+    /// interfaces without MAC address (loopback) have no link layer
+    has_mac: bool,
+    up: bool,
+    mtu: u32,
+}
+
+/// Write the link layer properties of up to `count` interfaces to `buf`.
+///
+/// Returns the number of interfaces, that have been written.
+pub unsafe fn sys_get_interface_links(buf: *mut InterfaceLink, count: usize) -> isize {
+    if buf.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+    let mut written = 0;
+    for (name, link) in all_interface_links().into_iter().take(count) {
+        let entry = InterfaceLink {
+            name: padded_name(name),
+            mac: link.mac.map_or([0; 6], |mac| mac.0),
+            has_mac: link.mac.is_some(),
+            up: link.up,
+            mtu: link.mtu as u32,
+        };
+        unsafe { buf.add(written).write(entry) };
+        written += 1;
+    }
+    written as isize
+}
+
+/// A neighbor cache entry, as it is handed to user space.
+/// The layout must match `NeighborEntry` in library/network.
+#[repr(C)]
+pub struct NeighborEntry {
+    /// driver name, padded with 0 bytes
+    interface: [u8; 16],
+    address: [u8; 16],
+    ipv6: bool,
+    /// see `NeighborState`
+    state: u8,
+    mac: [u8; 6],
+    /// time until the entry expires in ms (`u64::MAX` for permanent entries)
+    expires_in: u64,
+}
+
+/// Write up to `count` neighbor cache entries (of all interfaces) to `buf`.
+///
+/// Returns the number of entries, that have been written.
+pub unsafe fn sys_get_neighbors(buf: *mut NeighborEntry, count: usize) -> isize {
+    if buf.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+    let mut written = 0;
+    for (name, neighbor) in all_neighbors().into_iter().take(count) {
+        let (address, ipv6) = address_bytes(neighbor.address);
+        let entry = NeighborEntry {
+            interface: padded_name(name),
+            address,
+            ipv6,
+            state: neighbor.state.into(),
+            mac: neighbor.mac.0,
+            expires_in: millis_until(neighbor.expires_at),
+        };
+        unsafe { buf.add(written).write(entry) };
+        written += 1;
+    }
+    written as isize
+}
+
+/// Add a permanent neighbor cache entry for `addr_ptr` (e.g. "10.0.2.2") with the MAC address `mac_ptr`
+/// (e.g. "52:55:0a:00:02:02") to the interface, whose driver name contains `interface_ptr`.
+/// Like `sys_add_ip_address`, this is only for privileged applications.
+pub unsafe fn sys_add_neighbor(interface_ptr: *const u8, addr_ptr: *const u8, mac_ptr: *const u8) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    let (Ok(interface), Ok(addr_str), Ok(mac_str)) =
+        (unsafe { ptr_to_string(interface_ptr) }, unsafe { ptr_to_string(addr_ptr) }, unsafe { ptr_to_string(mac_ptr) })
+    else {
+        return Errno::EINVAL.into();
+    };
+    let (Ok(addr), Ok(mac)) = (IpAddress::from_str(&addr_str), EthernetAddress::from_str(&mac_str)) else {
+        return Errno::EINVAL.into();
+    };
+    if !addr.is_unicast() || !mac.is_unicast() {
+        return Errno::EINVAL.into();
+    }
+    info!("adding neighbor {addr} ({mac}) to {interface}");
+    match add_neighbor(&interface, addr, mac) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Remove the (permanent or learned) neighbor cache entry for `addr_ptr` from the interface, whose driver name contains `interface_ptr`.
+pub unsafe fn sys_remove_neighbor(interface_ptr: *const u8, addr_ptr: *const u8) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    let (Ok(interface), Ok(addr_str)) = (unsafe { ptr_to_string(interface_ptr) }, unsafe { ptr_to_string(addr_ptr) }) else {
+        return Errno::EINVAL.into();
+    };
+    let Ok(addr) = IpAddress::from_str(&addr_str) else {
+        return Errno::EINVAL.into();
+    };
+    info!("removing neighbor {addr} from {interface}");
+    match remove_neighbor(&interface, addr) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Forget the learned neighbor cache entries (the permanent ones are kept).
+/// If `interface_ptr` is null, those of all interfaces are forgotten.
+pub unsafe fn sys_flush_neighbors(interface_ptr: *const u8) -> isize {
+    if !packet::privileged() {
+        return Errno::EACCES.into();
+    }
+    let Ok(interface) = (unsafe { optional_string(interface_ptr) }) else {
+        return Errno::EINVAL.into();
+    };
+    info!("flushing neighbor cache");
+    match flush_neighbors(interface.as_deref()) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// A route, as it is handed to user space.
+/// The layout must match `RouteEntry` in library/network.
+#[repr(C)]
+pub struct RouteEntry {
+    /// driver name, padded with 0 bytes
+    interface: [u8; 16],
+    destination: [u8; 16],
+    gateway: [u8; 16],
+    ipv6: bool,
+    prefix_len: u8,
+    /// time until the route expires in ms (`u64::MAX`, if it doesn't expire)
+    expires_in: u64,
+}
+
+/// Write up to `count` routes (of all interfaces) to `buf`.
+///
+/// Returns the number of routes, that have been written.
+pub unsafe fn sys_get_routes(buf: *mut RouteEntry, count: usize) -> isize {
+    if buf.is_null() && count > 0 {
+        return Errno::EINVAL.into();
+    }
+    let mut written = 0;
+    for (name, route) in all_routes().into_iter().take(count) {
+        let (destination, ipv6) = address_bytes(route.cidr.address());
+        let (gateway, _) = address_bytes(route.via_router);
+        let entry = RouteEntry {
+            interface: padded_name(name),
+            destination,
+            gateway,
+            ipv6,
+            prefix_len: route.cidr.prefix_len(),
+            expires_in: millis_until(route.expires_at),
+        };
+        unsafe { buf.add(written).write(entry) };
+        written += 1;
+    }
+    written as isize
+}
+
+impl From<PacketError> for Errno {
+    fn from(error: PacketError) -> Self {
+        match error {
+            PacketError::NotPermitted => Errno::EACCES,
+            PacketError::NoSuchInterface => Errno::ENOENT,
+            PacketError::NoSuchSocket => Errno::EINVALH,
+            PacketError::InvalidFrame => Errno::EINVAL,
+            PacketError::WouldBlock => Errno::EAGAIN,
+            PacketError::TimedOut => Errno::ETIMEDOUT,
+        }
+    }
+}
+
+/// Open a packet socket on the interface, whose driver name contains `interface_ptr` (see `network::packet`).
+///
+/// It receives the frames with `ether_type` (0 means all frames). Returns the id of the socket.
+pub unsafe fn sys_packet_open(interface_ptr: *const u8, ether_type: usize) -> isize {
+    let Ok(interface) = (unsafe { ptr_to_string(interface_ptr) }) else {
+        return Errno::EINVAL.into();
+    };
+    let Ok(ether_type) = u16::try_from(ether_type) else {
+        return Errno::EINVAL.into();
+    };
+    info!("opening a packet socket on {interface} (EtherType {ether_type:#06x})");
+    match packet::open(&interface, (ether_type != 0).then_some(ether_type)) {
+        Ok(id) => id as isize,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Send a whole Ethernet frame on a packet socket. Returns its length.
+pub unsafe fn sys_packet_send(id: usize, data: *const u8, len: usize) -> isize {
+    if data.is_null() {
+        return Errno::EINVAL.into();
+    }
+    let frame = unsafe { core::slice::from_raw_parts(data, len) };
+    debug!("sending a frame of {len} bytes on packet socket {id}");
+    match packet::send(id, frame) {
+        Ok(()) => len as isize,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+/// Receive the next frame of a packet socket into `buf` (longer frames are cut off).
+///
+/// `timeout` is in ms, 0 doesn't wait and `usize::MAX` waits forever. Returns the number of bytes received.
+pub unsafe fn sys_packet_receive(id: usize, buf: *mut u8, len: usize, timeout: usize) -> isize {
+    if buf.is_null() {
+        return Errno::EINVAL.into();
+    }
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+    let timeout = (timeout != usize::MAX).then_some(timeout);
+    match packet::receive(id, buf, timeout) {
+        Ok(len) => len as isize,
+        Err(error) => Errno::from(error).into(),
+    }
+}
+
+pub fn sys_packet_close(id: usize) -> isize {
+    info!("closing packet socket {id}");
+    match packet::close(id) {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).into(),
+    }
 }
@@ -15,7 +15,7 @@ use x86_64::registers::control::{Efer, EferFlags};
 use x86_64::registers::model_specific::{KernelGsBase, LStar, SFMask, Star};
 use x86_64::structures::gdt::SegmentSelector;
 use x86_64::{PrivilegeLevel, VirtAddr};
-use crate::syscall::sys_net::{sys_get_ip_adresses, sys_sock_accept, sys_sock_bind, sys_sock_close, sys_sock_connect, sys_sock_open, sys_sock_receive, sys_sock_send};
+use crate::syscall::sys_net::{sys_get_ip_adresses, sys_sock_accept, sys_sock_bind, sys_sock_close, sys_sock_connect, sys_sock_open, sys_sock_receive, sys_sock_send, sys_poll, sys_sock_get_opt, sys_sock_set_opt, sys_sock_shutdown, sys_get_network_stats, sys_capture_start, sys_capture_stop, sys_capture_read, sys_add_ip_address, sys_remove_ip_address, sys_add_route, sys_remove_route, sys_resolve, sys_get_interface_links, sys_get_neighbors, sys_add_neighbor, sys_remove_neighbor, sys_flush_neighbors, sys_get_routes, sys_packet_open, sys_packet_send, sys_packet_receive, sys_packet_close, sys_sock_join_multicast, sys_sock_leave_multicast};
 use crate::syscall::sys_vmem::{sys_map_frame_buffer, sys_map_memory};
 use crate::syscall::sys_time::{sys_get_date, sys_get_system_time, sys_set_date, };
 use crate::syscall::sys_concurrent::{sys_process_execute_binary, sys_process_exit, sys_process_id, sys_thread_create, sys_thread_exit,
@@ -128,6 +128,31 @@ impl SyscallTable {
                 sys_sock_receive as *const _,
                 sys_sock_close as *const _,
                 sys_get_ip_adresses as *const _,
+                sys_sock_set_opt as *const _,
+                sys_sock_get_opt as *const _,
+                sys_poll as *const _,
+                sys_sock_shutdown as *const _,
+                sys_get_network_stats as *const _,
+                sys_capture_start as *const _,
+                sys_capture_stop as *const _,
+                sys_capture_read as *const _,
+                sys_add_ip_address as *const _,
+                sys_remove_ip_address as *const _,
+                sys_add_route as *const _,
+                sys_remove_route as *const _,
+                sys_resolve as *const _,
+                sys_get_interface_links as *const _,
+                sys_get_neighbors as *const _,
+                sys_add_neighbor as *const _,
+                sys_remove_neighbor as *const _,
+                sys_flush_neighbors as *const _,
+                sys_get_routes as *const _,
+                sys_packet_open as *const _,
+                sys_packet_send as *const _,
+                sys_packet_receive as *const _,
+                sys_packet_close as *const _,
+                sys_sock_join_multicast as *const _,
+                sys_sock_leave_multicast as *const _,
             ],
         }
     }
@@ -19,7 +19,7 @@ use alloc::string::String;
 use alloc::ffi::CString;
 use core::mem;
 
-use shared_types::{DirEntry, FileType, OpenOptions, RawDirent, SeekOrigin};
+use shared_types::{DirEntry, FileType, OpenOptions, PollEntry, RawDirent, SeekOrigin};
 use syscall::{SystemCall, return_vals::Errno, syscall};
 
 
@@ -82,6 +82,17 @@ pub fn readdir(fh: usize) -> Result<Option<DirEntry>, Errno> {
     }
 }
 
+/// Wait until at least one of the `entries` is ready for its interest or `timeout_ms` has passed
+/// (`None` waits forever, `Some(0)` just checks). \
+/// The kernel sets `ready` in every entry. Returns `Ok(number of ready entries)` or `Err`.
+pub fn poll(entries: &mut [PollEntry], timeout_ms: Option<usize>) -> Result<usize, Errno> {
+    syscall(SystemCall::Poll, &[
+        entries.as_mut_ptr() as usize,
+        entries.len(),
+        timeout_ms.unwrap_or(usize::MAX),
+    ])
+}
+
 impl DirEntry {
     pub fn from_dirent(dirent: &RawDirent) -> Option<Self> {
         // Convert d_type to a FileType enum
@@ -20,6 +20,58 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Description: readiness flags for `poll`
+    pub struct PollFlags: usize {
+        const READABLE = 1;
+        const WRITABLE = 2;
+    }
+}
+
+/// Description: kind of handle in a `PollEntry`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, IntoPrimitive, FromPrimitive)]
+#[repr(usize)]
+pub enum PollKind {
+    /// Handle of the naming service (returned by `open`)
+    #[num_enum(default)]
+    Object = 0,
+    /// Handle of a network socket
+    Socket = 1,
+}
+
+/// Description: internally used for `poll` syscall for passing handles and their readiness between kernel and user space
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct PollEntry {
+    pub kind: usize,     // see `PollKind`
+    pub handle: usize,   // naming service handle or socket handle
+    pub interest: usize, // `PollFlags` to wait for
+    pub ready: usize,    // `PollFlags` which are ready (set by the kernel)
+}
+
+impl PollEntry {
+    pub fn new(kind: PollKind, handle: usize, interest: PollFlags) -> Self {
+        PollEntry {
+            kind: kind.into(),
+            handle,
+            interest: interest.bits(),
+            ready: 0,
+        }
+    }
+
+    pub fn kind(&self) -> PollKind {
+        PollKind::from_primitive(self.kind)
+    }
+
+    pub fn interest(&self) -> PollFlags {
+        PollFlags::from_bits_truncate(self.interest)
+    }
+
+    pub fn ready(&self) -> PollFlags {
+        PollFlags::from_bits_truncate(self.ready)
+    }
+}
+
 /// Description: origin for `seek` 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, IntoPrimitive, FromPrimitive)]
 #[repr(usize)]
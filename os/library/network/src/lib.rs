@@ -4,14 +4,18 @@
 extern crate alloc;
 
 use core::{
+    cell::Cell,
     ffi::CStr,
-    net::{IpAddr, Ipv6Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     str::FromStr,
+    time::Duration,
 };
 
-use alloc::{ffi::CString, format, string::ToString, vec, vec::Vec};
+use alloc::{ffi::CString, format, string::{String, ToString}, vec, vec::Vec};
 use syscall::{SystemCall, return_vals::Errno, syscall};
 
+pub use naming::shared_types::{PollEntry, PollFlags, PollKind};
+
 pub struct UdpSocket {
     handle: usize,
     /// the (local) address this socket is bound to
@@ -23,26 +27,25 @@ impl UdpSocket {
         let protocol = 0;
         let handle = syscall(SystemCall::SockOpen, &[protocol]).map_err(|errno| match errno {
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })?;
-        // valid addresses do not contain 0 bytes
-        let addr = CString::new(address.ip().to_string()).unwrap();
-        syscall(
+        let addr = address_string(address.ip());
+        let result = syscall(
             SystemCall::SockBind,
             &[handle, protocol, addr.as_bytes_with_nul().as_ptr() as usize, address.port().into()],
         )
         .map_err(|errno| match errno {
             Errno::EEXIST => panic!("socket has already been openend"),
             Errno::EINVAL => NetworkError::InvalidAddress,
-            errno => NetworkError::Unknown(errno),
-        })?;
+            errno => NetworkError::from(errno),
+        });
+        close_on_error(handle, result)?;
         Ok(Self { handle, address })
     }
 
     pub fn send_to(&self, buf: &[u8], address: SocketAddr) -> Result<usize, NetworkError> {
         let protocol = 0;
-        // valid addresses do not contain 0 bytes
-        let addr = CString::new(address.ip().to_string()).unwrap();
+        let addr = address_string(address.ip());
         syscall(
             SystemCall::SockSend,
             &[
@@ -58,7 +61,7 @@ impl UdpSocket {
             Errno::EINVAL => NetworkError::InvalidAddress,
             Errno::EBUSY => NetworkError::DeviceBusy,
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })
     }
 
@@ -78,7 +81,7 @@ impl UdpSocket {
         )
         .map_err(|errno| match errno {
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })?;
         // This just exists for UDP. TCP and ICMP get the full isize for len.
         let num_bytes = result >> 16;
@@ -91,6 +94,134 @@ impl UdpSocket {
         };
         Ok((num_bytes, remote_addr))
     }
+
+    /// The local address, that this socket is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Let `send_to` and `recv_from` return `NetworkError::WouldBlock`, instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NetworkError> {
+        set_nonblocking(self.handle, nonblocking)
+    }
+
+    /// Let `send_to` and `recv_from` return `NetworkError::TimedOut`, if they have to wait longer than `timeout`.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_timeout(self.handle, timeout)
+    }
+
+    /// Let `recv_from` return `NetworkError::TimedOut`, if it has to wait longer than `timeout`.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::ReadTimeout, duration_to_ms(timeout))
+    }
+
+    pub fn read_timeout(&self) -> Result<Option<Duration>, NetworkError> {
+        get_option(self.handle, SocketOption::ReadTimeout).map(ms_to_duration)
+    }
+
+    /// Let `send_to` return `NetworkError::TimedOut`, if it has to wait longer than `timeout`.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::WriteTimeout, duration_to_ms(timeout))
+    }
+
+    pub fn write_timeout(&self) -> Result<Option<Duration>, NetworkError> {
+        get_option(self.handle, SocketOption::WriteTimeout).map(ms_to_duration)
+    }
+
+    /// Change the size of the receive buffer (in bytes).
+    /// Fails with `NetworkError::DeviceBusy`, as long as there are datagrams in the buffers.
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::RecvBufferSize, size)
+    }
+
+    /// Change the size of the send buffer (in bytes).
+    /// Fails with `NetworkError::DeviceBusy`, as long as there are datagrams in the buffers.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::SendBufferSize, size)
+    }
+
+    pub fn recv_buffer_size(&self) -> Result<usize, NetworkError> {
+        get_option(self.handle, SocketOption::RecvBufferSize)
+    }
+
+    pub fn send_buffer_size(&self) -> Result<usize, NetworkError> {
+        get_option(self.handle, SocketOption::SendBufferSize)
+    }
+
+    /// Set the hop limit (TTL) of outgoing packets (`None` uses the default of 64).
+    pub fn set_hop_limit(&self, hop_limit: Option<u8>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::HopLimit, hop_limit.unwrap_or(0).into())
+    }
+
+    pub fn hop_limit(&self) -> Result<Option<u8>, NetworkError> {
+        get_option(self.handle, SocketOption::HopLimit).map(|hop_limit| (hop_limit != 0).then_some(hop_limit as u8))
+    }
+
+    /// Allow sending to 255.255.255.255 and the broadcast addresses of the subnets.
+    /// Without this, `send_to` returns `NetworkError::PermissionDenied` for them.
+    pub fn set_broadcast(&self, broadcast: bool) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::Broadcast, broadcast.into())
+    }
+
+    pub fn broadcast(&self) -> Result<bool, NetworkError> {
+        get_option(self.handle, SocketOption::Broadcast).map(|broadcast| broadcast != 0)
+    }
+
+    /// Join an IPv4 multicast group on the interface with the address `interface`
+    /// (`Ipv4Addr::UNSPECIFIED` chooses the interface, through which the group is routed).
+    pub fn join_multicast_v4(&self, group: &Ipv4Addr, interface: &Ipv4Addr) -> Result<(), NetworkError> {
+        let interface = (!interface.is_unspecified()).then(|| interface.to_string());
+        self.multicast(SystemCall::SockJoinMulticast, IpAddr::V4(*group), interface)
+    }
+
+    /// Join an IPv6 multicast group on the interface with the index `interface`,
+    /// counting from 1 in the order of `get_interface_links` (0 chooses the interface, through which the group is routed).
+    pub fn join_multicast_v6(&self, group: &Ipv6Addr, interface: u32) -> Result<(), NetworkError> {
+        let interface = interface_by_index(interface)?;
+        self.multicast(SystemCall::SockJoinMulticast, IpAddr::V6(*group), interface)
+    }
+
+    /// Leave an IPv4 multicast group, that has been joined with `join_multicast_v4`.
+    pub fn leave_multicast_v4(&self, group: &Ipv4Addr, interface: &Ipv4Addr) -> Result<(), NetworkError> {
+        let interface = (!interface.is_unspecified()).then(|| interface.to_string());
+        self.multicast(SystemCall::SockLeaveMulticast, IpAddr::V4(*group), interface)
+    }
+
+    /// Leave an IPv6 multicast group, that has been joined with `join_multicast_v6`.
+    pub fn leave_multicast_v6(&self, group: &Ipv6Addr, interface: u32) -> Result<(), NetworkError> {
+        let interface = interface_by_index(interface)?;
+        self.multicast(SystemCall::SockLeaveMulticast, IpAddr::V6(*group), interface)
+    }
+
+    fn multicast(&self, call: SystemCall, group: IpAddr, interface: Option<String>) -> Result<(), NetworkError> {
+        let protocol = 0;
+        let group = address_string(group);
+        let interface = interface_name(interface.as_deref())?;
+        syscall(call, &[self.handle, protocol, group.as_bytes_with_nul().as_ptr() as usize, name_ptr(&interface)]).map_err(
+            |errno| match errno {
+                // not a multicast address
+                Errno::EINVAL => NetworkError::InvalidAddress,
+                Errno::ENOTSUP => panic!("invalid protocol"),
+                errno => NetworkError::from(errno),
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Describe this socket for `poll`.
+    pub fn poll_entry(&self, interest: PollFlags) -> PollEntry {
+        PollEntry::new(PollKind::Socket, self.handle, interest)
+    }
+}
+
+/// Get the driver name of the interface with the index `index` (counting from 1, 0 means none).
+fn interface_by_index(index: u32) -> Result<Option<String>, NetworkError> {
+    if index == 0 {
+        return Ok(None);
+    }
+    let links = get_interface_links();
+    let link = links.get(index as usize - 1).ok_or(NetworkError::NotFound)?;
+    Ok(Some(link.name().to_string()))
 }
 
 impl Drop for UdpSocket {
@@ -100,6 +231,20 @@ impl Drop for UdpSocket {
     }
 }
 
+/// Which half of a connection `TcpStream::shutdown` closes.
+///
+/// The numbers must match `Shutdown` in the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Shutdown {
+    /// Discard all data, that is received from now on.
+    Read = 0,
+    /// Send all queued data and then tell the remote host, that nothing more will come.
+    Write = 1,
+    /// Both of the above.
+    Both = 2,
+}
+
 pub struct TcpListener {
     handle: usize,
     /// the (local) address this socket is bound to
@@ -108,50 +253,129 @@ pub struct TcpListener {
 
 impl TcpListener {
     pub fn bind(address: SocketAddr) -> Result<Self, NetworkError> {
+        Self::bind_with_backlog(address, 0)
+    }
+
+    /// Like `bind`, but up to `backlog` clients can connect at the same time,
+    /// before they are accepted (0 means the default of the kernel).
+    pub fn bind_with_backlog(address: SocketAddr, backlog: usize) -> Result<Self, NetworkError> {
         let protocol = 1;
         let handle = syscall(SystemCall::SockOpen, &[protocol]).map_err(|errno| match errno {
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })?;
-        // valid addresses do not contain 0 bytes
-        let addr = CString::new(address.ip().to_string()).unwrap();
-        syscall(
+        let addr = address_string(address.ip());
+        let result = syscall(
             SystemCall::SockBind,
-            &[handle, protocol, addr.as_bytes_with_nul().as_ptr() as usize, address.port().into()],
+            &[handle, protocol, addr.as_bytes_with_nul().as_ptr() as usize, address.port().into(), backlog],
         )
         .map_err(|errno| match errno {
             Errno::EEXIST => panic!("socket as already been opened"),
             Errno::EINVAL => NetworkError::InvalidAddress,
-            errno => NetworkError::Unknown(errno),
-        })?;
+            errno => NetworkError::from(errno),
+        });
+        close_on_error(handle, result)?;
         Ok(Self { handle, address })
     }
 
+    /// Wait for a client and return the connection to it.
+    ///
+    /// The listener keeps listening, so this can be called again for the next client.
     pub fn accept(&self) -> Result<TcpStream, NetworkError> {
         let protocol = 1;
         // this should be the maximum length for an IP address
         let mut addr_buf = [0u8; 40];
-        let remote_port: u16 = syscall(SystemCall::SockAccept, &[self.handle, protocol, addr_buf.as_mut_ptr() as usize])
+        let result = syscall(SystemCall::SockAccept, &[self.handle, protocol, addr_buf.as_mut_ptr() as usize])
             .map_err(|errno| match errno {
                 Errno::EEXIST => panic!("socket as already been opened"),
                 Errno::EINVAL => NetworkError::InvalidAddress,
-                errno => NetworkError::Unknown(errno),
-            })?
-            .try_into()
-            .unwrap();
+                errno => NetworkError::from(errno),
+            })?;
+        // the connection has its own handle, which comes together with the remote port
+        let handle = result >> 16;
+        let remote_port = result as u16;
         let addr_str = CStr::from_bytes_until_nul(&addr_buf).unwrap().to_str().unwrap();
         let remote_addr = SocketAddr::new(IpAddr::from_str(addr_str).expect(&format!("failed to parse '{addr_str}'")), remote_port);
         Ok(TcpStream {
-            handle: self.handle,
+            handle,
             local_address: self.address,
             peer_address: remote_addr,
         })
     }
+
+    /// The local address, that this socket is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Let `accept` return `NetworkError::WouldBlock`, if no client has connected yet.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NetworkError> {
+        set_nonblocking(self.handle, nonblocking)
+    }
+
+    /// Let `accept` return `NetworkError::TimedOut`, if no client connects within `timeout`.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_timeout(self.handle, timeout)
+    }
+
+    /// Change the size of the receive buffer (in bytes).
+    /// This also applies to the accepted connections.
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::RecvBufferSize, size)
+    }
+
+    /// Change the size of the send buffer (in bytes).
+    /// This also applies to the accepted connections.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::SendBufferSize, size)
+    }
+
+    pub fn recv_buffer_size(&self) -> Result<usize, NetworkError> {
+        get_option(self.handle, SocketOption::RecvBufferSize)
+    }
+
+    pub fn send_buffer_size(&self) -> Result<usize, NetworkError> {
+        get_option(self.handle, SocketOption::SendBufferSize)
+    }
+
+    /// Send small segments right away, instead of collecting them (disables Nagle's algorithm).
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::Nagle, (!nodelay).into())
+    }
+
+    pub fn nodelay(&self) -> Result<bool, NetworkError> {
+        get_option(self.handle, SocketOption::Nagle).map(|nagle| nagle == 0)
+    }
+
+    /// Send keep-alive packets, if the connection has been idle for `interval` (`None` disables them).
+    pub fn set_keepalive(&self, interval: Option<Duration>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::KeepAlive, duration_to_ms(interval))
+    }
+
+    pub fn keepalive(&self) -> Result<Option<Duration>, NetworkError> {
+        get_option(self.handle, SocketOption::KeepAlive).map(ms_to_duration)
+    }
+
+    /// Set the hop limit (TTL) of outgoing packets (`None` uses the default of 64).
+    pub fn set_hop_limit(&self, hop_limit: Option<u8>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::HopLimit, hop_limit.unwrap_or(0).into())
+    }
+
+    pub fn hop_limit(&self) -> Result<Option<u8>, NetworkError> {
+        get_option(self.handle, SocketOption::HopLimit).map(|hop_limit| (hop_limit != 0).then_some(hop_limit as u8))
+    }
+
+    /// Describe this socket for `poll`.
+    pub fn poll_entry(&self, interest: PollFlags) -> PollEntry {
+        PollEntry::new(PollKind::Socket, self.handle, interest)
+    }
 }
 
 impl Drop for TcpListener {
     fn drop(&mut self) {
-        // TODO: just drop this when all connections are gone
+        // accepted connections have their own handles and stay open
+        let protocol = 1;
+        syscall(SystemCall::SockClose, &[self.handle, protocol]).expect("failed to close socket");
     }
 }
 
@@ -163,32 +387,46 @@ pub struct TcpStream {
 
 impl TcpStream {
     pub fn connect(address: SocketAddr) -> Result<Self, NetworkError> {
+        Self::connect_with_buffer_sizes(address, None, None)
+    }
+
+    /// Like `connect`, but with different sizes (in bytes) for the receive and send buffer.
+    /// They can't be changed after the connection has been established.
+    pub fn connect_with_buffer_sizes(
+        address: SocketAddr, recv_buffer_size: Option<usize>, send_buffer_size: Option<usize>,
+    ) -> Result<Self, NetworkError> {
         let protocol = 1;
         // this should be the maximum length for an IP address
         let mut addr_buf = [0u8; 40];
-        // valid addresses do not contain 0 bytes
-        let addr = CString::new(address.ip().to_string()).unwrap();
+        let addr = address_string(address.ip());
         let handle = syscall(SystemCall::SockOpen, &[protocol]).map_err(|errno| match errno {
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })?;
-        let local_port: u16 = syscall(
-            SystemCall::SockConnect,
-            &[
-                handle,
-                protocol,
-                addr.as_bytes_with_nul().as_ptr() as usize,
-                address.port().into(),
-                addr_buf.as_mut_ptr() as usize,
-            ],
-        )
-        .map_err(|errno| match errno {
-            Errno::EEXIST => panic!("socket as already been opened"),
-            Errno::EINVAL => NetworkError::InvalidAddress,
-            errno => NetworkError::Unknown(errno),
-        })?
-        .try_into()
-        .unwrap();
+        let mut connect = || {
+            if let Some(size) = recv_buffer_size {
+                set_option(handle, SocketOption::RecvBufferSize, size)?;
+            }
+            if let Some(size) = send_buffer_size {
+                set_option(handle, SocketOption::SendBufferSize, size)?;
+            }
+            syscall(
+                SystemCall::SockConnect,
+                &[
+                    handle,
+                    protocol,
+                    addr.as_bytes_with_nul().as_ptr() as usize,
+                    address.port().into(),
+                    addr_buf.as_mut_ptr() as usize,
+                ],
+            )
+            .map_err(|errno| match errno {
+                Errno::EEXIST => panic!("socket as already been opened"),
+                Errno::EINVAL => NetworkError::InvalidAddress,
+                errno => NetworkError::from(errno),
+            })
+        };
+        let local_port: u16 = close_on_error(handle, connect())?.try_into().unwrap();
         let addr_str = CStr::from_bytes_until_nul(&addr_buf).unwrap().to_str().unwrap();
         let local_address = SocketAddr::new(IpAddr::from_str(addr_str).expect(&format!("failed to parse '{addr_str}'")), local_port);
         Ok(Self {
@@ -203,22 +441,122 @@ impl TcpStream {
         syscall(SystemCall::SockSend, &[self.handle, protocol, buf.as_ptr() as usize, buf.len()]).map_err(|errno| match errno {
             Errno::EINVAL => panic!("socket can't send"),
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })
     }
 
+    /// Read received data into `buf`.
+    ///
+    /// This returns `Ok(0)`, when the remote host has closed the connection and all data has been read.
     pub fn read(&self, buf: &mut [u8]) -> Result<usize, NetworkError> {
         let protocol = 1;
         let num_bytes = syscall(SystemCall::SockReceive, &[self.handle, protocol, buf.as_ptr() as usize, buf.len()]).map_err(|errno| match errno {
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })?;
 
         Ok(num_bytes)
     }
+
+    /// Close the reading or the writing half of the connection (or both).
+    ///
+    /// After shutting down writing, the remote host reads the end of the stream,
+    /// once it has received all data, that has been written before.
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), NetworkError> {
+        syscall(SystemCall::SockShutdown, &[self.handle, how as usize])?;
+        Ok(())
+    }
+
+    /// Reset the connection, discarding all data, that hasn't been sent yet.
+    pub fn abort(&self) -> Result<(), NetworkError> {
+        let abort = 3;
+        syscall(SystemCall::SockShutdown, &[self.handle, abort])?;
+        Ok(())
+    }
+
+    /// The address of the remote host.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_address
+    }
+
+    /// The local address of the connection.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_address
+    }
+
+    /// Let `read` and `write` return `NetworkError::WouldBlock`, instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NetworkError> {
+        set_nonblocking(self.handle, nonblocking)
+    }
+
+    /// Let `read` and `write` return `NetworkError::TimedOut`, if they have to wait longer than `timeout`.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_timeout(self.handle, timeout)
+    }
+
+    /// Let `read` return `NetworkError::TimedOut`, if it has to wait longer than `timeout`.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::ReadTimeout, duration_to_ms(timeout))
+    }
+
+    pub fn read_timeout(&self) -> Result<Option<Duration>, NetworkError> {
+        get_option(self.handle, SocketOption::ReadTimeout).map(ms_to_duration)
+    }
+
+    /// Let `write` return `NetworkError::TimedOut`, if it has to wait longer than `timeout`.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::WriteTimeout, duration_to_ms(timeout))
+    }
+
+    pub fn write_timeout(&self) -> Result<Option<Duration>, NetworkError> {
+        get_option(self.handle, SocketOption::WriteTimeout).map(ms_to_duration)
+    }
+
+    pub fn recv_buffer_size(&self) -> Result<usize, NetworkError> {
+        get_option(self.handle, SocketOption::RecvBufferSize)
+    }
+
+    pub fn send_buffer_size(&self) -> Result<usize, NetworkError> {
+        get_option(self.handle, SocketOption::SendBufferSize)
+    }
+
+    /// Send small segments right away, instead of collecting them (disables Nagle's algorithm).
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::Nagle, (!nodelay).into())
+    }
+
+    pub fn nodelay(&self) -> Result<bool, NetworkError> {
+        get_option(self.handle, SocketOption::Nagle).map(|nagle| nagle == 0)
+    }
+
+    /// Send keep-alive packets, if the connection has been idle for `interval` (`None` disables them).
+    pub fn set_keepalive(&self, interval: Option<Duration>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::KeepAlive, duration_to_ms(interval))
+    }
+
+    pub fn keepalive(&self) -> Result<Option<Duration>, NetworkError> {
+        get_option(self.handle, SocketOption::KeepAlive).map(ms_to_duration)
+    }
+
+    /// Set the hop limit (TTL) of outgoing packets (`None` uses the default of 64).
+    pub fn set_hop_limit(&self, hop_limit: Option<u8>) -> Result<(), NetworkError> {
+        set_option(self.handle, SocketOption::HopLimit, hop_limit.unwrap_or(0).into())
+    }
+
+    pub fn hop_limit(&self) -> Result<Option<u8>, NetworkError> {
+        get_option(self.handle, SocketOption::HopLimit).map(|hop_limit| (hop_limit != 0).then_some(hop_limit as u8))
+    }
+
+    /// Describe this socket for `poll`.
+    pub fn poll_entry(&self, interest: PollFlags) -> PollEntry {
+        PollEntry::new(PollKind::Socket, self.handle, interest)
+    }
 }
 
 impl Drop for TcpStream {
+    /// Close the connection.
+    ///
+    /// The kernel keeps sending the data, that is still queued, before closing it.
     fn drop(&mut self) {
         let protocol = 1;
         syscall(SystemCall::SockClose, &[self.handle, protocol]).expect("failed to close socket");
@@ -235,26 +573,26 @@ impl IcmpSocket {
         let protocol = 2;
         let handle = syscall(SystemCall::SockOpen, &[protocol]).map_err(|errno| match errno {
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })?;
         // ICMP doesn't bind to an IP address, but the syscall still expects one.
         let addr = CString::new(Ipv6Addr::UNSPECIFIED.to_string()).unwrap();
-        syscall(
+        let result = syscall(
             SystemCall::SockBind,
             &[handle, protocol, addr.as_bytes_with_nul().as_ptr() as usize, ident.into()],
         )
         .map_err(|errno| match errno {
             Errno::EEXIST => panic!("socket has already been openend"),
             Errno::EINVAL => NetworkError::InvalidAddress,
-            errno => NetworkError::Unknown(errno),
-        })?;
+            errno => NetworkError::from(errno),
+        });
+        close_on_error(handle, result)?;
         Ok(Self { handle, ident })
     }
 
     pub fn send_to(&self, buf: &[u8], address: IpAddr) -> Result<usize, NetworkError> {
         let protocol = 2;
-        // valid addresses do not contain 0 bytes
-        let addr = CString::new(address.to_string()).unwrap();
+        let addr = address_string(address);
         syscall(
             SystemCall::SockSend,
             &[
@@ -269,7 +607,7 @@ impl IcmpSocket {
             Errno::EINVAL => NetworkError::InvalidAddress,
             Errno::EBUSY => NetworkError::DeviceBusy,
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })
     }
 
@@ -283,7 +621,7 @@ impl IcmpSocket {
         )
         .map_err(|errno| match errno {
             Errno::ENOTSUP => panic!("invalid protocol"),
-            errno => NetworkError::Unknown(errno),
+            errno => NetworkError::from(errno),
         })?;
         let address = if num_bytes > 0 {
             let addr_str = CStr::from_bytes_until_nul(&addr_buf).unwrap().to_str().unwrap();
@@ -294,6 +632,21 @@ impl IcmpSocket {
 
         Ok((num_bytes, address))
     }
+
+    /// Let `send_to` and `recv` return `NetworkError::WouldBlock`, instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NetworkError> {
+        set_nonblocking(self.handle, nonblocking)
+    }
+
+    /// Let `send_to` and `recv` return `NetworkError::TimedOut`, if they have to wait longer than `timeout`.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_timeout(self.handle, timeout)
+    }
+
+    /// Describe this socket for `poll`.
+    pub fn poll_entry(&self, interest: PollFlags) -> PollEntry {
+        PollEntry::new(PollKind::Socket, self.handle, interest)
+    }
 }
 
 impl Drop for IcmpSocket {
@@ -303,13 +656,258 @@ impl Drop for IcmpSocket {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+/// Sends and receives whole IP packets of one protocol, including the IP header.
+///
+/// Only applications, that are listed with `raw=` in network.conf, may open raw sockets,
+/// all others get `NetworkError::PermissionDenied`.
+pub struct RawSocket {
+    handle: usize,
+}
+
+impl RawSocket {
+    /// Receive copies of all IP packets with `version` and `protocol` (e.g. 89 for OSPF).
+    pub fn open(version: IpVersion, protocol: u8) -> Result<Self, NetworkError> {
+        let socket_type = 3;
+        let version = match version {
+            IpVersion::V4 => 4,
+            IpVersion::V6 => 6,
+        };
+        let handle = syscall(SystemCall::SockOpen, &[socket_type, version, protocol.into()]).map_err(|errno| match errno {
+            Errno::ENOTSUP => panic!("invalid protocol"),
+            errno => NetworkError::from(errno),
+        })?;
+        Ok(Self { handle })
+    }
+
+    /// Send `packet`, which has to start with an IP header of the version and protocol of the socket.
+    pub fn send(&self, packet: &[u8]) -> Result<usize, NetworkError> {
+        let socket_type = 3;
+        syscall(SystemCall::SockSend, &[self.handle, socket_type, packet.as_ptr() as usize, packet.len()]).map_err(
+            |errno| match errno {
+                Errno::ENOTSUP => panic!("invalid protocol"),
+                errno => NetworkError::from(errno),
+            },
+        )
+    }
+
+    /// Receive the next packet (including its IP header) and its source address.
+    /// Packets, that don't fit into `buf`, are discarded (0 is returned instead).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<(usize, IpAddr), NetworkError> {
+        let socket_type = 3;
+        // this should be the maximum length for an IP address
+        let mut addr_buf = [0u8; 40];
+        let num_bytes = syscall(
+            SystemCall::SockReceive,
+            &[self.handle, socket_type, buf.as_ptr() as usize, buf.len(), addr_buf.as_mut_ptr() as usize],
+        )
+        .map_err(|errno| match errno {
+            Errno::ENOTSUP => panic!("invalid protocol"),
+            errno => NetworkError::from(errno),
+        })?;
+        let address = CStr::from_bytes_until_nul(&addr_buf)
+            .ok()
+            .and_then(|addr_str| IpAddr::from_str(addr_str.to_str().ok()?).ok())
+            .unwrap_or(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+
+        Ok((num_bytes, address))
+    }
+
+    /// Let `send` and `recv` return `NetworkError::WouldBlock`, instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), NetworkError> {
+        set_nonblocking(self.handle, nonblocking)
+    }
+
+    /// Let `send` and `recv` return `NetworkError::TimedOut`, if they have to wait longer than `timeout`.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), NetworkError> {
+        set_timeout(self.handle, timeout)
+    }
+
+    /// Describe this socket for `poll`.
+    pub fn poll_entry(&self, interest: PollFlags) -> PollEntry {
+        PollEntry::new(PollKind::Socket, self.handle, interest)
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        let socket_type = 3;
+        syscall(SystemCall::SockClose, &[self.handle, socket_type]).expect("failed to close socket");
+    }
+}
+
+/// Sends and receives whole Ethernet frames on one interface (e.g. for ARP or LLDP tools).
+///
+/// Like raw sockets, packet sockets are only available to the applications listed with `raw=` in network.conf.
+/// The kernel still processes all frames, a packet socket only gets copies of them.
+pub struct PacketSocket {
+    id: usize,
+    nonblocking: Cell<bool>,
+    timeout: Cell<Option<Duration>>,
+}
+
+impl PacketSocket {
+    /// Receive the frames with `ether_type` (all frames, if it is `None`) of the interface,
+    /// whose driver name contains `interface` (e.g. "NE2000").
+    pub fn open(interface: &str, ether_type: Option<u16>) -> Result<Self, NetworkError> {
+        let interface = CString::new(interface).map_err(|_| NetworkError::InvalidArgument)?;
+        let id = syscall(
+            SystemCall::PacketOpen,
+            &[interface.as_bytes_with_nul().as_ptr() as usize, ether_type.unwrap_or(0).into()],
+        )?;
+        Ok(Self { id, nonblocking: Cell::new(false), timeout: Cell::new(None) })
+    }
+
+    /// Send `frame`, which has to start with an Ethernet header (the card adds the checksum).
+    pub fn send(&self, frame: &[u8]) -> Result<usize, NetworkError> {
+        syscall(SystemCall::PacketSend, &[self.id, frame.as_ptr() as usize, frame.len()]).map_err(|errno| match errno {
+            // shorter than an Ethernet header or longer than the MTU
+            Errno::EINVAL => NetworkError::InvalidArgument,
+            errno => NetworkError::from(errno),
+        })
+    }
+
+    /// Receive the next frame. Frames, that don't fit into `buf`, are cut off.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize, NetworkError> {
+        let timeout = if self.nonblocking.get() {
+            0
+        } else {
+            // 0 means not to wait, so round up to at least 1 ms
+            self.timeout.get().map_or(usize::MAX, |timeout| (timeout.as_millis() as usize).max(1))
+        };
+        Ok(syscall(SystemCall::PacketReceive, &[self.id, buf.as_mut_ptr() as usize, buf.len(), timeout])?)
+    }
+
+    /// Let `recv` return `NetworkError::WouldBlock`, instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.set(nonblocking);
+    }
+
+    /// Let `recv` return `NetworkError::TimedOut`, if it has to wait longer than `timeout`.
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.timeout.set(timeout);
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        syscall(SystemCall::PacketClose, &[self.id]).expect("failed to close packet socket");
+    }
+}
+
 #[derive(Debug)]
 pub enum NetworkError {
     DeviceBusy,
     InvalidAddress,
+    /// A socket option has an invalid value (or can't be changed anymore).
+    InvalidArgument,
+    /// The socket is nonblocking and the operation would have to wait.
+    WouldBlock,
+    /// The timeout of the socket has expired (or no DNS server has answered).
+    TimedOut,
+    /// There is no such interface, address, route, host name or multicast membership.
+    NotFound,
+    /// The address or route has already been added (or the multicast group has already been joined).
+    AlreadyExists,
+    /// The interface can't hold any more addresses, routes or multicast groups.
+    TableFull,
+    /// The port is bound by another socket (or there is no free ephemeral port).
+    AddressInUse,
+    /// The application may not open raw or packet sockets (or the UDP socket may not send broadcasts).
+    PermissionDenied,
     Unknown(Errno),
 }
 
+impl From<Errno> for NetworkError {
+    fn from(errno: Errno) -> Self {
+        match errno {
+            Errno::EAGAIN => NetworkError::WouldBlock,
+            Errno::ETIMEDOUT => NetworkError::TimedOut,
+            Errno::ENOENT => NetworkError::NotFound,
+            Errno::EEXIST => NetworkError::AlreadyExists,
+            Errno::ENOSPC => NetworkError::TableFull,
+            Errno::EADDRINUSE => NetworkError::AddressInUse,
+            Errno::EACCES => NetworkError::PermissionDenied,
+            errno => NetworkError::Unknown(errno),
+        }
+    }
+}
+
+/// Options for `SockSetOpt` and `SockGetOpt` (the numbers must match `SocketOption` in the kernel).
+#[derive(Debug, Clone, Copy)]
+#[repr(usize)]
+enum SocketOption {
+    Nonblocking = 0,
+    ReadTimeout = 1,
+    WriteTimeout = 2,
+    RecvBufferSize = 3,
+    SendBufferSize = 4,
+    Nagle = 5,
+    KeepAlive = 6,
+    HopLimit = 7,
+    Broadcast = 8,
+}
+
+/// Close a socket, that has been opened, but couldn't be bound or connected, so that its descriptor isn't leaked.
+fn close_on_error<T>(handle: usize, result: Result<T, NetworkError>) -> Result<T, NetworkError> {
+    if result.is_err() {
+        // the error of the setup is more interesting than the one of closing
+        let _ = syscall(SystemCall::SockClose, &[handle]);
+    }
+    result
+}
+
+fn set_option(handle: usize, option: SocketOption, value: usize) -> Result<(), NetworkError> {
+    syscall(SystemCall::SockSetOpt, &[handle, option as usize, value]).map_err(|errno| match errno {
+        Errno::ENOTSUP => panic!("socket doesn't support {option:?}"),
+        // the value is invalid or the buffers of a connected socket were resized
+        Errno::EINVAL | Errno::EISCONN => NetworkError::InvalidArgument,
+        // the buffers still hold data
+        Errno::EBUSY => NetworkError::DeviceBusy,
+        errno => NetworkError::from(errno),
+    })?;
+    Ok(())
+}
+
+fn get_option(handle: usize, option: SocketOption) -> Result<usize, NetworkError> {
+    syscall(SystemCall::SockGetOpt, &[handle, option as usize]).map_err(|errno| match errno {
+        Errno::ENOTSUP => panic!("socket doesn't support {option:?}"),
+        errno => NetworkError::from(errno),
+    })
+}
+
+/// Timeouts and intervals are passed as ms, where 0 means `None`.
+fn duration_to_ms(duration: Option<Duration>) -> usize {
+    // round up to at least 1 ms, so that short durations don't become `None`
+    duration.map_or(0, |duration| (duration.as_millis() as usize).max(1))
+}
+
+fn ms_to_duration(ms: usize) -> Option<Duration> {
+    (ms != 0).then(|| Duration::from_millis(ms as u64))
+}
+
+fn set_nonblocking(handle: usize, nonblocking: bool) -> Result<(), NetworkError> {
+    set_option(handle, SocketOption::Nonblocking, nonblocking.into())
+}
+
+fn set_timeout(handle: usize, timeout: Option<Duration>) -> Result<(), NetworkError> {
+    set_option(handle, SocketOption::ReadTimeout, duration_to_ms(timeout))?;
+    set_option(handle, SocketOption::WriteTimeout, duration_to_ms(timeout))
+}
+
+/// Wait until at least one of the `entries` is ready or `timeout` has expired (`None` waits forever).
+///
+/// Entries can be created with `poll_entry` for sockets and with `PollEntry::new` for handles
+/// of the naming service. Returns the number of ready entries, which is 0 after a timeout.
+pub fn poll(entries: &mut [PollEntry], timeout: Option<Duration>) -> Result<usize, NetworkError> {
+    Ok(naming::poll(entries, timeout.map(|timeout| timeout.as_millis() as usize))?)
+}
+
 /// Get all IP addresses of this host.
 pub fn get_ip_addresses() -> Vec<IpAddr> {
     let mut buf = [0u8; 4096];
@@ -318,22 +916,459 @@ pub fn get_ip_addresses() -> Vec<IpAddr> {
     split_ips(&buf)
 }
 
+/// Name and traffic counters of a network interface.
+///
+/// The detailed error counters (starting with `rx_overflows`) are only reported by some cards
+/// (currently the NE2000), all other cards leave them at 0.
+/// The layout must match the kernel's `InterfaceStats`.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct InterfaceStats {
+    name: [u8; 16],
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub rx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    pub tx_errors: u64,
+    /// Receive buffer overflows
+    pub rx_overflows: u64,
+    pub rx_crc_errors: u64,
+    pub rx_frame_errors: u64,
+    pub rx_fifo_errors: u64,
+    /// Frames, that the card had no room for
+    pub rx_missed: u64,
+    pub tx_collisions: u64,
+    pub tx_aborted: u64,
+    pub tx_fifo_errors: u64,
+    pub tx_carrier_errors: u64,
+}
+
+impl InterfaceStats {
+    /// Name of the driver of this interface (e.g. "NE2000").
+    pub fn name(&self) -> &str {
+        padded_name(&self.name)
+    }
+}
+
+/// Get the traffic counters of all network interfaces.
+pub fn get_network_stats() -> Vec<InterfaceStats> {
+    let mut stats = [InterfaceStats::default(); 8];
+    // this can't fail, the buffer is always valid
+    let count = syscall(SystemCall::GetNetworkStats, &[stats.as_mut_ptr() as usize, stats.len()]).unwrap();
+    stats[..count].to_vec()
+}
+
+/// Interfaces are selected by their driver name or a part of it (e.g. "NE2000"), like in `InterfaceStats`.
+fn interface_name(interface: Option<&str>) -> Result<Option<CString>, NetworkError> {
+    interface.map(CString::new).transpose().map_err(|_| NetworkError::InvalidArgument)
+}
+
+fn name_ptr(name: &Option<CString>) -> usize {
+    name.as_ref().map_or(0, |name| name.as_bytes_with_nul().as_ptr() as usize)
+}
+
+/// Network addresses are passed as "address/prefix length".
+fn cidr(address: IpAddr, prefix_len: u8) -> CString {
+    // valid addresses do not contain 0 bytes
+    CString::new(format!("{}/{prefix_len}", address.to_canonical())).unwrap()
+}
+
+fn map_config_error(errno: Errno) -> NetworkError {
+    match errno {
+        Errno::EINVAL => NetworkError::InvalidAddress,
+        errno => NetworkError::from(errno),
+    }
+}
+
+/// Add `address` with the given prefix length to an interface (e.g. 10.0.2.15/24).
+///
+/// Like all changes of the addresses, routes and neighbor cache, this fails with `PermissionDenied`,
+/// unless the application is listed with `raw=` in network.conf.
+pub fn add_ip_address(interface: &str, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+    let name = interface_name(Some(interface))?;
+    let address = cidr(address, prefix_len);
+    syscall(SystemCall::AddIpAddress, &[name_ptr(&name), address.as_bytes_with_nul().as_ptr() as usize]).map_err(map_config_error)?;
+    Ok(())
+}
+
+/// Remove `address` from an interface.
+pub fn remove_ip_address(interface: &str, address: IpAddr) -> Result<(), NetworkError> {
+    let name = interface_name(Some(interface))?;
+    let address = address_string(address);
+    syscall(SystemCall::RemoveIpAddress, &[name_ptr(&name), address.as_bytes_with_nul().as_ptr() as usize]).map_err(map_config_error)?;
+    Ok(())
+}
+
+/// Send the packets for `destination`/`prefix_len` to `gateway` (a prefix length of 0 is the default route).
+///
+/// Without an `interface`, the route is added to the interface, whose network contains the gateway.
+pub fn add_route(interface: Option<&str>, destination: IpAddr, prefix_len: u8, gateway: IpAddr) -> Result<(), NetworkError> {
+    let name = interface_name(interface)?;
+    let destination = cidr(destination, prefix_len);
+    let gateway = address_string(gateway);
+    syscall(
+        SystemCall::AddRoute,
+        &[name_ptr(&name), destination.as_bytes_with_nul().as_ptr() as usize, gateway.as_bytes_with_nul().as_ptr() as usize],
+    )
+    .map_err(map_config_error)?;
+    Ok(())
+}
+
+/// Remove the route for `destination`/`prefix_len`. Without an `interface`, it is removed from all interfaces.
+pub fn remove_route(interface: Option<&str>, destination: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+    let name = interface_name(interface)?;
+    let destination = cidr(destination, prefix_len);
+    syscall(SystemCall::RemoveRoute, &[name_ptr(&name), destination.as_bytes_with_nul().as_ptr() as usize]).map_err(map_config_error)?;
+    Ok(())
+}
+
+/// Decode a driver name, that is padded with 0 bytes.
+fn padded_name(name: &[u8; 16]) -> &str {
+    let len = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+    str::from_utf8(&name[..len]).unwrap_or("?")
+}
+
+/// Decode an address from the kernel (IPv4 addresses use the first 4 bytes).
+fn address_from_bytes(bytes: [u8; 16], ipv6: bool) -> IpAddr {
+    if ipv6 {
+        IpAddr::V6(Ipv6Addr::from(bytes))
+    } else {
+        IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))
+    }
+}
+
+/// The kernel passes lifetimes in ms, `u64::MAX` means forever.
+fn lifetime(millis: u64) -> Option<Duration> {
+    (millis != u64::MAX).then(|| Duration::from_millis(millis))
+}
+
+/// MAC address, MTU and link state of a network interface.
+/// The layout must match the kernel's `InterfaceLink`.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct InterfaceLink {
+    name: [u8; 16],
+    mac: [u8; 6],
+    has_mac: bool,
+    /// Whether a cable is plugged in
+    pub up: bool,
+    /// Largest IP packet, that can be sent
+    pub mtu: u32,
+}
+
+impl InterfaceLink {
+    /// Name of the driver of this interface (e.g. "NE2000").
+    pub fn name(&self) -> &str {
+        padded_name(&self.name)
+    }
+
+    /// The MAC address (`None` for the loopback interface).
+    pub fn mac(&self) -> Option<[u8; 6]> {
+        self.has_mac.then_some(self.mac)
+    }
+}
+
+/// Get the MAC address, MTU and link state of all network interfaces.
+pub fn get_interface_links() -> Vec<InterfaceLink> {
+    let mut links = [InterfaceLink::default(); 8];
+    // this can't fail, the buffer is always valid
+    let count = syscall(SystemCall::GetInterfaceLinks, &[links.as_mut_ptr() as usize, links.len()]).unwrap();
+    links[..count].to_vec()
+}
+
+/// State of a neighbor cache entry (the numbers must match `NeighborState` in the kernel).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighborState {
+    /// The entry has been learned (via ARP or neighbor discovery) and is used for sending.
+    Reachable,
+    /// The entry is too old, the address will be resolved again before sending.
+    Expired,
+    /// The entry has been added with `add_neighbor`.
+    Permanent,
+}
+
+/// An entry of the neighbor cache (ARP for IPv4, neighbor discovery for IPv6) of a network interface.
+/// The layout must match the kernel's `NeighborEntry`.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct NeighborEntry {
+    interface: [u8; 16],
+    address: [u8; 16],
+    ipv6: bool,
+    state: u8,
+    /// The MAC address of the neighbor
+    pub mac: [u8; 6],
+    expires_in: u64,
+}
+
+impl NeighborEntry {
+    /// Name of the driver of the interface (e.g. "NE2000").
+    pub fn interface(&self) -> &str {
+        padded_name(&self.interface)
+    }
+
+    pub fn address(&self) -> IpAddr {
+        address_from_bytes(self.address, self.ipv6)
+    }
+
+    pub fn state(&self) -> NeighborState {
+        match self.state {
+            0 => NeighborState::Reachable,
+            1 => NeighborState::Expired,
+            _ => NeighborState::Permanent,
+        }
+    }
+
+    /// Time until the entry expires (`None` for permanent entries).
+    pub fn expires_in(&self) -> Option<Duration> {
+        lifetime(self.expires_in)
+    }
+}
+
+/// Get the neighbor cache entries of all network interfaces.
+pub fn get_neighbors() -> Vec<NeighborEntry> {
+    let mut neighbors = [NeighborEntry::default(); 64];
+    // this can't fail, the buffer is always valid
+    let count = syscall(SystemCall::GetNeighbors, &[neighbors.as_mut_ptr() as usize, neighbors.len()]).unwrap();
+    neighbors[..count].to_vec()
+}
+
+/// Add a permanent neighbor cache entry, so that packets for `address` are sent to `mac` without asking first.
+///
+/// `address` must be in the network of one of the addresses of the interface.
+/// Like `add_ip_address`, this fails with `PermissionDenied`, unless the application is listed with `raw=` in network.conf.
+pub fn add_neighbor(interface: &str, address: IpAddr, mac: [u8; 6]) -> Result<(), NetworkError> {
+    let name = interface_name(Some(interface))?;
+    let address = address_string(address);
+    let [m0, m1, m2, m3, m4, m5] = mac;
+    let mac = CString::new(format!("{m0:02x}:{m1:02x}:{m2:02x}:{m3:02x}:{m4:02x}:{m5:02x}")).unwrap();
+    syscall(
+        SystemCall::AddNeighbor,
+        &[name_ptr(&name), address.as_bytes_with_nul().as_ptr() as usize, mac.as_bytes_with_nul().as_ptr() as usize],
+    )
+    .map_err(map_config_error)?;
+    Ok(())
+}
+
+/// Remove the (learned or permanent) neighbor cache entry for `address`.
+pub fn remove_neighbor(interface: &str, address: IpAddr) -> Result<(), NetworkError> {
+    let name = interface_name(Some(interface))?;
+    let address = address_string(address);
+    syscall(SystemCall::RemoveNeighbor, &[name_ptr(&name), address.as_bytes_with_nul().as_ptr() as usize]).map_err(map_config_error)?;
+    Ok(())
+}
+
+/// Forget the learned neighbor cache entries (the permanent ones are kept).
+/// Without an `interface`, the entries of all interfaces are forgotten.
+pub fn flush_neighbors(interface: Option<&str>) -> Result<(), NetworkError> {
+    let name = interface_name(interface)?;
+    syscall(SystemCall::FlushNeighbors, &[name_ptr(&name)]).map_err(map_config_error)?;
+    Ok(())
+}
+
+/// A route of a network interface.
+/// The layout must match the kernel's `RouteEntry`.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct RouteEntry {
+    interface: [u8; 16],
+    destination: [u8; 16],
+    gateway: [u8; 16],
+    ipv6: bool,
+    /// Prefix length of the destination network (0 for the default route)
+    pub prefix_len: u8,
+    expires_in: u64,
+}
+
+impl RouteEntry {
+    /// Name of the driver of the interface (e.g. "NE2000").
+    pub fn interface(&self) -> &str {
+        padded_name(&self.interface)
+    }
+
+    pub fn destination(&self) -> IpAddr {
+        address_from_bytes(self.destination, self.ipv6)
+    }
+
+    pub fn gateway(&self) -> IpAddr {
+        address_from_bytes(self.gateway, self.ipv6)
+    }
+
+    /// Time until the route expires (`None`, if it doesn't expire).
+    pub fn expires_in(&self) -> Option<Duration> {
+        lifetime(self.expires_in)
+    }
+}
+
+/// Get the routes of all network interfaces.
+pub fn get_routes() -> Vec<RouteEntry> {
+    let mut routes = [RouteEntry::default(); 64];
+    // this can't fail, the buffer is always valid
+    let count = syscall(SystemCall::GetRoutes, &[routes.as_mut_ptr() as usize, routes.len()]).unwrap();
+    routes[..count].to_vec()
+}
+
+/// Frames are cut off after this many bytes by the packet capture.
+pub const CAPTURE_SNAPSHOT_LENGTH: usize = 1518;
+/// Buffers passed to `read_capture` must be at least this big (one record header and one complete frame).
+pub const CAPTURE_READ_SIZE: usize = 16 + CAPTURE_SNAPSHOT_LENGTH;
+
+/// Which frames should be captured by `start_capture`. Every field, that is set, has to match.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CaptureFilter<'a> {
+    /// Only capture the interfaces, whose driver name contains this (e.g. "NE2000").
+    pub interface: Option<&'a str>,
+    /// Only capture frames with this EtherType (e.g. 0x0800 for IPv4).
+    pub ether_type: Option<u16>,
+    /// Only capture IP packets with this protocol (e.g. 17 for UDP).
+    pub ip_protocol: Option<u8>,
+    /// Only capture TCP and UDP packets, whose source or destination port is this.
+    pub port: Option<u16>,
+    /// Put the cards into promiscuous mode, so that frames for other hosts are captured as well.
+    pub promiscuous: bool,
+}
+
+/// Settings of a packet capture, as they are passed to the kernel.
+/// The layout must match the kernel's `CaptureOptions`.
+#[repr(C)]
+struct CaptureOptions {
+    interface: [u8; 16],
+    ether_type: u16,
+    port: u16,
+    ip_protocol: u8,
+    promiscuous: u8,
+}
+
+/// Start capturing the frames, that are received and sent by this host.
+///
+/// Like packet sockets, this is only allowed for the applications listed in the network configuration,
+/// all others get `NetworkError::PermissionDenied`.
+/// Frames of an earlier capture, that haven't been read yet, are discarded.
+/// Returns `false`, if promiscuous mode has been requested, but a card doesn't support it.
+pub fn start_capture(filter: &CaptureFilter) -> Result<bool, NetworkError> {
+    let mut options = CaptureOptions {
+        interface: [0; 16],
+        ether_type: filter.ether_type.unwrap_or(0),
+        port: filter.port.unwrap_or(0),
+        ip_protocol: filter.ip_protocol.unwrap_or(0),
+        promiscuous: filter.promiscuous.into(),
+    };
+    if let Some(interface) = filter.interface {
+        let len = interface.len().min(options.interface.len());
+        options.interface[..len].copy_from_slice(&interface.as_bytes()[..len]);
+    }
+    let supported = syscall(SystemCall::CaptureStart, &[&options as *const CaptureOptions as usize]).map_err(|errno| match errno {
+        Errno::EINVAL => NetworkError::InvalidArgument,
+        errno => NetworkError::from(errno),
+    })?;
+    Ok(supported != 0)
+}
+
+/// Stop capturing. The frames, that have been captured, can still be read with `read_capture`.
+///
+/// Returns the number of frames, that have been lost, because they haven't been read in time.
+pub fn stop_capture() -> Result<usize, NetworkError> {
+    syscall(SystemCall::CaptureStop, &[]).map_err(NetworkError::from)
+}
+
+/// Read the captured frames as libpcap records into `buf`, which must be at least `CAPTURE_READ_SIZE` bytes.
+///
+/// Returns the number of bytes read (0, if there are no new frames). Prepend `pcap_file_header()` to get a pcap file.
+pub fn read_capture(buf: &mut [u8]) -> Result<usize, NetworkError> {
+    syscall(SystemCall::CaptureRead, &[buf.as_mut_ptr() as usize, buf.len()]).map_err(|errno| match errno {
+        Errno::EINVAL => NetworkError::InvalidArgument,
+        errno => NetworkError::from(errno),
+    })
+}
+
+/// The header of a libpcap file, for the records returned by `read_capture` (Ethernet frames, in host byte order).
+pub fn pcap_file_header() -> [u8; 24] {
+    const MAGIC: u32 = 0xa1b2_c3d4;
+    const VERSION_MAJOR: u16 = 2;
+    const VERSION_MINOR: u16 = 4;
+    const LINKTYPE_ETHERNET: u32 = 1;
+
+    let mut header = [0u8; 24];
+    header[0..4].copy_from_slice(&MAGIC.to_ne_bytes());
+    header[4..6].copy_from_slice(&VERSION_MAJOR.to_ne_bytes());
+    header[6..8].copy_from_slice(&VERSION_MINOR.to_ne_bytes());
+    // bytes 8..16: time zone and accuracy of the timestamps, both 0
+    header[16..20].copy_from_slice(&(CAPTURE_SNAPSHOT_LENGTH as u32).to_ne_bytes());
+    header[20..24].copy_from_slice(&LINKTYPE_ETHERNET.to_ne_bytes());
+    header
+}
+
+/// Which addresses `resolve` looks up (the numbers must match `AddressFamily` in the kernel).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum AddressFamily {
+    /// IPv6 and IPv4 addresses (IPv6 first)
+    #[default]
+    Any = 0,
+    Ipv4 = 1,
+    Ipv6 = 2,
+}
+
+/// Options for `resolve`. `None` selects the defaults of the kernel (1 s timeout, 2 retries).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ResolveOptions {
+    pub family: AddressFamily,
+    /// How long to wait for an answer of a DNS server, before asking again
+    pub timeout: Option<Duration>,
+    /// How often a DNS server is asked again, if it doesn't answer in time
+    pub retries: Option<usize>,
+}
+
+/// Resolve this hostname with the default options, return a list of IP addresses.
+pub fn resolve_hostname(host: &str) -> Result<Vec<IpAddr>, NetworkError> {
+    resolve(host, &ResolveOptions::default())
+}
+
 /// Resolve this hostname, return a list of IP addresses.
-pub fn resolve_hostname(host: &str) -> Vec<IpAddr> {
+///
+/// Names are looked up in `/etc/hosts` first, then in the DNS cache of the kernel and then with the DNS servers.
+/// Fails with `NotFound`, if the name doesn't exist, and with `TimedOut`, if no DNS server has answered.
+pub fn resolve(host: &str, options: &ResolveOptions) -> Result<Vec<IpAddr>, NetworkError> {
     // this might already be an IP address
-    if let Ok(ip) = host.parse() {
-        vec![ip]
-    } else {
-        let host_c = CString::new(host).unwrap();
-        let mut buf = [0u8; 4096];
-        // this can't fail, unless the buffer is not big enough
-        syscall(
-            SystemCall::GetIpAddresses,
-            &[buf.as_mut_ptr() as usize, buf.len(), host_c.as_bytes_with_nul().as_ptr() as usize],
-        )
-        .unwrap();
-        split_ips(&buf)
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        let matches = match options.family {
+            AddressFamily::Any => true,
+            AddressFamily::Ipv4 => ip.is_ipv4(),
+            AddressFamily::Ipv6 => ip.is_ipv6(),
+        };
+        return if matches { Ok(vec![ip]) } else { Err(NetworkError::NotFound) };
     }
+    let host_c = CString::new(host).map_err(|_| NetworkError::InvalidAddress)?;
+    // 0 and usize::MAX select the defaults
+    let timeout = options.timeout.map_or(0, |timeout| timeout.as_millis().max(1) as usize);
+    let retries = options.retries.unwrap_or(usize::MAX);
+    let mut buf = [0u8; 4096];
+    syscall(
+        SystemCall::Resolve,
+        &[
+            host_c.as_bytes_with_nul().as_ptr() as usize,
+            options.family as usize,
+            timeout,
+            retries,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+        ],
+    )
+    .map_err(|errno| match errno {
+        Errno::EINVAL => NetworkError::InvalidAddress,
+        errno => NetworkError::from(errno),
+    })?;
+    Ok(split_ips(&buf))
+}
+
+/// Addresses are passed to the kernel as strings. IPv4 and IPv6 work alike,
+/// but IPv4-mapped IPv6 addresses (::ffff:a.b.c.d) are passed as IPv4 addresses,
+/// because they never appear on the wire.
+fn address_string(address: IpAddr) -> CString {
+    // valid addresses do not contain 0 bytes
+    CString::new(address.to_canonical().to_string()).unwrap()
 }
 
 /// Split a \0-byte seperated list of IP addresses
@@ -51,6 +51,31 @@ pub enum SystemCall {
     SockReceive,
     SockClose,
     GetIpAddresses,
+    SockSetOpt,
+    SockGetOpt,
+    Poll,
+    SockShutdown,
+    GetNetworkStats,
+    CaptureStart,
+    CaptureStop,
+    CaptureRead,
+    AddIpAddress,
+    RemoveIpAddress,
+    AddRoute,
+    RemoveRoute,
+    Resolve,
+    GetInterfaceLinks,
+    GetNeighbors,
+    AddNeighbor,
+    RemoveNeighbor,
+    FlushNeighbors,
+    GetRoutes,
+    PacketOpen,
+    PacketSend,
+    PacketReceive,
+    PacketClose,
+    SockJoinMulticast,
+    SockLeaveMulticast,
     // no syscall, just marking last number, see NUM_SYSCALLS
     // insert any new system calls before this marker
     LastEntryMarker,
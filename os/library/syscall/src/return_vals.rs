@@ -29,6 +29,13 @@ pub enum Errno {
     ENOTSUP    = -13, // Operation not supported
     ECONNRESET = -14, // Connection reset by peer
     ERDONLY    = -15, // Read-only file system
+    EAGAIN     = -16, // Operation would block
+    ETIMEDOUT  = -17, // Operation timed out
+    EISCONN    = -18, // Socket is already connected
+    ENOSPC     = -19, // No space left (e.g. in a table)
+    ENETUNREACH = -20, // Network is unreachable (e.g. no DNS server)
+    EADDRINUSE = -21, // Address or port is already in use
+    ENOTSOCK   = -22, // Descriptor refers to a different kind of socket
 }
 
 
@@ -0,0 +1,102 @@
+#!/usr/bin/env python3
+
+import argparse
+import os
+import socket
+import subprocess
+import sys
+import time
+
+
+## =============================================================================
+## FILE        : overflow_test.py
+## DESCRIPTION : regression test for the overflow recovery of the ne2000,
+##               run with `cargo make overflow-test`
+## =============================================================================
+## NOTES:
+## boots D3OS in QEMU without a window, types the nettest command into the
+## shell via the QEMU monitor and floods the card through the slirp hostfwd
+## (see nettest.py --flood --check)
+## the test passes, if the receive ring overflowed, no packet got corrupted
+## and nettest still answers afterwards (so the card didn't hang)
+## =============================================================================
+## DEPENDENCIES:
+## the image built by `cargo make image hdd ovmf` and qemu-system-x86_64
+## =============================================================================
+
+MONITOR = "overflow_test.sock"
+SERIAL_LOG = "overflow_test.log"
+
+GUEST_COMMAND = "nettest -u -l 10.0.2.15 1798 10.0.2.2 1798"
+
+# QEMU names of the keys, that aren't letters or digits
+KEYS = {" ": "spc", "-": "minus", ".": "dot", "\n": "ret"}
+
+QEMU_ARGS = [
+    "qemu-system-x86_64",
+    "-machine", "q35,nvdimm=on",
+    "-m", "1024M,slots=2,maxmem=4G",
+    "-cpu", "qemu64",
+    "-bios", "RELEASEX64_OVMF.fd",
+    "-boot", "d",
+    "-vga", "std",
+    "-rtc", "base=localtime",
+    "-display", "none",
+    "-serial", f"file:{SERIAL_LOG}",
+    "-monitor", f"unix:{MONITOR},server,nowait",
+    "-device", "piix3-ide,id=ide",
+    "-device", "ahci,id=ahci",
+    "-drive", "driver=raw,if=none,id=boot,file.filename=d3os.img",
+    "-drive", "driver=raw,if=none,id=hdd,file.filename=hdd.img",
+    "-device", "ide-hd,bus=ahci.0,drive=boot",
+    "-device", "ide-hd,bus=ide.0,drive=hdd",
+    "-device", "nvdimm,memdev=mem1,id=nv1,label-size=2M",
+    "-object", "memory-backend-file,id=mem1,share=on,mem-path=nvdimm0,size=16M",
+    "-nic", "model=ne2k_pci,id=ne2k,hostfwd=udp::1798-:1798",
+]
+
+
+def type_text(text):
+    # send the text to the keyboard of the guest, one key after the other
+    monitor = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    monitor.connect(MONITOR)
+    for char in text:
+        monitor.sendall(f"sendkey {KEYS.get(char, char)}\n".encode())
+        time.sleep(0.05)
+    monitor.close()
+
+
+def main():
+    ap = argparse.ArgumentParser(description="ne2000 overflow regression test in QEMU.")
+    ap.add_argument("--boot-time", type=int, default=30,
+                    help="seconds to wait for the shell of D3OS. Default: 30")
+    ap.add_argument("--duration", "-d", type=int, default=10,
+                    help="seconds to flood the card. Default: 10")
+    args = ap.parse_args()
+
+    os.chdir(os.path.dirname(os.path.abspath(__file__)))
+    os.makedirs("results", exist_ok=True)
+    if os.path.exists(MONITOR):
+        os.remove(MONITOR)
+
+    qemu = subprocess.Popen(QEMU_ARGS)
+    try:
+        time.sleep(args.boot_time)
+        if qemu.poll() is not None:
+            print(f"FAILED: QEMU has exited with {qemu.returncode}")
+            return 1
+
+        type_text(GUEST_COMMAND + "\n")
+        return subprocess.call([
+            sys.executable, "nettest.py", "127.0.0.1", "1797", "127.0.0.1", "1798",
+            "-m", "1", "--flood", "--check", "-d", str(args.duration),
+        ])
+    finally:
+        qemu.kill()
+        qemu.wait()
+        if os.path.exists(MONITOR):
+            os.remove(MONITOR)
+
+
+if __name__ == "__main__":
+    sys.exit(main())